@@ -0,0 +1,336 @@
+use std::path::PathBuf;
+
+use crate::{
+    config::DEFAULT_CONFIG_PATH, custom::DEFAULT_CUSTOM_MARKERS_PATH, download::MAP_DATA_URL,
+    exclusions::DEFAULT_EXCLUSIONS_PATH, map::MapSource, names::DEFAULT_LAYER_NAMES_PATH,
+};
+
+pub const DEFAULT_MAP_PATH: &str = "./assets/map_data.json";
+
+pub const USAGE: &str = "usage: sf-stations [OPTIONS] [MAP_DATA...]
+       sf-stations plan [PLAN OPTIONS]
+
+  MAP_DATA          path to a map JSON or a directory of them, or - to read
+                    one from stdin; several sources are merged
+                    (default: ./assets/map_data.json)
+
+options:
+  --download        use the map data downloaded from the interactive map,
+                    fetching it first if there is no cached copy yet
+  --map-url URL     where to download the map data from
+  --custom-markers PATH
+                    JSON list of your own points of interest
+                    (default: ./custom_markers.json, if it exists)
+  --config PATH     TOML file overriding the map bounds and orientation
+                    (default: ./sf-stations.toml, if it exists)
+  --exclusions PATH markers to keep out of the clustering, one pathName per
+                    line (default: ./excluded_markers.txt)
+  --layer-names PATH
+                    TOML file mapping layerIds to the names to show for them
+                    (default: ./layer_names.toml, if it exists)
+  -v, --verbose     also print the loaded layers
+  -h, --help        print this message
+
+plan options, to run the clustering without opening a window:
+  --map MAP_DATA    map JSON, directory or - for stdin, may be repeated
+                    (default: ./assets/map_data.json)
+  --stdin           read the map JSON from stdin, same as --map -
+  --k K             number of stations (default: 10)
+  --restarts N      number of runs from random points to keep the best of
+                    (default: 1)
+  --seed N          seed the runs are drawn from, for reproducible plans
+                    (default: random)
+  --out PATH        where to write the stations JSON (default: stdout)
+  -v, --verbose     also print the loaded layers
+
+plan exits with 1 on bad arguments or files that can't be read or written, 2
+if the map data can't be parsed and 3 if there are no markers to cluster.
+Everything but the stations JSON goes to stderr.";
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub maps: Vec<MapSource>,
+    pub download: bool,
+    pub map_url: String,
+    pub custom_markers: PathBuf,
+    pub config: PathBuf,
+    pub exclusions: PathBuf,
+    pub layer_names: PathBuf,
+    pub verbose: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PlanArgs {
+    pub maps: Vec<MapSource>,
+    pub k: usize,
+    pub restarts: u32,
+    pub seed: Option<u64>,
+    pub out: Option<PathBuf>,
+    pub verbose: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseOutcome {
+    Run(Args),
+    Plan(PlanArgs),
+    Help,
+}
+
+/// Parses the value following an option that takes a number.
+fn number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("'{}' requires a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for '{}'", value, flag))
+}
+
+fn map_source(maps: &[MapSource], arg: &str) -> Result<MapSource, String> {
+    match arg {
+        "-" if maps.contains(&MapSource::Stdin) => Err("stdin can only be read once".to_string()),
+        "-" => Ok(MapSource::Stdin),
+        path => Ok(MapSource::Path(PathBuf::from(path))),
+    }
+}
+
+impl Args {
+    /// Parses the arguments following the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<ParseOutcome, String> {
+        let mut maps = Vec::new();
+        let mut download = false;
+        let mut map_url = MAP_DATA_URL.to_string();
+        let mut custom_markers = PathBuf::from(DEFAULT_CUSTOM_MARKERS_PATH);
+        let mut config = PathBuf::from(DEFAULT_CONFIG_PATH);
+        let mut exclusions = PathBuf::from(DEFAULT_EXCLUSIONS_PATH);
+        let mut layer_names = PathBuf::from(DEFAULT_LAYER_NAMES_PATH);
+        let mut verbose = false;
+
+        let mut args = args.into_iter().peekable();
+        if args.peek().is_some_and(|arg| arg == "plan") {
+            args.next();
+            return Self::parse_plan(args);
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(ParseOutcome::Help),
+                "--download" => download = true,
+                "-v" | "--verbose" => verbose = true,
+                "--map-url" => {
+                    map_url = args
+                        .next()
+                        .ok_or_else(|| "'--map-url' requires a value".to_string())?;
+                }
+                "--custom-markers" => {
+                    custom_markers = args
+                        .next()
+                        .map(PathBuf::from)
+                        .ok_or_else(|| "'--custom-markers' requires a value".to_string())?;
+                }
+                "--config" => {
+                    config = args
+                        .next()
+                        .map(PathBuf::from)
+                        .ok_or_else(|| "'--config' requires a value".to_string())?;
+                }
+                "--exclusions" => {
+                    exclusions = args
+                        .next()
+                        .map(PathBuf::from)
+                        .ok_or_else(|| "'--exclusions' requires a value".to_string())?;
+                }
+                "--layer-names" => {
+                    layer_names = args
+                        .next()
+                        .map(PathBuf::from)
+                        .ok_or_else(|| "'--layer-names' requires a value".to_string())?;
+                }
+                flag if flag.starts_with('-') && flag != "-" => {
+                    return Err(format!("unknown option '{}'", flag));
+                }
+                source => maps.push(map_source(&maps, source)?),
+            }
+        }
+
+        if maps.is_empty() {
+            maps.push(MapSource::Path(PathBuf::from(DEFAULT_MAP_PATH)));
+        }
+
+        Ok(ParseOutcome::Run(Args {
+            maps,
+            download,
+            map_url,
+            custom_markers,
+            config,
+            exclusions,
+            layer_names,
+            verbose,
+        }))
+    }
+
+    fn parse_plan<I: Iterator<Item = String>>(mut args: I) -> Result<ParseOutcome, String> {
+        let mut maps = Vec::new();
+        let mut k = 10;
+        let mut restarts = 1;
+        let mut seed = None;
+        let mut out = None;
+        let mut verbose = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(ParseOutcome::Help),
+                "--map" => {
+                    let source = args
+                        .next()
+                        .ok_or_else(|| "'--map' requires a value".to_string())?;
+                    maps.push(map_source(&maps, &source)?);
+                }
+                "--stdin" => maps.push(map_source(&maps, "-")?),
+                "-v" | "--verbose" => verbose = true,
+                "--k" => k = number("--k", args.next())?,
+                "--restarts" => restarts = number("--restarts", args.next())?,
+                "--seed" => seed = Some(number("--seed", args.next())?),
+                "--out" => {
+                    out = Some(
+                        args.next()
+                            .map(PathBuf::from)
+                            .ok_or_else(|| "'--out' requires a value".to_string())?,
+                    );
+                }
+                other => return Err(format!("unknown plan option '{}'", other)),
+            }
+        }
+
+        if k == 0 {
+            return Err("'--k' must be at least 1".to_string());
+        }
+        if maps.is_empty() {
+            maps.push(MapSource::Path(PathBuf::from(DEFAULT_MAP_PATH)));
+        }
+
+        Ok(ParseOutcome::Plan(PlanArgs {
+            maps,
+            k,
+            restarts,
+            seed,
+            out,
+            verbose,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::load_map;
+
+    fn parse(args: &[&str]) -> Result<ParseOutcome, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn run(args: &[&str]) -> Args {
+        match parse(args) {
+            Ok(ParseOutcome::Run(args)) => args,
+            other => panic!("expected a run, got {:?}", other),
+        }
+    }
+
+    fn plan(args: &[&str]) -> PlanArgs {
+        match parse(args) {
+            Ok(ParseOutcome::Plan(args)) => args,
+            other => panic!("expected a plan, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn defaults_without_arguments() {
+        let args = run(&[]);
+        assert_eq!(args.maps, vec![MapSource::Path(PathBuf::from(DEFAULT_MAP_PATH))]);
+        assert!(!args.download);
+        assert_eq!(args.map_url, MAP_DATA_URL);
+        assert_eq!(args.custom_markers, PathBuf::from(DEFAULT_CUSTOM_MARKERS_PATH));
+        assert_eq!(args.config, PathBuf::from(DEFAULT_CONFIG_PATH));
+        assert_eq!(args.exclusions, PathBuf::from(DEFAULT_EXCLUSIONS_PATH));
+        assert_eq!(args.layer_names, PathBuf::from(DEFAULT_LAYER_NAMES_PATH));
+        assert!(!args.verbose);
+    }
+
+    #[test]
+    fn relative_and_absolute_paths_are_kept_as_given() {
+        let args = run(&["maps/a.json", "/srv/b.json"]);
+        assert_eq!(
+            args.maps,
+            vec![
+                MapSource::Path(PathBuf::from("maps/a.json")),
+                MapSource::Path(PathBuf::from("/srv/b.json")),
+            ]
+        );
+    }
+
+    #[test]
+    fn dash_reads_stdin_once() {
+        assert_eq!(run(&["-"]).maps, vec![MapSource::Stdin]);
+        assert!(parse(&["-", "-"]).is_err());
+        assert!(parse(&["plan", "--stdin", "--map", "-"]).is_err());
+    }
+
+    #[test]
+    fn flags() {
+        let args = run(&[
+            "--download",
+            "--map-url",
+            "https://example.com/map.json",
+            "--custom-markers",
+            "custom.json",
+            "--config",
+            "config.toml",
+            "--exclusions",
+            "excluded.txt",
+            "--layer-names",
+            "names.toml",
+            "-v",
+        ]);
+        assert!(args.download);
+        assert_eq!(args.map_url, "https://example.com/map.json");
+        assert_eq!(args.custom_markers, PathBuf::from("custom.json"));
+        assert_eq!(args.config, PathBuf::from("config.toml"));
+        assert_eq!(args.exclusions, PathBuf::from("excluded.txt"));
+        assert_eq!(args.layer_names, PathBuf::from("names.toml"));
+        assert!(args.verbose);
+    }
+
+    #[test]
+    fn bad_arguments() {
+        assert_eq!(parse(&["--help"]), Ok(ParseOutcome::Help));
+        assert!(parse(&["--frobnicate"]).is_err());
+        assert!(parse(&["--config"]).is_err());
+        assert!(parse(&["plan", "--k", "0"]).is_err());
+        assert!(parse(&["plan", "--k", "ten"]).is_err());
+        assert!(parse(&["plan", "stations.json"]).is_err());
+    }
+
+    #[test]
+    fn plan_defaults_and_flags() {
+        let defaults = plan(&["plan"]);
+        assert_eq!(defaults.maps, vec![MapSource::Path(PathBuf::from(DEFAULT_MAP_PATH))]);
+        assert_eq!((defaults.k, defaults.restarts, defaults.seed), (10, 1, None));
+        assert_eq!(defaults.out, None);
+
+        let args = plan(&[
+            "plan", "--map", "a.json", "--stdin", "--k", "12", "--restarts", "50", "--seed", "7",
+            "--out", "out.json",
+        ]);
+        assert_eq!(
+            args.maps,
+            vec![MapSource::Path(PathBuf::from("a.json")), MapSource::Stdin]
+        );
+        assert_eq!((args.k, args.restarts, args.seed), (12, 50, Some(7)));
+        assert_eq!(args.out, Some(PathBuf::from("out.json")));
+    }
+
+    #[test]
+    fn missing_file_is_named_in_the_error() {
+        let source = MapSource::Path(PathBuf::from("./no/such/map_data.json"));
+        let err = load_map(&source).expect_err("the file doesn't exist");
+        assert!(err.to_string().contains("./no/such/map_data.json"), "{}", err);
+    }
+}
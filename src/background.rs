@@ -0,0 +1,22 @@
+use std::{fs, io, path::Path};
+
+use egui::ColorImage;
+use tiny_skia::Pixmap;
+
+pub const DEFAULT_BACKGROUND_PATH: &str = "./assets/map.png";
+
+/// Decodes the map image drawn behind the markers. A missing file means there
+/// is no background.
+pub fn load_background(path: &Path) -> Result<Option<ColorImage>, String> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.to_string()),
+    };
+
+    let pixmap = Pixmap::decode_png(&bytes).map_err(|err| err.to_string())?;
+    Ok(Some(ColorImage::from_rgba_premultiplied(
+        [pixmap.width() as usize, pixmap.height() as usize],
+        pixmap.data(),
+    )))
+}
@@ -0,0 +1,3115 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    f32::consts::TAU,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use egui::{pos2, vec2, Pos2, Rect};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    rngs::StdRng,
+    seq::{index, SliceRandom},
+    Rng, SeedableRng,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export::{StationExport, StationRecord},
+    map::{Purity, ResourceMarker},
+    mask::BuildMask,
+    network::Network,
+    rates::Miner,
+    snapshot,
+    spatial::KdTree,
+};
+
+const MAP_LEFT: f32 = -324600.0;
+const MAP_TOP: f32 = -375000.0;
+const MAP_RIGHT: f32 = 425300.0;
+const MAP_BOT: f32 = 375000.0;
+
+/// Space left around the markers, as a fraction of their extent.
+const MAP_MARGIN: f32 = 0.05;
+
+/// Spacing of the world grid foundations snap to, 8 m. Its cells are centered
+/// on multiples of it from the world origin.
+pub const FOUNDATION_GRID: f32 = 800.0;
+
+/// Moves after which the median search gives up, in case it never settles.
+const MAX_ANNEAL_ITERATIONS: u32 = 100_000;
+/// Moves simulated annealing tries at every step, per direction.
+const METROPOLIS_MOVES_PER_DIRECTION: usize = 4;
+
+/// Weiszfeld iterations stop once the median moves less than this.
+const WEISZFELD_TOLERANCE: f32 = 0.1;
+const MAX_WEISZFELD_ITERATIONS: u32 = 1000;
+/// Smallest distance to a marker used as a divisor, so an iterate landing
+/// right on a marker doesn't divide by zero.
+const WEISZFELD_MIN_DISTANCE: f32 = 1e-3;
+
+/// Algorithm parameters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Parameters {
+    pub k: usize,
+    /// Starting step of the annealing if `anneal_step_fraction` is 0.
+    pub anneal_step: f32,
+    /// Starting step of the annealing as a share of the diagonal of the box
+    /// around the markers of a set and the starting spot.
+    pub anneal_step_fraction: f32,
+    pub anneal_epsilon: f32,
+    /// Factor the annealing step is multiplied by once no move improves.
+    pub anneal_cooling: f32,
+    /// Random directions tried per step besides the eight compass ones.
+    pub anneal_random_directions: usize,
+    /// Starting temperature of simulated annealing, relative to the error at
+    /// the start.
+    pub anneal_temperature: f32,
+    /// Factor the temperature is multiplied by along with the step.
+    pub anneal_temperature_cooling: f32,
+    pub k_median_max_iter: u32,
+    pub k_median_epsilon: f32,
+    pub algorithm: Algorithm,
+    /// How clusters are merged by the hierarchical algorithm.
+    pub linkage: Linkage,
+    /// Distance within which DBSCAN counts markers as neighbors.
+    pub dbscan_eps: f32,
+    /// Neighbors, the marker itself included, that make a marker the core of
+    /// a DBSCAN cluster.
+    pub dbscan_min_points: usize,
+    pub initialization: Initialization,
+    pub median_solver: MedianSolver,
+    /// Whether markers count by their purity rather than all the same.
+    pub weight_by_purity: bool,
+    pub purity_weights: PurityWeights,
+    /// Whether markers count by the items per minute `miner` extracts from
+    /// them, taking precedence over the purity weights.
+    pub weight_by_rate: bool,
+    pub miner: Miner,
+    /// Importance of the markers of a layer by `layerId`, 1 for those left
+    /// out.
+    pub layer_weights: BTreeMap<String, f32>,
+    pub metric: DistanceMetric,
+    pub loss: Loss,
+    /// Whether distances take the height into account.
+    pub three_d: bool,
+    /// Factor on height differences in 3D mode, as climbing costs more than
+    /// going along.
+    pub z_weight: f32,
+    /// Cost per unit of height between a marker and its station on top of
+    /// the distance, for ramps and lifts. 0 leaves heights to 3D mode.
+    pub climb_cost: f32,
+    /// Seed runs draw their random numbers from, and restart seeds are
+    /// derived from. A random one for every run if unset.
+    pub seed: Option<u64>,
+    /// Whether every layer is clustered on its own with its k from
+    /// `layer_ks` instead of all markers together.
+    pub per_layer: bool,
+    /// Stations per layer by `layerId` in per-layer mode, 0 for those left
+    /// out.
+    pub layer_ks: BTreeMap<String, usize>,
+    /// Whether `layer_ks` are proposed by sharing `layer_k_total` out over
+    /// the layers, see [`allocate_k`].
+    pub auto_layer_ks: bool,
+    /// Stations shared out over the layers with `auto_layer_ks`.
+    pub layer_k_total: usize,
+    /// What becomes of markers blocked by rocks or plants.
+    pub obstructed: Obstructed,
+    /// Factor on the weight of obstructed markers when down-weighting them.
+    pub obstructed_weight: f32,
+    /// Most a station serves, in `capacity_unit`, without a limit if unset.
+    pub capacity: Option<f32>,
+    pub capacity_unit: CapacityUnit,
+    /// Items per minute above which a station is flagged as overloaded.
+    pub throughput_cap: Option<f32>,
+    /// Farthest a marker may be from its station. Markers farther from every
+    /// station are left unassigned and out of the error.
+    pub max_radius: Option<f32>,
+    /// Areas in game coordinates stations may not be placed in, like the
+    /// ocean. Markers inside still count.
+    pub no_station_zones: Vec<Rect>,
+    /// Area in game coordinates to cluster the markers of, leaving out those
+    /// outside. All markers are clustered if unset.
+    pub region: Option<Rect>,
+    /// Factor on the variance of the weighted station sizes added to the
+    /// error, evening them out. 0 leaves the sizes alone.
+    pub balance: f32,
+    /// Factor on the length of the rail network, the minimum spanning tree
+    /// over the stations, added to the error. 0 leaves it out.
+    pub rail_weight: f32,
+    /// Factor on the weight of every set times the number of resources in it
+    /// beyond the first, added to the error, favoring stations serving a
+    /// single resource. 0 leaves it out.
+    pub mix_penalty: f32,
+    /// Whether markers farther from their point than `outlier_percentile`
+    /// percent of their set are left out when moving the point.
+    pub trim_outliers: bool,
+    pub outlier_percentile: f32,
+    /// Whether every marker pulls on every point by its membership, fuzzy
+    /// c-means style, instead of only on its closest one.
+    pub fuzzy: bool,
+    /// Fuzziness exponent of fuzzy assignment, above 1. The higher, the more
+    /// evenly markers are shared between points.
+    pub fuzziness: f32,
+    /// Station configurations an evolution keeps per generation.
+    pub evolution_population: usize,
+    pub evolution_generations: usize,
+    /// How far mutations jitter a station at most, as a share of the
+    /// diagonal of the map.
+    pub evolution_mutation: f32,
+    /// Whether runs end with [`Clustering::local_search`].
+    pub local_search: bool,
+    /// Markers at most this many times farther from their second closest
+    /// point than from their closest are tried on the second by the local
+    /// search.
+    pub local_search_ratio: f32,
+    /// Seconds the local search may take at most.
+    pub local_search_seconds: f32,
+    /// Whether the stations are refitted with [`Clustering::refit`] whenever
+    /// the clustered markers change, instead of only on demand.
+    pub refit_on_change: bool,
+    /// Least a movable station has to serve, in `min_cluster_unit`, for its
+    /// set not to be dissolved after a run. No least if unset.
+    pub min_cluster_size: Option<f32>,
+    pub min_cluster_unit: CapacityUnit,
+    /// What becomes of the stations of dissolved sets.
+    pub small_clusters: SmallClusters,
+    /// Whether stations are rounded to the foundation grid.
+    pub snap_to_grid: bool,
+    /// Grid spacing used instead of [`FOUNDATION_GRID`], e.g. for 1 m or 2 m
+    /// foundations.
+    pub grid_size: Option<f32>,
+    /// Whether stations are kept on the buildable cells of the mask, if one
+    /// is loaded.
+    pub buildable_only: bool,
+    /// Polylines in game coordinates of the rail or road network that
+    /// [`DistanceMetric::Network`] measures along.
+    pub network_lines: Vec<Vec<Pos2>>,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            k: 10,
+            anneal_step: 10000.0,
+            anneal_step_fraction: 0.25,
+            anneal_epsilon: 1.0,
+            anneal_cooling: 0.5,
+            anneal_random_directions: 8,
+            anneal_temperature: 0.01,
+            anneal_temperature_cooling: 0.5,
+            k_median_max_iter: 10,
+            k_median_epsilon: 10.0,
+            algorithm: Algorithm::default(),
+            linkage: Linkage::default(),
+            dbscan_eps: 20000.0,
+            dbscan_min_points: 4,
+            initialization: Initialization::default(),
+            median_solver: MedianSolver::default(),
+            weight_by_purity: true,
+            purity_weights: PurityWeights::default(),
+            weight_by_rate: false,
+            miner: Miner::default(),
+            layer_weights: BTreeMap::new(),
+            metric: DistanceMetric::default(),
+            loss: Loss::default(),
+            three_d: false,
+            z_weight: 1.0,
+            climb_cost: 0.0,
+            seed: None,
+            per_layer: false,
+            layer_ks: BTreeMap::new(),
+            auto_layer_ks: false,
+            layer_k_total: 10,
+            obstructed: Obstructed::default(),
+            obstructed_weight: 0.5,
+            capacity: None,
+            capacity_unit: CapacityUnit::default(),
+            throughput_cap: None,
+            max_radius: None,
+            no_station_zones: Vec::new(),
+            region: None,
+            balance: 0.0,
+            rail_weight: 0.0,
+            mix_penalty: 0.0,
+            trim_outliers: false,
+            outlier_percentile: 95.0,
+            fuzzy: false,
+            fuzziness: 2.0,
+            evolution_population: 20,
+            evolution_generations: 30,
+            evolution_mutation: 0.05,
+            local_search: false,
+            local_search_ratio: 1.2,
+            local_search_seconds: 2.0,
+            refit_on_change: false,
+            min_cluster_size: None,
+            min_cluster_unit: CapacityUnit::default(),
+            small_clusters: SmallClusters::default(),
+            snap_to_grid: false,
+            grid_size: None,
+            buildable_only: true,
+            network_lines: Vec::new(),
+        }
+    }
+}
+
+impl Parameters {
+    /// The point rounded to the center of its grid cell if snapping is on.
+    pub fn snap(&self, point: Pos2) -> Pos2 {
+        let size = self.grid_size.unwrap_or(FOUNDATION_GRID);
+        if self.snap_to_grid && size > 0.0 {
+            pos2((point.x / size).round() * size, (point.y / size).round() * size)
+        } else {
+            point
+        }
+    }
+
+    /// How much a marker of the layer with the given `layerId` counts in the
+    /// clustering.
+    pub fn marker_weight(&self, marker: &ResourceMarker, layer_id: &str) -> f32 {
+        let purity = if self.weight_by_rate {
+            // markers without a purity count like normal nodes
+            match self.miner.node_rate(marker) {
+                rate if rate > 0.0 => rate,
+                _ => self.miner.rate(&Purity::Normal).unwrap_or(1.0),
+            }
+        } else if self.weight_by_purity {
+            self.purity_weights.weight(&marker.purity)
+        } else {
+            1.0
+        };
+        let obstructed = match self.obstructed {
+            Obstructed::Include => 1.0,
+            _ if marker.obstructed != Some(true) => 1.0,
+            Obstructed::Exclude => 0.0,
+            Obstructed::DownWeight => self.obstructed_weight,
+        };
+        purity * obstructed * self.layer_weight(layer_id)
+    }
+
+    pub fn layer_weight(&self, layer_id: &str) -> f32 {
+        self.layer_weights.get(layer_id).copied().unwrap_or(1.0)
+    }
+
+    pub fn set_layer_weight(&mut self, layer_id: &str, weight: f32) {
+        if weight == 1.0 {
+            self.layer_weights.remove(layer_id);
+        } else {
+            self.layer_weights.insert(layer_id.to_string(), weight);
+        }
+    }
+
+    pub fn layer_k(&self, layer_id: &str) -> usize {
+        self.layer_ks.get(layer_id).copied().unwrap_or(0)
+    }
+
+    pub fn set_layer_k(&mut self, layer_id: &str, k: usize) {
+        if k == 0 {
+            self.layer_ks.remove(layer_id);
+        } else {
+            self.layer_ks.insert(layer_id.to_string(), k);
+        }
+    }
+}
+
+/// How much a node of each purity counts, by default in proportion to its
+/// yield.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PurityWeights {
+    pub impure: f32,
+    pub normal: f32,
+    pub pure: f32,
+}
+
+impl Default for PurityWeights {
+    fn default() -> Self {
+        Self {
+            impure: 1.0,
+            normal: 2.0,
+            pure: 4.0,
+        }
+    }
+}
+
+impl PurityWeights {
+    /// The weight of a purity, nodes of unknown purity counting as impure.
+    pub fn weight(&self, purity: &Purity) -> f32 {
+        match purity {
+            Purity::Impure | Purity::Unknown(_) => self.impure,
+            Purity::Normal => self.normal,
+            Purity::Pure => self.pure,
+        }
+    }
+}
+
+/// How "reset and run" places the stations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Algorithm {
+    /// Iterated assignment and median steps from random initial points.
+    #[default]
+    KMedian,
+    /// Merging the markers bottom-up until k clusters are left, see
+    /// [`crate::hierarchy::hierarchical`]. Deterministic.
+    Hierarchical,
+    /// A station per group of markers close together, as many as there are,
+    /// see [`crate::dbscan::dbscan`].
+    Dbscan,
+    /// Every way to put the stations on the nodes, see
+    /// [`crate::exact::exact`]. Only for few nodes and stations.
+    Exact,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 4] = [
+        Algorithm::KMedian,
+        Algorithm::Hierarchical,
+        Algorithm::Dbscan,
+        Algorithm::Exact,
+    ];
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Algorithm::KMedian => write!(f, "k-median"),
+            Algorithm::Hierarchical => write!(f, "hierarchical"),
+            Algorithm::Dbscan => write!(f, "DBSCAN"),
+            Algorithm::Exact => write!(f, "exact (on nodes)"),
+        }
+    }
+}
+
+/// Distance between two clusters in hierarchical clustering.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Linkage {
+    /// Of their closest markers.
+    Single,
+    /// Of their farthest markers.
+    Complete,
+    /// Mean over all pairs of their markers, by weight.
+    #[default]
+    Average,
+    /// Increase of the squared error when merging them.
+    Ward,
+}
+
+impl Linkage {
+    pub const ALL: [Linkage; 4] =
+        [Linkage::Single, Linkage::Complete, Linkage::Average, Linkage::Ward];
+}
+
+impl fmt::Display for Linkage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Linkage::Single => write!(f, "single"),
+            Linkage::Complete => write!(f, "complete"),
+            Linkage::Average => write!(f, "average"),
+            Linkage::Ward => write!(f, "Ward"),
+        }
+    }
+}
+
+/// Where the points that are not seeds start out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Initialization {
+    /// Anywhere in the bounds, uniformly at random.
+    Uniform,
+    /// On distinct markers picked at random.
+    #[default]
+    RandomMarkers,
+    /// k-means++: the first point on a random marker, every further one on a
+    /// marker picked with a probability proportional to its squared distance
+    /// to the closest point so far.
+    KMeansPlusPlus,
+}
+
+impl Initialization {
+    pub const ALL: [Initialization; 3] = [
+        Initialization::Uniform,
+        Initialization::RandomMarkers,
+        Initialization::KMeansPlusPlus,
+    ];
+}
+
+impl fmt::Display for Initialization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Initialization::Uniform => write!(f, "uniform random"),
+            Initialization::RandomMarkers => write!(f, "random markers"),
+            Initialization::KMeansPlusPlus => write!(f, "k-means++"),
+        }
+    }
+}
+
+/// What the clustering does with obstructed markers. Markers not known to be
+/// obstructed count as unobstructed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Obstructed {
+    #[default]
+    Include,
+    Exclude,
+    /// Counts them times `Parameters::obstructed_weight`.
+    DownWeight,
+}
+
+impl Obstructed {
+    pub const ALL: [Obstructed; 3] =
+        [Obstructed::Include, Obstructed::Exclude, Obstructed::DownWeight];
+}
+
+impl fmt::Display for Obstructed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Obstructed::Include => write!(f, "include"),
+            Obstructed::Exclude => write!(f, "exclude"),
+            Obstructed::DownWeight => write!(f, "down-weight"),
+        }
+    }
+}
+
+/// What the capacity of a station counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CapacityUnit {
+    /// Every node as one.
+    #[default]
+    Nodes,
+    /// Nodes by their weight, roughly their rate with purity weights.
+    Weight,
+    /// Items per minute of the miner on every node, see
+    /// [`Miner::node_rate`].
+    Rate,
+}
+
+impl CapacityUnit {
+    pub const ALL: [CapacityUnit; 3] =
+        [CapacityUnit::Nodes, CapacityUnit::Weight, CapacityUnit::Rate];
+}
+
+impl fmt::Display for CapacityUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CapacityUnit::Nodes => write!(f, "nodes"),
+            CapacityUnit::Weight => write!(f, "weight"),
+            CapacityUnit::Rate => write!(f, "items/min"),
+        }
+    }
+}
+
+/// What becomes of a station whose set was too small.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SmallClusters {
+    /// Leaves it out, leaving fewer stations than k.
+    #[default]
+    Remove,
+    /// Moves it to the nodes served worst.
+    Reseed,
+}
+
+impl SmallClusters {
+    pub const ALL: [SmallClusters; 2] = [SmallClusters::Remove, SmallClusters::Reseed];
+}
+
+impl fmt::Display for SmallClusters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SmallClusters::Remove => write!(f, "remove the station"),
+            SmallClusters::Reseed => write!(f, "move it to the worst served nodes"),
+        }
+    }
+}
+
+/// How the distance between a marker and a station is measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DistanceMetric {
+    /// Straight-line distance.
+    #[default]
+    Euclidean,
+    /// Distance along the axes, for belts and rails that follow the
+    /// foundation grid.
+    Manhattan,
+    /// Distance onto the network of [`Parameters::network_lines`], along it
+    /// and off again, see [`Network::distance`]. Straight-line where there
+    /// is no network connecting the two ends or only a difference is known.
+    Network,
+}
+
+impl DistanceMetric {
+    pub const ALL: [DistanceMetric; 3] = [
+        DistanceMetric::Euclidean,
+        DistanceMetric::Manhattan,
+        DistanceMetric::Network,
+    ];
+
+    pub fn distance(self, a: Pos2, b: Pos2) -> f32 {
+        self.length([a.x - b.x, a.y - b.y, 0.0])
+    }
+
+    /// The length of a difference of positions.
+    pub fn length(self, [x, y, z]: [f32; 3]) -> f32 {
+        match self {
+            DistanceMetric::Euclidean | DistanceMetric::Network => (x * x + y * y + z * z).sqrt(),
+            DistanceMetric::Manhattan => x.abs() + y.abs() + z.abs(),
+        }
+    }
+}
+
+impl fmt::Display for DistanceMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DistanceMetric::Euclidean => write!(f, "Euclidean"),
+            DistanceMetric::Manhattan => write!(f, "Manhattan"),
+            DistanceMetric::Network => write!(f, "along the network"),
+        }
+    }
+}
+
+/// What the error sums over the markers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Loss {
+    /// Distances, k-median: points move to the median of their markers.
+    #[default]
+    Distance,
+    /// Squared distances, k-means: points move to the centroid of their
+    /// markers, which punishes far markers more and spreads the points out.
+    SquaredDistance,
+}
+
+impl Loss {
+    pub const ALL: [Loss; 2] = [Loss::Distance, Loss::SquaredDistance];
+}
+
+impl fmt::Display for Loss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Loss::Distance => write!(f, "k-median (distances)"),
+            Loss::SquaredDistance => write!(f, "k-means (squared distances)"),
+        }
+    }
+}
+
+/// How the point of a set is moved to the median of its markers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MedianSolver {
+    /// Weiszfeld's iteratively reweighted mean, see [`geometric_median`].
+    #[default]
+    Weiszfeld,
+    /// Step search in a set of directions, see
+    /// [`Problem::simulated_annealing`].
+    Annealing,
+    /// Like `Annealing`, but also taking moves for the worse now and then,
+    /// see [`Problem::metropolis_annealing`].
+    SimulatedAnnealing,
+    /// k-medoids: the member marker closest to the others, see
+    /// [`Problem::medoid`].
+    Medoid,
+    /// The weighted median of every axis on its own, see
+    /// [`manhattan_median`]. Exact for Manhattan distances and a cheap,
+    /// deterministic stand-in for Euclidean ones.
+    PerAxis,
+}
+
+impl MedianSolver {
+    pub const ALL: [MedianSolver; 5] = [
+        MedianSolver::Weiszfeld,
+        MedianSolver::Annealing,
+        MedianSolver::SimulatedAnnealing,
+        MedianSolver::Medoid,
+        MedianSolver::PerAxis,
+    ];
+
+    /// Whether the solver searches with the `anneal_*` parameters.
+    pub fn is_annealing(self) -> bool {
+        matches!(self, MedianSolver::Annealing | MedianSolver::SimulatedAnnealing)
+    }
+}
+
+impl fmt::Display for MedianSolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MedianSolver::Weiszfeld => write!(f, "Weiszfeld"),
+            MedianSolver::Annealing => write!(f, "annealing (greedy)"),
+            MedianSolver::SimulatedAnnealing => write!(f, "simulated annealing"),
+            MedianSolver::Medoid => write!(f, "k-medoids"),
+            MedianSolver::PerAxis => write!(f, "per-axis median"),
+        }
+    }
+}
+
+/// Why a k-median run stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Convergence {
+    /// No marker changed its station in the last iteration, a fixed point.
+    Stable,
+    /// The error changed by less than `k_median_epsilon`.
+    Settled,
+    /// `k_median_max_iter` iterations passed without either.
+    MaxIterations,
+}
+
+impl fmt::Display for Convergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Convergence::Stable => write!(f, "converged: assignments stable"),
+            Convergence::Settled => write!(f, "converged: error settled"),
+            Convergence::MaxIterations => write!(f, "hit max iterations"),
+        }
+    }
+}
+
+/// What one k-median iteration did.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepOutcome {
+    /// Markers that changed their point, None in the first iteration.
+    pub reassigned: Option<usize>,
+    /// Error of the points after the step.
+    pub error: f32,
+    /// Whether the run converged with this step, and how.
+    pub convergence: Option<Convergence>,
+}
+
+impl StepOutcome {
+    pub fn converged(&self) -> bool {
+        self.convergence.is_some()
+    }
+}
+
+/// What results are comparable by: their errors only are for the same number
+/// of stations over the same markers, weighed and measured the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResultKey {
+    pub k: usize,
+    /// Hash of the positions of the clustered markers.
+    pub markers: u64,
+    pub metric: DistanceMetric,
+    /// Missing in keys saved before losses were selectable.
+    #[serde(default)]
+    pub loss: Loss,
+    /// Hash of the weights of the clustered markers and the height factor.
+    pub weights: u64,
+}
+
+impl ResultKey {
+    /// What differs from another key, in words.
+    pub fn changes(&self, other: &ResultKey) -> Vec<&'static str> {
+        [
+            (self.k != other.k, "number of stations"),
+            (self.markers != other.markers, "markers"),
+            (self.metric != other.metric, "distance metric"),
+            (self.loss != other.loss, "objective"),
+            (self.weights != other.weights, "weights"),
+        ]
+        .into_iter()
+        .filter(|(changed, _)| *changed)
+        .map(|(_, change)| change)
+        .collect()
+    }
+}
+
+/// The point minimizing the weighted sum of distances to `points`, found with
+/// Weiszfeld's algorithm starting from the weighted mean. `None` if there are
+/// no points or the weights are all zero.
+pub fn geometric_median(points: &[[f32; 3]], weights: &[f32]) -> Option<[f32; 3]> {
+    let mut median = weighted_mean(points, weights)?;
+    for _ in 0..MAX_WEISZFELD_ITERATIONS {
+        let mut numerator = [0.0; 3];
+        let mut denominator = 0.0;
+        for (point, weight) in points.iter().zip(weights) {
+            let distance = DistanceMetric::Euclidean.length(difference(*point, median));
+            let factor = weight / distance.max(WEISZFELD_MIN_DISTANCE);
+            for axis in 0..3 {
+                numerator[axis] += point[axis] * factor;
+            }
+            denominator += factor;
+        }
+        let next = numerator.map(|n| n / denominator);
+        let moved = DistanceMetric::Euclidean.length(difference(next, median));
+        median = next;
+        if moved < WEISZFELD_TOLERANCE {
+            break;
+        }
+    }
+
+    Some(median)
+}
+
+/// The point minimizing the weighted sum of Manhattan distances to `points`,
+/// which is the weighted median of each axis on its own. `None` if there are
+/// no points or the weights are all zero.
+pub fn manhattan_median(points: &[[f32; 3]], weights: &[f32]) -> Option<[f32; 3]> {
+    let mut median = [0.0; 3];
+    for (axis, value) in median.iter_mut().enumerate() {
+        *value = weighted_median(
+            points.iter().map(|p| p[axis]).zip(weights.iter().copied()).collect(),
+        )?;
+    }
+    Some(median)
+}
+
+/// `None` if there are no points or the weights are all zero.
+fn weighted_mean(points: &[[f32; 3]], weights: &[f32]) -> Option<[f32; 3]> {
+    let total: f32 = weights.iter().sum();
+    if points.is_empty() || total <= 0.0 {
+        return None;
+    }
+
+    let mut mean = [0.0; 3];
+    for (point, weight) in points.iter().zip(weights) {
+        for axis in 0..3 {
+            mean[axis] += point[axis] * weight / total;
+        }
+    }
+    Some(mean)
+}
+
+fn difference(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+/// The value at which half of the total weight lies on either side, the
+/// lower one if two values split it exactly. Values weighing nothing are
+/// never the median.
+fn weighted_median(mut values: Vec<(f32, f32)>) -> Option<f32> {
+    let total: f32 = values.iter().map(|(_, weight)| weight).sum();
+    if total <= 0.0 {
+        return None;
+    }
+
+    values.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    let mut below = 0.0;
+    for (value, weight) in &values {
+        below += weight;
+        if below >= total / 2.0 {
+            return Some(*value);
+        }
+    }
+    values.last().map(|(value, _)| *value)
+}
+
+pub fn default_map_bounds() -> Rect {
+    Rect::from_min_max(pos2(MAP_LEFT, MAP_TOP), pos2(MAP_RIGHT, MAP_BOT))
+}
+
+/// Bounding box of the markers with a margin around it, if there are any.
+pub fn marker_bounds(markers: &[ResourceMarker]) -> Option<Rect> {
+    let mut points = markers.iter().map(|m| pos2(m.x, m.y));
+    let first = points.next()?;
+    let bounds = points.fold(Rect::from_min_max(first, first), |rect, point| {
+        rect.union(Rect::from_min_max(point, point))
+    });
+    // keep a single marker from collapsing the view to a point
+    let margin = (bounds.size() * MAP_MARGIN).max(vec2(1000.0, 1000.0));
+    Some(bounds.expand2(margin))
+}
+
+/// What a clustering runs on: the markers, which of them take part, and how.
+pub struct Problem<'a> {
+    pub markers: &'a [ResourceMarker],
+    /// Per marker, whether it is assigned to a point at all.
+    pub clustered: Vec<bool>,
+    /// Per marker, how much its distance counts. Markers weighing nothing are
+    /// left out like unclustered ones.
+    pub weights: Vec<f32>,
+    pub params: &'a Parameters,
+    /// Area random initial points are drawn from.
+    pub bounds: Rect,
+    /// Points placed first that never move, with markers assigned to them like
+    /// to any other.
+    pub pinned: &'a [Pos2],
+    /// Initial points used after the pinned ones and before random ones.
+    pub seeds: &'a [Pos2],
+    /// Whether the seeds must stay where they are.
+    pub seeds_fixed: bool,
+    /// Where stations can be built, if a mask is loaded.
+    pub mask: Option<&'a BuildMask>,
+    /// The network of [`Parameters::network_lines`], if it has any.
+    pub network: Option<&'a Network>,
+}
+
+impl Problem<'_> {
+    /// Where a station for the given median goes: outside the no-station
+    /// zones, onto the closest buildable cell if the mask is on, then on the
+    /// grid if snapping is on.
+    pub fn place(&self, median: Pos2) -> Pos2 {
+        let point = outside_zones(&self.params.no_station_zones, median);
+        match self.mask {
+            Some(mask) if self.params.buildable_only => self.params.snap(mask.project(point)),
+            _ => self.params.snap(point),
+        }
+    }
+
+    /// Factor on height differences, 0 unless in 3D mode.
+    pub fn z_scale(&self) -> f32 {
+        if self.params.three_d {
+            self.params.z_weight
+        } else {
+            0.0
+        }
+    }
+
+    /// Distance of a marker to a point at height `z`, plus the climb. Along
+    /// the network the height difference is added on.
+    pub fn distance(&self, point: Pos2, z: f32, markeri: usize) -> f32 {
+        let marker = &self.markers[markeri];
+        let along = match self.network {
+            Some(network) if self.params.metric == DistanceMetric::Network => {
+                network.distance(point, pos2(marker.x, marker.y))
+            }
+            _ => None,
+        };
+        let length = match along {
+            Some(along) => along + ((z - marker.z) * self.z_scale()).abs(),
+            None => self.params.metric.length([
+                point.x - marker.x,
+                point.y - marker.y,
+                (z - marker.z) * self.z_scale(),
+            ]),
+        };
+        length + self.params.climb_cost * (z - marker.z).abs()
+    }
+
+    /// Partitions the markers into disjoint sets based on the closest point to
+    /// them, the points being at the given heights. The closest points are
+    /// looked up in a [`KdTree`] over the points.
+    pub fn assign(&self, points: &[Pos2], heights: &[f32]) -> Vec<Vec<usize>> {
+        if let Some(capacity) = self.params.capacity {
+            return self.assign_with_capacity(points, heights, capacity);
+        }
+
+        let tree = KdTree::new(points.iter().copied().enumerate());
+        let mut sets = vec![Vec::new(); points.len()];
+        for markeri in 0..self.markers.len() {
+            if !self.is_assignable(markeri) {
+                continue;
+            }
+
+            let marker = &self.markers[markeri];
+            let closest = tree.nearest(pos2(marker.x, marker.y), |i| {
+                let z = heights.get(i).copied().unwrap_or_default();
+                self.distance(points[i], z, markeri)
+            });
+            let Some((closest_index, closest_distance)) = closest else {
+                continue;
+            };
+            if !self.is_in_range(closest_distance) {
+                continue;
+            }
+            if let Some(set) = sets.get_mut(closest_index) {
+                set.push(markeri);
+            }
+        }
+
+        sets
+    }
+
+    /// Per marker, the point of the set it is in and its
+    /// [`Problem::distance`] to it, the one the clustering minimized.
+    pub fn stations_of_markers(
+        &self,
+        points: &[Pos2],
+        heights: &[f32],
+        sets: &[Vec<usize>],
+    ) -> Vec<Option<(usize, f32)>> {
+        let mut stations = vec![None; self.markers.len()];
+        for (seti, (point, set)) in points.iter().zip(sets).enumerate() {
+            let z = heights.get(seti).copied().unwrap_or_default();
+            for i in set {
+                stations[*i] = Some((seti, self.distance(*point, z, *i)));
+            }
+        }
+        stations
+    }
+
+    /// Whether a marker takes part in the assignment at all.
+    pub fn is_assignable(&self, markeri: usize) -> bool {
+        let marker = &self.markers[markeri];
+        // a marker without a position would turn its point into NaN
+        self.clustered[markeri]
+            && marker.x.is_finite()
+            && marker.y.is_finite()
+            && self.weights[markeri] > 0.0
+    }
+
+    fn is_in_range(&self, distance: f32) -> bool {
+        self.params.max_radius.is_none_or(|radius| distance <= radius)
+    }
+
+    /// Markers that could be assigned but are out of range of every point.
+    pub fn uncovered(&self, sets: &[Vec<usize>]) -> usize {
+        let assignable = (0..self.markers.len())
+            .filter(|markeri| self.is_assignable(*markeri))
+            .count();
+        assignable - sets.iter().map(Vec::len).sum::<usize>().min(assignable)
+    }
+
+    /// Assigns the markers closest to a point first, each to the closest point
+    /// with room left for it, or to the closest of all once every point is
+    /// full.
+    fn assign_with_capacity(
+        &self,
+        points: &[Pos2],
+        heights: &[f32],
+        capacity: f32,
+    ) -> Vec<Vec<usize>> {
+        let mut ranked: Vec<(usize, Vec<(usize, f32)>)> = (0..self.markers.len())
+            .filter(|markeri| self.is_assignable(*markeri))
+            .map(|markeri| {
+                let mut distances: Vec<(usize, f32)> = points
+                    .iter()
+                    .enumerate()
+                    .map(|(i, point)| {
+                        let z = heights.get(i).copied().unwrap_or_default();
+                        (i, self.distance(*point, z, markeri))
+                    })
+                    .filter(|(_, distance)| self.is_in_range(*distance))
+                    .collect();
+                distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+                (markeri, distances)
+            })
+            .collect();
+        ranked.sort_by(|a, b| {
+            let closest = |distances: &[(usize, f32)]| distances.first().map_or(0.0, |d| d.1);
+            closest(&a.1).total_cmp(&closest(&b.1))
+        });
+
+        let mut sets = vec![Vec::new(); points.len()];
+        let mut loads = vec![0.0; points.len()];
+        for (markeri, distances) in ranked {
+            let Some(&(closest, _)) = distances.first() else {
+                continue;
+            };
+            let demand = self.demand(markeri);
+            let point = distances
+                .iter()
+                .map(|(i, _)| *i)
+                .find(|i| loads[*i] + demand <= capacity)
+                .unwrap_or(closest);
+            loads[point] += demand;
+            sets[point].push(markeri);
+        }
+        sets
+    }
+
+    /// How much of a station's capacity a marker takes up.
+    pub fn demand(&self, markeri: usize) -> f32 {
+        self.amount(self.params.capacity_unit, markeri)
+    }
+
+    /// How much a marker counts in the given unit.
+    pub fn amount(&self, unit: CapacityUnit, markeri: usize) -> f32 {
+        match unit {
+            CapacityUnit::Nodes => 1.0,
+            CapacityUnit::Weight => self.weights[markeri],
+            CapacityUnit::Rate => self.params.miner.node_rate(&self.markers[markeri]),
+        }
+    }
+
+    /// How much of its capacity a set takes up.
+    pub fn load(&self, set: &[usize]) -> f32 {
+        set.iter().map(|i| self.demand(*i)).sum()
+    }
+
+    /// Capacity all markers that can be assigned take up together.
+    pub fn total_demand(&self) -> f32 {
+        (0..self.markers.len())
+            .filter(|markeri| self.is_assignable(*markeri))
+            .map(|markeri| self.demand(markeri))
+            .sum()
+    }
+
+    /// [`Problem::total_error`] with markers going to their closest point
+    /// regardless of the capacity.
+    pub fn uncapacitated_error(&self, points: &[Pos2], heights: &[f32]) -> f32 {
+        let free = Problem {
+            markers: self.markers,
+            clustered: self.clustered.clone(),
+            weights: self.weights.clone(),
+            params: &Parameters {
+                capacity: None,
+                ..self.params.clone()
+            },
+            bounds: self.bounds,
+            pinned: self.pinned,
+            seeds: self.seeds,
+            seeds_fixed: self.seeds_fixed,
+            mask: self.mask,
+            network: self.network,
+        };
+        free.total_error(points, heights)
+    }
+
+    /// The error of every point with the markers of its set, as in
+    /// [`Problem::set_error`].
+    pub fn set_errors(&self, points: &[Pos2], heights: &[f32], sets: &[Vec<usize>]) -> Vec<f32> {
+        points
+            .iter()
+            .zip(heights)
+            .zip(sets)
+            .map(|((point, z), set)| self.set_error(*point, *z, set))
+            .collect()
+    }
+
+    /// What every point adds to the error, largest first. `errors` are the
+    /// errors of the sets as kept by [`Clustering::set_errors`].
+    pub fn station_errors(
+        &self,
+        points: &[Pos2],
+        heights: &[f32],
+        sets: &[Vec<usize>],
+        errors: &[f32],
+    ) -> Vec<StationError> {
+        let mut stations: Vec<StationError> = points
+            .iter()
+            .zip(heights)
+            .zip(sets)
+            .zip(errors)
+            .enumerate()
+            .map(|(index, (((point, z), set), error))| {
+                let distances = set.iter().map(|i| (*i, self.distance(*point, *z, *i)));
+                StationError {
+                    index,
+                    nodes: set.len(),
+                    error: *error,
+                    mean_distance: if set.is_empty() {
+                        0.0
+                    } else {
+                        distances.clone().map(|(_, d)| d).sum::<f32>() / set.len() as f32
+                    },
+                    worst: distances.max_by(|a, b| a.1.total_cmp(&b.1)),
+                }
+            })
+            .collect();
+        stations.sort_by(|a, b| b.error.total_cmp(&a.error));
+        stations
+    }
+
+    /// Summed distance of the markers in a set to their point at height `z`,
+    /// or squared distance with the k-means loss, each times its weight.
+    pub fn set_error(&self, point: Pos2, z: f32, set: &[usize]) -> f32 {
+        set.iter().map(|i| self.cost(point, z, *i) * self.weights[*i]).sum()
+    }
+
+    /// What a marker adds to the error of a point, before its weight.
+    pub fn cost(&self, point: Pos2, z: f32, markeri: usize) -> f32 {
+        let distance = self.distance(point, z, markeri);
+        match self.params.loss {
+            Loss::Distance => distance,
+            Loss::SquaredDistance => distance * distance,
+        }
+    }
+
+    /// Summed error of all points with the markers assigned to them.
+    pub fn total_error(&self, points: &[Pos2], heights: &[f32]) -> f32 {
+        self.score(points, heights).1
+    }
+
+    /// Markers out of range of the points and the total error of the others,
+    /// as compared by [`is_better`].
+    pub fn score(&self, points: &[Pos2], heights: &[f32]) -> (usize, f32) {
+        let sets = self.assign(points, heights);
+        let error = points
+            .iter()
+            .zip(heights)
+            .zip(&sets)
+            .map(|((point, z), set)| self.set_error(*point, *z, set))
+            .sum();
+        (self.uncovered(&sets), self.objective(error, points, &sets))
+    }
+
+    /// Summed weight of the markers in a set.
+    pub fn size(&self, set: &[usize]) -> f32 {
+        set.iter().map(|i| self.weights[*i]).sum()
+    }
+
+    /// Variance of the sizes of the sets.
+    pub fn imbalance(&self, sets: &[Vec<usize>]) -> f32 {
+        if sets.is_empty() {
+            return 0.0;
+        }
+        let sizes: Vec<f32> = sets.iter().map(|set| self.size(set)).collect();
+        let mean = sizes.iter().sum::<f32>() / sizes.len() as f32;
+        sizes.iter().map(|size| (size - mean).powi(2)).sum::<f32>() / sizes.len() as f32
+    }
+
+    /// The markers of a set farther from its point than `outlier_percentile`
+    /// percent of them. Small sets have none, as their percentile is their
+    /// farthest marker.
+    pub fn outliers(&self, point: Pos2, z: f32, set: &[usize]) -> Vec<usize> {
+        if set.is_empty() {
+            return Vec::new();
+        }
+        let mut distances: Vec<f32> = set.iter().map(|i| self.distance(point, z, *i)).collect();
+        distances.sort_by(f32::total_cmp);
+        let share = self.params.outlier_percentile.clamp(0.0, 100.0) / 100.0;
+        let rank = ((share * set.len() as f32).ceil() as usize).clamp(1, set.len());
+        let threshold = distances[rank - 1];
+        set.iter().copied().filter(|i| self.distance(point, z, *i) > threshold).collect()
+    }
+
+    /// The distinct resources among the markers of a set: their item type,
+    /// or their layer for markers without one.
+    pub fn resources(&self, set: &[usize]) -> usize {
+        let resources: BTreeSet<(Option<&str>, usize)> = set
+            .iter()
+            .map(|i| {
+                let marker = &self.markers[*i];
+                match &marker.r#type {
+                    Some(resource) => (Some(resource.as_str()), 0),
+                    None => (None, marker.layer),
+                }
+            })
+            .collect();
+        resources.len()
+    }
+
+    /// Summed weight of the sets times the resources in them beyond the
+    /// first.
+    pub fn mix(&self, sets: &[Vec<usize>]) -> f32 {
+        sets.iter()
+            .map(|set| self.resources(set).saturating_sub(1) as f32 * self.size(set))
+            .sum()
+    }
+
+    /// The distance error of the points and their sets plus the balance, rail
+    /// network and resource mix terms.
+    pub fn objective(&self, mut error: f32, points: &[Pos2], sets: &[Vec<usize>]) -> f32 {
+        if self.params.balance > 0.0 {
+            error += self.params.balance * self.imbalance(sets);
+        }
+        if self.params.rail_weight > 0.0 {
+            error += self.params.rail_weight * rail_length(points);
+        }
+        if self.params.mix_penalty > 0.0 {
+            error += self.params.mix_penalty * self.mix(sets);
+        }
+        error
+    }
+
+    /// Heights for points that come without one, like imported stations: the
+    /// weighted mean height of the markers closest to each.
+    pub fn estimate_heights(&self, points: &[Pos2]) -> Vec<f32> {
+        let flat = Problem {
+            markers: self.markers,
+            clustered: self.clustered.clone(),
+            weights: self.weights.clone(),
+            params: &Parameters {
+                three_d: false,
+                climb_cost: 0.0,
+                ..self.params.clone()
+            },
+            bounds: self.bounds,
+            pinned: self.pinned,
+            seeds: self.seeds,
+            seeds_fixed: self.seeds_fixed,
+            mask: self.mask,
+            network: self.network,
+        };
+        flat.assign(points, &[])
+            .iter()
+            .map(|set| self.flat_height(set))
+            .collect()
+    }
+
+    /// Height of the point of a set outside 3D mode: the weighted median of
+    /// the marker heights with a climb cost, as that is the cheapest then,
+    /// their mean otherwise.
+    fn flat_height(&self, set: &[usize]) -> f32 {
+        if self.params.climb_cost > 0.0 {
+            weighted_median(set.iter().map(|i| (self.markers[*i].z, self.weights[*i])).collect())
+                .unwrap_or_default()
+        } else {
+            self.mean_height(set)
+        }
+    }
+
+    /// Weighted mean height of the markers in a set, 0 for an empty one.
+    fn mean_height(&self, set: &[usize]) -> f32 {
+        let points: Vec<[f32; 3]> = set.iter().map(|i| [0.0, 0.0, self.markers[*i].z]).collect();
+        let weights: Vec<f32> = set.iter().map(|i| self.weights[*i]).collect();
+        weighted_mean(&points, &weights).map_or(0.0, |mean| mean[2])
+    }
+
+    /// The key of the results of this problem.
+    pub fn result_key(&self) -> ResultKey {
+        let mut markers = Vec::new();
+        let mut weights = self.z_scale().to_le_bytes().to_vec();
+        weights.extend(self.params.climb_cost.to_le_bytes());
+        let clustered = self.markers.iter().zip(&self.clustered).zip(&self.weights);
+        for ((m, clustered), weight) in clustered {
+            if *clustered && *weight > 0.0 {
+                markers.extend([m.x, m.y, m.z].iter().flat_map(|v| v.to_le_bytes()));
+                weights.extend(weight.to_le_bytes());
+            }
+        }
+        ResultKey {
+            k: self.params.k,
+            markers: snapshot::hash(&markers),
+            metric: self.params.metric,
+            loss: self.params.loss,
+            weights: snapshot::hash(&weights),
+        }
+    }
+
+    /// Positions of the markers taking part in the clustering.
+    pub fn clustered_positions(&self) -> Vec<Pos2> {
+        self.markers
+            .iter()
+            .zip(self.clustered.iter().zip(&self.weights))
+            .filter(|(m, (clustered, weight))| {
+                **clustered && **weight > 0.0 && m.x.is_finite() && m.y.is_finite()
+            })
+            .map(|(m, _)| pos2(m.x, m.y))
+            .collect()
+    }
+
+    /// The number of points actually placed: k, but no more than there are
+    /// markers to cluster, as the others would have nothing to serve. Pinned
+    /// points are placed regardless.
+    pub fn effective_k(&self) -> usize {
+        let usable = (0..self.markers.len()).filter(|i| self.is_assignable(*i)).count();
+        self.params.k.min(usable.max(self.pinned.len()))
+    }
+
+    /// Number of leading points, pinned ones and fixed seeds, the optimizer
+    /// must not move.
+    pub fn fixed_points(&self, points: &[Pos2]) -> usize {
+        let seeds = if self.seeds_fixed { self.seeds.len() } else { 0 };
+        (self.pinned.len() + seeds).min(points.len())
+    }
+
+    /// The median of the markers in a set with the configured solver, weighted
+    /// by their weights, and its height. Weiszfeld's algorithm is only for
+    /// Euclidean distances, for Manhattan ones the exact per-axis median is
+    /// used instead, as with the per-axis solver, and both leave the climb
+    /// cost out in 3D mode. Outside 3D mode the height is the markers' mean
+    /// one, or their median with a climb cost. `None` for an empty set. The
+    /// annealing starts from `previous`, the point and height of the set
+    /// before, if given and better than the centroid, and draws from `seed`.
+    /// With the k-means loss it is the weighted centroid whatever the solver.
+    pub fn median(
+        &self,
+        set: &[usize],
+        previous: Option<(Pos2, f32)>,
+        seed: u64,
+    ) -> Option<(Pos2, f32)> {
+        self.median_searched(set, previous, seed).0
+    }
+
+    /// [`Problem::median`] and the moves the annealing took to find it, 0
+    /// with the other solvers.
+    pub fn median_searched(
+        &self,
+        set: &[usize],
+        previous: Option<(Pos2, f32)>,
+        seed: u64,
+    ) -> (Option<(Pos2, f32)>, usize) {
+        if set.is_empty() {
+            return (None, 0);
+        }
+        let z_scale = self.z_scale();
+        let solver = match self.params.loss {
+            Loss::Distance => Some(self.params.median_solver),
+            // the centroid minimizes squared Euclidean distances exactly
+            Loss::SquaredDistance => None,
+        };
+        let (median, z, moves) = match solver {
+            None => {
+                let points: Vec<[f32; 3]> = set
+                    .iter()
+                    .map(|i| {
+                        let marker = &self.markers[*i];
+                        [marker.x, marker.y, marker.z * z_scale]
+                    })
+                    .collect();
+                let weights: Vec<f32> = set.iter().map(|i| self.weights[*i]).collect();
+                let Some([x, y, z]) = weighted_mean(&points, &weights) else {
+                    return (None, 0);
+                };
+                (pos2(x, y), z / z_scale, 0)
+            }
+            Some(solver @ (MedianSolver::Weiszfeld | MedianSolver::PerAxis)) => {
+                let points: Vec<[f32; 3]> = set
+                    .iter()
+                    .map(|i| {
+                        let marker = &self.markers[*i];
+                        [marker.x, marker.y, marker.z * z_scale]
+                    })
+                    .collect();
+                let weights: Vec<f32> = set.iter().map(|i| self.weights[*i]).collect();
+                let median = match (solver, self.params.metric) {
+                    // a stand-in along the network, where the annealing
+                    // solvers measure the actual distances
+                    (
+                        MedianSolver::Weiszfeld,
+                        DistanceMetric::Euclidean | DistanceMetric::Network,
+                    ) => {
+                        geometric_median(&points, &weights)
+                    }
+                    _ => manhattan_median(&points, &weights),
+                };
+                let Some([x, y, z]) = median else {
+                    return (None, 0);
+                };
+                (pos2(x, y), z / z_scale, 0)
+            }
+            Some(MedianSolver::Annealing) => self.simulated_annealing(set, previous, seed),
+            Some(MedianSolver::SimulatedAnnealing) => {
+                self.metropolis_annealing(set, previous, seed)
+            }
+            Some(MedianSolver::Medoid) => {
+                let medoid = self.medoid(set).map(|i| &self.markers[i]);
+                return (medoid.map(|marker| (pos2(marker.x, marker.y), marker.z)), 0);
+            }
+        };
+
+        if z_scale > 0.0 {
+            (Some((median, z)), moves)
+        } else {
+            (Some((median, self.flat_height(set))), moves)
+        }
+    }
+
+    /// Per marker, its membership in every point, fuzzy c-means style: from 0
+    /// to 1, summing to 1, the larger the closer the point is compared to
+    /// the others. Empty for markers not assigned at all.
+    pub fn memberships(&self, points: &[Pos2], heights: &[f32]) -> Vec<Vec<f32>> {
+        let exponent = 2.0 / (self.params.fuzziness.max(1.1) - 1.0);
+        (0..self.markers.len())
+            .map(|i| {
+                if !self.is_assignable(i) || points.is_empty() {
+                    return Vec::new();
+                }
+                let distances: Vec<f32> = points
+                    .iter()
+                    .enumerate()
+                    .map(|(j, point)| {
+                        self.distance(*point, heights.get(j).copied().unwrap_or_default(), i)
+                    })
+                    .collect();
+                let closest = distances.iter().copied().fold(f32::MAX, f32::min);
+                // relative to the closest, so the powers stay in range
+                let shares: Vec<f32> = distances
+                    .iter()
+                    .map(|d| {
+                        if closest <= 0.0 {
+                            f32::from(*d <= 0.0)
+                        } else {
+                            (closest / d).powf(exponent)
+                        }
+                    })
+                    .collect();
+                let total: f32 = shares.iter().sum();
+                shares.iter().map(|share| share / total).collect()
+            })
+            .collect()
+    }
+
+    /// The median of all markers, each weighted by its membership in point
+    /// `j` to the power of the fuzziness, and its height. Always by
+    /// Weiszfeld's algorithm or the per-axis median, whatever the solver.
+    pub fn fuzzy_median(&self, memberships: &[Vec<f32>], j: usize) -> Option<(Pos2, f32)> {
+        let z_scale = self.z_scale();
+        let fuzziness = self.params.fuzziness.max(1.1);
+        let (points, weights): (Vec<[f32; 3]>, Vec<f32>) = memberships
+            .iter()
+            .enumerate()
+            .filter_map(|(i, membership)| {
+                let weight = self.weights[i] * membership.get(j)?.powf(fuzziness);
+                let marker = &self.markers[i];
+                Some(([marker.x, marker.y, marker.z], weight))
+            })
+            .unzip();
+        let scaled: Vec<[f32; 3]> = points.iter().map(|[x, y, z]| [*x, *y, z * z_scale]).collect();
+        let [x, y, z] = match self.params.metric {
+            DistanceMetric::Euclidean | DistanceMetric::Network => {
+                geometric_median(&scaled, &weights)
+            }
+            DistanceMetric::Manhattan => manhattan_median(&scaled, &weights),
+        }?;
+        if z_scale > 0.0 {
+            Some((pos2(x, y), z / z_scale))
+        } else {
+            let z = weighted_mean(&points, &weights).map_or(0.0, |mean| mean[2]);
+            Some((pos2(x, y), z))
+        }
+    }
+
+    /// The marker of a set with the smallest summed distance to the others,
+    /// PAM style. `None` for an empty set.
+    pub fn medoid(&self, set: &[usize]) -> Option<usize> {
+        set.iter().copied().min_by(|a, b| {
+            let error = |i: usize| {
+                let marker = &self.markers[i];
+                self.set_error(pos2(marker.x, marker.y), marker.z, set)
+            };
+            error(*a).total_cmp(&error(*b))
+        })
+    }
+
+    /// In k-medoids mode, the marker of a set a point sits on.
+    pub fn medoid_at(&self, point: Pos2, set: &[usize]) -> Option<usize> {
+        if self.params.median_solver != MedianSolver::Medoid
+            || self.params.loss != Loss::Distance
+        {
+            return None;
+        }
+        set.iter()
+            .copied()
+            .find(|i| pos2(self.markers[*i].x, self.markers[*i].y) == point)
+    }
+
+    /// Searches the median by moving in the eight compass directions and
+    /// `anneal_random_directions` random ones, also along the height in 3D
+    /// mode. The step shrinks by `anneal_cooling` whenever no move improves
+    /// the error, with new random directions for every step. Starts from
+    /// [`Problem::anneal_start`]. Returns the median, its height and the
+    /// moves tried.
+    pub fn simulated_annealing(
+        &self,
+        indices: &[usize],
+        start: Option<(Pos2, f32)>,
+        seed: u64,
+    ) -> (Pos2, f32, usize) {
+        let three_d = self.z_scale() > 0.0;
+        let mut rng = anneal_rng(indices, seed);
+        let cooling = self.params.anneal_cooling.clamp(0.01, 0.99);
+
+        let (mut median, mut z, mut min) = self.anneal_start(indices, start);
+
+        let mut step = self.anneal_start_step(indices, (median, z));
+        let mut iterations = 0;
+        'cooling: while step > self.params.anneal_epsilon {
+            let directions =
+                anneal_directions(three_d, self.params.anneal_random_directions, &mut rng);
+            // take the best move until none improves, then cool down
+            loop {
+                iterations += 1;
+                if iterations > MAX_ANNEAL_ITERATIONS {
+                    break 'cooling;
+                }
+
+                let best = directions
+                    .iter()
+                    .map(|[x, y, dz]| {
+                        let temp_median = median + step * vec2(*x, *y);
+                        let temp_z = z + step * dz;
+                        (self.set_error(temp_median, temp_z, indices), temp_median, temp_z)
+                    })
+                    .min_by(|a, b| a.0.total_cmp(&b.0));
+                match best {
+                    Some((d, temp_median, temp_z)) if d < min => {
+                        min = d;
+                        median = temp_median;
+                        z = temp_z;
+                    }
+                    _ => break,
+                }
+            }
+            step *= cooling;
+        }
+
+        (median, z, iterations as usize)
+    }
+
+    /// Searches the median like [`Problem::simulated_annealing`], but moves
+    /// in one random direction at a time and also takes moves that make the
+    /// error worse by Δ, with probability exp(-Δ/T). The temperature T starts
+    /// at `anneal_temperature` times the error at the start and cools by
+    /// `anneal_temperature_cooling` whenever the step shrinks. Returns the
+    /// best spot visited and the moves tried.
+    pub fn metropolis_annealing(
+        &self,
+        indices: &[usize],
+        start: Option<(Pos2, f32)>,
+        seed: u64,
+    ) -> (Pos2, f32, usize) {
+        let three_d = self.z_scale() > 0.0;
+        let mut rng = anneal_rng(indices, seed);
+        let cooling = self.params.anneal_cooling.clamp(0.01, 0.99);
+        let temperature_cooling = self.params.anneal_temperature_cooling.clamp(0.0, 1.0);
+
+        let (mut median, mut z, mut error) = self.anneal_start(indices, start);
+        let mut best = (median, z, error);
+
+        let mut temperature = self.params.anneal_temperature * error;
+        let mut step = self.anneal_start_step(indices, (median, z));
+        let mut iterations = 0;
+        'cooling: while step > self.params.anneal_epsilon {
+            let directions =
+                anneal_directions(three_d, self.params.anneal_random_directions, &mut rng);
+            for _ in 0..directions.len() * METROPOLIS_MOVES_PER_DIRECTION {
+                iterations += 1;
+                if iterations > MAX_ANNEAL_ITERATIONS {
+                    break 'cooling;
+                }
+
+                let Some([x, y, dz]) = directions.choose(&mut rng) else {
+                    break 'cooling;
+                };
+                let temp_median = median + step * vec2(*x, *y);
+                let temp_z = z + step * dz;
+                let d = self.set_error(temp_median, temp_z, indices);
+                let delta = d - error;
+                let accepted = delta < 0.0
+                    || (temperature > 0.0 && rng.gen::<f32>() < (-delta / temperature).exp());
+                if accepted {
+                    median = temp_median;
+                    z = temp_z;
+                    error = d;
+                    if error < best.2 {
+                        best = (median, z, error);
+                    }
+                }
+            }
+            step *= cooling;
+            temperature *= temperature_cooling;
+        }
+
+        (best.0, best.1, iterations as usize)
+    }
+
+    /// Where the annealing of a non-empty set starts, with the error there:
+    /// `previous`, the spot of the point before, once it does better than the
+    /// centroid of the markers, as it does when the run has mostly settled.
+    fn anneal_start(&self, indices: &[usize], previous: Option<(Pos2, f32)>) -> (Pos2, f32, f32) {
+        let (centroid, centroid_z) = self.centroid(indices);
+        let centroid_error = self.set_error(centroid, centroid_z, indices);
+        match previous {
+            Some((point, z)) => {
+                let error = self.set_error(point, z, indices);
+                if error < centroid_error {
+                    (point, z, error)
+                } else {
+                    (centroid, centroid_z, centroid_error)
+                }
+            }
+            None => (centroid, centroid_z, centroid_error),
+        }
+    }
+
+    /// Starting step of the annealing: `anneal_step_fraction` of the diagonal
+    /// of the box around the markers and the starting spot, including their
+    /// heights in 3D mode, so tight sets don't spend their moves shrinking the
+    /// step and wide ones can get far from where they started.
+    fn anneal_start_step(&self, indices: &[usize], (start, start_z): (Pos2, f32)) -> f32 {
+        let fraction = self.params.anneal_step_fraction;
+        if fraction <= 0.0 {
+            return self.params.anneal_step;
+        }
+        let mut bounds = Rect::from_min_max(start, start);
+        let (mut low, mut high) = (start_z, start_z);
+        for i in indices {
+            let marker = &self.markers[*i];
+            bounds.extend_with(pos2(marker.x, marker.y));
+            low = low.min(marker.z);
+            high = high.max(marker.z);
+        }
+        let height = if self.z_scale() > 0.0 { high - low } else { 0.0 };
+        fraction * bounds.size().length().hypot(height)
+    }
+
+    /// Mean position and height of the markers of a non-empty set.
+    fn centroid(&self, indices: &[usize]) -> (Pos2, f32) {
+        let sum = indices
+            .iter()
+            .map(|i| vec2(self.markers[*i].x, self.markers[*i].y))
+            .fold(Pos2::ZERO, |acc, v| acc + v);
+        (sum / indices.len() as f32, self.mean_height(indices))
+    }
+
+    /// The station plan for the given points, as written by "export stations".
+    /// Their heights are only included in 3D mode.
+    pub fn station_export(
+        &self,
+        points: &[Pos2],
+        heights: &[f32],
+        total_error: f32,
+    ) -> StationExport {
+        let sets = self.assign(points, heights);
+        self.station_export_of_sets(points, heights, &sets, total_error)
+    }
+
+    /// [`Problem::station_export`] with the markers already assigned.
+    pub fn station_export_of_sets(
+        &self,
+        points: &[Pos2],
+        heights: &[f32],
+        sets: &[Vec<usize>],
+        total_error: f32,
+    ) -> StationExport {
+        let errors = self.set_errors(points, heights, sets);
+        let mut breakdown = self.station_errors(points, heights, sets, &errors);
+        breakdown.sort_by_key(|station| station.index);
+        let stations = points
+            .iter()
+            .zip(heights)
+            .zip(sets)
+            .zip(breakdown)
+            .enumerate()
+            .map(|(index, (((point, z), set), station))| StationRecord {
+                index,
+                x: point.x,
+                y: point.y,
+                z: self.params.three_d.then_some(*z),
+                medoid: self.medoid_at(*point, set).map(|i| self.markers[i].pathName.clone()),
+                markers: set.iter().map(|i| self.markers[*i].pathName.clone()).collect(),
+                total_distance: station.error,
+                mean_distance: station.mean_distance,
+                worst_marker: station
+                    .worst
+                    .map(|(i, distance)| (self.markers[i].pathName.clone(), distance)),
+                layer: None,
+                pinned: self.pinned.contains(point),
+            })
+            .collect();
+
+        StationExport {
+            total_error,
+            parameters: self.params.clone(),
+            stations,
+        }
+    }
+}
+
+/// What one point adds to the error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationError {
+    pub index: usize,
+    pub nodes: usize,
+    /// Weighted summed distance of its markers, see [`Problem::set_error`].
+    pub error: f32,
+    /// Unweighted mean distance of its markers.
+    pub mean_distance: f32,
+    /// The marker farthest from it and its distance.
+    pub worst: Option<(usize, f32)>,
+}
+
+/// Shares `total` stations out over layers given as (weight, nodes), in
+/// proportion to their weights with the leftovers going to the largest
+/// fractional parts. Every layer with nodes gets at least one station, even
+/// if that makes more than `total`, and none gets more than its nodes.
+pub fn allocate_k(total: usize, layers: &[(f32, usize)]) -> Vec<usize> {
+    let mut ks: Vec<usize> = layers.iter().map(|(_, nodes)| (*nodes).min(1)).collect();
+    let weight: f32 = layers.iter().filter(|(_, nodes)| *nodes > 0).map(|(w, _)| w).sum();
+    let mut left = total.saturating_sub(ks.iter().sum());
+    if weight <= 0.0 {
+        return ks;
+    }
+
+    // the share of every layer on top of its first station
+    let spare = left as f32;
+    let quotas: Vec<f32> = layers
+        .iter()
+        .map(|(w, nodes)| if *nodes > 0 { spare * w / weight } else { 0.0 })
+        .collect();
+    for ((k, quota), (_, nodes)) in ks.iter_mut().zip(&quotas).zip(layers) {
+        let extra = (quota.floor() as usize).min(nodes.saturating_sub(*k)).min(left);
+        *k += extra;
+        left -= extra;
+    }
+    while left > 0 {
+        let behind = (0..layers.len())
+            .filter(|i| ks[*i] < layers[*i].1)
+            .max_by(|a, b| {
+                let lag = |i: usize| quotas[i] + layers[i].1.min(1) as f32 - ks[i] as f32;
+                lag(*a).total_cmp(&lag(*b))
+            });
+        let Some(i) = behind else {
+            break;
+        };
+        ks[i] += 1;
+        left -= 1;
+    }
+    ks
+}
+
+/// The stations of one layer in per-layer mode.
+#[derive(Debug, Clone)]
+pub struct LayerRun {
+    pub layer: usize,
+    pub k: usize,
+    pub error: f32,
+}
+
+/// Runs a k-median over the markers of every layer in `layer_ks` on its own,
+/// with the k given for it. The points of the result are those of the layers
+/// one after the other, assigned only the markers of their layer.
+pub fn run_per_layer(
+    problem: &Problem,
+    layer_ks: &[(usize, usize)],
+) -> (Clustering, Vec<LayerRun>) {
+    let mut combined = Clustering {
+        best_so_far: 0.0,
+        best_so_far_uncovered: 0,
+        best_so_far_solver: Some(problem.params.median_solver),
+        ..Clustering::default()
+    };
+    // every layer draws from its own seed derived from the one of the run
+    let seed = problem.params.seed.unwrap_or_else(rand::random);
+    combined.seed = Some(seed);
+    let mut runs = Vec::new();
+    for (layeri, &(layer, k)) in layer_ks.iter().enumerate() {
+        let params = Parameters {
+            k,
+            ..problem.params.clone()
+        };
+        let layer_problem = Problem {
+            markers: problem.markers,
+            clustered: problem
+                .clustered
+                .iter()
+                .zip(problem.markers)
+                .map(|(clustered, m)| *clustered && m.layer == layer)
+                .collect(),
+            weights: problem.weights.clone(),
+            params: &params,
+            bounds: problem.bounds,
+            pinned: &[],
+            seeds: &[],
+            seeds_fixed: false,
+            mask: problem.mask,
+            network: problem.network,
+        };
+        if !layer_problem.clustered.contains(&true) {
+            continue;
+        }
+
+        let mut clustering = Clustering::default();
+        clustering.reinitialize_seeded(&layer_problem, seed.wrapping_add(layeri as u64));
+        clustering.run_k_median(&layer_problem);
+        let points = clustering.best_so_far_points;
+        let heights = clustering.best_so_far_heights;
+        let k = points.len();
+        combined.sets.extend(layer_problem.assign(&points, &heights));
+        combined.points.extend(points);
+        combined.heights.extend(heights);
+        combined.best_so_far += clustering.best_so_far;
+        combined.best_so_far_uncovered += clustering.best_so_far_uncovered;
+        runs.push(LayerRun {
+            layer,
+            k,
+            error: clustering.best_so_far,
+        });
+    }
+
+    if runs.is_empty() {
+        combined.best_so_far = f32::MAX;
+        combined.best_so_far_uncovered = usize::MAX;
+    }
+    combined.last_error = combined.best_so_far;
+    combined.best_so_far_points = combined.points.clone();
+    combined.best_so_far_heights = combined.heights.clone();
+    combined.refresh_set_errors(problem);
+    (combined, runs)
+}
+
+/// The state of a k-median run and the best result seen across runs.
+#[derive(Debug, Clone)]
+pub struct Clustering {
+    pub points: Vec<Pos2>,
+    /// Height of each point, see [`Problem::median`].
+    pub heights: Vec<f32>,
+    pub sets: Vec<Vec<usize>>,
+    pub last_error: f32,
+    pub best_so_far: f32,
+    /// Markers out of range of the best points, which count before the error.
+    pub best_so_far_uncovered: usize,
+    pub best_so_far_points: Vec<Pos2>,
+    pub best_so_far_heights: Vec<f32>,
+    /// Median solver the best points were found with, if known.
+    pub best_so_far_solver: Option<MedianSolver>,
+    /// Seed the points were last started from and the annealing draws from,
+    /// if known.
+    pub seed: Option<u64>,
+    /// Iterations since the points were last started over.
+    pub iterations: usize,
+    /// Markers that changed their station, per iteration after the first.
+    pub reassignments: Vec<usize>,
+    /// Why the last run stopped, if it did.
+    pub convergence: Option<Convergence>,
+    /// How much the local search after the last run lowered the error.
+    pub local_search_gain: Option<f32>,
+    /// Sets dissolved for being too small after the last run.
+    pub dissolved: Option<usize>,
+    /// Moves the annealing tried for the medians of the run so far.
+    pub median_moves: usize,
+    /// Median searches of the run so far that stopped at the move cap.
+    pub capped_medians: usize,
+    /// Whether the points are the best possible ones on the nodes, as found
+    /// by [`crate::exact::exact`].
+    pub optimal: bool,
+    /// Error of every point with the markers of its set, from the last step.
+    pub set_errors: Vec<f32>,
+}
+
+impl Default for Clustering {
+    fn default() -> Self {
+        Self {
+            points: Vec::new(),
+            heights: Vec::new(),
+            sets: Vec::new(),
+            last_error: f32::MAX,
+            best_so_far: f32::MAX,
+            best_so_far_uncovered: usize::MAX,
+            best_so_far_points: Vec::new(),
+            best_so_far_heights: Vec::new(),
+            best_so_far_solver: None,
+            seed: None,
+            iterations: 0,
+            reassignments: Vec::new(),
+            convergence: None,
+            local_search_gain: None,
+            dissolved: None,
+            median_moves: 0,
+            capped_medians: 0,
+            optimal: false,
+            set_errors: Vec::new(),
+        }
+    }
+}
+
+/// Edges of the Euclidean minimum spanning tree over the points, by Prim's
+/// algorithm.
+pub fn spanning_tree(points: &[Pos2]) -> Vec<(usize, usize)> {
+    let mut edges = Vec::with_capacity(points.len().saturating_sub(1));
+    if points.is_empty() {
+        return edges;
+    }
+    // per point left out, the closest point in the tree and its distance
+    let mut closest: Vec<Option<(usize, f32)>> =
+        points.iter().map(|p| Some((0, p.distance(points[0])))).collect();
+    closest[0] = None;
+    while let Some((next, (from, _))) = closest
+        .iter()
+        .enumerate()
+        .filter_map(|(i, closest)| closest.map(|c| (i, c)))
+        .min_by(|a, b| a.1 .1.total_cmp(&b.1 .1))
+    {
+        edges.push((from, next));
+        closest[next] = None;
+        for (i, closest) in closest.iter_mut().enumerate() {
+            if let Some((_, distance)) = closest {
+                let d = points[i].distance(points[next]);
+                if d < *distance {
+                    *closest = Some((next, d));
+                }
+            }
+        }
+    }
+    edges
+}
+
+/// Length of the rail network connecting the points, see [`spanning_tree`].
+pub fn rail_length(points: &[Pos2]) -> f32 {
+    spanning_tree(points)
+        .iter()
+        .map(|(a, b)| points[*a].distance(points[*b]))
+        .sum()
+}
+
+/// Unit directions for [`Problem::simulated_annealing`]: the eight compass
+/// ones, up and down in 3D, and `random` ones spread evenly over the circle,
+/// or the sphere in 3D.
+fn anneal_directions<R: Rng>(three_d: bool, random: usize, rng: &mut R) -> Vec<[f32; 3]> {
+    let mut directions: Vec<[f32; 3]> = (0..8)
+        .map(|i| {
+            let angle = i as f32 * TAU / 8.0;
+            [angle.cos(), angle.sin(), 0.0]
+        })
+        .collect();
+    if three_d {
+        directions.extend([[0.0, 0.0, 1.0], [0.0, 0.0, -1.0]]);
+    }
+    directions.extend((0..random).map(|_| {
+        let angle = rng.gen_range(0.0..TAU);
+        let z: f32 = if three_d { rng.gen_range(-1.0..1.0) } else { 0.0 };
+        let r = (1.0 - z * z).sqrt();
+        [r * angle.cos(), r * angle.sin(), z]
+    }));
+    directions
+}
+
+/// Random numbers for the annealing of a set, the same for the same set and
+/// seed.
+fn anneal_rng(indices: &[usize], seed: u64) -> StdRng {
+    StdRng::seed_from_u64(indices.iter().fold(seed, |seed, i| seed.rotate_left(5) ^ *i as u64))
+}
+
+/// Whether a median search that took this many moves stopped at the cap
+/// rather than because its step cooled down.
+fn hit_move_cap(moves: usize) -> bool {
+    moves > MAX_ANNEAL_ITERATIONS as usize
+}
+
+/// Moves a point inside a zone onto the closest spot on its edge, repeatedly
+/// in case that is inside another zone.
+pub fn outside_zones(zones: &[Rect], mut point: Pos2) -> Pos2 {
+    for _ in 0..=zones.len() {
+        let Some(zone) = zones.iter().find(|zone| zone.contains(point)) else {
+            break;
+        };
+        // a unit past the edge, as Rect::contains includes the edge itself
+        let edges = [
+            (point.x - zone.min.x, pos2(zone.min.x - 1.0, point.y)),
+            (zone.max.x - point.x, pos2(zone.max.x + 1.0, point.y)),
+            (point.y - zone.min.y, pos2(point.x, zone.min.y - 1.0)),
+            (zone.max.y - point.y, pos2(point.x, zone.max.y + 1.0)),
+        ];
+        if let Some((_, edge)) = edges.iter().min_by(|a, b| a.0.total_cmp(&b.0)) {
+            point = *edge;
+        }
+    }
+    point
+}
+
+/// Whether a result beats another: leaving fewer markers uncovered first, then
+/// by the lower error.
+pub fn is_better((uncovered, error): (usize, f32), (best_uncovered, best): (usize, f32)) -> bool {
+    uncovered < best_uncovered || (uncovered == best_uncovered && error < best)
+}
+
+impl Clustering {
+    /// A result with a point at the median of each of the given non-empty
+    /// sets of markers, taken as the best so far.
+    pub fn from_sets(problem: &Problem, sets: Vec<Vec<usize>>) -> Self {
+        let mut clustering = Clustering::default();
+        for set in &sets {
+            if let Some((point, z)) = problem.median(set, None, 0) {
+                clustering.points.push(problem.place(point));
+                clustering.heights.push(z);
+            }
+        }
+        clustering.set_errors = problem.set_errors(&clustering.points, &clustering.heights, &sets);
+        let error = clustering.set_errors.iter().sum();
+        clustering.sets = sets;
+        clustering.last_error = problem.objective(error, &clustering.points, &clustering.sets);
+        clustering.best_so_far = clustering.last_error;
+        clustering.best_so_far_uncovered = problem.uncovered(&clustering.sets);
+        clustering.best_so_far_points = clustering.points.clone();
+        clustering.best_so_far_heights = clustering.heights.clone();
+        clustering.best_so_far_solver = Some(problem.params.median_solver);
+        clustering
+    }
+
+    /// A result with the given points and the markers assigned to them,
+    /// taken as the best so far.
+    pub fn from_points(problem: &Problem, points: Vec<Pos2>, heights: Vec<f32>) -> Self {
+        let sets = problem.assign(&points, &heights);
+        let mut clustering = Clustering {
+            points,
+            heights,
+            ..Clustering::default()
+        };
+        clustering.set_errors = problem.set_errors(&clustering.points, &clustering.heights, &sets);
+        let error = clustering.set_errors.iter().sum();
+        clustering.sets = sets;
+        clustering.last_error = problem.objective(error, &clustering.points, &clustering.sets);
+        clustering.best_so_far = clustering.last_error;
+        clustering.best_so_far_uncovered = problem.uncovered(&clustering.sets);
+        clustering.best_so_far_points = clustering.points.clone();
+        clustering.best_so_far_heights = clustering.heights.clone();
+        clustering
+    }
+
+    pub fn best_score(&self) -> (usize, f32) {
+        (self.best_so_far_uncovered, self.best_so_far)
+    }
+
+    /// Starts over from the pinned points and seeds, filling up to
+    /// [`Problem::effective_k`] with points placed as the parameters ask for.
+    /// Draws from the seed of the parameters, or a random one if unset.
+    pub fn reinitialize(&mut self, problem: &Problem) {
+        let seed = problem.params.seed.unwrap_or_else(rand::random);
+        self.reinitialize_seeded(problem, seed);
+    }
+
+    /// Starts over from the best points instead of fresh ones, assigning the
+    /// markers to them once, so the next run polishes them.
+    pub fn continue_from_best(&mut self, problem: &Problem) {
+        self.points = self.best_so_far_points.clone();
+        self.heights = self.best_so_far_heights.clone();
+        if self.heights.len() != self.points.len() {
+            self.heights = problem.estimate_heights(&self.points);
+        }
+        self.sets = problem.assign(&self.points, &self.heights);
+        self.set_errors = problem.set_errors(&self.points, &self.heights, &self.sets);
+        self.start_run();
+    }
+
+    /// Keeps the points after the markers changed, but assigns the markers
+    /// to them again and takes up to `steps` steps from there, so a small
+    /// change doesn't need a run from fresh points.
+    pub fn refit(&mut self, problem: &Problem, steps: u32) {
+        if self.points.is_empty() {
+            return;
+        }
+        if self.heights.len() != self.points.len() {
+            self.heights = problem.estimate_heights(&self.points);
+        }
+        self.sets = problem.assign(&self.points, &self.heights);
+        self.start_run();
+        for _ in 0..steps {
+            if self.step_k_median(problem).converged() {
+                break;
+            }
+        }
+    }
+
+    /// Forgets how the last run went, as a new one starts.
+    fn start_run(&mut self) {
+        self.optimal = false;
+        self.last_error = f32::MAX;
+        self.iterations = 0;
+        self.median_moves = 0;
+        self.capped_medians = 0;
+        self.reassignments.clear();
+        self.convergence = None;
+        self.local_search_gain = None;
+        self.dissolved = None;
+    }
+
+    /// [`Clustering::reinitialize`] from the given seed, which the annealing
+    /// of the following iterations draws from as well.
+    pub fn reinitialize_seeded(&mut self, problem: &Problem, seed: u64) {
+        self.reinitialize_with(problem, &mut StdRng::seed_from_u64(seed));
+        self.seed = Some(seed);
+    }
+
+    /// [`Clustering::reinitialize`] drawing from the given random numbers.
+    pub fn reinitialize_with<R: Rng>(&mut self, problem: &Problem, rng: &mut R) {
+        let k = problem.effective_k();
+        self.points = problem.pinned.iter().chain(problem.seeds).copied().take(k).collect();
+
+        let positions = problem.clustered_positions();
+        let metric = problem.params.metric;
+        match problem.params.initialization {
+            Initialization::Uniform => {}
+            Initialization::RandomMarkers => {
+                // markers sharing a spot, or one with a pinned point or seed,
+                // would start two points together with one left empty
+                let mut spots: Vec<Pos2> =
+                    positions.iter().copied().filter(|p| !self.points.contains(p)).collect();
+                spots.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+                spots.dedup();
+                let count = (k - self.points.len()).min(spots.len());
+                let picked = index::sample(rng, spots.len(), count);
+                self.points.extend(picked.iter().map(|i| spots[i]));
+            }
+            Initialization::KMeansPlusPlus => {
+                while self.points.len() < k {
+                    let next = if self.points.is_empty() {
+                        positions.choose(rng).copied()
+                    } else {
+                        let weights = positions.iter().map(|p| {
+                            self.points
+                                .iter()
+                                .map(|point| metric.distance(*point, *p).powi(2))
+                                .fold(f32::MAX, f32::min)
+                        });
+                        // fails once every marker has a point on it
+                        WeightedIndex::new(weights)
+                            .ok()
+                            .map(|weights| positions[weights.sample(rng)])
+                    };
+                    match next {
+                        Some(point) => self.points.push(point),
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        // with markers sharing a spot or uniform initialization the rest go
+        // anywhere
+        let missing = k - self.points.len();
+        let bounds = problem.bounds;
+        self.points.extend((0..missing).map(|_| {
+            let x = rng.gen_range(bounds.min.x..bounds.max.x);
+            let y = rng.gen_range(bounds.min.y..bounds.max.y);
+            pos2(x, y)
+        }));
+
+        self.heights = problem.estimate_heights(&self.points);
+        self.sets = vec![Vec::new(); k];
+        self.start_run();
+    }
+
+    /// Moves every movable point without markers onto the marker farthest from
+    /// the point it is assigned to, taking that marker over. Markers are only
+    /// taken from sets that keep at least one.
+    fn reseed_empty_sets(&mut self, problem: &Problem) {
+        let fixed = problem.fixed_points(&self.points);
+        for seti in fixed..self.points.len() {
+            if !self.sets[seti].is_empty() {
+                continue;
+            }
+
+            let distance = |(owner, i): &(usize, usize)| {
+                problem.distance(self.points[*owner], self.heights[*owner], *i)
+            };
+            let farthest = self
+                .sets
+                .iter()
+                .enumerate()
+                .filter(|(_, set)| set.len() > 1)
+                .flat_map(|(owner, set)| set.iter().map(move |i| (owner, *i)))
+                .max_by(|a, b| distance(a).total_cmp(&distance(b)));
+            if let Some((owner, i)) = farthest {
+                let marker = &problem.markers[i];
+                self.points[seti] = pos2(marker.x, marker.y);
+                self.heights[seti] = marker.z;
+                self.sets[owner].retain(|m| *m != i);
+                self.sets[seti].push(i);
+            }
+        }
+    }
+
+    pub fn run_k_median(&mut self, problem: &Problem) {
+        for i in 0..problem.params.k_median_max_iter {
+            if self.step_k_median(problem).converged() {
+                break;
+            }
+            self.give_up_after(problem, i);
+        }
+        if problem.params.local_search {
+            self.local_search(problem);
+        }
+        self.dissolve_small_sets(problem);
+    }
+
+    /// Tries to improve the best points where the assignment and median steps
+    /// get stuck, for at most `local_search_seconds`. First every marker
+    /// about as close to its second closest point as to its closest is moved
+    /// over, with the medians of both sets solved again, then the point with
+    /// the largest error is moved onto the marker farthest from its point and
+    /// settled with one more step, as long as that helps. Changes are kept
+    /// only if they lower the error. Returns by how much they did.
+    pub fn local_search(&mut self, problem: &Problem) -> f32 {
+        let deadline =
+            Instant::now() + Duration::from_secs_f32(problem.params.local_search_seconds.max(0.0));
+        let seed = self.seed.unwrap_or_default();
+        let mut points = self.best_so_far_points.clone();
+        let mut heights = self.best_so_far_heights.clone();
+        if points.len() < 2 || heights.len() != points.len() {
+            return 0.0;
+        }
+        let fixed = problem.fixed_points(&points);
+        let start = problem.score(&points, &heights);
+        let mut score = start;
+
+        // the median of a changed set in place of its point, unless fixed
+        let resolve = |points: &mut [Pos2], heights: &mut [f32], seti: usize, set: &[usize]| {
+            if seti < fixed {
+                return;
+            }
+            let previous = (points[seti], heights[seti]);
+            if let Some((median, z)) = problem.median(set, Some(previous), seed) {
+                points[seti] = problem.place(median);
+                heights[seti] = z;
+            }
+        };
+
+        let mut sets = problem.assign(&points, &heights);
+        let assigned: Vec<usize> = sets.iter().flatten().copied().collect();
+        for i in assigned {
+            if Instant::now() > deadline {
+                break;
+            }
+            // the marker may have moved with an earlier change
+            let Some(from) = sets.iter().position(|set| set.contains(&i)) else {
+                continue;
+            };
+            let own = problem.distance(points[from], heights[from], i);
+            let second = (0..points.len())
+                .filter(|seti| *seti != from)
+                .map(|seti| (seti, problem.distance(points[seti], heights[seti], i)))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            let Some((to, distance)) = second else {
+                continue;
+            };
+            if distance > own * problem.params.local_search_ratio || (from < fixed && to < fixed) {
+                continue;
+            }
+
+            let (mut candidate_points, mut candidate_heights) = (points.clone(), heights.clone());
+            let from_set: Vec<usize> = sets[from].iter().copied().filter(|m| *m != i).collect();
+            let mut to_set = sets[to].clone();
+            to_set.push(i);
+            resolve(&mut candidate_points, &mut candidate_heights, from, &from_set);
+            resolve(&mut candidate_points, &mut candidate_heights, to, &to_set);
+            let candidate = problem.score(&candidate_points, &candidate_heights);
+            if is_better(candidate, score) {
+                (points, heights, score) = (candidate_points, candidate_heights, candidate);
+                sets = problem.assign(&points, &heights);
+            }
+        }
+
+        while Instant::now() <= deadline {
+            let worst = sets
+                .iter()
+                .enumerate()
+                .skip(fixed)
+                .map(|(seti, set)| (seti, problem.set_error(points[seti], heights[seti], set)))
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            let farthest = sets
+                .iter()
+                .enumerate()
+                .flat_map(|(owner, set)| set.iter().map(move |i| (owner, *i)))
+                .map(|(owner, i)| (i, problem.distance(points[owner], heights[owner], i)))
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            let (Some((worst, _)), Some((i, _))) = (worst, farthest) else {
+                break;
+            };
+
+            let (mut candidate_points, mut candidate_heights) = (points.clone(), heights.clone());
+            let marker = &problem.markers[i];
+            candidate_points[worst] = problem.place(pos2(marker.x, marker.y));
+            candidate_heights[worst] = marker.z;
+            let candidate_sets = problem.assign(&candidate_points, &candidate_heights);
+            for (seti, set) in candidate_sets.iter().enumerate() {
+                resolve(&mut candidate_points, &mut candidate_heights, seti, set);
+            }
+            let candidate = problem.score(&candidate_points, &candidate_heights);
+            if !is_better(candidate, score) {
+                break;
+            }
+            (points, heights, score) = (candidate_points, candidate_heights, candidate);
+            sets = problem.assign(&points, &heights);
+        }
+
+        let gain = if score.0 == start.0 { start.1 - score.1 } else { 0.0 };
+        if is_better(score, start) {
+            self.best_so_far = score.1;
+            self.best_so_far_uncovered = score.0;
+            self.best_so_far_points = points.clone();
+            self.best_so_far_heights = heights.clone();
+            self.last_error = score.1;
+            self.set_errors = problem.set_errors(&points, &heights, &sets);
+            self.points = points;
+            self.heights = heights;
+            self.sets = sets;
+        }
+        self.local_search_gain = Some(gain);
+        gain
+    }
+
+    /// Dissolves the sets of movable points serving less than
+    /// `min_cluster_size`, smallest first, their markers going to the closest
+    /// points left. The freed point is removed or, with
+    /// [`SmallClusters::Reseed`], moved once onto the marker adding the most
+    /// error, with the medians solved again, and removed if its set is still
+    /// too small. Works on the best points and replaces them even if the
+    /// error went up, as it mostly does with fewer stations. Returns how many
+    /// sets were dissolved.
+    pub fn dissolve_small_sets(&mut self, problem: &Problem) -> usize {
+        let Some(min) = problem.params.min_cluster_size else {
+            return 0;
+        };
+        let seed = self.seed.unwrap_or_default();
+        let mut points = self.best_so_far_points.clone();
+        let mut heights = self.best_so_far_heights.clone();
+        if heights.len() != points.len() {
+            return 0;
+        }
+        let fixed = problem.fixed_points(&points);
+        let unit = problem.params.min_cluster_unit;
+        let size = |set: &[usize]| set.iter().map(|i| problem.amount(unit, *i)).sum::<f32>();
+
+        let mut reseeded = vec![false; points.len()];
+        let mut dissolved = 0;
+        while points.len() > 1 {
+            let sets = problem.assign(&points, &heights);
+            let smallest = sets
+                .iter()
+                .enumerate()
+                .skip(fixed)
+                .map(|(seti, set)| (seti, size(set)))
+                .filter(|(_, size)| *size < min)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            let Some((seti, _)) = smallest else {
+                break;
+            };
+            dissolved += 1;
+
+            let worst = sets
+                .iter()
+                .enumerate()
+                .filter(|(owner, _)| *owner != seti)
+                .flat_map(|(owner, set)| set.iter().map(move |i| (owner, *i)))
+                .map(|(owner, i)| {
+                    let distance = problem.distance(points[owner], heights[owner], i);
+                    (i, problem.weights[i] * distance)
+                })
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            match worst {
+                Some((i, _))
+                    if problem.params.small_clusters == SmallClusters::Reseed
+                        && !reseeded[seti] =>
+                {
+                    let marker = &problem.markers[i];
+                    points[seti] = problem.place(pos2(marker.x, marker.y));
+                    heights[seti] = marker.z;
+                    reseeded[seti] = true;
+                    let sets = problem.assign(&points, &heights);
+                    for (setj, set) in sets.iter().enumerate().skip(fixed) {
+                        let previous = (points[setj], heights[setj]);
+                        if let Some((median, z)) = problem.median(set, Some(previous), seed) {
+                            points[setj] = problem.place(median);
+                            heights[setj] = z;
+                        }
+                    }
+                }
+                _ => {
+                    points.remove(seti);
+                    heights.remove(seti);
+                    reseeded.remove(seti);
+                }
+            }
+        }
+
+        if dissolved > 0 {
+            let sets = problem.assign(&points, &heights);
+            let (uncovered, error) = problem.score(&points, &heights);
+            self.best_so_far = error;
+            self.best_so_far_uncovered = uncovered;
+            self.best_so_far_points = points.clone();
+            self.best_so_far_heights = heights.clone();
+            self.last_error = error;
+            self.set_errors = problem.set_errors(&points, &heights, &sets);
+            self.points = points;
+            self.heights = heights;
+            self.sets = sets;
+        }
+        self.dissolved = Some(dissolved);
+        dissolved
+    }
+
+    /// Recomputes [`Clustering::set_errors`] after the points or sets were
+    /// changed from outside.
+    pub fn refresh_set_errors(&mut self, problem: &Problem) {
+        self.set_errors = problem.set_errors(&self.points, &self.heights, &self.sets);
+    }
+
+    /// Records that the run stops for good if iteration `i` of it was the
+    /// last allowed.
+    pub fn give_up_after(&mut self, problem: &Problem, i: u32) {
+        if i + 1 >= problem.params.k_median_max_iter && self.convergence.is_none() {
+            self.convergence = Some(Convergence::MaxIterations);
+        }
+    }
+
+    /// Markers assigned to another point in the current sets than in
+    /// `previous`, or None if no marker was assigned before.
+    fn count_reassigned(&self, problem: &Problem, previous: &[Vec<usize>]) -> Option<usize> {
+        if previous.len() != self.sets.len() || previous.iter().all(Vec::is_empty) {
+            return None;
+        }
+        let owners = |sets: &[Vec<usize>]| {
+            let mut owners = vec![None; problem.markers.len()];
+            for (seti, set) in sets.iter().enumerate() {
+                for i in set {
+                    owners[*i] = Some(seti);
+                }
+            }
+            owners
+        };
+        let (before, after) = (owners(previous), owners(&self.sets));
+        Some(before.iter().zip(&after).filter(|(a, b)| a != b).count())
+    }
+
+    /// One assignment, median update and error evaluation. The run has
+    /// converged once no marker changed its point or the error settled.
+    pub fn step_k_median(&mut self, problem: &Problem) -> StepOutcome {
+        if self.heights.len() != self.points.len() {
+            self.heights = problem.estimate_heights(&self.points);
+        }
+        let sets = problem.assign(&self.points, &self.heights);
+        let previous = std::mem::replace(&mut self.sets, sets);
+        self.reseed_empty_sets(problem);
+        let reassigned = self.count_reassigned(problem, &previous);
+        self.iterations += 1;
+        self.optimal = false;
+        self.local_search_gain = None;
+        self.dissolved = None;
+        self.reassignments.extend(reassigned);
+
+        // calculate median for each set and adjust points accordingly
+        let fixed = problem.fixed_points(&self.points);
+        let memberships =
+            problem.params.fuzzy.then(|| problem.memberships(&self.points, &self.heights));
+        for (seti, set) in self.sets.iter().enumerate().skip(fixed) {
+            let previous = (self.points[seti], self.heights[seti]);
+            let seed = self.seed.unwrap_or_default();
+            let (median, moves) = match &memberships {
+                Some(memberships) => (problem.fuzzy_median(memberships, seti), 0),
+                None if problem.params.trim_outliers => {
+                    let outliers = problem.outliers(previous.0, previous.1, set);
+                    let inliers: Vec<usize> =
+                        set.iter().copied().filter(|i| !outliers.contains(i)).collect();
+                    problem.median_searched(&inliers, Some(previous), seed)
+                }
+                None => problem.median_searched(set, Some(previous), seed),
+            };
+            self.median_moves += moves;
+            self.capped_medians += usize::from(hit_move_cap(moves));
+            if let Some((median, z)) = median {
+                self.points[seti] = problem.place(median);
+                self.heights[seti] = z;
+            }
+        }
+        debug_assert!(
+            self.points.iter().all(|p| p.x.is_finite() && p.y.is_finite())
+                && self.heights.iter().all(|z| z.is_finite()),
+            "points are not finite: {:?} {:?}",
+            self.points,
+            self.heights
+        );
+
+        // find new error values and abort if threshold reached
+        self.set_errors = problem.set_errors(&self.points, &self.heights, &self.sets);
+        let total_error = self.set_errors.iter().sum();
+        let total_error = problem.objective(total_error, &self.points, &self.sets);
+
+        let uncovered = problem.uncovered(&self.sets);
+        if is_better((uncovered, total_error), (self.best_so_far_uncovered, self.best_so_far)) {
+            self.best_so_far = total_error;
+            self.best_so_far_uncovered = uncovered;
+            self.best_so_far_points = self.points.clone();
+            self.best_so_far_heights = self.heights.clone();
+            self.best_so_far_solver = Some(problem.params.median_solver);
+        }
+
+        // fuzzy points keep moving with the memberships after the
+        // assignment stopped changing
+        let stable = reassigned == Some(0) && !problem.params.fuzzy;
+        // the error before the first iteration is not one of this run
+        let settled = self.iterations > 1
+            && (total_error - self.last_error).abs() < problem.params.k_median_epsilon;
+        self.last_error = total_error;
+        self.convergence = if stable {
+            Some(Convergence::Stable)
+        } else if settled {
+            Some(Convergence::Settled)
+        } else {
+            None
+        };
+        StepOutcome {
+            reassigned,
+            error: total_error,
+            convergence: self.convergence,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::map::{LastCheck, NodeKind};
+
+    pub fn marker(x: f32, y: f32) -> ResourceMarker {
+        ResourceMarker {
+            pathName: format!("marker at {} {}", x, y),
+            x,
+            y,
+            z: 0.0,
+            r#type: None,
+            purity: Purity::default(),
+            obstructed: None,
+            lastCheck: LastCheck::default(),
+            core: None,
+            layer: 0,
+            kind: NodeKind::Solid,
+            satellites: Vec::new(),
+        }
+    }
+
+    pub fn markers(positions: &[(f32, f32)]) -> Vec<ResourceMarker> {
+        positions.iter().map(|(x, y)| marker(*x, *y)).collect()
+    }
+
+    /// All markers clustered with weight 1 over their bounding box.
+    pub fn problem<'a>(markers: &'a [ResourceMarker], params: &'a Parameters) -> Problem<'a> {
+        Problem {
+            markers,
+            clustered: vec![true; markers.len()],
+            weights: vec![1.0; markers.len()],
+            params,
+            bounds: marker_bounds(markers).unwrap_or_else(default_map_bounds),
+            pinned: &[],
+            seeds: &[],
+            seeds_fixed: false,
+            mask: None,
+            network: None,
+        }
+    }
+
+    const COLLINEAR: [(f32, f32); 3] = [(0.0, 0.0), (1000.0, 0.0), (3000.0, 0.0)];
+    const SQUARE: [(f32, f32); 4] = [(0.0, 0.0), (2000.0, 0.0), (0.0, 2000.0), (2000.0, 2000.0)];
+
+    #[test]
+    fn annealing_finds_known_medians() {
+        let params = Parameters {
+            median_solver: MedianSolver::Annealing,
+            ..Parameters::default()
+        };
+        for (positions, median) in [
+            (&COLLINEAR[..], pos2(1000.0, 0.0)),
+            (&SQUARE[..], pos2(1000.0, 1000.0)),
+        ] {
+            let markers = markers(positions);
+            let problem = problem(&markers, &params);
+            let set: Vec<usize> = (0..markers.len()).collect();
+            for seed in 0..5 {
+                let (found, _, _) = problem.simulated_annealing(&set, None, seed);
+                assert!(
+                    found.distance(median) <= params.anneal_epsilon,
+                    "{:?} instead of {:?} for {:?}",
+                    found,
+                    median,
+                    positions
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn annealing_stops_within_the_move_cap() {
+        // the step shrinks only when no move improves; once improving moves
+        // stopped shrinking it, this input spun forever
+        let markers = markers(&COLLINEAR);
+        let set: Vec<usize> = (0..markers.len()).collect();
+        let params = Parameters::default();
+        let (_, _, moves) = problem(&markers, &params).simulated_annealing(&set, None, 1);
+        assert!(moves < 1000, "{} moves", moves);
+        assert!(!hit_move_cap(moves));
+
+        // a step that can never get below the epsilon leaves only the cap
+        let params = Parameters {
+            anneal_epsilon: -1.0,
+            median_solver: MedianSolver::Annealing,
+            ..Parameters::default()
+        };
+        let problem = problem(&markers, &params);
+        for (median, z, moves) in [
+            problem.simulated_annealing(&set, None, 1),
+            problem.metropolis_annealing(&set, None, 1),
+        ] {
+            assert!(moves as u32 <= MAX_ANNEAL_ITERATIONS + 1, "{} moves", moves);
+            assert!(hit_move_cap(moves));
+            assert!(median.x.is_finite() && median.y.is_finite() && z.is_finite());
+        }
+
+        // and the run counts the searches that stopped there
+        let mut clustering = Clustering {
+            points: vec![pos2(0.0, 0.0)],
+            heights: vec![0.0],
+            sets: vec![set],
+            ..Clustering::default()
+        };
+        clustering.step_k_median(&problem);
+        assert_eq!(clustering.capped_medians, 1);
+    }
+
+    fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+        DistanceMetric::Euclidean.length(difference(a, b)) < 1.0
+    }
+
+    #[test]
+    fn weiszfeld_finds_known_medians() {
+        let flat = |positions: &[(f32, f32)]| -> Vec<[f32; 3]> {
+            positions.iter().map(|(x, y)| [*x, *y, 0.0]).collect()
+        };
+        // positions, weights and their median
+        type Case = (&'static [(f32, f32)], &'static [f32], [f32; 3]);
+        let cases: [Case; 6] = [
+            // one point is its own median
+            (&[(5.0, -7.0)], &[1.0], [5.0, -7.0, 0.0]),
+            // any point between two equally weighted ones is, the mean is
+            // where Weiszfeld starts and stays
+            (&[(0.0, 0.0), (1000.0, 0.0)], &[1.0, 1.0], [500.0, 0.0, 0.0]),
+            // the heavier of two
+            (&[(0.0, 0.0), (1000.0, 0.0)], &[3.0, 1.0], [0.0, 0.0, 0.0]),
+            (&COLLINEAR, &[1.0; 3], [1000.0, 0.0, 0.0]),
+            (&SQUARE, &[1.0; 4], [1000.0, 1000.0, 0.0]),
+            // a point weighing as much as the others together
+            (
+                &[(0.0, 0.0), (1000.0, 0.0), (0.0, 1000.0)],
+                &[2.0, 1.0, 1.0],
+                [0.0, 0.0, 0.0],
+            ),
+        ];
+        for (positions, weights, median) in cases {
+            let found = geometric_median(&flat(positions), weights).expect("has points");
+            assert!(close(found, median), "{:?} instead of {:?}", found, median);
+        }
+
+        // the Fermat point of a triangle without an angle of 120° or more
+        // sees every side at 120°
+        let triangle = flat(&[(0.0, 0.0), (4000.0, 0.0), (1000.0, 3000.0)]);
+        let found = geometric_median(&triangle, &[1.0; 3]).expect("has points");
+        let to = |i: usize| difference(triangle[i], found);
+        let angle = |a: [f32; 3], b: [f32; 3]| {
+            let dot = a[0] * b[0] + a[1] * b[1];
+            (dot / (a[0].hypot(a[1]) * b[0].hypot(b[1]))).acos().to_degrees()
+        };
+        for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+            assert!((angle(to(a), to(b)) - 120.0).abs() < 0.5, "{:?}", found);
+        }
+
+        assert_eq!(geometric_median(&[], &[]), None);
+        assert_eq!(geometric_median(&flat(&SQUARE), &[0.0; 4]), None);
+    }
+
+    #[test]
+    fn squared_loss_takes_the_weighted_centroid() {
+        let markers = markers(&[(0.0, 0.0), (300.0, 0.0), (0.0, 600.0), (900.0, 900.0)]);
+        let params = Parameters {
+            loss: Loss::SquaredDistance,
+            // the solver only matters for plain distances
+            median_solver: MedianSolver::Annealing,
+            ..Parameters::default()
+        };
+        let problem = Problem {
+            weights: vec![1.0, 1.0, 2.0, 0.0],
+            ..problem(&markers, &params)
+        };
+        let (median, moves) = problem.median_searched(&[0, 1, 2, 3], None, 0);
+        // (0 + 300 + 2 * 0) / 4 and (0 + 0 + 2 * 600) / 4, the last marker
+        // weighing nothing
+        assert_eq!(median, Some((pos2(75.0, 300.0), 0.0)));
+        assert_eq!(moves, 0);
+        assert_eq!(problem.median_searched(&[1], None, 0).0, Some((pos2(300.0, 0.0), 0.0)));
+        assert_eq!(problem.median_searched(&[3], None, 0).0, None);
+    }
+
+    fn assert_finite(clustering: &Clustering) {
+        assert!(clustering.points.iter().all(|p| p.x.is_finite() && p.y.is_finite()));
+        assert!(clustering.heights.iter().all(|z| z.is_finite()));
+        assert!(clustering.last_error.is_finite());
+    }
+
+    #[test]
+    fn empty_sets_are_reseeded() {
+        let markers = markers(&[
+            (0.0, 0.0),
+            (100.0, 0.0),
+            (0.0, 100.0),
+            (5000.0, 0.0),
+            (5100.0, 0.0),
+            (5000.0, 100.0),
+        ]);
+        for median_solver in MedianSolver::ALL {
+            let params = Parameters {
+                k: 3,
+                median_solver,
+                ..Parameters::default()
+            };
+            let problem = problem(&markers, &params);
+            // the third point is far from every marker, so it starts empty
+            let mut clustering = Clustering {
+                points: vec![pos2(0.0, 0.0), pos2(5000.0, 0.0), pos2(1e6, 1e6)],
+                ..Clustering::default()
+            };
+            assert!(problem.assign(&clustering.points, &[0.0; 3])[2].is_empty());
+
+            clustering.step_k_median(&problem);
+            assert!(clustering.sets.iter().all(|set| !set.is_empty()), "{:?}", clustering.sets);
+            assert_finite(&clustering);
+            clustering.run_k_median(&problem);
+            assert_finite(&clustering);
+            assert!(clustering.best_so_far.is_finite());
+        }
+    }
+
+    #[test]
+    fn more_points_than_markers_stay_finite() {
+        // nothing to take over for the empty sets
+        let markers = markers(&[(0.0, 0.0), (1000.0, 0.0)]);
+        let params = Parameters::default();
+        let problem = problem(&markers, &params);
+        let mut clustering = Clustering {
+            points: vec![pos2(0.0, 0.0), pos2(1000.0, 0.0), pos2(-1e6, 0.0), pos2(1e6, 0.0)],
+            ..Clustering::default()
+        };
+        clustering.run_k_median(&problem);
+        assert_finite(&clustering);
+        assert_eq!(clustering.best_so_far, 0.0);
+    }
+
+    #[test]
+    fn station_distances_include_the_height() {
+        let mut markers = markers(&[(0.0, 0.0), (3000.0, 0.0)]);
+        markers[0].z = 400.0;
+        let points = [pos2(0.0, 0.0), pos2(3000.0, 4000.0)];
+        let heights = [0.0, 0.0];
+        let sets = [vec![0], vec![1]];
+
+        let flat = Parameters::default();
+        let stations = problem(&markers, &flat).stations_of_markers(&points, &heights, &sets);
+        assert_eq!(stations, vec![Some((0, 0.0)), Some((1, 4000.0))]);
+
+        let params = Parameters {
+            three_d: true,
+            z_weight: 2.0,
+            climb_cost: 0.5,
+            ..Parameters::default()
+        };
+        let problem = problem(&markers, &params);
+        let stations = problem.stations_of_markers(&points, &heights, &sets);
+        // 400 up at twice the weight, plus half of it as the climb cost
+        assert_eq!(stations[0], Some((0, 800.0 + 200.0)));
+        assert_eq!(stations[1], Some((1, 4000.0)));
+        assert_eq!(stations[0].unwrap().1, problem.distance(points[0], 0.0, 0));
+    }
+
+    #[test]
+    fn random_marker_initialization_leaves_no_set_empty_on_real_data() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/map_data.json");
+        let data = crate::map::load_maps(&[crate::map::MapSource::Path(path)]).unwrap();
+        let mut markers = data.markers;
+        markers.extend(crate::map::pressurizer_markers(&markers));
+        let world = default_map_bounds();
+        assert_eq!(Initialization::default(), Initialization::RandomMarkers);
+
+        for k in [10, 40, 120] {
+            let params = Parameters {
+                k,
+                ..Parameters::default()
+            };
+            let problem = Problem {
+                clustered: markers
+                    .iter()
+                    .map(|m| {
+                        data.layers[m.layer].collectible.is_none()
+                            && !m.is_pressurizer()
+                            && m.issue(world).is_none()
+                    })
+                    .collect(),
+                weights: markers
+                    .iter()
+                    .map(|m| params.marker_weight(m, &data.layers[m.layer].layerId))
+                    .collect(),
+                ..problem(&markers, &params)
+            };
+            for seed in 0..10 {
+                let mut clustering = Clustering::default();
+                clustering.reinitialize_seeded(&problem, seed);
+                assert_eq!(clustering.points.len(), k);
+                let sets = problem.assign(&clustering.points, &clustering.heights);
+                let empty = sets.iter().filter(|set| set.is_empty()).count();
+                assert_eq!(empty, 0, "{} empty sets for k = {} and seed {}", empty, k, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn weighted_median_ties_and_zero_weights() {
+        let median = |values: &[(f32, f32)]| weighted_median(values.to_vec());
+        assert_eq!(median(&[(3.0, 1.0), (1.0, 1.0), (2.0, 1.0)]), Some(2.0));
+        // an even count splits between two values, the lower one is taken
+        assert_eq!(median(&[(2.0, 1.0), (1.0, 1.0)]), Some(1.0));
+        assert_eq!(median(&[(4.0, 1.0), (3.0, 1.0), (1.0, 1.0), (2.0, 1.0)]), Some(2.0));
+        assert_eq!(median(&[(1.0, 2.0), (5.0, 1.0), (9.0, 1.0)]), Some(1.0));
+        // equal values count together
+        assert_eq!(median(&[(7.0, 1.0), (7.0, 1.0), (1.0, 1.0), (9.0, 1.0)]), Some(7.0));
+
+        // values weighing nothing are never the median
+        assert_eq!(median(&[(0.0, 0.0), (5.0, 1.0), (10.0, 0.0)]), Some(5.0));
+        assert_eq!(median(&[(1.0, 1.0), (2.0, 0.0), (3.0, 1.0)]), Some(1.0));
+        assert_eq!(median(&[(-4.0, 0.0), (1.0, 1.0), (3.0, 1.0), (8.0, 0.0)]), Some(1.0));
+        assert_eq!(median(&[(1.0, 0.0), (2.0, 0.0)]), None);
+        assert_eq!(median(&[]), None);
+    }
+
+    #[test]
+    fn per_axis_median_takes_every_axis_on_its_own() {
+        let points = [[0.0, 10.0, 0.0], [5.0, 0.0, 2.0], [9.0, 4.0, 1.0], [100.0, 100.0, 9.0]];
+        assert_eq!(manhattan_median(&points, &[1.0, 1.0, 1.0, 0.0]), Some([5.0, 4.0, 1.0]));
+        assert_eq!(manhattan_median(&points, &[1.0; 4]), Some([5.0, 4.0, 1.0]));
+        assert_eq!(manhattan_median(&points, &[0.0, 0.0, 0.0, 1.0]), Some([100.0, 100.0, 9.0]));
+        assert_eq!(manhattan_median(&points, &[0.0; 4]), None);
+    }
+
+    #[test]
+    fn station_distances_follow_the_network() {
+        // an L from the station around to the marker
+        let network = Network::new(vec![vec![
+            pos2(0.0, 0.0),
+            pos2(1000.0, 0.0),
+            pos2(1000.0, 1000.0),
+        ]])
+        .expect("small enough");
+        let markers = markers(&[(1000.0, 1000.0), (500.0, 100.0)]);
+        let params = Parameters {
+            metric: DistanceMetric::Network,
+            ..Parameters::default()
+        };
+        let problem = Problem {
+            network: Some(&network),
+            ..problem(&markers, &params)
+        };
+        let stations = problem.stations_of_markers(&[pos2(0.0, 0.0)], &[0.0], &[vec![0, 1]]);
+        assert_eq!(stations[0], Some((0, 2000.0)));
+        // 100 onto the line and 500 along it
+        assert_eq!(stations[1], Some((0, 600.0)));
+        assert!(params.metric.distance(pos2(0.0, 0.0), pos2(1000.0, 1000.0)) < 1500.0);
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture median_timing`.
+    #[test]
+    #[ignore = "timing on the full marker set, run by hand"]
+    fn median_timing_on_the_full_map() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/map_data.json");
+        let data = crate::map::load_maps(&[crate::map::MapSource::Path(path)]).unwrap();
+        let markers: Vec<ResourceMarker> = data
+            .markers
+            .into_iter()
+            .filter(|m| data.layers[m.layer].collectible.is_none())
+            .collect();
+        let all: Vec<usize> = (0..markers.len()).collect();
+        let base = Parameters {
+            k: 40,
+            ..Parameters::default()
+        };
+        let mut clustering = Clustering::default();
+        let base_problem = problem(&markers, &base);
+        clustering.reinitialize_seeded(&base_problem, 44);
+        let mut sets = base_problem.assign(&clustering.points, &clustering.heights);
+        sets.push(all);
+
+        let mut errors = Vec::new();
+        for solver in [MedianSolver::Weiszfeld, MedianSolver::Annealing] {
+            let params = Parameters {
+                median_solver: solver,
+                ..base.clone()
+            };
+            let problem = problem(&markers, &params);
+            let started = std::time::Instant::now();
+            let error: f32 = sets
+                .iter()
+                .map(|set| match problem.median_searched(set, None, 44).0 {
+                    Some((median, z)) => problem.set_error(median, z, set),
+                    None => 0.0,
+                })
+                .sum();
+            println!(
+                "{}: {} medians of {} markers in {:?}, error {:.0}",
+                solver,
+                sets.len(),
+                markers.len(),
+                started.elapsed(),
+                error
+            );
+            errors.push(error);
+        }
+        // Weiszfeld converges on the Euclidean median the annealing searches
+        assert!(errors[0] <= errors[1] * 1.001, "{:?}", errors);
+    }
+
+    #[test]
+    fn k_means_plus_plus_picks_distinct_markers() {
+        let positions: Vec<(f32, f32)> =
+            (0..12).map(|i| ((i % 4) as f32 * 1000.0, (i / 4) as f32 * 700.0)).collect();
+        let markers = markers(&positions);
+        for k in [5, 12] {
+            let params = Parameters {
+                k,
+                initialization: Initialization::KMeansPlusPlus,
+                ..Parameters::default()
+            };
+            let problem = problem(&markers, &params);
+            for seed in 0..20 {
+                let mut clustering = Clustering::default();
+                clustering.reinitialize_seeded(&problem, seed);
+                let points = clustering.points.clone();
+                assert_eq!(points.len(), k);
+                for (i, point) in points.iter().enumerate() {
+                    assert!(markers.iter().any(|m| pos2(m.x, m.y) == *point), "{:?}", point);
+                    // markers already picked weigh nothing for the next pick
+                    assert!(!points[..i].contains(point), "{:?} twice in {:?}", point, points);
+                }
+
+                clustering.reinitialize_seeded(&problem, seed);
+                assert_eq!(clustering.points, points, "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn local_search_frees_a_doubled_up_point() {
+        let markers = markers(&[
+            (0.0, -10.0),
+            (0.0, 0.0),
+            (0.0, 10.0),
+            (10000.0, -10.0),
+            (10000.0, 0.0),
+            (10000.0, 10.0),
+        ]);
+        let params = Parameters {
+            k: 2,
+            ..Parameters::default()
+        };
+        let problem = problem(&markers, &params);
+        let search = |points: Vec<Pos2>| {
+            let mut clustering = Clustering {
+                best_so_far_heights: vec![0.0; points.len()],
+                best_so_far_points: points,
+                ..Clustering::default()
+            };
+            let gain = clustering.local_search(&problem);
+            (clustering, gain)
+        };
+
+        // both points on the first group leave the second to one of them,
+        // where the medians alone stay stuck
+        let stuck = vec![pos2(0.0, -20.0), pos2(0.0, 20.0)];
+        let before = problem.score(&stuck, &[0.0, 0.0]).1;
+        let (clustering, gain) = search(stuck);
+        let optimum = 4.0 * 10.0;
+        assert!(gain > 0.0);
+        assert!((clustering.best_so_far - optimum).abs() < 1.0, "{}", clustering.best_so_far);
+        assert!((before - gain - clustering.best_so_far).abs() < 1.0);
+        let mut xs: Vec<f32> = clustering.best_so_far_points.iter().map(|p| p.x.round()).collect();
+        xs.sort_by(f32::total_cmp);
+        assert_eq!(xs, [0.0, 10000.0]);
+
+        // a point on each group is as good as it gets
+        let optimal = vec![pos2(0.0, 0.0), pos2(10000.0, 0.0)];
+        let (clustering, gain) = search(optimal.clone());
+        assert_eq!(gain, 0.0);
+        assert_eq!(clustering.best_so_far_points, optimal);
+        assert_eq!(clustering.local_search_gain, Some(0.0));
+    }
+
+    /// Markers scattered around two unequal clumps, for the median searches.
+    fn scattered(seed: u64) -> Vec<ResourceMarker> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..30)
+            .map(|i| {
+                let center = if i % 3 == 0 { (8000.0, 3000.0) } else { (0.0, 0.0) };
+                let x = center.0 + rng.gen_range(-2000.0..2000.0);
+                let y = center.1 + rng.gen_range(-2000.0..2000.0);
+                marker(x, y)
+            })
+            .collect()
+    }
+
+    /// Error of the Weiszfeld median of all the markers, and of what the
+    /// solver of the parameters finds from the centroid.
+    fn median_against_weiszfeld(
+        markers: &[ResourceMarker],
+        params: &Parameters,
+        seed: u64,
+    ) -> (f32, f32) {
+        let problem = problem(markers, params);
+        let set: Vec<usize> = (0..markers.len()).collect();
+        let positions: Vec<[f32; 3]> = markers.iter().map(|m| [m.x, m.y, 0.0]).collect();
+        let [x, y, _] = geometric_median(&positions, &vec![1.0; markers.len()]).unwrap();
+        let weiszfeld = problem.set_error(pos2(x, y), 0.0, &set);
+        let (median, z) = problem.median_searched(&set, None, seed).0.unwrap();
+        (weiszfeld, problem.set_error(median, z, &set))
+    }
+
+    #[test]
+    fn greedy_annealing_gets_to_the_weiszfeld_median() {
+        let params = Parameters {
+            median_solver: MedianSolver::Annealing,
+            ..Parameters::default()
+        };
+        for seed in 0..5 {
+            let (weiszfeld, annealed) = median_against_weiszfeld(&scattered(seed), &params, seed);
+            assert!(annealed <= weiszfeld * 1.0001, "{} against {}", annealed, weiszfeld);
+        }
+    }
+
+    #[test]
+    fn metropolis_annealing_gets_to_the_weiszfeld_median() {
+        let params = Parameters {
+            median_solver: MedianSolver::SimulatedAnnealing,
+            ..Parameters::default()
+        };
+        assert!(params.anneal_temperature > 0.0);
+        for seed in 0..5 {
+            let (weiszfeld, annealed) = median_against_weiszfeld(&scattered(seed), &params, seed);
+            // worse moves are taken along the way, but the best spot is kept
+            assert!(annealed <= weiszfeld * 1.001, "{} against {}", annealed, weiszfeld);
+        }
+    }
+
+    #[test]
+    fn annealing_never_ends_worse_than_it_starts() {
+        for solver in [MedianSolver::Annealing, MedianSolver::SimulatedAnnealing] {
+            let params = Parameters {
+                median_solver: solver,
+                ..Parameters::default()
+            };
+            for seed in 0..5 {
+                let markers = scattered(seed);
+                let problem = problem(&markers, &params);
+                let set: Vec<usize> = (0..markers.len()).collect();
+                let (centroid, _) = problem.centroid(&set);
+                let centroid_error = problem.set_error(centroid, 0.0, &set);
+                let starts = [
+                    (pos2(markers[3].x, markers[3].y), 0.0),
+                    (pos2(30000.0, -30000.0), 0.0),
+                    problem.median_searched(&set, None, seed).0.unwrap(),
+                ];
+                for start in starts {
+                    // the better of the start and the centroid is where it begins
+                    let (_, _, begin) = problem.anneal_start(&set, Some(start));
+                    let start_error = problem.set_error(start.0, start.1, &set);
+                    assert_eq!(begin, start_error.min(centroid_error));
+
+                    let (median, z) = problem.median_searched(&set, Some(start), seed).0.unwrap();
+                    let error = problem.set_error(median, z, &set);
+                    assert!(error <= begin, "{} from {} with {}", error, begin, solver);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn annealing_starts_from_a_share_of_the_set() {
+        let mut markers = markers(&[(0.0, 0.0), (30.0, 0.0), (0.0, 40.0)]);
+        let set = [0, 1, 2];
+        let fixed = Parameters {
+            anneal_step_fraction: 0.0,
+            median_solver: MedianSolver::Annealing,
+            ..Parameters::default()
+        };
+        let derived = Parameters {
+            anneal_step_fraction: 0.25,
+            ..fixed.clone()
+        };
+        let start = (pos2(10.0, 10.0), 0.0);
+        assert_eq!(problem(&markers, &fixed).anneal_start_step(&set, start), 10000.0);
+        // a quarter of the 30 by 40 box's diagonal
+        assert_eq!(problem(&markers, &derived).anneal_start_step(&set, start), 12.5);
+        // a start outside the markers widens the box, to 90 by 120
+        let outside = (pos2(90.0, -80.0), 0.0);
+        assert_eq!(problem(&markers, &derived).anneal_start_step(&set, outside), 0.25 * 150.0);
+
+        // with far fewer moves for the tight set, to the same median
+        let (fixed_median, fixed_moves) = problem(&markers, &fixed).median_searched(&set, None, 7);
+        let (median, moves) = problem(&markers, &derived).median_searched(&set, None, 7);
+        assert!(moves * 2 < fixed_moves, "{} against {} moves", moves, fixed_moves);
+        let error = |median: Option<(Pos2, f32)>| {
+            let (point, z) = median.unwrap();
+            problem(&markers, &derived).set_error(point, z, &set)
+        };
+        assert!(error(median) <= error(fixed_median) + 1.0);
+
+        // heights only count in 3D
+        markers[2].z = 120.0;
+        let three_d = Parameters {
+            three_d: true,
+            z_weight: 1.0,
+            ..derived.clone()
+        };
+        assert_eq!(problem(&markers, &derived).anneal_start_step(&set, start), 12.5);
+        // the 50 across and 120 up
+        assert_eq!(problem(&markers, &three_d).anneal_start_step(&set, start), 0.25 * 130.0);
+    }
+}
@@ -0,0 +1,72 @@
+use egui::Color32;
+
+/// Parses "#rrggbb" or "#rrggbbaa", with or without the leading '#'.
+pub fn parse_hex_color(s: &str) -> Option<Color32> {
+    let hex = s.trim();
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if !hex.is_ascii() || (hex.len() != 6 && hex.len() != 8) {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    let (r, g, b) = (channel(0)?, channel(2)?, channel(4)?);
+    let a = if hex.len() == 8 { channel(6)? } else { 255 };
+
+    Some(Color32::from_rgba_unmultiplied(r, g, b, a))
+}
+
+pub fn hex_color_or(s: &str, fallback: Color32) -> Color32 {
+    parse_hex_color(s).unwrap_or(fallback)
+}
+
+/// Marker colors of a resource layer, parsed once when the map is loaded.
+#[derive(Debug, Clone, Copy)]
+pub struct LayerColors {
+    pub inside: Color32,
+    pub outside: Color32,
+}
+
+impl LayerColors {
+    pub fn parse(inside: &str, outside: &str) -> Self {
+        Self {
+            inside: hex_color_or(inside, Color32::WHITE),
+            outside: hex_color_or(outside, Color32::GRAY),
+        }
+    }
+}
+
+/// Distinguishable color for the i-th of k clusters.
+pub fn cluster_color(i: usize, k: usize) -> Color32 {
+    Color32::from_rgb((255.0 / k as f32 * i as f32) as u8, 128, 196)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_six_and_eight_digits() {
+        assert_eq!(parse_hex_color("#a414a4"), Some(Color32::from_rgb(0xa4, 0x14, 0xa4)));
+        assert_eq!(parse_hex_color("A414A4"), Some(Color32::from_rgb(0xa4, 0x14, 0xa4)));
+        assert_eq!(
+            parse_hex_color("#ff000080"),
+            Some(Color32::from_rgba_unmultiplied(255, 0, 0, 0x80))
+        );
+        assert_eq!(parse_hex_color(" 00ff00ff "), Some(Color32::from_rgb(0, 255, 0)));
+    }
+
+    #[test]
+    fn rejects_short_long_and_invalid_input() {
+        for s in ["", "#", "#fff", "#12345", "#1234567", "#123456789", "#gg0000", "#ä12345"] {
+            assert_eq!(parse_hex_color(s), None, "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn falls_back_on_malformed_colors() {
+        assert_eq!(hex_color_or("#zzzzzz", Color32::GRAY), Color32::GRAY);
+        let colors = LayerColors::parse("nope", "#000000");
+        assert_eq!(colors.inside, Color32::WHITE);
+        assert_eq!(colors.outside, Color32::BLACK);
+    }
+}
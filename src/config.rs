@@ -0,0 +1,167 @@
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use egui::{pos2, Rect};
+use serde::Deserialize;
+
+use crate::{background::DEFAULT_BACKGROUND_PATH, cluster, map::ResourceLayer};
+
+pub const DEFAULT_CONFIG_PATH: &str = "./sf-stations.toml";
+
+/// Settings for maps whose extents or coordinates differ from vanilla. Edges
+/// left out keep the ones derived from the markers.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub map_left: Option<f32>,
+    pub map_top: Option<f32>,
+    pub map_right: Option<f32>,
+    pub map_bot: Option<f32>,
+    /// Draw the map with the y axis pointing up instead of down.
+    pub flip_y: bool,
+    /// Game units per unit of the map edges above.
+    pub scale: f32,
+    /// `layerId`s whose markers are loaded, all of them if unset.
+    pub include_layers: Option<Vec<String>>,
+    /// `layerId`s whose markers are never loaded.
+    pub exclude_layers: Vec<String>,
+    /// PNG of the map drawn behind the markers.
+    pub background_image: PathBuf,
+    /// Edges of the background image in the units of the map edges. Left out
+    /// edges are those of the standard community map image, which covers the
+    /// vanilla world.
+    pub background_left: Option<f32>,
+    pub background_top: Option<f32>,
+    pub background_right: Option<f32>,
+    pub background_bot: Option<f32>,
+    /// PNG marking where stations can be built, light on buildable ground,
+    /// spanning the same area as the background image.
+    pub mask_image: Option<PathBuf>,
+    /// Map data older than this many days gets a warning, 0 turns it off.
+    pub max_map_age_days: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            map_left: None,
+            map_top: None,
+            map_right: None,
+            map_bot: None,
+            flip_y: false,
+            scale: 1.0,
+            include_layers: None,
+            exclude_layers: Vec::new(),
+            background_image: PathBuf::from(DEFAULT_BACKGROUND_PATH),
+            background_left: None,
+            background_top: None,
+            background_right: None,
+            background_bot: None,
+            mask_image: None,
+            max_map_age_days: 180,
+        }
+    }
+}
+
+impl Config {
+    /// `bounds` with the configured edges, scaled to game units, replacing
+    /// its own.
+    pub fn apply_bounds(&self, bounds: Rect) -> Rect {
+        self.scaled_edges(
+            bounds,
+            [self.map_left, self.map_top, self.map_right, self.map_bot],
+        )
+    }
+
+    /// Where the background image goes, in game units.
+    pub fn background_bounds(&self) -> Rect {
+        self.scaled_edges(
+            cluster::default_map_bounds(),
+            [
+                self.background_left,
+                self.background_top,
+                self.background_right,
+                self.background_bot,
+            ],
+        )
+    }
+
+    /// `bounds` with the given left, top, right and bottom edges scaled to
+    /// game units replacing its own.
+    fn scaled_edges(&self, bounds: Rect, [left, top, right, bot]: [Option<f32>; 4]) -> Rect {
+        let edge = |configured: Option<f32>, fallback: f32| {
+            configured.map_or(fallback, |edge| edge * self.scale)
+        };
+        Rect::from_min_max(
+            pos2(edge(left, bounds.min.x), edge(top, bounds.min.y)),
+            pos2(edge(right, bounds.max.x), edge(bot, bounds.max.y)),
+        )
+    }
+
+    /// Whether the markers of a layer pass the include and exclude lists.
+    pub fn keeps_layer(&self, layer: &ResourceLayer) -> bool {
+        let included = self
+            .include_layers
+            .as_ref()
+            .is_none_or(|ids| ids.contains(&layer.layerId));
+        included && !self.exclude_layers.contains(&layer.layerId)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !(self.scale.is_finite() && self.scale > 0.0) {
+            return Err(format!("scale must be positive, got {}", self.scale));
+        }
+        if let (Some(left), Some(right)) = (self.map_left, self.map_right) {
+            if right <= left {
+                return Err(format!(
+                    "map_right ({}) must be greater than map_left ({})",
+                    right, left
+                ));
+            }
+        }
+        if let (Some(top), Some(bot)) = (self.map_top, self.map_bot) {
+            if bot <= top {
+                return Err(format!(
+                    "map_bot ({}) must be greater than map_top ({})",
+                    bot, top
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "{}", err),
+            ConfigError::Parse(err) => write!(f, "{}", err.message()),
+            ConfigError::Invalid(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Loads the config, treating a missing file as an empty one.
+pub fn load_config(path: &Path) -> Result<Config, ConfigError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(err) => return Err(ConfigError::Io(err)),
+    };
+
+    let config: Config = toml::from_str(&content).map_err(ConfigError::Parse)?;
+    config.validate().map_err(ConfigError::Invalid)?;
+    Ok(config)
+}
@@ -0,0 +1,71 @@
+use std::{fs, io, path::Path};
+
+use serde::Deserialize;
+
+use crate::map::{LastCheck, NodeKind, Purity, ResourceLayer, ResourceMarker};
+
+pub const DEFAULT_CUSTOM_MARKERS_PATH: &str = "./custom_markers.json";
+
+pub const CUSTOM_LAYER_ID: &str = "customMarkers";
+
+/// A point of interest that is not part of the upstream map data, like the
+/// HUB or an existing factory. `custom_markers.json` holds a list of them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CustomMarker {
+    pub name: String,
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub z: f32,
+    /// "#rrggbb" or "#rrggbbaa".
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default = "default_include_in_clustering")]
+    pub include_in_clustering: bool,
+}
+
+fn default_include_in_clustering() -> bool {
+    true
+}
+
+impl CustomMarker {
+    pub fn to_resource_marker(&self, layer: usize) -> ResourceMarker {
+        ResourceMarker {
+            pathName: format!("custom:{}", self.name),
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            r#type: None,
+            purity: Purity::Unknown(String::new()),
+            obstructed: None,
+            lastCheck: LastCheck::Unknown(String::new()),
+            core: None,
+            layer,
+            kind: NodeKind::Other,
+            satellites: Vec::new(),
+        }
+    }
+}
+
+pub fn custom_layer() -> ResourceLayer {
+    ResourceLayer {
+        layerId: CUSTOM_LAYER_ID.to_string(),
+        name: "Custom markers".to_string(),
+        purity: None,
+        outsideColor: "#000000".to_string(),
+        insideColor: "#ffffff".to_string(),
+        icon: String::new(),
+        markers: Vec::new(),
+        collectible: None,
+        kind: NodeKind::Other,
+    }
+}
+
+/// Loads the custom markers, treating a missing file as having none.
+pub fn load_custom_markers(path: &Path) -> io::Result<Vec<CustomMarker>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
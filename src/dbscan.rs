@@ -0,0 +1,106 @@
+use egui::pos2;
+
+use crate::{cluster::Problem, spatial::KdTree};
+
+/// The markers within `eps` of a marker, itself included. Distances in 3D or
+/// Manhattan are never shorter than on the plane, so the tree finds every
+/// candidate.
+fn neighborhood(problem: &Problem, tree: &KdTree, markeri: usize, eps: f32) -> Vec<usize> {
+    let marker = &problem.markers[markeri];
+    let point = pos2(marker.x, marker.y);
+    let mut neighbors = tree.within(point, eps);
+    neighbors.retain(|j| problem.distance(point, marker.z, *j) <= eps);
+    neighbors
+}
+
+/// Groups the clustered markers with DBSCAN: markers with at least
+/// `dbscan_min_points` markers within `dbscan_eps`, themselves included, are
+/// core markers, and a cluster is all markers within reach of a chain of core
+/// ones. Markers in no cluster are noise and left out of the sets.
+pub fn dbscan(problem: &Problem) -> Vec<Vec<usize>> {
+    let eps = problem.params.dbscan_eps.max(1.0);
+    let min_points = problem.params.dbscan_min_points.max(1);
+    let markers: Vec<usize> =
+        (0..problem.markers.len()).filter(|i| problem.is_assignable(*i)).collect();
+    let tree = KdTree::new(markers.iter().map(|i| {
+        let marker = &problem.markers[*i];
+        (*i, pos2(marker.x, marker.y))
+    }));
+
+    let mut visited = vec![false; problem.markers.len()];
+    let mut assigned = vec![false; problem.markers.len()];
+    let mut sets = Vec::new();
+    for i in &markers {
+        if visited[*i] {
+            continue;
+        }
+        visited[*i] = true;
+        let neighbors = neighborhood(problem, &tree, *i, eps);
+        if neighbors.len() < min_points {
+            continue;
+        }
+
+        let mut set = vec![*i];
+        assigned[*i] = true;
+        let mut queue = neighbors;
+        while let Some(j) = queue.pop() {
+            if !assigned[j] {
+                assigned[j] = true;
+                set.push(j);
+            }
+            if visited[j] {
+                continue;
+            }
+            visited[j] = true;
+            let reachable = neighborhood(problem, &tree, j, eps);
+            if reachable.len() >= min_points {
+                queue.extend(reachable);
+            }
+        }
+        sets.push(set);
+    }
+    sets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::{
+        tests::{markers, problem},
+        Parameters,
+    };
+
+    #[test]
+    fn finds_two_clumps_and_leaves_out_noise() {
+        let markers = markers(&[
+            (0.0, 0.0),
+            (300.0, 0.0),
+            (0.0, 300.0),
+            (10000.0, 0.0),
+            (10300.0, 0.0),
+            (10000.0, 300.0),
+            // nowhere near either clump
+            (5000.0, 8000.0),
+        ]);
+        let clump_of = |min_points| {
+            let params = Parameters {
+                dbscan_eps: 500.0,
+                dbscan_min_points: min_points,
+                ..Parameters::default()
+            };
+            let mut sets = dbscan(&problem(&markers, &params));
+            for set in &mut sets {
+                set.sort();
+            }
+            sets.sort();
+            sets
+        };
+
+        assert_eq!(clump_of(3), [vec![0, 1, 2], vec![3, 4, 5]]);
+        // every clump marker has two neighbors, three with itself
+        assert!(clump_of(4).is_empty());
+        // counting itself, the lone marker makes a cluster of one
+        assert_eq!(clump_of(1).len(), 3);
+        assert_eq!(clump_of(1)[2], [6]);
+    }
+}
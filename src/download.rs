@@ -0,0 +1,104 @@
+use std::{error::Error, fmt, fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::map::Map;
+
+/// The JSON the satisfactory-calculator interactive map loads its markers from.
+pub const MAP_DATA_URL: &str = "https://satisfactory-calculator.com/en/interactive-map/index/json";
+
+/// Describes the cached download so we can tell whether a fresh copy is newer.
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheMeta {
+    url: String,
+    version: i64,
+    lastBuild: String,
+}
+
+#[derive(Debug)]
+pub enum DownloadError {
+    Http(Box<ureq::Error>),
+    Io(io::Error),
+    Parse(serde_json::Error),
+    NoCacheDir,
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::Http(err) => write!(f, "download failed: {}", err),
+            DownloadError::Io(err) => write!(f, "failed to access map data cache: {}", err),
+            DownloadError::Parse(err) => write!(f, "downloaded map data is invalid: {}", err),
+            DownloadError::NoCacheDir => write!(f, "no cache directory available"),
+        }
+    }
+}
+
+impl Error for DownloadError {}
+
+impl From<io::Error> for DownloadError {
+    fn from(err: io::Error) -> Self {
+        DownloadError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for DownloadError {
+    fn from(err: serde_json::Error) -> Self {
+        DownloadError::Parse(err)
+    }
+}
+
+pub fn cache_dir() -> Result<PathBuf, DownloadError> {
+    dirs::cache_dir()
+        .map(|dir| dir.join("sf-stations"))
+        .ok_or(DownloadError::NoCacheDir)
+}
+
+fn cached_map_path() -> Result<PathBuf, DownloadError> {
+    Ok(cache_dir()?.join("map_data.json"))
+}
+
+fn cached_meta_path() -> Result<PathBuf, DownloadError> {
+    Ok(cache_dir()?.join("map_data.meta.json"))
+}
+
+/// Loads the previously downloaded map data, if there is any.
+pub fn load_cached() -> Result<Option<Map>, DownloadError> {
+    let path = cached_map_path()?;
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content = fs::read_to_string(path)?;
+    Ok(Some(serde_json::from_str(&content)?))
+}
+
+/// Downloads the current map data and replaces the cached copy with it.
+pub fn fetch(url: &str) -> Result<Map, DownloadError> {
+    let content = ureq::get(url)
+        .call()
+        .map_err(|err| DownloadError::Http(Box::new(err)))?
+        .into_string()?;
+    let map: Map = serde_json::from_str(&content)?;
+
+    let meta = CacheMeta {
+        url: url.to_string(),
+        version: map.version,
+        lastBuild: map.lastBuild.clone(),
+    };
+    fs::create_dir_all(cache_dir()?)?;
+    fs::write(cached_map_path()?, &content)?;
+    fs::write(cached_meta_path()?, serde_json::to_string_pretty(&meta)?)?;
+
+    Ok(map)
+}
+
+/// Uses the cached download if present and only hits the network otherwise.
+pub fn cached_or_fetch(url: &str) -> Result<Map, DownloadError> {
+    match load_cached() {
+        Ok(Some(map)) => Ok(map),
+        // an unreadable cache is no reason not to download a fresh copy
+        Ok(None) | Err(_) => fetch(url),
+    }
+}
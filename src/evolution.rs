@@ -0,0 +1,222 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+use egui::vec2;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    cluster::{is_better, Clustering, Problem},
+    restarts::RestartBest,
+    sweep::SweepInput,
+};
+
+/// Assignment and median steps every configuration gets before it is scored.
+const LLOYD_STEPS: u32 = 2;
+/// Best configurations carried over to the next generation unchanged.
+const ELITES: usize = 2;
+/// Configurations drawn per parent choice, the best of them winning.
+const TOURNAMENT: usize = 3;
+
+/// The state after a generation of an [`Evolution`].
+#[derive(Debug, Clone)]
+pub struct Generation {
+    /// Generations done, counting the starting population as 0.
+    pub index: usize,
+    /// Best configuration found so far.
+    pub best: RestartBest,
+}
+
+/// A configuration settled by [`LLOYD_STEPS`] steps, scored by its best
+/// points.
+fn settle(problem: &Problem, mut clustering: Clustering) -> RestartBest {
+    for _ in 0..LLOYD_STEPS {
+        if clustering.step_k_median(problem).converged() {
+            break;
+        }
+    }
+    RestartBest {
+        error: clustering.best_so_far,
+        uncovered: clustering.best_so_far_uncovered,
+        points: clustering.best_so_far_points,
+        heights: clustering.best_so_far_heights,
+        solver: clustering.best_so_far_solver,
+        // a single run from a seed doesn't give the same
+        seed: None,
+    }
+}
+
+fn tournament<'a>(population: &'a [RestartBest], rng: &mut StdRng) -> &'a RestartBest {
+    (0..TOURNAMENT)
+        .filter_map(|_| population.choose(rng))
+        .reduce(|a, b| if is_better(b.score(), a.score()) { b } else { a })
+        .expect("the population is not empty")
+}
+
+/// A child of two configurations: every movable station of `a`, or with even
+/// odds the station of `b` closest to it, each then jittered by up to
+/// `evolution_mutation` of the map's extent or moved onto a random marker with
+/// a chance of one in the number of stations.
+fn offspring(
+    problem: &Problem,
+    a: &RestartBest,
+    b: &RestartBest,
+    rng: &mut StdRng,
+) -> Clustering {
+    let mut points = a.points.clone();
+    let mut heights = a.heights.clone();
+    let fixed = problem.fixed_points(&points);
+    let positions = problem.clustered_positions();
+    let jitter = problem.params.evolution_mutation * problem.bounds.size().length();
+    let chance = 1.0 / points.len().max(1) as f64;
+    for seti in fixed..points.len() {
+        if rng.gen_bool(0.5) {
+            let closest = b.points.iter().enumerate().skip(fixed).min_by(|(_, p), (_, q)| {
+                p.distance(points[seti]).total_cmp(&q.distance(points[seti]))
+            });
+            if let Some((j, point)) = closest {
+                points[seti] = *point;
+                heights[seti] = b.heights[j];
+            }
+        }
+        if rng.gen_bool(chance) {
+            if rng.gen_bool(0.5) {
+                let offset = vec2(rng.gen_range(-1.0..=1.0), rng.gen_range(-1.0..=1.0));
+                points[seti] += jitter * offset;
+            } else if let Some(position) = positions.choose(rng) {
+                points[seti] = *position;
+            }
+        }
+    }
+    Clustering {
+        points,
+        heights,
+        seed: Some(rng.gen()),
+        ..Clustering::default()
+    }
+}
+
+/// Evolves `evolution_population` configurations of stations over
+/// `evolution_generations` generations: each generation keeps the best
+/// [`ELITES`] and fills up with offspring of parents won in tournaments.
+/// Configurations start like single runs and are settled by a few steps
+/// before scoring. Calls `on_generation` after every generation, including
+/// the starting one, and stops early once `cancel` is set.
+pub fn evolve(
+    problem: &Problem,
+    seed: u64,
+    cancel: &AtomicBool,
+    mut on_generation: impl FnMut(Generation),
+) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let size = problem.params.evolution_population.max(ELITES + 1);
+    let mut population: Vec<RestartBest> = Vec::with_capacity(size);
+    for _ in 0..size {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut clustering = Clustering::default();
+        clustering.reinitialize_seeded(problem, rng.gen());
+        population.push(settle(problem, clustering));
+    }
+
+    for index in 0..=problem.params.evolution_generations {
+        population.sort_by(|a, b| {
+            (a.uncovered.cmp(&b.uncovered)).then(a.error.total_cmp(&b.error))
+        });
+        on_generation(Generation {
+            index,
+            best: population[0].clone(),
+        });
+        if index == problem.params.evolution_generations {
+            return;
+        }
+
+        let mut next: Vec<RestartBest> = population[..ELITES].to_vec();
+        while next.len() < size {
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            let a = tournament(&population, &mut rng);
+            let b = tournament(&population, &mut rng);
+            let child = offspring(problem, a, b, &mut rng);
+            next.push(settle(problem, child));
+        }
+        population = next;
+    }
+}
+
+/// An evolution in the background, sending the best configuration after
+/// every generation.
+pub struct Evolution {
+    rx: Receiver<Generation>,
+    cancel: Arc<AtomicBool>,
+    /// Latest generation received.
+    pub latest: Option<Generation>,
+    pub generations_total: usize,
+    pub running: bool,
+}
+
+impl Evolution {
+    pub fn start(input: SweepInput) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let seed = input.params.seed.unwrap_or_else(rand::random);
+        let generations_total = input.params.evolution_generations;
+
+        let cancelled = cancel.clone();
+        thread::spawn(move || {
+            let problem = input.problem(&input.params);
+            evolve(&problem, seed, &cancelled, |generation| {
+                // the receiver is gone if the app was closed meanwhile
+                let _ = tx.send(generation);
+            });
+        });
+
+        Self {
+            rx,
+            cancel,
+            latest: None,
+            generations_total,
+            running: true,
+        }
+    }
+
+    /// Takes in what the thread has sent so far, returning the best
+    /// configuration of the latest generation received, if any.
+    pub fn poll(&mut self) -> Option<RestartBest> {
+        let mut best = None;
+        loop {
+            match self.rx.try_recv() {
+                Ok(generation) => {
+                    best = Some(generation.best.clone());
+                    self.latest = Some(generation);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.running = false;
+                    break;
+                }
+            }
+        }
+        best
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn progress(&self) -> f32 {
+        let done = self.latest.as_ref().map_or(0, |generation| generation.index);
+        if self.generations_total == 0 {
+            1.0
+        } else {
+            done as f32 / self.generations_total as f32
+        }
+    }
+}
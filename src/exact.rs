@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+
+use egui::{pos2, Pos2};
+
+use crate::cluster::{Clustering, Parameters, Problem};
+
+/// Most placements the exact search tries, C(n, k) for n spots and k
+/// stations, about a few seconds' worth.
+pub const MAX_EXACT_PLACEMENTS: u64 = 10_000_000;
+
+/// Places the stations on the nodes with the smallest error possible there,
+/// the discrete k-median: tries every way to choose k of the distinct spots
+/// of the clustered markers, placed like any station. Pinned points and fixed
+/// seeds stay and count toward k. Refuses capacities and more than
+/// [`MAX_EXACT_PLACEMENTS`] placements.
+pub fn exact(problem: &Problem) -> Result<Clustering, String> {
+    if problem.params.capacity.is_some() {
+        return Err("the exact search doesn't take station capacities".to_string());
+    }
+
+    let markers: Vec<usize> =
+        (0..problem.markers.len()).filter(|i| problem.is_assignable(*i)).collect();
+    let mut seen = HashSet::new();
+    let spots: Vec<(Pos2, f32)> = markers
+        .iter()
+        .map(|i| {
+            let marker = &problem.markers[*i];
+            (problem.place(pos2(marker.x, marker.y)), marker.z)
+        })
+        .filter(|(point, z)| seen.insert((point.x.to_bits(), point.y.to_bits(), z.to_bits())))
+        .collect();
+
+    let mut fixed: Vec<Pos2> = problem.pinned.to_vec();
+    if problem.seeds_fixed {
+        fixed.extend(problem.seeds);
+    }
+    fixed.truncate(problem.params.k);
+    let fixed_heights = problem.estimate_heights(&fixed);
+    let choose = problem.effective_k().saturating_sub(fixed.len()).min(spots.len());
+    let placements = binomial(spots.len() as u64, choose as u64);
+    if placements > MAX_EXACT_PLACEMENTS {
+        return Err(format!(
+            "the exact search tries at most {} placements, not {} for {} stations on {} spots",
+            MAX_EXACT_PLACEMENTS,
+            placements,
+            choose,
+            spots.len()
+        ));
+    }
+
+    // the error of every marker from every spot, and from the closest fixed
+    // point
+    let errors: Vec<Vec<f32>> = spots
+        .iter()
+        .map(|(point, z)| {
+            markers.iter().map(|i| problem.cost(*point, *z, *i) * problem.weights[*i]).collect()
+        })
+        .collect();
+    let base: Vec<f32> = markers
+        .iter()
+        .map(|i| {
+            fixed
+                .iter()
+                .zip(&fixed_heights)
+                .map(|(point, z)| problem.cost(*point, *z, *i) * problem.weights[*i])
+                .fold(f32::MAX, f32::min)
+        })
+        .collect();
+
+    let mut closest = vec![base];
+    closest.resize(choose + 1, vec![0.0; markers.len()]);
+    let mut search = Search {
+        errors: &errors,
+        choose,
+        chosen: Vec::with_capacity(choose),
+        closest,
+        best: (f32::MAX, Vec::new()),
+    };
+    search.run(0);
+
+    let mut points = fixed;
+    let mut heights = fixed_heights;
+    for spot in &search.best.1 {
+        points.push(spots[*spot].0);
+        heights.push(spots[*spot].1);
+    }
+    let mut clustering = Clustering::from_points(problem, points, heights);
+    clustering.optimal = is_plain(problem.params);
+    Ok(clustering)
+}
+
+/// Whether the error is no more than the sum the search minimizes, so its
+/// result is the best there is.
+fn is_plain(params: &Parameters) -> bool {
+    params.max_radius.is_none()
+        && params.balance == 0.0
+        && params.rail_weight == 0.0
+        && params.mix_penalty == 0.0
+}
+
+/// Choices of k out of n for k at most n, saturating.
+fn binomial(n: u64, k: u64) -> u64 {
+    let k = k.min(n - k);
+    let mut choices: u128 = 1;
+    for i in 0..k {
+        // exact, as the product of i + 1 consecutive numbers divides by (i + 1)!
+        choices = choices * (n - i) as u128 / (i + 1) as u128;
+        if choices > u64::MAX as u128 {
+            return u64::MAX;
+        }
+    }
+    choices as u64
+}
+
+struct Search<'a> {
+    /// The error of every marker per spot.
+    errors: &'a [Vec<f32>],
+    choose: usize,
+    chosen: Vec<usize>,
+    /// The error of every marker from its closest point, with the fixed
+    /// points and then with every spot chosen so far.
+    closest: Vec<Vec<f32>>,
+    /// The smallest error and the spots giving it.
+    best: (f32, Vec<usize>),
+}
+
+impl Search<'_> {
+    /// Tries every choice of the spots left from `start` on.
+    fn run(&mut self, start: usize) {
+        let depth = self.chosen.len();
+        if depth == self.choose {
+            let error: f32 = self.closest[depth].iter().sum();
+            if error < self.best.0 {
+                self.best = (error, self.chosen.clone());
+            }
+            return;
+        }
+
+        let left = self.choose - depth;
+        for spot in start..=self.errors.len() - left {
+            let (before, after) = self.closest.split_at_mut(depth + 1);
+            let with = after[0].iter_mut().zip(&before[depth]).zip(&self.errors[spot]);
+            for ((to, from), error) in with {
+                *to = from.min(*error);
+            }
+            self.chosen.push(spot);
+            self.run(spot + 1);
+            self.chosen.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::cluster::{
+        tests::{markers, problem},
+        MedianSolver,
+    };
+
+    /// The smallest error of any k of the markers as stations, by trying
+    /// every choice.
+    fn brute_force(problem: &Problem, k: usize) -> f32 {
+        let n = problem.markers.len();
+        let mut best = f32::MAX;
+        for mask in 0u32..1 << n {
+            if mask.count_ones() as usize != k {
+                continue;
+            }
+            let points: Vec<Pos2> = (0..n)
+                .filter(|i| mask & 1 << i != 0)
+                .map(|i| pos2(problem.markers[i].x, problem.markers[i].y))
+                .collect();
+            best = best.min(problem.total_error(&points, &vec![0.0; k]));
+        }
+        best
+    }
+
+    #[test]
+    fn exact_is_no_worse_than_the_heuristic_on_the_nodes() {
+        let mut rng = StdRng::seed_from_u64(95);
+        for instance in 0..8 {
+            let positions: Vec<(f32, f32)> = (0..rng.gen_range(6..=12))
+                .map(|_| (rng.gen_range(0.0..10000.0), rng.gen_range(0.0..10000.0)))
+                .collect();
+            let markers = markers(&positions);
+            let k = rng.gen_range(1..=4);
+            // k-medoids keeps the stations on the nodes like the exact search
+            let params = Parameters {
+                k,
+                median_solver: MedianSolver::Medoid,
+                seed: Some(instance),
+                ..Parameters::default()
+            };
+            let problem = problem(&markers, &params);
+
+            let exact = exact(&problem).expect("small enough");
+            assert!(exact.optimal);
+            let optimum = brute_force(&problem, k);
+            assert!((exact.best_so_far - optimum).abs() <= optimum * 1e-5, "instance {}", instance);
+
+            for seed in 0..5 {
+                let mut heuristic = Clustering::default();
+                heuristic.reinitialize_seeded(&problem, seed);
+                heuristic.run_k_median(&problem);
+                assert!(
+                    heuristic.best_so_far >= exact.best_so_far * (1.0 - 1e-5),
+                    "instance {}: heuristic {} below exact {}",
+                    instance,
+                    heuristic.best_so_far,
+                    exact.best_so_far
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn refuses_too_many_placements() {
+        assert_eq!(binomial(12, 3), 220);
+        assert_eq!(binomial(5, 0), 1);
+        assert_eq!(binomial(200, 100), u64::MAX);
+
+        let positions: Vec<(f32, f32)> = (0..60).map(|i| (i as f32 * 100.0, 0.0)).collect();
+        let markers = markers(&positions);
+        let params = Parameters {
+            k: 20,
+            ..Parameters::default()
+        };
+        assert!(exact(&problem(&markers, &params)).is_err());
+    }
+}
@@ -0,0 +1,27 @@
+use std::{collections::BTreeSet, fs, io, path::Path};
+
+pub const DEFAULT_EXCLUSIONS_PATH: &str = "./excluded_markers.txt";
+
+/// Loads the `pathName`s of markers kept out of the clustering, one per line.
+/// A missing file excludes nothing.
+pub fn load_exclusions(path: &Path) -> io::Result<BTreeSet<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(BTreeSet::new()),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn save_exclusions(path: &Path, excluded: &BTreeSet<String>) -> io::Result<()> {
+    let mut content = String::new();
+    for path_name in excluded {
+        content.push_str(path_name);
+        content.push('\n');
+    }
+    fs::write(path, content)
+}
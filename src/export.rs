@@ -0,0 +1,222 @@
+use std::{
+    borrow::Cow,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use egui::Pos2;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cluster::{Parameters, StationError},
+    map::{NodeKind, ResourceMarker},
+};
+
+/// A station plan as written by "export stations", in game coordinates.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StationExport {
+    pub total_error: f32,
+    pub parameters: Parameters,
+    pub stations: Vec<StationRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StationRecord {
+    pub index: usize,
+    pub x: f32,
+    pub y: f32,
+    /// Height of the station, only set by plans made with 3D distances.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub z: Option<f32>,
+    /// In k-medoids mode, `pathName` of the marker the station sits on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub medoid: Option<String>,
+    /// `pathName`s of the markers assigned to this station.
+    pub markers: Vec<String>,
+    pub total_distance: f32,
+    /// Unweighted mean distance of the markers.
+    #[serde(default)]
+    pub mean_distance: f32,
+    /// `pathName` of the marker farthest from the station and its distance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worst_marker: Option<(String, f32)>,
+    /// In per-layer mode, `layerId` of the layer the station serves.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+    /// Whether the station was pinned in place rather than placed.
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+pub fn save_stations(path: &Path, export: &StationExport) -> io::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(export)?)
+}
+
+pub fn load_stations(path: &Path) -> io::Result<StationExport> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// One line of the marker assignment CSV.
+#[derive(Debug)]
+pub struct AssignmentRow<'a> {
+    pub path_name: &'a str,
+    pub layer: &'a str,
+    pub kind: NodeKind,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub purity: String,
+    pub obstructed: Option<bool>,
+    /// Station index and distance to it, if a clustering has been run.
+    pub station: Option<(usize, f32)>,
+}
+
+pub const ASSIGNMENT_CSV_HEADER: &str = "pathName,layer,kind,x,y,z,purity,obstructed,station,distance";
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+pub fn csv_field(s: &str) -> Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", s.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+pub fn write_assignments_csv<W: Write>(mut w: W, rows: &[AssignmentRow]) -> io::Result<()> {
+    writeln!(w, "{}", ASSIGNMENT_CSV_HEADER)?;
+    for row in rows {
+        let obstructed = row.obstructed.map(|o| o.to_string()).unwrap_or_default();
+        let (station, distance) = match row.station {
+            Some((station, distance)) => (station.to_string(), distance.to_string()),
+            None => (String::new(), String::new()),
+        };
+        writeln!(
+            w,
+            "{},{},{},{},{},{},{},{},{},{}",
+            csv_field(row.path_name),
+            csv_field(row.layer),
+            row.kind,
+            row.x,
+            row.y,
+            row.z,
+            csv_field(&row.purity),
+            obstructed,
+            station,
+            distance
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn save_assignments_csv(path: &Path, rows: &[AssignmentRow]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    write_assignments_csv(&mut file, rows)?;
+    file.flush()
+}
+
+pub const STATION_ERRORS_CSV_HEADER: &str =
+    "station,nodes,error,mean_distance,worst_marker,worst_distance";
+
+/// Writes what every station adds to the error, in the order given.
+pub fn write_station_errors_csv<W: Write>(
+    mut w: W,
+    markers: &[ResourceMarker],
+    errors: &[StationError],
+) -> io::Result<()> {
+    writeln!(w, "{}", STATION_ERRORS_CSV_HEADER)?;
+    for station in errors {
+        let (worst, distance) = match station.worst {
+            Some((i, distance)) => (csv_field(&markers[i].pathName), distance.to_string()),
+            None => (Cow::Borrowed(""), String::new()),
+        };
+        writeln!(
+            w,
+            "{},{},{},{},{},{}",
+            station.index, station.nodes, station.error, station.mean_distance, worst, distance
+        )?;
+    }
+    Ok(())
+}
+
+pub fn save_station_errors_csv(
+    path: &Path,
+    markers: &[ResourceMarker],
+    errors: &[StationError],
+) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    write_station_errors_csv(&mut file, markers, errors)?;
+    file.flush()
+}
+
+/// The in-game console command that teleports the player to a map position.
+///
+/// Both use centimeters, but the map data's y axis points the other way than
+/// the game's, so it is flipped here.
+pub fn teleport_command(x: f32, y: f32, z: f32) -> String {
+    format!("Teleport {:.0} {:.0} {:.0}", x, -y, z)
+}
+
+/// The best points as compact text: a header line with the total error and k,
+/// then `index, x, y` per station in whole centimeters, followed by `z` if
+/// heights are given.
+pub fn points_summary(total_error: f32, points: &[Pos2], heights: Option<&[f32]>) -> String {
+    let mut text = format!("total error {:.0}, k = {}", total_error, points.len());
+    for (i, point) in points.iter().enumerate() {
+        text.push_str(&format!("\n{}, {:.0}, {:.0}", i, point.x, point.y));
+        if let Some(z) = heights.and_then(|heights| heights.get(i)) {
+            text.push_str(&format!(", {:.0}", z));
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_fields_only_when_needed() {
+        assert_eq!(csv_field("iron"), "iron");
+        assert!(matches!(csv_field("iron"), Cow::Borrowed(_)));
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+    }
+
+    #[test]
+    fn writes_a_row_per_marker() {
+        let row = |path_name, layer, station| AssignmentRow {
+            path_name,
+            layer,
+            kind: NodeKind::Solid,
+            x: 1.5,
+            y: -2.0,
+            z: 0.0,
+            purity: "pure".to_string(),
+            obstructed: Some(false),
+            station,
+        };
+        let rows = [
+            row("Node_1", "Iron, \"pure\"\nnodes", Some((3, 12.5))),
+            row("Node_2", "iron", None),
+        ];
+        let mut csv = Vec::new();
+        write_assignments_csv(&mut csv, &rows).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(
+            csv,
+            format!(
+                "{}\n\
+                 Node_1,\"Iron, \"\"pure\"\"\nnodes\",solid,1.5,-2,0,pure,false,3,12.5\n\
+                 Node_2,iron,solid,1.5,-2,0,pure,false,,\n",
+                ASSIGNMENT_CSV_HEADER
+            )
+        );
+        // the quoted line break is the only one beyond a row per marker
+        assert_eq!(csv.lines().count(), rows.len() + 2);
+        assert_eq!(ASSIGNMENT_CSV_HEADER.split(',').count(), 10);
+    }
+}
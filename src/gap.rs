@@ -0,0 +1,240 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+use egui::{pos2, Rect};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    cluster::Parameters,
+    restarts::{best_of_restarts, RestartBest},
+    sweep::SweepInput,
+};
+
+/// The gap statistic for one k: how much lower the log of the best error is
+/// on the markers than on markers spread uniformly over their bounding box.
+#[derive(Debug, Clone)]
+pub struct GapRow {
+    pub k: usize,
+    /// Log of the best error on the markers.
+    pub log_error: f32,
+    /// Mean log of the best error over the reference sets.
+    pub reference_log_error: f32,
+    pub gap: f32,
+    /// Standard deviation of the reference log errors, widened for their
+    /// number as in Tibshirani et al.
+    pub spread: f32,
+}
+
+enum GapMessage {
+    Run,
+    Row(GapRow),
+}
+
+/// The gap statistic over a range of k computed in the background.
+pub struct GapStatistic {
+    rx: Receiver<GapMessage>,
+    cancel: Arc<AtomicBool>,
+    /// Rows by k.
+    pub rows: Vec<GapRow>,
+    pub runs_done: usize,
+    pub runs_total: usize,
+    pub running: bool,
+}
+
+/// The clustered markers of the input moved to uniformly random spots within
+/// their bounding box, each keeping its weight. None without clustered
+/// markers.
+fn reference(input: &SweepInput, rng: &mut StdRng) -> Option<SweepInput> {
+    let clustered: Vec<usize> =
+        (0..input.markers.len()).filter(|i| input.clustered[*i]).collect();
+    let bounds = clustered
+        .iter()
+        .map(|i| pos2(input.markers[*i].x, input.markers[*i].y))
+        .map(|point| Rect::from_min_max(point, point))
+        .reduce(Rect::union)?;
+    let markers = clustered
+        .iter()
+        .map(|i| {
+            let mut marker = input.markers[*i].clone();
+            marker.x = uniform(rng, bounds.min.x, bounds.max.x);
+            marker.y = uniform(rng, bounds.min.y, bounds.max.y);
+            marker.satellites.clear();
+            marker
+        })
+        .collect();
+    Some(SweepInput {
+        markers,
+        clustered: vec![true; clustered.len()],
+        weights: clustered.iter().map(|i| input.weights[*i]).collect(),
+        bounds,
+        ..input.clone()
+    })
+}
+
+fn uniform(rng: &mut StdRng, from: f32, to: f32) -> f32 {
+    if from < to {
+        rng.gen_range(from..to)
+    } else {
+        from
+    }
+}
+
+/// The smallest k whose gap is at least that of k + 1 less its spread, the
+/// number of clusters the gap statistic suggests. A k without a row for
+/// k + 1, as with skipped ks, can't be suggested.
+pub fn suggest(rows: &[GapRow]) -> Option<usize> {
+    rows.iter()
+        .filter(|row| {
+            rows.iter()
+                .find(|next| next.k == row.k + 1)
+                .is_some_and(|next| row.gap >= next.gap - next.spread)
+        })
+        .map(|row| row.k)
+        .min()
+}
+
+impl GapStatistic {
+    /// Runs the clustering `restarts` times for every k in `ks`, on the
+    /// markers and on `references` reference sets drawn once for all k.
+    /// A k whose best error isn't positive, as with a station on every node,
+    /// gets no row.
+    pub fn start(input: SweepInput, ks: Vec<usize>, restarts: usize, references: usize) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let runs_total = ks.len() * restarts * (references + 1);
+
+        let cancelled = cancel.clone();
+        let base_seed = input.params.seed.unwrap_or_else(rand::random);
+        thread::spawn(move || {
+            let mut rng = StdRng::seed_from_u64(base_seed);
+            let inputs: Option<Vec<SweepInput>> = std::iter::once(Some(input.clone()))
+                .chain((0..references).map(|_| reference(&input, &mut rng)))
+                .collect();
+            let Some(inputs) = inputs else {
+                return;
+            };
+            for k in ks {
+                let on_run = |_: &RestartBest| {
+                    // the receiver is gone if the app was closed meanwhile
+                    if tx.send(GapMessage::Run).is_err() {
+                        cancelled.store(true, Ordering::Relaxed);
+                    }
+                };
+                let mut log_errors = Vec::with_capacity(inputs.len());
+                for input in &inputs {
+                    let params = Parameters {
+                        k,
+                        ..input.params.clone()
+                    };
+                    let problem = input.problem(&params);
+                    let best = best_of_restarts(&problem, restarts, base_seed, &cancelled, on_run);
+                    if cancelled.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    if let Some(best) = best.filter(|best| best.error > 0.0) {
+                        log_errors.push(best.error.ln());
+                    }
+                }
+                if log_errors.len() < inputs.len() || references == 0 {
+                    continue;
+                }
+
+                let log_error = log_errors[0];
+                let reference = &log_errors[1..];
+                let mean = reference.iter().sum::<f32>() / references as f32;
+                let variance = reference.iter().map(|l| (l - mean).powi(2)).sum::<f32>()
+                    / references as f32;
+                let row = GapRow {
+                    k,
+                    log_error,
+                    reference_log_error: mean,
+                    gap: mean - log_error,
+                    spread: variance.sqrt() * (1.0 + 1.0 / references as f32).sqrt(),
+                };
+                if tx.send(GapMessage::Row(row)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self {
+            rx,
+            cancel,
+            rows: Vec::new(),
+            runs_done: 0,
+            runs_total,
+            running: true,
+        }
+    }
+
+    /// Takes in what the thread has sent so far.
+    pub fn poll(&mut self) {
+        loop {
+            match self.rx.try_recv() {
+                Ok(GapMessage::Run) => self.runs_done += 1,
+                Ok(GapMessage::Row(row)) => self.rows.push(row),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.running = false;
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.runs_total == 0 {
+            1.0
+        } else {
+            self.runs_done as f32 / self.runs_total as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(gaps: &[(usize, f32, f32)]) -> Vec<GapRow> {
+        gaps.iter()
+            .map(|&(k, gap, spread)| GapRow {
+                k,
+                log_error: 0.0,
+                reference_log_error: gap,
+                gap,
+                spread,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn suggests_the_first_k_not_beaten_by_the_next() {
+        assert_eq!(suggest(&rows(&[(1, 0.1, 0.1), (2, 0.5, 0.1), (3, 0.55, 0.1)])), Some(2));
+        // within the spread of the next counts as not beaten
+        assert_eq!(suggest(&rows(&[(1, 0.1, 0.1), (2, 0.15, 0.1)])), Some(1));
+        assert_eq!(suggest(&rows(&[(1, 0.1, 0.1), (2, 0.5, 0.1)])), None);
+        assert_eq!(suggest(&[]), None);
+    }
+
+    #[test]
+    fn compares_with_k_plus_one_only() {
+        // 2 and 4 aren't neighbours, so 2 isn't compared with 4
+        assert_eq!(suggest(&rows(&[(2, 0.5, 0.1), (4, 0.1, 0.1), (5, 0.3, 0.1)])), None);
+        assert_eq!(
+            suggest(&rows(&[(2, 0.5, 0.1), (4, 0.3, 0.1), (5, 0.2, 0.1), (6, 0.9, 0.1)])),
+            Some(4)
+        );
+        // a missing row for a k, as no positive error left it out
+        assert_eq!(suggest(&rows(&[(1, 0.1, 0.1), (3, 0.2, 0.1), (4, 0.2, 0.1)])), Some(3));
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+
+use egui::pos2;
+
+use crate::cluster::{Clustering, Linkage, Problem};
+
+/// Most markers the hierarchical clustering takes on, as it keeps the
+/// distance between every pair of them, about 50 MB at this size.
+pub const MAX_HIERARCHICAL_MARKERS: usize = 5000;
+
+/// Merges the clustered markers bottom-up with the linkage of the parameters
+/// until k clusters are left, each getting a point at its median. Uses the
+/// nearest-neighbor chain algorithm, taking O(n²) time and memory.
+pub fn hierarchical(problem: &Problem) -> Result<Clustering, String> {
+    let markers: Vec<usize> =
+        (0..problem.markers.len()).filter(|i| problem.is_assignable(*i)).collect();
+    if markers.len() > MAX_HIERARCHICAL_MARKERS {
+        return Err(format!(
+            "hierarchical clustering takes at most {} markers, not {}",
+            MAX_HIERARCHICAL_MARKERS,
+            markers.len()
+        ));
+    }
+
+    let merges = nn_chain(problem, &markers);
+    Ok(Clustering::from_sets(problem, cut(&markers, merges, problem.params.k)))
+}
+
+/// Distances between the markers as a condensed upper triangle.
+struct Distances {
+    n: usize,
+    values: Vec<f32>,
+}
+
+impl Distances {
+    fn index(&self, a: usize, b: usize) -> usize {
+        let (i, j) = if a < b { (a, b) } else { (b, a) };
+        i * self.n - i * (i + 1) / 2 + j - i - 1
+    }
+
+    fn get(&self, a: usize, b: usize) -> f32 {
+        self.values[self.index(a, b)]
+    }
+
+    fn set(&mut self, a: usize, b: usize, value: f32) {
+        let index = self.index(a, b);
+        self.values[index] = value;
+    }
+}
+
+/// The merges of the dendrogram as (cluster, cluster, height), each cluster
+/// given by a marker in it, in no particular order. Ward linkage works on
+/// squared distances.
+fn nn_chain(problem: &Problem, markers: &[usize]) -> Vec<(usize, usize, f32)> {
+    let n = markers.len();
+    let linkage = problem.params.linkage;
+    let mut distances = Distances {
+        n,
+        values: Vec::with_capacity(n * n.saturating_sub(1) / 2),
+    };
+    for (a, i) in markers.iter().enumerate() {
+        let marker = &problem.markers[*i];
+        for j in &markers[a + 1..] {
+            let d = problem.distance(pos2(marker.x, marker.y), marker.z, *j);
+            distances.values.push(if linkage == Linkage::Ward { d * d } else { d });
+        }
+    }
+
+    let mut sizes: Vec<f32> = markers.iter().map(|i| problem.weights[*i]).collect();
+    let mut active = vec![true; n];
+    let mut chain: Vec<usize> = Vec::new();
+    let mut merges = Vec::with_capacity(n.saturating_sub(1));
+    while merges.len() + 1 < n {
+        if chain.is_empty() {
+            chain.extend(active.iter().position(|active| *active));
+        }
+
+        // follow nearest neighbors until two are each other's
+        let (a, b) = loop {
+            let a = chain[chain.len() - 1];
+            let previous = chain.len().checked_sub(2).map(|i| chain[i]);
+            // the previous one wins ties, so the chain can't go in circles
+            let mut nearest = previous.map(|p| (p, distances.get(a, p)));
+            for x in (0..n).filter(|x| active[*x] && *x != a) {
+                let d = distances.get(a, x);
+                if nearest.is_none_or(|(_, best)| d < best) {
+                    nearest = Some((x, d));
+                }
+            }
+            let Some((b, _)) = nearest else {
+                unreachable!("a cluster is left to merge with");
+            };
+            if Some(b) == previous {
+                break (a, b);
+            }
+            chain.push(b);
+        };
+        chain.truncate(chain.len() - 2);
+
+        // b takes a over
+        let d_ab = distances.get(a, b);
+        let (size_a, size_b) = (sizes[a], sizes[b]);
+        for x in (0..n).filter(|x| active[*x] && *x != a && *x != b) {
+            let (d_ax, d_bx) = (distances.get(a, x), distances.get(b, x));
+            let size_x = sizes[x];
+            let d = match linkage {
+                Linkage::Single => d_ax.min(d_bx),
+                Linkage::Complete => d_ax.max(d_bx),
+                Linkage::Average => (size_a * d_ax + size_b * d_bx) / (size_a + size_b),
+                Linkage::Ward => {
+                    ((size_a + size_x) * d_ax + (size_b + size_x) * d_bx - size_x * d_ab)
+                        / (size_a + size_b + size_x)
+                }
+            };
+            distances.set(b, x, d);
+        }
+        active[a] = false;
+        sizes[b] += size_a;
+        merges.push((a, b, d_ab));
+    }
+    merges
+}
+
+/// The sets of marker indices left after the lowest merges down to k
+/// clusters. Fine as the linkages are all reducible, so merges never get
+/// lower than those before.
+fn cut(markers: &[usize], mut merges: Vec<(usize, usize, f32)>, k: usize) -> Vec<Vec<usize>> {
+    merges.sort_by(|a, b| a.2.total_cmp(&b.2));
+    let mut parents: Vec<usize> = (0..markers.len()).collect();
+    fn root(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+    for (a, b, _) in merges.iter().take(markers.len().saturating_sub(k)) {
+        let (a, b) = (root(&mut parents, *a), root(&mut parents, *b));
+        parents[a] = b;
+    }
+
+    let mut sets: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (a, i) in markers.iter().enumerate() {
+        sets.entry(root(&mut parents, a)).or_default().push(*i);
+    }
+    sets.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::{
+        tests::{markers, problem},
+        Parameters,
+    };
+
+    fn sorted(mut sets: Vec<Vec<usize>>) -> Vec<Vec<usize>> {
+        for set in &mut sets {
+            set.sort();
+        }
+        sets.sort();
+        sets
+    }
+
+    #[test]
+    fn splits_two_obvious_groups_with_every_linkage() {
+        let markers = markers(&[
+            (0.0, 0.0),
+            (5000.0, 5000.0),
+            (100.0, 0.0),
+            (5100.0, 5000.0),
+            (0.0, 100.0),
+            (5000.0, 5100.0),
+        ]);
+        for linkage in Linkage::ALL {
+            let params = Parameters {
+                k: 2,
+                linkage,
+                ..Parameters::default()
+            };
+            let clustering = hierarchical(&problem(&markers, &params)).unwrap();
+            assert_eq!(
+                sorted(clustering.sets.clone()),
+                [vec![0, 2, 4], vec![1, 3, 5]],
+                "{:?}",
+                linkage
+            );
+            assert_eq!(clustering.points.len(), 2);
+        }
+    }
+
+    #[test]
+    fn cuts_the_lowest_merges() {
+        // 0 and 1 merge first, then 2 joins them, 3 last
+        let merges = vec![(2, 1, 5.0), (3, 1, 9.0), (0, 1, 1.0)];
+        let markers = [10, 11, 12, 13];
+        assert_eq!(sorted(cut(&markers, merges.clone(), 4)), [[10], [11], [12], [13]]);
+        assert_eq!(sorted(cut(&markers, merges.clone(), 3)), [vec![10, 11], vec![12], vec![13]]);
+        assert_eq!(sorted(cut(&markers, merges.clone(), 2)), [vec![10, 11, 12], vec![13]]);
+        assert_eq!(sorted(cut(&markers, merges.clone(), 1)), [vec![10, 11, 12, 13]]);
+        // more clusters than markers leaves every marker on its own
+        assert_eq!(cut(&markers, merges, 9).len(), 4);
+    }
+
+    #[test]
+    fn refuses_too_many_markers() {
+        let positions: Vec<(f32, f32)> =
+            (0..=MAX_HIERARCHICAL_MARKERS).map(|i| (i as f32, 0.0)).collect();
+        let markers = markers(&positions);
+        let params = Parameters::default();
+        let err = hierarchical(&problem(&markers, &params)).unwrap_err();
+        assert!(err.contains(&(MAX_HIERARCHICAL_MARKERS + 1).to_string()), "{}", err);
+    }
+}
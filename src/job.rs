@@ -0,0 +1,170 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+use crate::{
+    cluster::{Clustering, Problem},
+    sweep::SweepInput,
+};
+
+/// A k-median run in the background, sending its state after every
+/// iteration.
+pub struct Job {
+    rx: Receiver<Clustering>,
+    cancel: Arc<AtomicBool>,
+    pub running: bool,
+}
+
+/// What a job does on its thread: starts `clustering` over if `reinitialize`
+/// is set, then runs it to the end, or for one iteration if `single_step` is
+/// set. Every state goes to `send`, which returns false once nobody listens.
+fn run(
+    problem: &Problem,
+    clustering: &mut Clustering,
+    reinitialize: bool,
+    single_step: bool,
+    cancel: &AtomicBool,
+    mut send: impl FnMut(&Clustering) -> bool,
+) {
+    if reinitialize {
+        clustering.reinitialize(problem);
+        if !send(clustering) {
+            return;
+        }
+    }
+    let iterations = if single_step { 1 } else { problem.params.k_median_max_iter };
+    for i in 0..iterations {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        let settled = clustering.step_k_median(problem).converged();
+        if !single_step {
+            clustering.give_up_after(problem, i);
+            if clustering.convergence.is_some() {
+                if problem.params.local_search {
+                    clustering.local_search(problem);
+                }
+                clustering.dissolve_small_sets(problem);
+            }
+        }
+        if !send(clustering) || settled {
+            return;
+        }
+    }
+}
+
+impl Job {
+    /// Continues `clustering`, starting it over first if `reinitialize` is set,
+    /// for a whole run or a single iteration if `single_step` is set.
+    pub fn start(
+        input: SweepInput,
+        mut clustering: Clustering,
+        reinitialize: bool,
+        single_step: bool,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+
+        let cancelled = cancel.clone();
+        thread::spawn(move || {
+            let problem = input.problem(&input.params);
+            // the receiver is gone once the job was superseded
+            let send = |state: &Clustering| tx.send(state.clone()).is_ok();
+            run(&problem, &mut clustering, reinitialize, single_step, &cancelled, send);
+        });
+
+        Self {
+            rx,
+            cancel,
+            running: true,
+        }
+    }
+
+    /// The latest state sent since the last call, if any.
+    pub fn poll(&mut self) -> Option<Clustering> {
+        let mut latest = None;
+        loop {
+            match self.rx.try_recv() {
+                Ok(clustering) => latest = Some(clustering),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.running = false;
+                    break;
+                }
+            }
+        }
+        latest
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::{
+        tests::{markers, problem},
+        Convergence, Parameters,
+    };
+
+    fn states(
+        problem: &Problem,
+        mut clustering: Clustering,
+        reinitialize: bool,
+        single_step: bool,
+    ) -> Vec<Clustering> {
+        let mut states = Vec::new();
+        let send = |state: &Clustering| {
+            states.push(state.clone());
+            true
+        };
+        run(problem, &mut clustering, reinitialize, single_step, &AtomicBool::new(false), send);
+        states
+    }
+
+    #[test]
+    fn rerun_forgets_the_stale_error_of_the_last_run() {
+        let positions: Vec<(f32, f32)> = (0..60)
+            .map(|i| {
+                let jitter = (i * i % 7) as f32 * 90.0;
+                ((i % 10) as f32 * 1000.0 + jitter, (i / 10) as f32 * 1500.0)
+            })
+            .collect();
+        let markers = markers(&positions);
+        let params = Parameters {
+            k: 5,
+            seed: Some(3),
+            ..Parameters::default()
+        };
+        let problem = problem(&markers, &params);
+
+        // the error the first iteration of a fresh run reaches
+        let first = states(&problem, Clustering::default(), true, true);
+        let first_error = first.last().expect("a step was taken").last_error;
+
+        // a finished run that happened to end at that error
+        let mut stale = states(&problem, Clustering::default(), true, false)
+            .pop()
+            .expect("the run sent its states");
+        assert!(stale.convergence.is_some());
+        stale.last_error = first_error;
+        stale.convergence = Some(Convergence::Settled);
+
+        let rerun = states(&problem, stale, true, false);
+        let fresh = &rerun[0];
+        assert_eq!(fresh.iterations, 0);
+        assert_eq!(fresh.last_error, f32::MAX);
+        assert_eq!(fresh.convergence, None);
+        assert_eq!(rerun[1].last_error, first_error);
+        assert_eq!(rerun[1].convergence, None, "the first iteration settled on the stale error");
+        assert!(rerun.len() > 2);
+        assert!(rerun.last().expect("the rerun sent its states").convergence.is_some());
+    }
+}
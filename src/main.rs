@@ -1,356 +1,4622 @@
-use std::{collections::HashSet, error::Error, fs, path::Path};
+mod args;
+mod background;
+mod cluster;
+mod color;
+mod config;
+mod custom;
+mod dbscan;
+mod download;
+mod evolution;
+mod exact;
+mod exclusions;
+mod gap;
+mod export;
+mod hierarchy;
+mod job;
+mod overlay;
+mod names;
+mod project;
+mod quality;
+mod rates;
+mod render;
+mod report;
+mod restarts;
+mod save;
+mod snapshot;
+mod spatial;
+mod svg;
+mod sweep;
+mod map;
+mod mask;
+mod network;
+mod plan;
+mod planner;
 
-use egui::{emath::RectTransform, pos2, vec2, Color32, DragValue, Pos2, Rect, Sense, Stroke};
-use rand::Rng;
-use serde::Deserialize;
-
-const MAP_LEFT: f32 = -324600.0;
-const MAP_TOP: f32 = -375000.0;
-const MAP_RIGHT: f32 = 425300.0;
-const MAP_BOT: f32 = 375000.0;
-
-#[allow(non_snake_case)]
-#[derive(Debug, Deserialize)]
-struct Map {
-    pub options: Vec<Resources>,
-    pub version: i64,
-    pub lastBuild: String,
-}
-
-#[allow(non_snake_case)]
-#[derive(Debug, Deserialize)]
-struct Resources {
-    pub tabId: String,
-    pub name: String,
-    pub options: Vec<ResourceCategory>,
-}
-
-#[derive(Debug, Deserialize)]
-struct ResourceCategory {
-    pub name: String,
-    pub r#type: Option<String>,
-    pub options: Vec<ResourceLayer>,
-}
-
-#[allow(non_snake_case)]
-#[derive(Debug, Clone, Deserialize)]
-struct ResourceLayer {
-    pub layerId: String,
-    pub name: String,
-    pub purity: Option<String>,
-    pub outsideColor: String,
-    pub insideColor: String,
-    pub icon: String,
-    pub markers: Vec<ResourceMarker>,
-}
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
 
-#[allow(non_snake_case)]
-#[derive(Debug, Clone, Deserialize)]
-struct ResourceMarker {
-    pub pathName: String,
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
-    pub r#type: Option<String>,
-    pub purity: String,
-    pub obstructed: Option<bool>,
-    pub lastCheck: String,
-}
+use egui::{emath::RectTransform, pos2, vec2, Color32, DragValue, Pos2, Rect, Sense, Stroke};
 
-impl Map {
-    pub fn extract_layers_and_markers(&self) -> (Vec<ResourceLayer>, Vec<ResourceMarker>) {
-        let mut markers = Vec::new();
-        let mut layers = Vec::new();
-        for o0 in &self.options {
-            for o1 in &o0.options {
-                for o2 in &o1.options {
-                    layers.push(o2.clone());
-                    for marker in &o2.markers {
-                        markers.push(marker.clone());
-                    }
-                }
-            }
-        }
+use args::{Args, ParseOutcome, USAGE};
+use cluster::{
+    Algorithm, CapacityUnit, Clustering, DistanceMetric, Initialization, LayerRun, Linkage, Loss,
+    MedianSolver, Obstructed, Parameters, Problem, ResultKey, SmallClusters, StationError,
+};
+use color::LayerColors;
+use config::Config;
+use custom::CustomMarker;
+use download::DownloadError;
+use evolution::Evolution;
+use export::{AssignmentRow, StationExport};
+use gap::GapStatistic;
+use job::Job;
+use mask::BuildMask;
+use network::Network;
+use map::{
+    LastCheck, Map, MapData, MapSource, MarkerIssue, NodeKind, ResourceLayer, ResourceMarker,
+    SourceSummary,
+};
+use names::LayerNames;
+use planner::ResourceTotals;
+use project::Project;
+use quality::Quality;
+use rates::Throughput;
+use render::{Canvas, Raster};
+use restarts::{RestartBest, Restarts};
+use spatial::KdTree;
+use svg::{SvgLayer, SvgMap};
+use sweep::{Sweep, SweepCache, SweepInput, SweepRow};
 
-        (layers, markers)
-    }
-}
+/// Where the parameters and layer selection are kept between runs.
+const SESSION_KEY: &str = "session";
+/// Steps [`Frontend::refit`] takes at most.
+const REFIT_STEPS: u32 = 5;
 
 struct Frontend {
     // rendering and other control stuff
     layers: Vec<ResourceLayer>,
+    layer_colors: Vec<LayerColors>,
+    included_layers: Vec<bool>,
     markers: Vec<ResourceMarker>,
+    /// Tree over the positions of the markers, for finding those pointed at.
+    marker_tree: KdTree,
+    /// Area shown and sampled for random initial points, in game coordinates.
+    map_bounds: Rect,
+    config_path: PathBuf,
+    config: Config,
+    config_status: Option<String>,
+    layer_names_path: PathBuf,
+    layer_names: LayerNames,
+    layer_names_status: Option<String>,
     run_continuously: bool,
+    show_cluster_rings: bool,
+    // map image behind the markers, loaded on the next frame when `None`
+    background: Option<Option<egui::TextureHandle>>,
+    background_opacity: f32,
+    background_status: Option<String>,
+    /// Where stations can be built, from `mask_image` or a picked file.
+    mask: Option<BuildMask>,
+    mask_path: Option<PathBuf>,
+    mask_status: Option<String>,
+    // the mask drawn over the map, uploaded the first time it is shown
+    mask_texture: Option<egui::TextureHandle>,
+    show_mask: bool,
+    collapse_wells: bool,
+    /// Leaves liquid nodes and wells out of the clustering, as they are
+    /// usually piped rather than shipped by train.
+    exclude_fluids: bool,
+
+    // stale marker filter
+    ignore_older_than_days: Option<u32>,
+    ignore_before_build: Option<u32>,
+    ignore_before_date: Option<chrono::NaiveDate>,
+    /// What is typed for `ignore_before_date`, kept while it isn't a date.
+    ignore_before_date_text: String,
+
+    // existing stations to start from
+    seed_points: Vec<Pos2>,
+    seeds_fixed: bool,
+    /// Points the optimizer leaves where they are, the first ones of the
+    /// clustering.
+    pinned_points: Vec<Pos2>,
+    /// Whether dragging on the map draws a no-station zone.
+    drawing_zones: bool,
+    /// Game coordinates the zone being drawn started at.
+    zone_start: Option<Pos2>,
+    /// The network of `network_lines`, rebuilt whenever they change.
+    network: Option<Network>,
+    /// The lines the network was last built from, or failed to be.
+    network_built_for: Vec<Vec<Pos2>>,
+    network_status: Option<String>,
+    /// Whether clicking on the map adds a point to `network_draft`.
+    drawing_network: bool,
+    /// The line being drawn, added to the network once finished.
+    network_draft: Vec<Pos2>,
+    /// Marker clicked last, whose route to its station is drawn if
+    /// `show_route` is set.
+    route_marker: Option<usize>,
+    show_route: bool,
+    /// Where the shift-drag selecting the region started, in game coordinates.
+    region_start: Option<Pos2>,
+    seed_text: String,
+    seed_status: Option<String>,
+
+    // custom markers, the ones included in the clustering are in `markers`
+    custom_markers_path: PathBuf,
+    custom_markers: Vec<CustomMarker>,
+    custom_colors: Vec<Color32>,
+    custom_layer: Option<usize>,
+    custom_status: Option<String>,
+
+    // markers with unusable positions, by pathName
+    invalid_markers: BTreeMap<String, MarkerIssue>,
+    include_invalid: bool,
+
+    // markers permanently kept out of the clustering, by pathName
+    exclusions_path: PathBuf,
+    excluded: BTreeSet<String>,
+    exclusions_status: Option<String>,
+
+    // collectible categories opted back into the clustering, by name
+    clustered_collectibles: BTreeSet<String>,
+
+    // built miners read from a save file
+    save_markers: Vec<ResourceMarker>,
+    save_layer: Option<usize>,
+    save_status: Option<String>,
+
+    // layers and markers past these come from custom markers and saves
+    map_layer_count: usize,
+    map_marker_count: usize,
+    // the map's markers before the config's layer filter, and per layer
+    // whether the filter drops it
+    unfiltered_markers: Vec<ResourceMarker>,
+    filtered_layers: Vec<bool>,
+
+    // map data source
+    map_sources: Vec<MapSource>,
+    map_summaries: Vec<SourceSummary>,
+    map_mtime: Option<SystemTime>,
+    watch_map_file: bool,
+    last_watch_poll: Instant,
+    map_version: i64,
+    map_url: String,
+    download: Option<Receiver<Result<Map, DownloadError>>>,
+    map_status: Option<String>,
+    /// Set while there is no usable map data at all.
+    load_error: Option<String>,
+    /// Whether the warning about old map data was closed.
+    map_age_dismissed: bool,
 
     // things that change every run
-    points: Vec<Pos2>,
-    sets: Vec<Vec<usize>>,
-    last_error: f32,
-    best_so_far: f32,
-    best_so_far_points: Vec<Pos2>,
-
-    // algorithm parameters
-    k: usize,
-    anneal_step: f32,
-    anneal_epsilon: f32,
-    k_median_max_iter: u32,
-    k_median_epsilon: f32,
+    clustering: Clustering,
+    /// In-game teleport commands for the current points.
+    teleports: Vec<String>,
+    /// Station picked in the error breakdown to highlight on the map.
+    highlighted_station: Option<usize>,
+    restarts: usize,
+    restart_run: Option<Restarts>,
+    /// The result of every run of the last restarts.
+    restart_runs: Vec<RestartBest>,
+    /// Whether the stations get halos as wide as they move between restarts.
+    show_stability: bool,
+    runs_csv_path: String,
+    stability_csv_path: String,
+    restart_status: Option<String>,
+    evolution: Option<Evolution>,
+    /// The k-median run going on in the background.
+    job: Option<Job>,
+    /// What the best so far was found for.
+    result_key: Option<ResultKey>,
+    /// Why the best so far was last dropped.
+    best_cleared: Option<String>,
+    /// Why "continue from best" was refused the last time, if it was.
+    continue_refused: Option<String>,
+    /// Strongest membership below which markers count as contested in fuzzy
+    /// mode.
+    contested_threshold: f32,
+    /// Why the last hierarchical clustering or exact search failed.
+    algorithm_status: Option<String>,
+    /// The points of the last DBSCAN run.
+    dbscan_points: Option<Vec<Pos2>>,
+    /// Quality metrics and the points they were computed for.
+    quality: Option<(Vec<Pos2>, Quality)>,
+    /// The layers of the last per-layer run and the points it made.
+    layer_plan: Option<(Vec<Pos2>, Vec<LayerRun>)>,
+
+    // sweep over a range of k
+    sweep_from: usize,
+    sweep_to: usize,
+    sweep_restarts: usize,
+    /// Cost of a station in units of the error, to pick the k of a sweep by.
+    /// 0 leaves it to the knee.
+    station_cost: f32,
+    sweep: Option<Sweep>,
+    sweep_cache: SweepCache,
+    /// Seed and fingerprint the rows of the running sweep are cached under.
+    sweep_key: (Option<u64>, u64),
+    sweep_csv_path: String,
+    sweep_status: Option<String>,
+    /// The gap statistic over the k range of the sweep, with this many
+    /// reference sets.
+    gap: Option<GapStatistic>,
+    gap_references: usize,
+
+    params: Parameters,
+
+    // station export
+    export_path: String,
+    csv_path: String,
+    station_csv_path: String,
+    svg_path: String,
+    overlay_path: String,
+    report_path: String,
+    planner_path: String,
+    image_size: u32,
+    image_assignment_lines: bool,
+    export_status: Option<String>,
+
+    // project file
+    project_path: String,
+    project_status: Option<String>,
 }
 
 impl Frontend {
-    fn new(layers: Vec<ResourceLayer>, markers: Vec<ResourceMarker>) -> Self {
-        Self {
-            layers,
-            markers,
+    fn new(
+        data: MapData,
+        map_sources: Vec<MapSource>,
+        map_url: String,
+        custom_markers_path: PathBuf,
+        config_path: PathBuf,
+        exclusions_path: PathBuf,
+        layer_names_path: PathBuf,
+    ) -> Self {
+        let mut frontend = Self {
+            layers: Vec::new(),
+            layer_colors: Vec::new(),
+            included_layers: Vec::new(),
+            markers: Vec::new(),
+            marker_tree: KdTree::default(),
+            map_bounds: cluster::default_map_bounds(),
+            config_path,
+            config: Config::default(),
+            config_status: None,
+            layer_names_path,
+            layer_names: LayerNames::default(),
+            layer_names_status: None,
             run_continuously: false,
-            points: Vec::new(),
-            sets: Vec::new(),
-            last_error: f32::MAX,
-            best_so_far: f32::MAX,
-            best_so_far_points: Vec::new(),
-            k: 10,
-            anneal_step: 10000.0,
-            anneal_epsilon: 1.0,
-            k_median_max_iter: 10,
-            k_median_epsilon: 10.0,
-        }
+            show_cluster_rings: true,
+            background: None,
+            background_opacity: 0.5,
+            background_status: None,
+            mask: None,
+            mask_path: None,
+            mask_status: None,
+            mask_texture: None,
+            show_mask: false,
+            collapse_wells: false,
+            exclude_fluids: false,
+            ignore_older_than_days: None,
+            ignore_before_build: None,
+            ignore_before_date: None,
+            ignore_before_date_text: String::new(),
+            seed_points: Vec::new(),
+            seeds_fixed: true,
+            pinned_points: Vec::new(),
+            drawing_zones: false,
+            network: None,
+            network_built_for: Vec::new(),
+            network_status: None,
+            drawing_network: false,
+            network_draft: Vec::new(),
+            route_marker: None,
+            show_route: true,
+            zone_start: None,
+            region_start: None,
+            seed_text: String::new(),
+            seed_status: None,
+            custom_markers_path,
+            custom_markers: Vec::new(),
+            custom_colors: Vec::new(),
+            custom_layer: None,
+            custom_status: None,
+            invalid_markers: BTreeMap::new(),
+            include_invalid: false,
+            exclusions_path,
+            excluded: BTreeSet::new(),
+            exclusions_status: None,
+            clustered_collectibles: BTreeSet::new(),
+            save_markers: Vec::new(),
+            save_layer: None,
+            save_status: None,
+            map_layer_count: 0,
+            map_marker_count: 0,
+            unfiltered_markers: Vec::new(),
+            filtered_layers: Vec::new(),
+            map_mtime: sources_mtime(&map_sources),
+            map_sources,
+            map_summaries: Vec::new(),
+            watch_map_file: false,
+            last_watch_poll: Instant::now(),
+            map_version: data.version(),
+            map_url,
+            download: None,
+            map_status: None,
+            load_error: None,
+            map_age_dismissed: false,
+            clustering: Clustering::default(),
+            teleports: Vec::new(),
+            highlighted_station: None,
+            restarts: 20,
+            restart_run: None,
+            restart_runs: Vec::new(),
+            show_stability: true,
+            runs_csv_path: "./restarts.csv".to_string(),
+            stability_csv_path: "./stability.csv".to_string(),
+            restart_status: None,
+            evolution: None,
+            job: None,
+            result_key: None,
+            best_cleared: None,
+            continue_refused: None,
+            algorithm_status: None,
+            contested_threshold: 0.6,
+            dbscan_points: None,
+            quality: None,
+            layer_plan: None,
+            sweep_from: 1,
+            sweep_to: 30,
+            sweep_restarts: 10,
+            station_cost: 0.0,
+            sweep: None,
+            sweep_cache: SweepCache::new(),
+            sweep_key: (None, 0),
+            sweep_csv_path: "./sweep.csv".to_string(),
+            sweep_status: None,
+            gap: None,
+            gap_references: 10,
+            params: Parameters::default(),
+            export_path: "./stations.json".to_string(),
+            csv_path: "./assignments.csv".to_string(),
+            station_csv_path: "./station_errors.csv".to_string(),
+            svg_path: "./stations.svg".to_string(),
+            overlay_path: "./stations_layer.json".to_string(),
+            report_path: "./report.md".to_string(),
+            planner_path: "./station_resources.json".to_string(),
+            image_size: 2048,
+            image_assignment_lines: true,
+            export_status: None,
+            project_path: "./project.json".to_string(),
+            project_status: None,
+        };
+        frontend.reload_config();
+        frontend.reload_layer_names();
+        frontend.replace_map(data);
+        frontend.reload_custom_markers();
+        frontend.reload_exclusions();
+        frontend
     }
 }
 
 impl eframe::App for Frontend {
-    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
-        egui::SidePanel::right("side_panel").show(ctx, |ui| {
-            ui.heading("Controls");
-            ui.label("k");
-            ui.add(DragValue::new(&mut self.k).range(1..=50));
-            ui.label("max iterations");
-            ui.add(DragValue::new(&mut self.k_median_max_iter).range(1..=100));
-            ui.label("anneal epsilon");
-            ui.add(DragValue::new(&mut self.anneal_epsilon).range(0.1..=10000.0));
-            ui.label("k median epsilon");
-            ui.add(DragValue::new(&mut self.k_median_epsilon).range(0.1..=10000.0));
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        // marker indices may change by next start, so the current points and
+        // their assignment are not worth keeping
+        let session = Project {
+            points: Vec::new(),
+            last_error: f32::MAX,
+            ..self.to_project()
+        };
+        eframe::set_value(storage, SESSION_KEY, &session);
+    }
 
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if let Some(err) = self.load_error.clone() {
+            self.show_load_error(ctx, &err);
+            return;
+        }
+        self.refresh_network();
+
+        if let Some(warning) = self.map_age_warning() {
+            egui::TopBottomPanel::top("map_age").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.colored_label(ui.visuals().warn_fg_color, warning);
+                    let downloading = self.download.is_some();
+                    if ui
+                        .add_enabled(!downloading, egui::Button::new("download latest"))
+                        .clicked()
+                    {
+                        self.start_download();
+                    }
+                    if ui.button("dismiss").clicked() {
+                        self.map_age_dismissed = true;
+                    }
+                });
+            });
+        }
+
+        egui::SidePanel::right("side_panel").show(ctx, |ui| {
+            self.algorithm_panel(ui);
+            self.rates_panel(ui);
+            ui.separator();
+            self.staleness_panel(ui);
+            ui.separator();
+            self.run_panel(ui);
+            ui.separator();
+            self.information_panel(ui);
+            ui.separator();
+            self.sweep_panel(ui);
+            ui.separator();
+            self.teleports_panel(ui);
+            ui.separator();
+            self.export_panel(ui);
+            ui.separator();
+            self.layers_panel(ui);
+            ui.separator();
+            self.existing_stations_panel(ui);
+            ui.separator();
+            self.region_panel(ui);
+            ui.separator();
+            self.zones_panel(ui);
+            ui.separator();
+            self.network_panel(ui);
+            ui.separator();
+            self.outliers_panel(ui);
+            self.contested_panel(ui);
+            self.diagnostics_panel(ui);
+            ui.separator();
+            self.exclusions_panel(ui);
+            ui.separator();
+            self.save_file_panel(ui);
+            ui.separator();
+            self.custom_markers_panel(ui);
+            ui.separator();
+            self.config_panel(ui);
+            ui.separator();
+            self.project_panel(ui);
             ui.separator();
+            self.map_data_panel(ui);
+        });
 
-            if ui.button("reset and run").clicked() {
-                self.reinitialize();
-                self.run_k_median();
+        self.poll_download(ctx);
+        self.poll_sweep(ctx);
+        self.poll_gap(ctx);
+        self.poll_restarts(ctx);
+        self.poll_evolution(ctx);
+        self.poll_job(ctx);
+        self.check_result_key();
+        self.poll_map_file(ctx);
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let (response, mut painter) =
+                ui.allocate_painter(ui.available_size(), Sense::click_and_drag());
+            let to_screen = RectTransform::from_to(
+                self.view_rect(),
+                response.rect,
+            );
+            if let Some(texture) = self.background_texture(ui.ctx()) {
+                painter.image(
+                    texture.id(),
+                    to_screen.transform_rect(self.config.background_bounds()),
+                    Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+                    Color32::WHITE.gamma_multiply(self.background_opacity),
+                );
+            }
+            if self.show_mask {
+                if let Some(mask) = &self.mask {
+                    let texture = self.mask_texture.get_or_insert_with(|| {
+                        let image = mask.overlay_image();
+                        ui.ctx().load_texture("mask", image, egui::TextureOptions::NEAREST)
+                    });
+                    painter.image(
+                        texture.id(),
+                        to_screen.transform_rect(mask.bounds()),
+                        Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+                        Color32::WHITE,
+                    );
+                }
             }
+            self.paint_map(&mut painter, &to_screen, false);
 
-            if ui.button("reset").clicked() {
-                self.reinitialize();
+            let shift = ui.input(|input| input.modifiers.shift);
+            if self.region_start.is_some() || (shift && response.drag_started()) {
+                let from_screen = to_screen.inverse();
+                let pointer = ui
+                    .ctx()
+                    .input(|input| input.pointer.latest_pos())
+                    .map(|pos| from_screen.transform_pos(pos));
+                if response.drag_started() {
+                    self.region_start = pointer;
+                }
+                if let (Some(start), Some(end)) = (self.region_start, pointer) {
+                    let region = Rect::from_two_pos(start, end);
+                    if response.drag_stopped() || !response.dragged() {
+                        self.region_start = None;
+                        if region.area() > 0.0 {
+                            self.params.region = Some(region);
+                            self.points_changed();
+                        }
+                    } else {
+                        paint_region(&mut painter, &to_screen, region);
+                    }
+                }
+                return;
             }
 
-            if ui.button("step once").clicked() {
-                if self.sets.len() != self.k {
-                    self.reinitialize();
+            if self.drawing_zones {
+                let from_screen = to_screen.inverse();
+                let pointer = ui
+                    .ctx()
+                    .input(|input| input.pointer.latest_pos())
+                    .map(|pos| from_screen.transform_pos(pos));
+                if response.drag_started() {
+                    self.zone_start = pointer;
+                }
+                if let (Some(start), Some(end)) = (self.zone_start, pointer) {
+                    let zone = Rect::from_two_pos(start, end);
+                    if response.drag_stopped() {
+                        self.zone_start = None;
+                        if zone.area() > 0.0 {
+                            self.params.no_station_zones.push(zone);
+                        }
+                    } else {
+                        paint_zone(&mut painter, &to_screen, zone);
+                    }
                 }
-                self.run_k_median();
+                return;
             }
 
-            ui.checkbox(&mut self.run_continuously, "run continuously");
-            if self.run_continuously {
-                self.reinitialize();
-                self.run_k_median();
+            if self.drawing_network {
+                let hover = response.hover_pos();
+                if let Some(pos) = hover.filter(|_| response.clicked()) {
+                    let point = self
+                        .network_vertex_at(&to_screen, pos)
+                        .unwrap_or_else(|| to_screen.inverse().transform_pos(pos));
+                    if self.network_draft.last() != Some(&point) {
+                        self.network_draft.push(point);
+                    }
+                }
+                if response.double_clicked() {
+                    self.finish_network_line();
+                }
+                if let (Some(last), Some(pos)) = (self.network_draft.last(), hover) {
+                    let from = to_screen.transform_pos(*last);
+                    painter.line(from, pos, Stroke::new(1.0, NETWORK_COLOR));
+                }
+                return;
             }
 
-            ui.separator();
+            let station = response
+                .hover_pos()
+                .and_then(|pos| self.station_at(&to_screen, pos));
+            if let Some(i) = station {
+                if response.clicked() {
+                    self.toggle_pin(i);
+                }
+                let action = if i < self.pinned_points.len() { "unpin" } else { "pin" };
+                response.on_hover_text(format!("station {}\nclick to {}", i, action));
+                return;
+            }
 
-            ui.heading("Information");
-            ui.label(format!("Last run total error: {}", self.last_error));
-            ui.label(format!("Best so far: {}", self.best_so_far));
-            ui.label(format!("Best so far points: {:#?}", self.best_so_far_points));
+            let hovered = response
+                .hover_pos()
+                .and_then(|pos| self.marker_at(&to_screen, pos));
+            if let Some(i) = hovered {
+                let marker = &self.markers[i];
+                let layer = self.layer_names.name(&self.layers[marker.layer]).to_string();
+                let path_name = marker.pathName.clone();
+                if response.secondary_clicked() {
+                    self.toggle_exclusion(&path_name);
+                }
+                if response.clicked() {
+                    self.route_marker = Some(i);
+                }
+                let action = if self.excluded.contains(&path_name) {
+                    "include"
+                } else {
+                    "exclude"
+                };
+                response.on_hover_text(format!(
+                    "{}\n{}\nright click to {}",
+                    layer, path_name, action
+                ));
+            }
         });
+    }
+}
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::drag());
-            let to_screen = RectTransform::from_to(
-                Rect::from_min_max(pos2(MAP_LEFT, MAP_TOP), pos2(MAP_RIGHT, MAP_BOT)),
-                response.rect,
-            );
-            for layer in &self.layers {
-                for marker in &layer.markers {
-                    // find closest_distance (for coloring)
-                    let mut closest_distance = f32::MAX;
-                    let mut closest_index = 0;
-                    for (i, point) in self.points.iter().enumerate() {
-                        let distance = (*point - pos2(marker.x, marker.y)).length();
-                        if distance < closest_distance {
-                            closest_distance = distance;
-                            closest_index = i;
-                        }
+impl Frontend {
+    /// Draws markers, custom markers and points, optionally with a line from
+    /// every marker to the point it is assigned to.
+    fn paint_map(
+        &self,
+        canvas: &mut impl Canvas,
+        to_screen: &RectTransform,
+        assignment_lines: bool,
+    ) {
+        if assignment_lines {
+            for (point, set) in self.clustering.points.iter().zip(&self.clustering.sets) {
+                let to = to_screen.transform_pos(*point);
+                for i in set {
+                    let marker = &self.markers[*i];
+                    let from = to_screen.transform_pos(pos2(marker.x, marker.y));
+                    canvas.line(from, to, Stroke::new(0.5, Color32::from_gray(160)));
+                }
+            }
+        }
+
+        let problem = self.problem();
+        // in fuzzy mode, how far each marker is from belonging to one station
+        let mut ambiguity = Vec::new();
+        if self.params.fuzzy {
+            ambiguity = problem
+                .memberships(&self.clustering.points, &self.clustering.heights)
+                .iter()
+                .map(|membership| {
+                    // markers left out have no memberships
+                    membership.iter().copied().reduce(f32::max).map_or(0.0, |m| 1.0 - m)
+                })
+                .collect();
+        }
+        let mut outliers = vec![false; self.markers.len()];
+        if self.params.trim_outliers {
+            for (_, i, _) in self.outliers() {
+                outliers[i] = true;
+            }
+        }
+        // markers out of range of every station with a max service radius,
+        // or DBSCAN noise
+        let noise = self.dbscan_result().is_some();
+        let mut uncovered = Vec::new();
+        if (self.params.max_radius.is_some() || noise) && !self.clustering.sets.is_empty() {
+            uncovered = (0..self.markers.len()).map(|i| problem.is_assignable(i)).collect();
+            for i in self.clustering.sets.iter().flatten() {
+                uncovered[*i] = false;
+            }
+        }
+        for (markeri, marker) in self.markers.iter().enumerate() {
+            // custom markers are drawn separately below
+            if !self.is_present(marker) || Some(marker.layer) == self.custom_layer {
+                continue;
+            }
+            if !(marker.x.is_finite() && marker.y.is_finite()) {
+                continue;
+            }
+
+            let pos = to_screen.transform_pos(pos2(marker.x, marker.y));
+
+            if let Some(ambiguity) = ambiguity.get(markeri).filter(|a| **a > 0.0) {
+                canvas.circle_stroke(pos, 8.0, Stroke::new(ambiguity * 6.0, Color32::WHITE));
+            }
+
+            let is_uncovered = uncovered.get(markeri).copied().unwrap_or(false);
+            if is_uncovered && !noise {
+                canvas.circle_stroke(pos, 7.0, Stroke::new(2.0, Color32::from_rgb(255, 140, 0)));
+            }
+            if outliers[markeri] {
+                canvas.circle_stroke(pos, 9.0, Stroke::new(2.0, Color32::from_rgb(220, 60, 220)));
+            }
+
+            if self.show_cluster_rings && !self.clustering.points.is_empty() {
+                // find closest_distance (for coloring)
+                let mut closest_distance = f32::MAX;
+                let mut closest_index = 0;
+                for (i, point) in self.clustering.points.iter().enumerate() {
+                    let z = self.clustering.heights.get(i).copied().unwrap_or_default();
+                    let distance = problem.distance(*point, z, markeri);
+                    if distance < closest_distance {
+                        closest_distance = distance;
+                        closest_index = i;
                     }
+                }
 
-                    let color = Color32::from_rgb(
-                        (255.0 / self.k as f32 * closest_index as f32) as u8,
-                        128,
-                        196,
-                    );
-                    let pos = to_screen.transform_pos(pos2(marker.x, marker.y));
-                    painter.circle(pos, 3.0, color, Stroke::default());
+                let color = color::cluster_color(closest_index, self.clustering.points.len());
+                canvas.circle_stroke(pos, 6.0, Stroke::new(1.5, color));
+            }
+
+            let colors = &self.layer_colors[marker.layer];
+            let (inside, outside) = if self.excluded.contains(&marker.pathName) {
+                (Color32::from_gray(90), Color32::from_gray(60))
+            } else if !self.in_region(marker) {
+                (colors.inside.gamma_multiply(0.2), colors.outside.gamma_multiply(0.2))
+            } else if is_uncovered && noise {
+                (Color32::from_gray(140), Color32::from_gray(90))
+            } else if self.is_stale(marker) {
+                (colors.inside.gamma_multiply(0.3), colors.outside.gamma_multiply(0.3))
+            } else {
+                (colors.inside, colors.outside)
+            };
+            let radius = if marker.is_pressurizer() { 5.5 } else { 3.5 };
+            if marker.obstructed == Some(true) && self.params.obstructed != Obstructed::Include {
+                canvas.circle_stroke(pos, radius, Stroke::new(1.5, inside));
+            } else {
+                canvas.circle(pos, radius, inside, Stroke::new(1.0, outside));
+            }
+        }
+
+        for (marker, color) in self.custom_markers.iter().zip(&self.custom_colors) {
+            let pos = to_screen.transform_pos(pos2(marker.x, marker.y));
+            if marker.include_in_clustering {
+                canvas.circle(pos, 5.0, *color, Stroke::new(1.0, Color32::BLACK));
+            } else {
+                canvas.circle_stroke(pos, 5.0, Stroke::new(2.0, *color));
+            }
+            canvas.label(pos + vec2(7.0, 0.0), &marker.name, Color32::WHITE);
+        }
+
+        for zone in &self.params.no_station_zones {
+            paint_zone(canvas, to_screen, *zone);
+        }
+        for line in self.params.network_lines.iter().chain([&self.network_draft]) {
+            for pair in line.windows(2) {
+                let from = to_screen.transform_pos(pair[0]);
+                let to = to_screen.transform_pos(pair[1]);
+                canvas.line(from, to, Stroke::new(2.0, NETWORK_COLOR));
+            }
+        }
+        if let Some(route) = self.route() {
+            for pair in route.windows(2) {
+                let from = to_screen.transform_pos(pair[0]);
+                let to = to_screen.transform_pos(pair[1]);
+                canvas.line(from, to, Stroke::new(3.0, Color32::YELLOW));
+            }
+        }
+        if let Some(region) = self.params.region {
+            paint_region(canvas, to_screen, region);
+        }
+
+        // the rail network the error counts
+        if self.params.rail_weight > 0.0 {
+            let points = &self.clustering.points;
+            for (a, b) in cluster::spanning_tree(points) {
+                let from = to_screen.transform_pos(points[a]);
+                let to = to_screen.transform_pos(points[b]);
+                canvas.line(from, to, Stroke::new(2.0, Color32::from_rgb(200, 160, 90)));
+            }
+        }
+
+        // the station picked under "Errors by station" and its nodes
+        let highlighted = self.highlighted_station.and_then(|i| {
+            Some((self.clustering.points.get(i)?, self.clustering.sets.get(i)?))
+        });
+        if let Some((point, set)) = highlighted {
+            let stroke = Stroke::new(2.0, Color32::YELLOW);
+            let center = to_screen.transform_pos(*point);
+            for i in set {
+                let marker = &self.markers[*i];
+                let pos = to_screen.transform_pos(pos2(marker.x, marker.y));
+                canvas.line(center, pos, Stroke::new(1.0, Color32::YELLOW));
+                canvas.circle_stroke(pos, 8.0, stroke);
+            }
+            canvas.circle_stroke(center, 18.0, stroke);
+        }
+
+        // stations at capacity get a red ring
+        if let Some(capacity) = self.params.capacity {
+            for (point, set) in self.clustering.points.iter().zip(&self.clustering.sets) {
+                if problem.load(set) >= capacity {
+                    let pos = to_screen.transform_pos(*point);
+                    canvas.circle_stroke(pos, 14.0, Stroke::new(2.5, Color32::RED));
                 }
             }
+        }
 
-            for point in &self.points {
+        // stations that move between restarts get a halo as wide as they move
+        if self.show_stability {
+            let stability = restarts::stability(&self.clustering.points, &self.restart_runs);
+            for (point, distance) in self.clustering.points.iter().zip(&stability) {
                 let pos = to_screen.transform_pos(*point);
-                painter.circle(pos, 8.0, Color32::GREEN, Stroke::default());
+                let radius = distance * to_screen.scale().x.abs();
+                let fill = Color32::from_rgba_unmultiplied(255, 140, 0, 50);
+                canvas.circle(pos, radius, fill, Stroke::new(1.0, Color32::from_rgb(255, 140, 0)));
+            }
+        }
+
+        // medoids get a ring of their own
+        for (point, set) in self.clustering.points.iter().zip(&self.clustering.sets) {
+            if let Some(i) = problem.medoid_at(*point, set) {
+                let pos = to_screen.transform_pos(pos2(self.markers[i].x, self.markers[i].y));
+                canvas.circle_stroke(pos, 11.0, Stroke::new(2.0, Color32::WHITE));
             }
+        }
+
+        // per point, its layer, number within the layer and the layer's k
+        let layer_labels: Vec<(usize, usize, usize)> = self
+            .layer_runs()
+            .unwrap_or_default()
+            .iter()
+            .flat_map(|run| (0..run.k).map(move |j| (run.layer, j + 1, run.k)))
+            .collect();
+        let pinned = self.pinned_points.len().min(self.clustering.points.len());
+        let seeded = (pinned + self.seed_points.len()).min(self.clustering.points.len());
+        for (i, point) in self.clustering.points.iter().enumerate() {
+            let pos = to_screen.transform_pos(*point);
+            if i < pinned {
+                // a padlock
+                let body = Rect::from_center_size(pos + vec2(0.0, 2.0), vec2(12.0, 9.0));
+                canvas.circle_stroke(pos + vec2(0.0, -2.5), 3.5, Stroke::new(2.0, Color32::GOLD));
+                canvas.rect(body, Color32::GOLD, Stroke::new(1.0, Color32::BLACK));
+                continue;
+            }
+            if let Some((layer, j, k)) = layer_labels.get(i) {
+                let colors = &self.layer_colors[*layer];
+                canvas.circle(pos, 8.0, colors.inside, Stroke::new(2.0, colors.outside));
+                let name = self.layer_names.name(&self.layers[*layer]);
+                let label = format!("{} {}/{}", name, j, k);
+                canvas.label(pos + vec2(10.0, -12.0), &label, Color32::WHITE);
+            } else if (pinned..seeded).contains(&i) {
+                let rect = Rect::from_center_size(pos, vec2(14.0, 14.0));
+                canvas.rect(rect, Color32::GOLD, Stroke::new(1.0, Color32::BLACK));
+            } else {
+                canvas.circle(pos, 8.0, Color32::GREEN, Stroke::default());
+            }
+            if let Some(z) = self.clustering.heights.get(i).filter(|_| self.params.three_d) {
+                canvas.label(pos + vec2(10.0, 0.0), &format!("z {:.0}", z), Color32::WHITE);
+            }
+        }
+    }
+
+    /// Renders the map into a square PNG named after the time and k.
+    fn export_image(&mut self) {
+        let size = self.image_size;
+        let Some(mut raster) = Raster::new(size, size, size as f32 / 1024.0, Color32::from_gray(27))
+        else {
+            self.export_status = Some(format!("cannot render a {0}×{0} image", size));
+            return;
+        };
+        let to_screen = RectTransform::from_to(
+            self.view_rect(),
+            Rect::from_min_size(Pos2::ZERO, vec2(size as f32, size as f32)),
+        );
+        self.paint_map(&mut raster, &to_screen, self.image_assignment_lines);
+
+        let path = PathBuf::from(format!(
+            "stations_k{}_{}.png",
+            self.clustering.points.len(),
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        self.export_status = Some(match raster.save_png(&path) {
+            Ok(()) => format!("saved image to {}", path.display()),
+            Err(err) => format!("failed to save image to {}: {}", path.display(), err),
         });
     }
-}
 
-impl Frontend {
-    fn reinitialize(&mut self) {
-        // initialization strategy: random (surely this will be fine)
-        self.points = (0..self.k)
-            .map(|_| {
-                let x = rand::thread_rng().gen_range(MAP_LEFT..MAP_RIGHT);
-                let y = rand::thread_rng().gen_range(MAP_TOP..MAP_BOT);
-                pos2(x, y)
+    /// Swaps in new map data. Sets and points index into the old markers, so
+    /// they are dropped as well, while the best points are plain coordinates
+    /// and only need their error recomputed.
+    fn replace_map(&mut self, data: MapData) {
+        let MapData {
+            layers,
+            markers,
+            sources,
+            ..
+        } = data;
+        let previous_extra_layers = self.take_extra_layers();
+        self.layer_colors = layers
+            .iter()
+            .map(|layer| LayerColors::parse(&layer.insideColor, &layer.outsideColor))
+            .collect();
+        // keep the layer selection for layers that still exist
+        self.included_layers = layers
+            .iter()
+            .map(|layer| {
+                self.layers
+                    .iter()
+                    .zip(&self.included_layers)
+                    .find(|(old, _)| old.layerId == layer.layerId)
+                    .is_none_or(|(_, included)| *included)
             })
-        .collect::<Vec<_>>();
-
-        self.sets = vec![Vec::new(); self.k];
+            .collect();
+        self.layers = layers;
+        self.unfiltered_markers = markers;
+        self.map_version = sources.iter().map(|s| s.version).max().unwrap_or_default();
+        self.map_age_dismissed = false;
+        self.map_summaries = sources;
+        self.map_layer_count = self.layers.len();
+        self.apply_layer_filter(&previous_extra_layers);
     }
 
-    fn simulated_annealing(&self, indices: &Vec<usize>) -> Pos2 {
-        let directions = vec![
-            vec2(1.0, 0.0),
-            vec2(-1.0, 0.0),
-            vec2(0.0, 1.0),
-            vec2(0.0, -1.0),
-        ];
+    /// Rebuilds the markers from the map's with the config's layer filter
+    /// applied, so dropped layers never take part at all. The extra layers
+    /// must have been taken off before.
+    fn apply_layer_filter(&mut self, previous_extra_layers: &[(String, bool)]) {
+        self.filtered_layers = self
+            .layers
+            .iter()
+            .map(|layer| !self.config.keeps_layer(layer))
+            .collect();
+        let mut markers: Vec<ResourceMarker> = self
+            .unfiltered_markers
+            .iter()
+            .filter(|m| !self.filtered_layers[m.layer])
+            .cloned()
+            .collect();
+        markers.extend(map::pressurizer_markers(&markers));
 
-        let mut step = self.anneal_step;
-        let mut median = indices
+        let world = self.config.apply_bounds(cluster::default_map_bounds());
+        self.invalid_markers = markers
+            .iter()
+            .filter_map(|m| Some((m.pathName.clone(), m.issue(world)?)))
+            .collect();
+        let valid: Vec<ResourceMarker> = markers
             .iter()
-            .map(|i| vec2(self.markers[*i].x, self.markers[*i].y))
-            .fold(Pos2::ZERO, |acc, v| acc + v);
-        median.x /= indices.len() as f32;
-        median.y /= indices.len() as f32;
+            .filter(|m| !self.invalid_markers.contains_key(&m.pathName))
+            .cloned()
+            .collect();
+        self.map_bounds =
+            cluster::marker_bounds(&valid).unwrap_or_else(cluster::default_map_bounds);
+        self.markers = markers;
+        self.cancel_job();
+        self.clustering.points.clear();
+        self.clustering.sets.clear();
+        self.clustering.last_error = f32::MAX;
+        self.clustering.iterations = 0;
+        self.clustering.reassignments.clear();
+        self.clustering.convergence = None;
+        self.map_marker_count = self.markers.len();
+        self.append_extra_layers(previous_extra_layers);
+
+        if !self.clustering.best_so_far_points.is_empty() {
+            self.rescore_best();
+        }
+    }
 
-        let mut min: f32 = indices
+    /// Removes the layers and markers appended after the map's own ones,
+    /// returning whether each of those layers was included.
+    fn take_extra_layers(&mut self) -> Vec<(String, bool)> {
+        let previous = self.layers[self.map_layer_count..]
             .iter()
-            .map(|i| {
-                let factor = str_to_factor(self.markers[*i].purity.as_str());
-                ((self.markers[*i].x - median.x).powi(2) + (self.markers[*i].y - median.y).powi(2))
-                    .sqrt() * factor
-            })
-        .sum();
-        let mut improved = false;
+            .zip(&self.included_layers[self.map_layer_count..])
+            .map(|(layer, included)| (layer.layerId.clone(), *included))
+            .collect();
+        self.layers.truncate(self.map_layer_count);
+        self.layer_colors.truncate(self.map_layer_count);
+        self.included_layers.truncate(self.map_layer_count);
+        self.markers.truncate(self.map_marker_count);
+        self.custom_layer = None;
+        self.save_layer = None;
+        previous
+    }
+
+    /// Appends the layers of markers that do not come from the map data: the
+    /// custom markers and the miners read from a save file.
+    fn append_extra_layers(&mut self, previous: &[(String, bool)]) {
+        let push_layer = |frontend: &mut Self, layer: ResourceLayer| {
+            let included = previous
+                .iter()
+                .find(|(id, _)| *id == layer.layerId)
+                .is_none_or(|(_, included)| *included);
+            frontend
+                .layer_colors
+                .push(LayerColors::parse(&layer.insideColor, &layer.outsideColor));
+            frontend.included_layers.push(included);
+            frontend.layers.push(layer);
+            frontend.layers.len() - 1
+        };
+
+        let layer = push_layer(self, custom::custom_layer());
+        self.markers.extend(
+            self.custom_markers
+                .iter()
+                .filter(|m| m.include_in_clustering)
+                .map(|m| m.to_resource_marker(layer)),
+        );
+        self.custom_layer = Some(layer);
 
-        while step > self.anneal_epsilon {
-            for direction in &directions {
-                let temp_median = median + step * *direction;
-                let d = indices
+        if !self.save_markers.is_empty() {
+            let layer = push_layer(self, save::miner_layer());
+            self.markers.extend(self.save_markers.iter().map(|m| ResourceMarker {
+                layer,
+                ..m.clone()
+            }));
+            self.save_layer = Some(layer);
+        }
+
+        self.marker_tree =
+            KdTree::new(self.markers.iter().enumerate().map(|(i, m)| (i, pos2(m.x, m.y))));
+        self.clustering.sets.clear();
+    }
+
+    fn reload_custom_markers(&mut self) {
+        let path = self.custom_markers_path.clone();
+        match custom::load_custom_markers(&path) {
+            Ok(custom_markers) => {
+                self.custom_colors = custom_markers
                     .iter()
-                    .map(|i| {
-                        let factor = str_to_factor(self.markers[*i].purity.as_str());
-                        ((self.markers[*i].x - median.x).powi(2)
-                         + (self.markers[*i].y - median.y).powi(2))
-                            .sqrt() * factor
+                    .map(|m| {
+                        let color = m.color.as_deref().unwrap_or_default();
+                        color::hex_color_or(color, Color32::WHITE)
                     })
-                .sum();
-                if d < min {
-                    min = d;
-                    median = temp_median;
-                    improved = true;
-                    break;
-                }
+                    .collect();
+                self.custom_status = Some(format!("{} custom markers", custom_markers.len()));
+                self.custom_markers = custom_markers;
+
+                let previous = self.take_extra_layers();
+                self.append_extra_layers(&previous);
+            }
+            Err(err) => {
+                self.custom_status = Some(format!(
+                    "failed to load {}: {}",
+                    path.display(),
+                    err
+                ));
+            }
+        }
+    }
+
+    /// Re-reads the config, keeping the current one if the file is invalid.
+    fn reload_config(&mut self) {
+        match config::load_config(&self.config_path) {
+            Ok(config) => {
+                self.config = config;
+                self.config_status = None;
+                self.background = None;
+                self.mask_path = self.config.mask_image.clone();
+                self.reload_mask();
+                let previous_extra_layers = self.take_extra_layers();
+                self.apply_layer_filter(&previous_extra_layers);
+            }
+            Err(err) => {
+                self.config_status = Some(format!(
+                    "bad config {}: {}",
+                    self.config_path.display(),
+                    err
+                ));
             }
+        }
+    }
 
-            if !improved {
-                step *= 0.5;
+    fn reload_layer_names(&mut self) {
+        match names::load_layer_names(&self.layer_names_path) {
+            Ok(names) => {
+                self.layer_names_status = (names.len() > 0)
+                    .then(|| format!("{} layers renamed", names.len()));
+                self.layer_names = names;
+            }
+            Err(err) => {
+                self.layer_names_status = Some(format!(
+                    "bad layer names {}: {}",
+                    self.layer_names_path.display(),
+                    err
+                ));
             }
         }
+    }
 
-        median
+    /// The kind of every layer, by index.
+    fn layer_kinds(&self) -> Vec<NodeKind> {
+        self.layers.iter().map(|layer| layer.kind).collect()
     }
 
-    fn run_k_median(&mut self) {
-        for _ in 0..self.k_median_max_iter {
-            // clear previous sets
-            for set in self.sets.iter_mut() {
-                set.clear();
+    /// The names of the collectible categories among the layers, in order.
+    fn collectible_categories(&self) -> Vec<String> {
+        let mut categories: Vec<String> = Vec::new();
+        for category in self.layers.iter().filter_map(|l| l.collectible.as_ref()) {
+            if !categories.contains(category) {
+                categories.push(category.clone());
             }
+        }
+        categories
+    }
 
-            // partition markers into disjoint sets based on the closest point to them
-            for (markeri, marker) in self.markers.iter().enumerate() {
-                let mut closest_distance = f32::MAX;
-                let mut closest_index = 0;
-                for (i, point) in self.points.iter().enumerate() {
-                    let factor = str_to_factor(marker.purity.as_str());
-                    let distance = (*point - pos2(marker.x, marker.y)).length() * factor;
-                    if distance < closest_distance {
-                        closest_distance = distance;
-                        closest_index = i;
-                    }
+    /// The warning to show if the map data is older than the config allows.
+    fn map_age_warning(&self) -> Option<String> {
+        let max_days = self.config.max_map_age_days;
+        if self.map_age_dismissed || max_days == 0 {
+            return None;
+        }
+
+        let date = map::oldest_build_date(&self.map_summaries)?;
+        ((chrono::Utc::now() - date).num_days() > max_days as i64).then(|| {
+            format!(
+                "map data from {} — node purities may have changed",
+                date.format("%Y-%m-%d")
+            )
+        })
+    }
+
+    /// The display name of every layer, by index.
+    fn layer_display_names(&self) -> Vec<&str> {
+        self.layers.iter().map(|layer| self.layer_names.name(layer)).collect()
+    }
+
+    /// The background image, uploaded to the GPU the first time it is needed
+    /// after the config was loaded.
+    fn background_texture(&mut self, ctx: &egui::Context) -> Option<egui::TextureHandle> {
+        let texture = self.background.get_or_insert_with(|| {
+            let path = &self.config.background_image;
+            match background::load_background(path) {
+                Ok(image) => {
+                    self.background_status = None;
+                    image.map(|image| {
+                        ctx.load_texture("background", image, egui::TextureOptions::LINEAR)
+                    })
+                }
+                Err(err) => {
+                    self.background_status =
+                        Some(format!("bad background image {}: {}", path.display(), err));
+                    None
                 }
-                self.sets[closest_index].push(markeri);
             }
+        });
+        texture.clone()
+    }
 
-            // calculate median for each set and adjust points accordingly
-            for (seti, set) in self.sets.iter().enumerate() {
-                let median = self.simulated_annealing(&set);
-                self.points[seti] = median;
+    /// Reads the mask at `mask_path` over the background's area, dropping
+    /// the previous one.
+    fn reload_mask(&mut self) {
+        self.mask = None;
+        self.mask_texture = None;
+        self.mask_status = None;
+        let Some(path) = &self.mask_path else {
+            return;
+        };
+        match mask::load_mask(path, self.config.background_bounds()) {
+            Ok(mask) => self.mask = Some(mask),
+            Err(err) => {
+                self.mask_status = Some(format!("bad mask image {}: {}", path.display(), err));
             }
+        }
+    }
 
-            // find new error values and abort if threshold reached
-            let total_error: f32 = self
-                .points
-                .iter()
-                .zip(self.sets.iter())
-                .map(|(point, set)| {
-                    let set_error: f32 = set
-                        .iter()
-                        .map(|i| {
-                            ((self.markers[*i].x - point.x).powi(2)
-                             + (self.markers[*i].y - point.y).powi(2))
-                                .sqrt()
-                        })
-                    .sum();
-                    set_error
-                })
-            .sum();
+    fn pick_mask_file(&mut self) {
+        let path = rfd::FileDialog::new().add_filter("PNG image", &["png"]).pick_file();
+        if path.is_some() {
+            self.mask_path = path;
+            self.reload_mask();
+        }
+    }
 
-            if total_error < self.best_so_far {
-                self.best_so_far = total_error;
-                self.best_so_far_points = self.points.clone();
+    /// Area in game coordinates random initial points are drawn from.
+    fn view_bounds(&self) -> Rect {
+        let bounds = self.config.apply_bounds(self.map_bounds);
+        // a single configured edge can still end up on the wrong side of the
+        // derived opposite one
+        if bounds.is_positive() {
+            bounds
+        } else {
+            self.map_bounds
+        }
+    }
+
+    /// The game coordinates shown in the map area, with the y axis flipped if
+    /// the config asks for it.
+    fn view_rect(&self) -> Rect {
+        let bounds = self.view_bounds();
+        if self.config.flip_y {
+            Rect::from_min_max(pos2(bounds.min.x, bounds.max.y), pos2(bounds.max.x, bounds.min.y))
+        } else {
+            bounds
+        }
+    }
+
+    fn pick_save_file(&mut self) {
+        let path = rfd::FileDialog::new()
+            .add_filter("Satisfactory save", &["sav"])
+            .pick_file();
+        if let Some(path) = path {
+            self.load_save(&path);
+        }
+    }
+
+    /// Takes the built miners and train stations from a save file, the miners
+    /// as markers of their own layer and the stations as initial points.
+    fn load_save(&mut self, path: &Path) {
+        let objects = match save::read_save(path) {
+            Ok(objects) => objects,
+            Err(err) => {
+                self.save_status = Some(format!("failed to read {}: {}", path.display(), err));
+                return;
             }
+        };
+
+        let map_markers = &self.markers[..self.map_marker_count];
+        self.save_markers = objects
+            .miners
+            .iter()
+            .map(|miner| miner.to_resource_marker(map_markers))
+            .collect();
+        self.seed_points = objects
+            .stations
+            .iter()
+            .map(|station| pos2(station.position[0], station.position[1]))
+            .collect();
+        self.save_status = Some(format!(
+            "found {} miners and {} train stations",
+            objects.miners.len(),
+            objects.stations.len()
+        ));
+
+        let previous = self.take_extra_layers();
+        self.append_extra_layers(&previous);
+    }
+
+    /// Re-reads the map files, keeping the current data if that fails.
+    fn reload_map(&mut self) -> bool {
+        if self.map_sources.is_empty() {
+            return false;
+        }
 
-            if (total_error - self.last_error).abs() < self.k_median_epsilon as f32 {
-                self.last_error =  total_error;
-                break;
+        self.map_mtime = sources_mtime(&self.map_sources);
+        match map::load_maps(&self.map_sources) {
+            Ok(data) => {
+                self.map_status = Some(format!("loaded {} files", data.sources.len()));
+                self.load_error = None;
+                self.replace_map(data);
+                true
             }
+            Err(err) => {
+                if self.load_error.is_some() {
+                    self.load_error = Some(err.to_string());
+                } else {
+                    self.map_status = Some(err.to_string());
+                }
+                false
+            }
+        }
+    }
 
-            self.last_error =  total_error;
+    fn pick_map_file(&mut self) {
+        let path = rfd::FileDialog::new()
+            .add_filter("map data", &["json", "gz"])
+            .pick_file();
+        if let Some(path) = path {
+            let previous = std::mem::replace(&mut self.map_sources, vec![MapSource::Path(path)]);
+            if !self.reload_map() && self.load_error.is_none() {
+                self.map_sources = previous;
+            }
         }
     }
-}
 
-fn str_to_factor(s: &str) -> f32 {
-    match s {
-        "impure" => 1.0,
-        "normal" => 2.0,
-        "pure" => 4.0,
-        _ => 1.0,
+    fn show_load_error(&mut self, ctx: &egui::Context, err: &str) {
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.heading("Could not load map data");
+            ui.label(err);
+            if !self.map_sources.is_empty() {
+                let sources: Vec<String> = self.map_sources.iter().map(|s| s.to_string()).collect();
+                ui.label(format!("Tried: {}", sources.join(", ")));
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("pick file…").clicked() {
+                    self.pick_map_file();
+                }
+                if ui.button("retry").clicked() {
+                    self.reload_map();
+                }
+            });
+        });
     }
-}
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let map_content = fs::read_to_string(Path::new("./assets/map_data.json"))?;
-    let map: Map = serde_json::from_str(&map_content)?;
+    fn poll_map_file(&mut self, ctx: &egui::Context) {
+        const POLL_INTERVAL: Duration = Duration::from_secs(1);
 
-    let (layers, markers) = map.extract_layers_and_markers();
-    eprintln!("layers = {:#?}", layers);
+        if !self.watch_map_file || self.map_sources.is_empty() {
+            return;
+        }
 
-    let native_options = eframe::NativeOptions::default();
-    eframe::run_native(
-        "Satisfactory station planner",
+        if self.last_watch_poll.elapsed() >= POLL_INTERVAL {
+            self.last_watch_poll = Instant::now();
+            let mtime = sources_mtime(&self.map_sources);
+            if mtime.is_some() && mtime != self.map_mtime {
+                self.reload_map();
+            }
+        }
+        ctx.request_repaint_after(POLL_INTERVAL);
+    }
+
+    fn start_download(&mut self) {
+        let (tx, rx) = mpsc::channel();
+        let url = self.map_url.clone();
+        thread::spawn(move || {
+            // the receiver is gone if the app was closed mid-download
+            let _ = tx.send(download::fetch(&url));
+        });
+        self.download = Some(rx);
+        self.map_status = Some(format!("downloading {}", self.map_url));
+    }
+
+    fn poll_download(&mut self, ctx: &egui::Context) {
+        let Some(rx) = &self.download else {
+            return;
+        };
+
+        match rx.try_recv() {
+            Ok(Ok(map)) => {
+                self.map_status = Some(if map.version == self.map_version {
+                    format!("map data is up to date (version {})", map.version)
+                } else {
+                    format!("updated map data to version {}", map.version)
+                });
+                self.replace_map(MapData::from_map(self.map_url.clone(), &map));
+                self.map_sources.clear();
+                self.download = None;
+            }
+            Ok(Err(err)) => {
+                self.map_status = Some(err.to_string());
+                self.download = None;
+            }
+            Err(TryRecvError::Empty) => ctx.request_repaint(),
+            Err(TryRecvError::Disconnected) => {
+                self.map_status = Some("download was aborted".to_string());
+                self.download = None;
+            }
+        }
+    }
+
+    fn reinitialize(&mut self) {
+        self.with_clustering(Clustering::reinitialize);
+        self.teleports.clear();
+    }
+
+    /// The markers and parameters as they are currently set up in the UI.
+    fn problem(&self) -> Problem<'_> {
+        Problem {
+            markers: &self.markers,
+            clustered: self.markers.iter().map(|m| self.is_clustered(m)).collect(),
+            weights: self
+                .markers
+                .iter()
+                .map(|m| self.params.marker_weight(m, &self.layers[m.layer].layerId))
+                .collect(),
+            params: &self.params,
+            bounds: self.params.region.unwrap_or_else(|| self.view_bounds()),
+            pinned: &self.pinned_points,
+            seeds: &self.seed_points,
+            seeds_fixed: self.seeds_fixed,
+            mask: self.mask.as_ref(),
+            network: self.network.as_ref(),
+        }
+    }
+
+    /// Recomputes the errors after the weights or the metric changed, so they
+    /// compare with those of the next runs.
+    fn reweigh(&mut self) {
+        self.rescore_best();
+        self.clustering.last_error = f32::MAX;
+    }
+
+    /// Recomputes the error and uncovered markers of the best points.
+    fn rescore_best(&mut self) {
+        let (uncovered, error) = if self.clustering.best_so_far_points.is_empty() {
+            (usize::MAX, f32::MAX)
+        } else {
+            self.problem().score(
+                &self.clustering.best_so_far_points,
+                &self.clustering.best_so_far_heights,
+            )
+        };
+        self.clustering.best_so_far_uncovered = uncovered;
+        self.clustering.best_so_far = error;
+    }
+
+    /// Drops the best so far once the stations, markers, weights or distances
+    /// differ from those it was found for, as its error no longer compares.
+    fn check_result_key(&mut self) {
+        let key = self.problem().result_key();
+        let Some(old) = self.result_key.replace(key) else {
+            return;
+        };
+        if old == key {
+            return;
+        }
+        let refit =
+            old.markers != key.markers && self.params.refit_on_change && !self.params.per_layer;
+        if !self.clustering.best_so_far_points.is_empty() {
+            // runs going on would bring the old best back
+            self.cancel_job();
+            if let Some(restarts) = self.restart_run.take() {
+                restarts.cancel();
+            }
+            self.restart_runs.clear();
+            if let Some(evolution) = self.evolution.take() {
+                evolution.cancel();
+            }
+            self.clear_best();
+            self.best_cleared = Some(format!(
+                "Best so far cleared as the {} changed",
+                old.changes(&key).join(", ")
+            ));
+        }
+        // the steps find a best for the new markers
+        if refit {
+            self.refit();
+        }
+    }
+
+    /// Takes the current setup as the one the best so far was found for.
+    fn adopt_result_key(&mut self) {
+        self.result_key = Some(self.problem().result_key());
+    }
+
+    fn clear_best(&mut self) {
+        self.clustering.best_so_far = f32::MAX;
+        self.clustering.best_so_far_uncovered = usize::MAX;
+        self.clustering.best_so_far_points.clear();
+        self.clustering.best_so_far_heights.clear();
+        self.clustering.best_so_far_solver = None;
+        self.best_cleared = None;
+    }
+
+    /// Rounds the stations, the best ones included, to the grid, leaving the
+    /// pinned ones in place, and recomputes their errors.
+    fn snap_points(&mut self) {
+        self.cancel_job();
+        let pinned = self.pinned_points.len();
+        for points in [&mut self.clustering.points, &mut self.clustering.best_so_far_points] {
+            for point in points.iter_mut().skip(pinned) {
+                *point = self.params.snap(*point);
+            }
+        }
+        let problem = self.problem();
+        let sets = problem.assign(&self.clustering.points, &self.clustering.heights);
+        let error = problem.total_error(&self.clustering.points, &self.clustering.heights);
+        self.clustering.sets = sets;
+        self.clustering.last_error = error;
+        self.rescore_best();
+        self.points_changed();
+    }
+
+    fn with_clustering(&mut self, f: impl FnOnce(&mut Clustering, &Problem)) {
+        let mut clustering = std::mem::take(&mut self.clustering);
+        f(&mut clustering, &self.problem());
+        self.clustering = clustering;
+    }
+
+    fn import_seeds(&mut self) {
+        match parse_points(&self.seed_text) {
+            Ok(points) => {
+                self.seed_status = Some(if points.len() > self.params.k {
+                    format!("imported {} stations, only the first k are used", points.len())
+                } else {
+                    format!("imported {} stations", points.len())
+                });
+                self.seed_points = points;
+            }
+            Err(err) => self.seed_status = Some(err),
+        }
+    }
+
+    /// Whether the marker's last verification is older than the configured
+    /// limits. Markers without a usable check date are never stale.
+    fn is_stale(&self, marker: &ResourceMarker) -> bool {
+        match marker.lastCheck {
+            LastCheck::Date(date) => {
+                self.ignore_older_than_days
+                    .is_some_and(|days| (chrono::Utc::now() - date).num_days() > days as i64)
+                    || self.ignore_before_date.is_some_and(|cutoff| date.date_naive() < cutoff)
+            }
+            LastCheck::Build(build) => self.ignore_before_build.is_some_and(|min| build < min),
+            LastCheck::Unknown(_) => false,
+        }
+    }
+
+    /// The number of stations, the algorithm and how it measures and moves them.
+    fn algorithm_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Controls");
+        ui.add_enabled_ui(!self.params.per_layer, |ui| {
+            ui.label("k");
+            ui.add(DragValue::new(&mut self.params.k).range(1..=50));
+        });
+        // the problem is only worth building with few markers
+        let clustered = self.markers.iter().filter(|m| self.is_clustered(m)).count();
+        if !self.params.per_layer && clustered < self.params.k {
+            let effective_k = self.problem().effective_k();
+            if effective_k < self.params.k {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!(
+                        "only {} markers to cluster, so {} of the {} stations stay unused",
+                        effective_k,
+                        self.params.k - effective_k,
+                        self.params.k
+                    ),
+                );
+            }
+        }
+        ui.checkbox(&mut self.params.per_layer, "cluster each layer on its own")
+            .on_hover_text("with the k set for every layer in the layer list");
+        if self.params.per_layer {
+            self.allocation_ui(ui);
+        }
+        ui.label("algorithm");
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_salt("algorithm")
+                .selected_text(self.params.algorithm.to_string())
+                .show_ui(ui, |ui| {
+                    for algorithm in Algorithm::ALL {
+                        ui.selectable_value(
+                            &mut self.params.algorithm,
+                            algorithm,
+                            algorithm.to_string(),
+                        );
+                    }
+                });
+            if self.params.algorithm == Algorithm::Hierarchical {
+                egui::ComboBox::from_id_salt("linkage")
+                    .selected_text(format!("{} linkage", self.params.linkage))
+                    .show_ui(ui, |ui| {
+                        for linkage in Linkage::ALL {
+                            ui.selectable_value(
+                                &mut self.params.linkage,
+                                linkage,
+                                linkage.to_string(),
+                            );
+                        }
+                    });
+            }
+        });
+        if self.params.algorithm == Algorithm::Dbscan {
+            ui.horizontal(|ui| {
+                ui.label("eps");
+                ui.add(
+                    DragValue::new(&mut self.params.dbscan_eps)
+                        .range(100.0..=500000.0)
+                        .speed(100.0),
+                );
+                ui.label("min points");
+                ui.add(DragValue::new(&mut self.params.dbscan_min_points).range(1..=100));
+            });
+        }
+        if let Some(status) = &self.algorithm_status {
+            ui.label(status);
+        }
+        if let Some(clusters) = self.dbscan_result() {
+            if ui
+                .button(format!("run k-median with k = {}", clusters))
+                .on_hover_text("as many stations as DBSCAN found groups")
+                .clicked()
+            {
+                self.params.algorithm = Algorithm::KMedian;
+                self.params.k = clusters;
+                self.start_job(true);
+            }
+        }
+        ui.label("max iterations");
+        ui.add(DragValue::new(&mut self.params.k_median_max_iter).range(1..=100));
+        ui.add_enabled_ui(self.params.median_solver.is_annealing(), |ui| {
+            ui.label("anneal step");
+            ui.add(
+                DragValue::new(&mut self.params.anneal_step_fraction)
+                    .range(0.0..=1.0)
+                    .speed(0.01),
+            )
+            .on_hover_text(
+                "share of the extent of a station's nodes the search starts with, \
+                 0 for the fixed step below",
+            );
+            ui.add_enabled(
+                self.params.anneal_step_fraction <= 0.0,
+                DragValue::new(&mut self.params.anneal_step).range(1.0..=100000.0),
+            );
+            ui.label("anneal epsilon");
+            ui.add(DragValue::new(&mut self.params.anneal_epsilon).range(0.1..=10000.0));
+            ui.label("anneal cooling");
+            ui.add(
+                DragValue::new(&mut self.params.anneal_cooling)
+                    .range(0.05..=0.95)
+                    .speed(0.01),
+            )
+            .on_hover_text("factor on the step once no move improves the error");
+            ui.label("random directions");
+            ui.add(DragValue::new(&mut self.params.anneal_random_directions).range(0..=64))
+                .on_hover_text("tried per step besides the eight compass directions");
+        });
+        ui.add_enabled_ui(self.params.median_solver == MedianSolver::SimulatedAnnealing, |ui| {
+            ui.label("anneal temperature");
+            ui.add(
+                DragValue::new(&mut self.params.anneal_temperature)
+                    .range(0.0..=1.0)
+                    .speed(0.001),
+            )
+            .on_hover_text(
+                "relative to the error at the start; worse moves are taken with \
+                 probability exp(-Δ/T)",
+            );
+            ui.label("temperature cooling");
+            ui.add(
+                DragValue::new(&mut self.params.anneal_temperature_cooling)
+                    .range(0.0..=1.0)
+                    .speed(0.01),
+            )
+            .on_hover_text("factor on the temperature whenever the step shrinks");
+        });
+        ui.label("k median epsilon");
+        ui.add(DragValue::new(&mut self.params.k_median_epsilon).range(0.1..=10000.0));
+        ui.label("initialization");
+        egui::ComboBox::from_id_salt("initialization")
+            .selected_text(self.params.initialization.to_string())
+            .show_ui(ui, |ui| {
+                for initialization in Initialization::ALL {
+                    ui.selectable_value(
+                        &mut self.params.initialization,
+                        initialization,
+                        initialization.to_string(),
+                    );
+                }
+            });
+        ui.label("objective");
+        let loss_before = self.params.loss;
+        egui::ComboBox::from_id_salt("loss")
+            .selected_text(self.params.loss.to_string())
+            .show_ui(ui, |ui| {
+                for loss in Loss::ALL {
+                    ui.selectable_value(&mut self.params.loss, loss, loss.to_string());
+                }
+            });
+        let k_means = self.params.loss == Loss::SquaredDistance;
+        if k_means {
+            // the centroid is only the best spot for Euclidean distances
+            self.params.metric = DistanceMetric::Euclidean;
+        }
+        ui.label("distance");
+        let metric_before = self.params.metric;
+        ui.add_enabled_ui(!k_means, |ui| {
+            egui::ComboBox::from_id_salt("metric")
+                .selected_text(self.params.metric.to_string())
+                .show_ui(ui, |ui| {
+                    for metric in DistanceMetric::ALL {
+                        ui.selectable_value(
+                            &mut self.params.metric,
+                            metric,
+                            metric.to_string(),
+                        );
+                    }
+                });
+        });
+        if self.params.metric != metric_before || self.params.loss != loss_before {
+            self.reweigh();
+        }
+        if self.params.metric == DistanceMetric::Network && self.network.is_none() {
+            ui.label("straight-line distances until a network is drawn or imported");
+        }
+        ui.label("median solver");
+        ui.add_enabled_ui(!k_means, |ui| {
+            egui::ComboBox::from_id_salt("median_solver")
+                .selected_text(self.params.median_solver.to_string())
+                .show_ui(ui, |ui| {
+                    for solver in MedianSolver::ALL {
+                        ui.selectable_value(
+                            &mut self.params.median_solver,
+                            solver,
+                            solver.to_string(),
+                        );
+                    }
+                });
+        })
+        .response
+        .on_disabled_hover_text("k-means moves stations to the centroid of their nodes");
+    }
+
+    /// Marker weights, miner rates, station limits and penalties. The markers are
+    /// reweighed when any of them changes.
+    fn rates_panel(&mut self, ui: &mut egui::Ui) {
+        let mut weights_changed = ui
+            .checkbox(&mut self.params.weight_by_rate, "weight by extraction rate")
+            .on_hover_text("items per minute of the miners below, also used in reports")
+            .changed();
+        ui.horizontal(|ui| {
+            let miner = &mut self.params.miner;
+            ui.label("miner mark");
+            weights_changed |= ui.add(DragValue::new(&mut miner.mark).range(1..=3)).changed();
+            ui.label("clock speed");
+            let mut percent = miner.clock_speed * 100.0;
+            if ui
+                .add(DragValue::new(&mut percent).range(1.0..=250.0).suffix("%"))
+                .changed()
+            {
+                miner.clock_speed = percent / 100.0;
+                weights_changed = true;
+            }
+            weights_changed |= ui
+                .checkbox(&mut miner.belt_capped, "belt cap")
+                .on_hover_text(format!("at most {} per minute", rates::BELT_LIMIT))
+                .changed();
+        });
+        ui.add_enabled_ui(!self.params.weight_by_rate, |ui| {
+            weights_changed |=
+                ui.checkbox(&mut self.params.weight_by_purity, "weight by purity").changed();
+        });
+        ui.add_enabled_ui(self.params.weight_by_purity && !self.params.weight_by_rate, |ui| {
+            ui.horizontal(|ui| {
+                let weights = &mut self.params.purity_weights;
+                for (label, weight) in [
+                    ("impure", &mut weights.impure),
+                    ("normal", &mut weights.normal),
+                    ("pure", &mut weights.pure),
+                ] {
+                    ui.label(label);
+                    weights_changed |= ui
+                        .add(DragValue::new(weight).range(0.0..=100.0).speed(0.1))
+                        .changed();
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            let before = self.params.capacity;
+            optional_drag_value(ui, &mut self.params.capacity, "station capacity", 10.0);
+            egui::ComboBox::from_id_salt("capacity_unit")
+                .selected_text(self.params.capacity_unit.to_string())
+                .show_ui(ui, |ui| {
+                    for unit in CapacityUnit::ALL {
+                        ui.selectable_value(
+                            &mut self.params.capacity_unit,
+                            unit,
+                            unit.to_string(),
+                        );
+                    }
+                });
+            weights_changed |= self.params.capacity != before;
+        });
+        ui.horizontal(|ui| {
+            let cap = &mut self.params.throughput_cap;
+            optional_drag_value(ui, cap, "throughput cap", rates::MK6_BELT_LIMIT);
+            ui.label("items/min");
+            if let Some(cap) = self.params.throughput_cap {
+                if ui
+                    .button("use as capacity")
+                    .on_hover_text("limit every station to the cap instead of flagging it")
+                    .clicked()
+                {
+                    self.params.capacity = Some(cap);
+                    self.params.capacity_unit = CapacityUnit::Rate;
+                    weights_changed = true;
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("balance");
+            weights_changed |= ui
+                .add(
+                    DragValue::new(&mut self.params.balance)
+                        .range(0.0..=1e9)
+                        .speed(100.0),
+                )
+                .on_hover_text(
+                    "factor on the variance of the weighted station sizes added to the error",
+                )
+                .changed();
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.params.fuzzy, "fuzzy assignment").on_hover_text(
+                "every node pulls on every station by its membership, closer ones more",
+            );
+            ui.add_enabled_ui(self.params.fuzzy, |ui| {
+                ui.label("fuzziness");
+                ui.add(
+                    DragValue::new(&mut self.params.fuzziness)
+                        .range(1.1..=5.0)
+                        .speed(0.05),
+                );
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.params.local_search, "local search").on_hover_text(
+                "after every run, try moving boundary nodes and the worst station",
+            );
+            ui.add_enabled_ui(self.params.local_search, |ui| {
+                ui.label("ratio");
+                ui.add(
+                    DragValue::new(&mut self.params.local_search_ratio)
+                        .range(1.0..=3.0)
+                        .speed(0.01),
+                )
+                .on_hover_text("nodes this many times farther from their second station");
+                ui.add(
+                    DragValue::new(&mut self.params.local_search_seconds)
+                        .range(0.1..=60.0)
+                        .speed(0.1)
+                        .suffix(" s"),
+                )
+                .on_hover_text("time limit");
+            });
+        });
+        ui.horizontal(|ui| {
+            let min_size = &mut self.params.min_cluster_size;
+            optional_drag_value(ui, min_size, "least per station", 2.0);
+            egui::ComboBox::from_id_salt("min_cluster_unit")
+                .selected_text(self.params.min_cluster_unit.to_string())
+                .show_ui(ui, |ui| {
+                    for unit in CapacityUnit::ALL {
+                        ui.selectable_value(
+                            &mut self.params.min_cluster_unit,
+                            unit,
+                            unit.to_string(),
+                        );
+                    }
+                });
+        });
+        ui.add_enabled_ui(self.params.min_cluster_size.is_some(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("smaller sets")
+                    .on_hover_text("dissolved after every run, their nodes going elsewhere");
+                egui::ComboBox::from_id_salt("small_clusters")
+                    .selected_text(self.params.small_clusters.to_string())
+                    .show_ui(ui, |ui| {
+                        for small in SmallClusters::ALL {
+                            ui.selectable_value(
+                                &mut self.params.small_clusters,
+                                small,
+                                small.to_string(),
+                            );
+                        }
+                    });
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.params.trim_outliers, "trim outliers").on_hover_text(
+                "nodes far out don't pull their station, but are still served and counted",
+            );
+            ui.add_enabled_ui(self.params.trim_outliers, |ui| {
+                ui.add(
+                    DragValue::new(&mut self.params.outlier_percentile)
+                        .range(50.0..=100.0)
+                        .suffix(" %"),
+                )
+                .on_hover_text("nodes farther than this percentile of their station");
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("mix penalty");
+            weights_changed |= ui
+                .add(
+                    DragValue::new(&mut self.params.mix_penalty)
+                        .range(0.0..=1e6)
+                        .speed(10.0),
+                )
+                .on_hover_text(
+                    "factor on the node weight of every station times its resources beyond \
+                     the first, added to the error",
+                )
+                .changed();
+        });
+        ui.horizontal(|ui| {
+            ui.label("rail weight");
+            weights_changed |= ui
+                .add(
+                    DragValue::new(&mut self.params.rail_weight)
+                        .range(0.0..=1000.0)
+                        .speed(0.1),
+                )
+                .on_hover_text(
+                    "factor on the length of the rail network connecting the stations, \
+                     added to the error",
+                )
+                .changed();
+        });
+        let radius_before = self.params.max_radius;
+        optional_drag_value(ui, &mut self.params.max_radius, "max service radius", 75000.0);
+        weights_changed |= self.params.max_radius != radius_before;
+        let mut snap_changed = ui
+            .checkbox(&mut self.params.snap_to_grid, "snap to foundation grid")
+            .on_hover_text("round stations to the 8 m world grid")
+            .changed();
+        ui.add_enabled_ui(self.params.snap_to_grid, |ui| {
+            let before = self.params.grid_size;
+            optional_drag_value(ui, &mut self.params.grid_size, "other grid size", 100.0);
+            snap_changed |= self.params.grid_size != before;
+        });
+        if snap_changed && self.params.snap_to_grid {
+            self.snap_points();
+        }
+        if let Some(capacity) = self.params.capacity {
+            let problem = self.problem();
+            let available = capacity * self.params.k as f32;
+            let demand = problem.total_demand();
+            if available < demand {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!(
+                        "{} stations hold {:.0}, less than the {:.0} to serve",
+                        self.params.k, available, demand
+                    ),
+                );
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label("obstructed markers");
+            let before = self.params.obstructed;
+            egui::ComboBox::from_id_salt("obstructed")
+                .selected_text(self.params.obstructed.to_string())
+                .show_ui(ui, |ui| {
+                    for obstructed in Obstructed::ALL {
+                        ui.selectable_value(
+                            &mut self.params.obstructed,
+                            obstructed,
+                            obstructed.to_string(),
+                        );
+                    }
+                });
+            weights_changed |= self.params.obstructed != before;
+            if self.params.obstructed == Obstructed::DownWeight {
+                weights_changed |= ui
+                    .add(
+                        DragValue::new(&mut self.params.obstructed_weight)
+                            .range(0.0..=1.0)
+                            .speed(0.01),
+                    )
+                    .changed();
+            }
+        });
+        ui.horizontal(|ui| {
+            weights_changed |= ui.checkbox(&mut self.params.three_d, "3D distances").changed();
+            ui.add_enabled_ui(self.params.three_d, |ui| {
+                ui.label("height weight");
+                weights_changed |= ui
+                    .add(DragValue::new(&mut self.params.z_weight).range(0.0..=20.0).speed(0.1))
+                    .changed();
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("climb cost");
+            weights_changed |= ui
+                .add(
+                    DragValue::new(&mut self.params.climb_cost)
+                        .range(0.0..=100.0)
+                        .speed(0.1),
+                )
+                .on_hover_text("added per unit of height between a node and its station")
+                .changed();
+        });
+        if weights_changed {
+            self.reweigh();
+        }
+    }
+
+    /// Which markers count as too old to trust.
+    fn staleness_panel(&mut self, ui: &mut egui::Ui) {
+        optional_drag_value(
+            ui,
+            &mut self.ignore_older_than_days,
+            "ignore markers older than (days)",
+            365,
+        );
+        optional_drag_value(
+            ui,
+            &mut self.ignore_before_build,
+            "ignore markers checked before build",
+            0,
+        );
+        self.ignore_before_date_control(ui);
+    }
+
+    /// Running, stepping and restarting the clustering, plus the display toggles.
+    fn run_panel(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("reset and run").clicked() {
+                if self.params.per_layer {
+                    self.run_per_layer();
+                } else if self.params.algorithm == Algorithm::Hierarchical {
+                    self.run_hierarchical();
+                } else if self.params.algorithm == Algorithm::Dbscan {
+                    self.run_dbscan();
+                } else if self.params.algorithm == Algorithm::Exact {
+                    self.run_placement(exact::exact);
+                } else {
+                    self.start_job(true);
+                }
+            }
+            let running = self.job.is_some();
+            if ui.add_enabled(running, egui::Button::new("stop")).clicked() {
+                self.cancel_job();
+            }
+            if running {
+                ui.spinner();
+            }
+        });
+
+        if ui.button("reset").clicked() {
+            self.cancel_job();
+            self.reinitialize();
+        }
+
+        if ui.button("reset to defaults").clicked() {
+            self.reset_session(ui.ctx());
+        }
+
+        if ui.add_enabled(!self.params.per_layer, egui::Button::new("step once")).clicked() {
+            self.start_step();
+        }
+
+        let continuable = !self.params.per_layer && self.params.algorithm == Algorithm::KMedian;
+        if ui
+            .add_enabled(continuable, egui::Button::new("continue from best"))
+            .on_hover_text("run again from the best points, with the current parameters")
+            .clicked()
+        {
+            self.continue_from_best();
+        }
+        if let Some(refused) = &self.continue_refused {
+            ui.colored_label(ui.visuals().warn_fg_color, refused);
+        }
+        ui.horizontal(|ui| {
+            let refittable = !self.params.per_layer && !self.clustering.points.is_empty();
+            if ui
+                .add_enabled(refittable, egui::Button::new("refit"))
+                .on_hover_text("assign the nodes again and take a few steps from the stations")
+                .clicked()
+            {
+                self.refit();
+            }
+            ui.checkbox(&mut self.params.refit_on_change, "when the nodes change");
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("restarts");
+            ui.add(DragValue::new(&mut self.restarts).range(1..=10000));
+        });
+        ui.horizontal(|ui| {
+            let mut fixed = self.params.seed.is_some();
+            if ui
+                .checkbox(&mut fixed, "fixed seed")
+                .on_hover_text("runs, restarts and sweeps give the same results every time")
+                .changed()
+            {
+                self.params.seed = fixed.then_some(0);
+            }
+            if let Some(seed) = &mut self.params.seed {
+                ui.add(DragValue::new(seed));
+            }
+        });
+        let restarting = self.restart_run.is_some();
+        ui.horizontal(|ui| {
+            let run = egui::Button::new(format!("run {} restarts", self.restarts));
+            if ui.add_enabled(!restarting, run).clicked() {
+                self.start_restarts();
+            }
+            if ui.add_enabled(restarting, egui::Button::new("cancel")).clicked() {
+                if let Some(restarts) = &self.restart_run {
+                    restarts.cancel();
+                }
+            }
+        });
+        if let Some(restarts) = &self.restart_run {
+            ui.add(egui::ProgressBar::new(restarts.progress()).show_percentage());
+        }
+        if let Some(summary) = restarts::summarize(&self.restart_runs) {
+            ui.label(format!(
+                "{} runs: error min {:.1}, median {:.1}, mean {:.1}, max {:.1}",
+                summary.runs, summary.min, summary.median, summary.mean, summary.max
+            ));
+            self.run_histogram(ui, &summary);
+            ui.checkbox(&mut self.show_stability, "show station stability").on_hover_text(
+                "halos as wide as the mean distance to the closest station in the best \
+                 quarter of the other runs",
+            );
+            ui.text_edit_singleline(&mut self.runs_csv_path);
+            if ui.button("export runs as CSV").clicked() {
+                self.export_runs();
+            }
+            ui.text_edit_singleline(&mut self.stability_csv_path);
+            if ui.button("export stability as CSV").clicked() {
+                self.export_stability();
+            }
+            if let Some(status) = &self.restart_status {
+                ui.label(status);
+            }
+        }
+        ui.collapsing("Evolution", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("population");
+                ui.add(
+                    DragValue::new(&mut self.params.evolution_population).range(3..=500),
+                );
+                ui.label("generations");
+                ui.add(
+                    DragValue::new(&mut self.params.evolution_generations).range(1..=1000),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("mutation");
+                ui.add(
+                    DragValue::new(&mut self.params.evolution_mutation)
+                        .range(0.0..=0.5)
+                        .speed(0.001),
+                )
+                .on_hover_text("farthest a station is jittered, as a share of the map");
+            });
+            let evolving = self.evolution.is_some();
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!evolving, egui::Button::new("evolve")).clicked() {
+                    self.evolution = Some(Evolution::start(self.problem_input()));
+                }
+                if ui.add_enabled(evolving, egui::Button::new("cancel")).clicked() {
+                    if let Some(evolution) = &self.evolution {
+                        evolution.cancel();
+                    }
+                }
+            });
+            if let Some(evolution) = &self.evolution {
+                ui.add(egui::ProgressBar::new(evolution.progress()).show_percentage());
+                if let Some(generation) = &evolution.latest {
+                    ui.label(format!(
+                        "Generation {}: best error {}",
+                        generation.index, generation.best.error
+                    ));
+                }
+            }
+        });
+
+        ui.checkbox(&mut self.run_continuously, "run continuously");
+        ui.checkbox(&mut self.show_cluster_rings, "show cluster membership");
+        ui.checkbox(&mut self.collapse_wells, "treat resource wells as one point");
+        if ui
+            .checkbox(&mut self.exclude_fluids, "leave out liquids and wells (piped)")
+            .changed()
+        {
+            self.clustering.sets.clear();
+        }
+        ui.add(
+            egui::Slider::new(&mut self.background_opacity, 0.0..=1.0)
+                .text("background opacity"),
+        );
+        if let Some(status) = &self.background_status {
+            ui.label(status);
+        }
+        ui.horizontal(|ui| {
+            if ui.button("load mask…").clicked() {
+                self.pick_mask_file();
+            }
+            if self.mask.is_some() {
+                ui.checkbox(&mut self.show_mask, "show mask");
+            }
+        });
+        if self.mask.is_some() {
+            ui.checkbox(&mut self.params.buildable_only, "keep stations on buildable cells");
+        }
+        if let Some(status) = &self.mask_status {
+            ui.label(status);
+        }
+        if self.run_continuously && self.job.is_none() && !self.params.per_layer {
+            self.start_job(true);
+        }
+    }
+
+    /// Figures on the map data, the current stations and the markers left out.
+    fn information_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Information");
+        let measure = match self.params.loss {
+            Loss::Distance => "distances",
+            Loss::SquaredDistance => "squared distances (k-means)",
+        };
+        if self.params.weight_by_rate {
+            ui.label(format!("errors are {} weighted by extraction rate", measure));
+        } else if self.params.weight_by_purity {
+            ui.label(format!("errors are {} weighted by purity", measure));
+        } else {
+            ui.label(format!("errors are plain {}", measure));
+        }
+        let last_builds: Vec<&str> = self
+            .map_summaries
+            .iter()
+            .map(|s| s.last_build.as_str())
+            .filter(|b| !b.is_empty())
+            .collect();
+        let last_build = if last_builds.is_empty() {
+            "unknown".to_string()
+        } else {
+            last_builds.join(", ")
+        };
+        ui.label(format!("Map data version {}, last build {}", self.map_version, last_build));
+        if let Some(date) = map::oldest_build_date(&self.map_summaries) {
+            ui.label(format!("Map data from {}", date.format("%Y-%m-%d")));
+        }
+        ui.label(format!(
+            "Last run total error ({}): {}",
+            self.params.loss, self.clustering.last_error
+        ));
+        if self.clustering.iterations > 0 {
+            let iterations = self.clustering.iterations;
+            match self.clustering.convergence {
+                Some(convergence) => {
+                    ui.label(format!("{} after {} iterations", convergence, iterations))
+                }
+                None => ui.label(format!("Iteration {}", iterations)),
+            };
+            let reassignments = &self.clustering.reassignments;
+            if !reassignments.is_empty() {
+                // the latest ones, as long runs would fill the panel
+                let shown = &reassignments[reassignments.len().saturating_sub(10)..];
+                let counts: Vec<String> = shown.iter().map(usize::to_string).collect();
+                ui.label(format!("Markers reassigned per iteration: {}", counts.join(", ")))
+                    .on_hover_text("assignments are stable once this reaches 0");
+            }
+        }
+        if self.params.max_radius.is_some() && !self.clustering.sets.is_empty() {
+            let problem = self.problem();
+            let uncovered = problem.uncovered(&self.clustering.sets);
+            let assigned: usize = self.clustering.sets.iter().map(Vec::len).sum();
+            let share = 100.0 * uncovered as f32 / (uncovered + assigned).max(1) as f32;
+            ui.label(format!("Uncovered markers: {} ({:.1}%)", uncovered, share));
+            ui.label("errors only count the markers in range of a station");
+        }
+        if self.params.rail_weight > 0.0 && !self.clustering.points.is_empty() {
+            let length = cluster::rail_length(&self.clustering.points);
+            ui.label(format!(
+                "Rail network: {:.0}, adding {:.0} to the error",
+                length,
+                self.params.rail_weight * length
+            ));
+        }
+        if let Some(groups) = self.dbscan_result() {
+            let noise = self.problem().uncovered(&self.clustering.sets);
+            ui.label(format!("DBSCAN: {} groups, {} markers noise", groups, noise));
+        }
+        if self.params.capacity.is_some() && !self.clustering.points.is_empty() {
+            let free = self
+                .problem()
+                .uncapacitated_error(&self.clustering.points, &self.clustering.heights);
+            ui.label(format!("Without the capacity: {}", free));
+        }
+        if self.params.balance > 0.0 && !self.clustering.sets.is_empty() {
+            let imbalance = self.problem().imbalance(&self.clustering.sets);
+            ui.label(format!(
+                "Size variance: {:.1}, adding {:.0} to the error",
+                imbalance,
+                self.params.balance * imbalance
+            ));
+        }
+        if !self.clustering.sets.is_empty() {
+            ui.collapsing("Station sizes", |ui| self.size_chart(ui));
+        }
+        if !self.clustering.set_errors.is_empty() {
+            ui.collapsing("Errors by station", |ui| self.station_error_list(ui));
+        }
+        if let Some(runs) = self.layer_runs() {
+            for run in runs {
+                ui.label(format!(
+                    "  {}: {} stations, error {:.0}",
+                    self.layer_names.name(&self.layers[run.layer]),
+                    run.k,
+                    run.error
+                ));
+            }
+        }
+        let quality = self
+            .quality
+            .as_ref()
+            .filter(|(points, _)| *points == self.clustering.points)
+            .map(|(_, quality)| *quality);
+        match quality {
+            Some(quality) => {
+                let optional = |value: Option<f32>| match value {
+                    Some(value) => format!("{:.3}", value),
+                    None => "n/a".to_string(),
+                };
+                ui.label(format!("Silhouette: {}", optional(quality.silhouette)))
+                    .on_hover_text(format!(
+                        "averaged over at most {} markers",
+                        quality::SILHOUETTE_SAMPLE
+                    ));
+                ui.label(format!("Max cluster radius: {:.0}", quality.max_radius));
+                ui.label(format!("Davies–Bouldin index: {}", optional(quality.davies_bouldin)));
+            }
+            None => {
+                if ui.button("compute quality metrics").clicked() {
+                    let problem = self.problem();
+                    let metrics = quality::quality(
+                        &problem,
+                        &self.clustering.points,
+                        &self.clustering.heights,
+                        &self.clustering.sets,
+                    );
+                    self.quality = Some((self.clustering.points.clone(), metrics));
+                }
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label(format!("Best so far: {}", self.clustering.best_so_far));
+            if ui.button("clear best").clicked() {
+                self.clear_best();
+            }
+        });
+        if let Some(cleared) = &self.best_cleared {
+            ui.label(cleared);
+        }
+        if let Some(solver) = self.clustering.best_so_far_solver {
+            ui.label(format!("Best found with: {}", solver));
+        }
+        if self.clustering.optimal {
+            ui.label("Provably optimal with the stations on nodes");
+        }
+        if let Some(gain) = self.clustering.local_search_gain {
+            ui.label(format!("Local search lowered the error by {:.0}", gain));
+        }
+        if self.clustering.median_moves > 0 {
+            ui.label(format!(
+                "Annealing moves this run: {}",
+                self.clustering.median_moves
+            ));
+        }
+        if self.clustering.capped_medians > 0 {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!(
+                    "{} median searches stopped at the move cap",
+                    self.clustering.capped_medians
+                ),
+            );
+        }
+        if let Some(dissolved) = self.clustering.dissolved {
+            ui.label(format!(
+                "Dissolved {} small sets, {} stations left",
+                dissolved,
+                self.clustering.best_so_far_points.len()
+            ));
+        }
+        if let Some(seed) = self.clustering.seed {
+            ui.horizontal(|ui| {
+                ui.label(format!("Seed: {}", seed));
+                if ui.button("copy").clicked() {
+                    ui.ctx().copy_text(seed.to_string());
+                }
+                if ui.button("use as fixed seed").clicked() {
+                    self.params.seed = Some(seed);
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label(format!("Best so far points: {}", self.clustering.best_so_far_points.len()));
+            if ui.button("copy results").clicked() {
+                let heights = self
+                    .params
+                    .three_d
+                    .then_some(self.clustering.best_so_far_heights.as_slice());
+                ui.ctx().copy_text(export::points_summary(
+                    self.clustering.best_so_far,
+                    &self.clustering.best_so_far_points,
+                    heights,
+                ));
+            }
+        });
+        let kept = self
+            .unfiltered_markers
+            .iter()
+            .filter(|m| !self.filtered_layers[m.layer])
+            .count();
+        ui.label(format!(
+            "Map markers after layer filter: {} of {}",
+            kept,
+            self.unfiltered_markers.len()
+        ));
+        let stale = self.markers.iter().filter(|m| self.is_stale(m)).count();
+        let unknown = self
+            .markers
+            .iter()
+            .filter(|m| matches!(m.lastCheck, LastCheck::Unknown(_)))
+            .count();
+        ui.label(format!("Stale markers excluded: {}", stale));
+        let excluded = self
+            .markers
+            .iter()
+            .filter(|m| self.excluded.contains(&m.pathName))
+            .count();
+        ui.label(format!("Markers excluded by list: {}", excluded));
+        if unknown > 0 {
+            ui.label(format!("Markers with unknown check date: {}", unknown));
+        }
+        let mut obstructed = vec![0; self.layers.len()];
+        for marker in self.markers.iter().filter(|m| m.obstructed == Some(true)) {
+            obstructed[marker.layer] += 1;
+        }
+        let total: usize = obstructed.iter().sum();
+        if total > 0 {
+            egui::CollapsingHeader::new(format!("Obstructed markers: {}", total))
+                .id_salt("obstructed_markers")
+                .show(ui, |ui| {
+                    for (layer, count) in self.layers.iter().zip(&obstructed) {
+                        if *count > 0 {
+                            ui.label(format!("{}: {}", self.layer_names.name(layer), count));
+                        }
+                    }
+                });
+        }
+    }
+
+    /// Sweeps over k and the gap statistic.
+    fn sweep_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Sweep");
+        ui.horizontal(|ui| {
+            ui.label("k from");
+            ui.add(DragValue::new(&mut self.sweep_from).range(1..=50));
+            ui.label("to");
+            ui.add(DragValue::new(&mut self.sweep_to).range(1..=50));
+        });
+        ui.horizontal(|ui| {
+            ui.label("restarts");
+            ui.add(DragValue::new(&mut self.sweep_restarts).range(1..=1000));
+        });
+        ui.horizontal(|ui| {
+            ui.label("station cost");
+            ui.add(
+                DragValue::new(&mut self.station_cost)
+                    .range(0.0..=1e9)
+                    .speed(100.0),
+            )
+            .on_hover_text(
+                "added to the error for every station, the sweep then picks the cheapest k",
+            );
+        });
+        let running = self.sweep.as_ref().is_some_and(|sweep| sweep.running);
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!running, egui::Button::new("run sweep")).clicked() {
+                self.start_sweep();
+            }
+            if ui.add_enabled(running, egui::Button::new("cancel")).clicked() {
+                if let Some(sweep) = &self.sweep {
+                    sweep.cancel();
+                }
+            }
+        });
+        let mut load = None;
+        if let Some(sweep) = &self.sweep {
+            if sweep.running {
+                ui.add(egui::ProgressBar::new(sweep.progress()).show_percentage());
+            }
+            let costed = self.station_cost > 0.0;
+            let cheapest = sweep::cheapest(&sweep.rows, self.station_cost).filter(|_| costed);
+            egui::Grid::new("sweep_results").striped(true).show(ui, |ui| {
+                ui.label("k");
+                ui.label("best error");
+                ui.label("max radius");
+                if costed {
+                    ui.label("with stations");
+                }
+                ui.end_row();
+                for row in &sweep.rows {
+                    let k = egui::RichText::new(row.k.to_string());
+                    ui.label(if Some(row.k) == cheapest { k.strong() } else { k });
+                    ui.label(format!("{:.0}", row.best_error));
+                    ui.label(format!("{:.0}", row.max_radius));
+                    if costed {
+                        ui.label(format!("{:.0}", row.combined(self.station_cost)));
+                    }
+                    ui.end_row();
+                }
+            });
+            if let Some(k) = cheapest {
+                if ui.button(format!("load the cheapest, k = {}", k)).clicked() {
+                    load = sweep.rows.iter().find(|row| row.k == k).cloned();
+                }
+            }
+        }
+        if let Some(row) = self.sweep_plot(ui).or(load) {
+            self.load_sweep_row(row);
+        }
+        ui.text_edit_singleline(&mut self.sweep_csv_path);
+        if ui.button("export sweep as CSV").clicked() {
+            self.export_sweep();
+        }
+        if let Some(status) = &self.sweep_status {
+            ui.label(status);
+        }
+
+        ui.label("gap statistic").on_hover_text(
+            "compares the error with that on nodes spread uniformly over their bounding box",
+        );
+        ui.horizontal(|ui| {
+            ui.label("reference sets");
+            ui.add(DragValue::new(&mut self.gap_references).range(1..=100));
+        });
+        let gap_running = self.gap.as_ref().is_some_and(|gap| gap.running);
+        ui.horizontal(|ui| {
+            let button = egui::Button::new("run gap statistic");
+            if ui.add_enabled(!gap_running, button).clicked() {
+                self.start_gap();
+            }
+            if ui.add_enabled(gap_running, egui::Button::new("cancel")).clicked() {
+                if let Some(gap) = &self.gap {
+                    gap.cancel();
+                }
+            }
+        });
+        if let Some(gap) = &self.gap {
+            if gap.running {
+                ui.add(egui::ProgressBar::new(gap.progress()).show_percentage());
+            }
+        }
+        self.gap_plot(ui);
+        let suggested = self.gap.as_ref().and_then(|gap| gap::suggest(&gap.rows));
+        if let Some(k) = suggested {
+            if ui
+                .button(format!("run k-median with k = {}", k))
+                .on_hover_text("the smallest k whose gap is within the spread of the next")
+                .clicked()
+            {
+                self.params.algorithm = Algorithm::KMedian;
+                self.params.k = k;
+                self.start_job(true);
+            }
+        }
+    }
+
+    /// Console commands to visit the stations.
+    fn teleports_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Teleports");
+        if !self.teleports.is_empty() && ui.button("copy all").clicked() {
+            ui.ctx().copy_text(self.teleports.join("\n"));
+        }
+        for (i, command) in self.teleports.iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui.button("copy").clicked() {
+                    ui.ctx().copy_text(command.clone());
+                }
+                ui.label(format!("{}: {}", i, command));
+            });
+        }
+    }
+
+    /// Every export with its path.
+    fn export_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Export");
+        ui.text_edit_singleline(&mut self.export_path);
+        ui.horizontal(|ui| {
+            if ui.button("export stations").clicked() {
+                self.export_stations();
+            }
+            if ui.button("import stations").clicked() {
+                self.import_stations();
+            }
+        });
+        ui.text_edit_singleline(&mut self.csv_path);
+        if ui.button("export assignments as CSV").clicked() {
+            self.export_assignments();
+        }
+        ui.text_edit_singleline(&mut self.station_csv_path);
+        if ui.button("export errors by station as CSV").clicked() {
+            self.export_station_errors();
+        }
+        ui.text_edit_singleline(&mut self.svg_path);
+        if ui.button("export SVG").clicked() {
+            self.export_svg();
+        }
+        ui.text_edit_singleline(&mut self.overlay_path);
+        ui.horizontal(|ui| {
+            if ui.button("export map layer").clicked() {
+                self.export_overlay();
+            }
+            if ui.button("import as existing stations").clicked() {
+                self.import_overlay();
+            }
+        });
+        ui.text_edit_singleline(&mut self.report_path);
+        if ui.button("export report").clicked() {
+            self.export_report();
+        }
+        ui.text_edit_singleline(&mut self.planner_path);
+        ui.label("with the miners set under \"weight by extraction rate\"");
+        if ui.button("export resources for planners").clicked() {
+            self.export_resource_totals();
+        }
+        ui.horizontal(|ui| {
+            ui.label("image size");
+            ui.add(DragValue::new(&mut self.image_size).range(256..=8192));
+        });
+        ui.checkbox(&mut self.image_assignment_lines, "draw assignment lines");
+        if ui.button("export image").clicked() {
+            self.export_image();
+        }
+        if let Some(status) = &self.export_status {
+            ui.label(status);
+        }
+    }
+
+    /// Layer weights and the collectibles shown on the map.
+    fn layers_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Layers");
+        ui.label("a layer weighing 0 is ignored by the clustering");
+        let mut layer_weights_changed = false;
+        let mut layers_changed = false;
+        egui::ScrollArea::vertical()
+            .id_salt("layers")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for kind in NodeKind::ALL {
+                    let indices: Vec<usize> = (0..self.layers.len())
+                        .filter(|i| self.layers[*i].kind == kind)
+                        .collect();
+                    if indices.is_empty() {
+                        continue;
+                    }
+
+                    let mut all = indices.iter().all(|i| self.included_layers[*i]);
+                    let heading = egui::RichText::new(kind.heading()).strong();
+                    if ui.checkbox(&mut all, heading).changed() {
+                        for i in &indices {
+                            self.included_layers[*i] = all;
+                        }
+                        layers_changed = true;
+                    }
+                    ui.indent(kind.heading(), |ui| {
+                        for i in indices {
+                            let layer = &self.layers[i];
+                            let name = self.layer_names.name(layer);
+                            let included = &mut self.included_layers[i];
+                            if self.filtered_layers.get(i).copied().unwrap_or(false) {
+                                let text = format!("{} (left out by the config)", name);
+                                ui.add_enabled(false, egui::Checkbox::new(included, text));
+                                continue;
+                            }
+
+                            ui.horizontal(|ui| {
+                                layers_changed |= ui.checkbox(included, name).changed();
+                                let mut weight = self.params.layer_weight(&layer.layerId);
+                                let slider = egui::Slider::new(&mut weight, 0.0..=5.0)
+                                    .text("weight");
+                                if ui.add(slider).changed() {
+                                    self.params.set_layer_weight(&layer.layerId, weight);
+                                    layer_weights_changed = true;
+                                }
+                                if self.params.per_layer {
+                                    let mut k = self.params.layer_k(&layer.layerId);
+                                    ui.label("k");
+                                    if ui.add(DragValue::new(&mut k).range(0..=50)).changed() {
+                                        self.params.set_layer_k(&layer.layerId, k);
+                                    }
+                                }
+                            });
+                        }
+                    });
+                }
+            });
+        if layer_weights_changed {
+            self.reweigh();
+        }
+        if layers_changed && self.params.per_layer && self.params.auto_layer_ks {
+            self.allocate_layer_ks();
+        }
+        let categories = self.collectible_categories();
+        if !categories.is_empty() {
+            ui.label("collectibles are only shown, tick a category to cluster it too");
+            for category in categories {
+                let mut clustered = self.clustered_collectibles.contains(&category);
+                if ui.checkbox(&mut clustered, format!("cluster {}", category)).changed() {
+                    if clustered {
+                        self.clustered_collectibles.insert(category);
+                    } else {
+                        self.clustered_collectibles.remove(&category);
+                    }
+                    self.clustering.sets.clear();
+                }
+            }
+        }
+    }
+
+    /// Stations already built and the ones pinned in place.
+    fn existing_stations_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Existing stations");
+        ui.label("one \"x, y\" per line, or a JSON list of points");
+        ui.add(egui::TextEdit::multiline(&mut self.seed_text).desired_rows(3));
+        ui.horizontal(|ui| {
+            if ui.button("use as initial points").clicked() {
+                self.import_seeds();
+            }
+            if ui.button("clear").clicked() {
+                self.seed_points.clear();
+                self.seed_status = None;
+            }
+        });
+        ui.checkbox(&mut self.seeds_fixed, "keep existing stations in place");
+        if let Some(status) = &self.seed_status {
+            ui.label(status);
+        }
+
+        ui.label("pinned stations stay in place, click a station on the map to pin it");
+        let mut toggled = None;
+        egui::ScrollArea::vertical()
+            .id_salt("pinned_stations")
+            .max_height(150.0)
+            .show(ui, |ui| {
+                for (i, point) in self.clustering.points.iter().enumerate() {
+                    let mut pinned = i < self.pinned_points.len();
+                    let text = format!("station {} at {:.0}, {:.0}", i, point.x, point.y);
+                    if ui.checkbox(&mut pinned, text).changed() {
+                        toggled = Some(i);
+                    }
+                }
+            });
+        if let Some(i) = toggled {
+            self.toggle_pin(i);
+        }
+    }
+
+    /// The rectangle the clustering is limited to.
+    fn region_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Region");
+        if let Some(mut region) = self.params.region {
+            let inside = self.markers.iter().filter(|m| self.is_clustered(m)).count();
+            ui.label(format!("clustering the {} markers inside", inside));
+            let mut changed = false;
+            egui::Grid::new("region").show(ui, |ui| {
+                ui.label("from");
+                changed |= ui.add(DragValue::new(&mut region.min.x).speed(100.0)).changed();
+                changed |= ui.add(DragValue::new(&mut region.min.y).speed(100.0)).changed();
+                ui.end_row();
+                ui.label("to");
+                changed |= ui.add(DragValue::new(&mut region.max.x).speed(100.0)).changed();
+                changed |= ui.add(DragValue::new(&mut region.max.y).speed(100.0)).changed();
+                ui.end_row();
+            });
+            let clear = ui.button("clear").clicked();
+            if clear {
+                self.params.region = None;
+            } else if changed {
+                self.params.region = Some(Rect::from_two_pos(region.min, region.max));
+            }
+            if clear || changed {
+                self.points_changed();
+            }
+        } else {
+            ui.label("all markers, shift-drag on the map to cluster only those in an area");
+        }
+    }
+
+    /// Areas where no station may stand.
+    fn zones_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("No-station zones");
+        ui.checkbox(&mut self.drawing_zones, "draw zones by dragging on the map");
+        let mut removed = None;
+        for (i, zone) in self.params.no_station_zones.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{:.0}, {:.0} to {:.0}, {:.0}",
+                    zone.min.x, zone.min.y, zone.max.x, zone.max.y
+                ));
+                if ui.small_button("delete").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            self.params.no_station_zones.remove(i);
+        }
+    }
+
+    /// The drawn network used by the network distance.
+    fn network_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Network");
+        if ui
+            .checkbox(&mut self.drawing_network, "draw lines by clicking on the map")
+            .on_hover_text("points within a few pixels of a vertex join it")
+            .changed()
+        {
+            self.network_draft.clear();
+        }
+        if !self.network_draft.is_empty() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} points", self.network_draft.len()));
+                if ui.button("finish line").on_hover_text("or double click").clicked() {
+                    self.finish_network_line();
+                }
+                if ui.button("discard").clicked() {
+                    self.network_draft.clear();
+                }
+            });
+        }
+        ui.horizontal(|ui| {
+            if ui.button("import lines…").clicked() {
+                self.pick_network_file();
+            }
+            if ui.button("clear").clicked() {
+                self.params.network_lines.clear();
+            }
+        });
+        if let Some(network) = &self.network {
+            ui.label(format!(
+                "{} lines, {} vertices",
+                network.lines().len(),
+                network.vertex_count()
+            ));
+            ui.checkbox(&mut self.show_route, "show the route of the clicked node");
+        }
+        if let Some(status) = &self.network_status {
+            ui.label(status);
+        }
+    }
+
+    /// Markers left out by trimming, if it is enabled.
+    fn outliers_panel(&mut self, ui: &mut egui::Ui) {
+        if self.params.trim_outliers {
+            ui.heading("Outliers");
+            ui.label("left out of moving their station, maybe better served by truck");
+            let outliers = self.outliers();
+            egui::ScrollArea::vertical()
+                .id_salt("outliers")
+                .max_height(150.0)
+                .show(ui, |ui| {
+                    for (station, i, distance) in &outliers {
+                        let marker = &self.markers[*i];
+                        ui.label(format!(
+                            "{} at {:.0}, {:.0}: station {}, {:.0} away",
+                            self.layer_names.name(&self.layers[marker.layer]),
+                            marker.x,
+                            marker.y,
+                            station,
+                            distance
+                        ));
+                    }
+                });
+            if outliers.is_empty() {
+                ui.label("none");
+            }
+
+            ui.separator();
+        }
+    }
+
+    /// Markers split between stations by the fuzzy assignment, if it is enabled.
+    fn contested_panel(&mut self, ui: &mut egui::Ui) {
+        if self.params.fuzzy {
+            ui.heading("Contested markers");
+            ui.horizontal(|ui| {
+                ui.label("strongest membership below");
+                ui.add(egui::Slider::new(&mut self.contested_threshold, 0.0..=1.0));
+            });
+            let memberships = self
+                .problem()
+                .memberships(&self.clustering.points, &self.clustering.heights);
+            egui::ScrollArea::vertical()
+                .id_salt("contested")
+                .max_height(150.0)
+                .show(ui, |ui| {
+                    for (marker, membership) in self.markers.iter().zip(&memberships) {
+                        let mut ranked: Vec<(usize, f32)> =
+                            membership.iter().copied().enumerate().collect();
+                        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+                        if ranked.first().is_none_or(|(_, m)| *m >= self.contested_threshold)
+                        {
+                            continue;
+                        }
+                        let shares: Vec<String> = ranked
+                            .iter()
+                            .take(3)
+                            .map(|(j, m)| format!("station {} {:.0}%", j, m * 100.0))
+                            .collect();
+                        ui.label(format!(
+                            "{} at {:.0}, {:.0}: {}",
+                            self.layer_names.name(&self.layers[marker.layer]),
+                            marker.x,
+                            marker.y,
+                            shares.join(", ")
+                        ));
+                    }
+                });
+
+            ui.separator();
+        }
+    }
+
+    /// Markers whose data couldn't be used.
+    fn diagnostics_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Diagnostics");
+        if self.invalid_markers.is_empty() {
+            ui.label("all marker positions look fine");
+        } else {
+            ui.checkbox(&mut self.include_invalid, "cluster flagged markers anyway");
+            egui::ScrollArea::vertical()
+                .id_salt("invalid")
+                .max_height(120.0)
+                .show(ui, |ui| {
+                    for (path_name, issue) in &self.invalid_markers {
+                        ui.label(format!("{}: {}", path_name, issue));
+                    }
+                });
+        }
+    }
+
+    /// Markers excluded by hand.
+    fn exclusions_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Excluded markers");
+        ui.label("right click a marker on the map to exclude or include it");
+        let mut include = None;
+        egui::ScrollArea::vertical()
+            .id_salt("excluded")
+            .max_height(120.0)
+            .show(ui, |ui| {
+                for path_name in &self.excluded {
+                    ui.horizontal(|ui| {
+                        if ui.small_button("remove").clicked() {
+                            include = Some(path_name.clone());
+                        }
+                        ui.label(path_name);
+                    });
+                }
+            });
+        if let Some(path_name) = include {
+            self.toggle_exclusion(&path_name);
+        }
+        if let Some(status) = &self.exclusions_status {
+            ui.label(status);
+        }
+    }
+
+    /// Loading the markers from a save.
+    fn save_file_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Save file");
+        if ui.button("load from save…").clicked() {
+            self.pick_save_file();
+        }
+        if let Some(status) = &self.save_status {
+            ui.label(status);
+        }
+    }
+
+    /// Reloading the custom markers.
+    fn custom_markers_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Custom markers");
+        ui.label(self.custom_markers_path.display().to_string());
+        if ui.button("reload custom markers").clicked() {
+            self.reload_custom_markers();
+        }
+        if let Some(status) = &self.custom_status {
+            ui.label(status);
+        }
+    }
+
+    /// Reloading the config and the layer names.
+    fn config_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Config");
+        ui.label(self.config_path.display().to_string());
+        if ui.button("reload config").clicked() {
+            self.reload_config();
+        }
+        if let Some(status) = &self.config_status {
+            ui.label(status);
+        }
+        ui.label(self.layer_names_path.display().to_string());
+        if ui.button("reload layer names").clicked() {
+            self.reload_layer_names();
+        }
+        if let Some(status) = &self.layer_names_status {
+            ui.label(status);
+        }
+    }
+
+    /// Saving and loading projects.
+    fn project_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Project");
+        ui.text_edit_singleline(&mut self.project_path);
+        ui.horizontal(|ui| {
+            if ui.button("save project").clicked() {
+                self.save_project();
+            }
+            if ui.button("load project").clicked() {
+                self.load_project();
+            }
+        });
+        if let Some(status) = &self.project_status {
+            ui.label(status);
+        }
+    }
+
+    /// The map files in use and getting newer ones.
+    fn map_data_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Map data");
+        ui.label(format!("Version: {}", self.map_version));
+        for summary in &self.map_summaries {
+            ui.label(format!(
+                "{}: {} layers, {} markers",
+                summary.source, summary.layers, summary.markers
+            ));
+            if !summary.skipped.is_empty() {
+                ui.collapsing(format!("{} entries skipped", summary.skipped.len()), |ui| {
+                    for skipped in &summary.skipped {
+                        ui.label(skipped);
+                    }
+                });
+            }
+        }
+        if ui.button("open map file…").clicked() {
+            self.pick_map_file();
+        }
+        ui.horizontal(|ui| {
+            let reloadable = !self.map_sources.is_empty()
+                && !self.map_sources.contains(&MapSource::Stdin);
+            if ui
+                .add_enabled(reloadable, egui::Button::new("reload map"))
+                .clicked()
+            {
+                self.reload_map();
+            }
+            ui.add_enabled(
+                reloadable,
+                egui::Checkbox::new(&mut self.watch_map_file, "reload on change"),
+            );
+        });
+        ui.horizontal(|ui| {
+            let downloading = self.download.is_some();
+            if ui
+                .add_enabled(!downloading, egui::Button::new("download latest"))
+                .clicked()
+            {
+                self.start_download();
+            }
+            if downloading {
+                ui.spinner();
+            }
+        });
+        if let Some(status) = &self.map_status {
+            ui.label(status);
+        }
+    }
+
+    /// A checkbox enabling a cutoff date typed as YYYY-MM-DD, starting a year
+    /// back. While the text isn't a date the last one typed stays in effect.
+    fn ignore_before_date_control(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let mut enabled = self.ignore_before_date.is_some();
+            ui.checkbox(&mut enabled, "ignore markers checked before");
+            ui.add_enabled(
+                enabled,
+                egui::TextEdit::singleline(&mut self.ignore_before_date_text)
+                    .hint_text("YYYY-MM-DD")
+                    .desired_width(80.0),
+            );
+            let typed =
+                chrono::NaiveDate::parse_from_str(self.ignore_before_date_text.trim(), "%Y-%m-%d");
+            self.ignore_before_date = match (enabled, typed) {
+                (false, _) => None,
+                (true, Ok(date)) => Some(date),
+                (true, Err(_)) => {
+                    if self.ignore_before_date.is_some() {
+                        ui.colored_label(ui.visuals().warn_fg_color, "not a date");
+                    }
+                    self.ignore_before_date.or_else(|| {
+                        let year_ago = chrono::Utc::now().date_naive() - chrono::Days::new(365);
+                        self.ignore_before_date_text = year_ago.format("%Y-%m-%d").to_string();
+                        Some(year_ago)
+                    })
+                }
+            };
+        });
+    }
+
+    /// Whether the marker is currently shown and used, which for resource
+    /// wells means either the satellites or their pressurizer.
+    fn is_present(&self, marker: &ResourceMarker) -> bool {
+        marker.is_used_part_of_well(self.collapse_wells) && self.included_layers[marker.layer]
+    }
+
+    /// Whether the marker takes part in the clustering. Collectibles only do
+    /// if their category was opted in, fluids only unless left out.
+    fn is_clustered(&self, marker: &ResourceMarker) -> bool {
+        let layer = &self.layers[marker.layer];
+        self.is_present(marker)
+            && layer.collectible.as_ref().is_none_or(|c| self.clustered_collectibles.contains(c))
+            && !(self.exclude_fluids && layer.kind.is_fluid())
+            && !self.is_stale(marker)
+            && !self.excluded.contains(&marker.pathName)
+            && (self.include_invalid || !self.invalid_markers.contains_key(&marker.pathName))
+            && self.in_region(marker)
+    }
+
+    fn in_region(&self, marker: &ResourceMarker) -> bool {
+        self.params.region.is_none_or(|region| region.contains(pos2(marker.x, marker.y)))
+    }
+
+    /// The shown marker closest to a screen position, if one is close enough
+    /// to be pointed at.
+    fn marker_at(&self, to_screen: &RectTransform, pos: Pos2) -> Option<usize> {
+        const PICK_DISTANCE: f32 = 8.0;
+
+        let scale = to_screen.scale().abs().min_elem();
+        if scale <= 0.0 {
+            return None;
+        }
+        let game_pos = to_screen.inverse().transform_pos(pos);
+        self.marker_tree
+            .within(game_pos, PICK_DISTANCE / scale)
+            .into_iter()
+            .filter(|i| self.is_present(&self.markers[*i]))
+            .map(|i| {
+                let marker = &self.markers[i];
+                (i, to_screen.transform_pos(pos2(marker.x, marker.y)).distance(pos))
+            })
+            .filter(|(_, distance)| *distance <= PICK_DISTANCE)
+            .min_by(|(a, a_distance), (b, b_distance)| {
+                a_distance.total_cmp(b_distance).then(a.cmp(b))
+            })
+            .map(|(i, _)| i)
+    }
+
+    /// The point drawn under the given screen position, if any.
+    /// Rebuilds the network once its lines changed, rescoring the best
+    /// points if distances are measured along it.
+    fn refresh_network(&mut self) {
+        if self.network_built_for == self.params.network_lines {
+            return;
+        }
+        self.network_built_for = self.params.network_lines.clone();
+        self.network = None;
+        self.network_status = None;
+        if !self.network_built_for.is_empty() {
+            match Network::new(self.network_built_for.clone()) {
+                Ok(network) => self.network = Some(network),
+                Err(err) => self.network_status = Some(err),
+            }
+        }
+        if self.params.metric == DistanceMetric::Network {
+            self.reweigh();
+        }
+    }
+
+    fn finish_network_line(&mut self) {
+        let line = std::mem::take(&mut self.network_draft);
+        if line.len() >= 2 {
+            self.params.network_lines.push(line);
+        }
+    }
+
+    fn pick_network_file(&mut self) {
+        let Some(path) = rfd::FileDialog::new().add_filter("JSON", &["json"]).pick_file() else {
+            return;
+        };
+        match network::load_lines(&path) {
+            Ok(lines) => {
+                self.params.network_lines.extend(lines);
+                self.network_status = None;
+            }
+            Err(err) => {
+                self.network_status =
+                    Some(format!("failed to import {}: {}", path.display(), err));
+            }
+        }
+    }
+
+    /// The vertex of the network or of the line being drawn closest to the
+    /// screen position, if one is near enough to join.
+    fn network_vertex_at(&self, to_screen: &RectTransform, pos: Pos2) -> Option<Pos2> {
+        const PICK_DISTANCE: f32 = 8.0;
+
+        self.params
+            .network_lines
+            .iter()
+            .chain([&self.network_draft])
+            .flatten()
+            .map(|point| (*point, to_screen.transform_pos(*point).distance(pos)))
+            .filter(|(_, distance)| *distance <= PICK_DISTANCE)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(point, _)| point)
+    }
+
+    /// The way along the network from the clicked marker to its station,
+    /// when distances are measured along it.
+    fn route(&self) -> Option<Vec<Pos2>> {
+        if !self.show_route || self.params.metric != DistanceMetric::Network {
+            return None;
+        }
+        let markeri = self.route_marker?;
+        let marker = self.markers.get(markeri)?;
+        let seti = self.clustering.sets.iter().position(|set| set.contains(&markeri))?;
+        let station = *self.clustering.points.get(seti)?;
+        self.network.as_ref()?.route(pos2(marker.x, marker.y), station)
+    }
+
+    fn station_at(&self, to_screen: &RectTransform, pos: Pos2) -> Option<usize> {
+        const PICK_DISTANCE: f32 = 10.0;
+
+        self.clustering
+            .points
+            .iter()
+            .map(|point| to_screen.transform_pos(*point).distance(pos))
+            .enumerate()
+            .filter(|(_, distance)| *distance <= PICK_DISTANCE)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i)
+    }
+
+    /// Pins the point at `i` in place or frees it again. Pinned points come
+    /// first, so it moves to the end of the pinned ones or right after them.
+    fn toggle_pin(&mut self, i: usize) {
+        self.cancel_job();
+        let to = if i < self.pinned_points.len() {
+            self.pinned_points.remove(i);
+            self.pinned_points.len()
+        } else {
+            self.pinned_points.push(self.clustering.points[i]);
+            self.pinned_points.len() - 1
+        };
+
+        let clustering = &mut self.clustering;
+        let point = clustering.points.remove(i);
+        clustering.points.insert(to, point);
+        if i < clustering.heights.len() && to < clustering.heights.len() {
+            let z = clustering.heights.remove(i);
+            clustering.heights.insert(to, z);
+        }
+        if i < clustering.sets.len() && to < clustering.sets.len() {
+            let set = clustering.sets.remove(i);
+            clustering.sets.insert(to, set);
+        }
+        self.points_changed();
+    }
+
+    fn reload_exclusions(&mut self) {
+        match exclusions::load_exclusions(&self.exclusions_path) {
+            Ok(excluded) => self.excluded = excluded,
+            Err(err) => {
+                self.exclusions_status = Some(format!(
+                    "failed to load {}: {}",
+                    self.exclusions_path.display(),
+                    err
+                ));
+            }
+        }
+    }
+
+    /// Excludes the marker if it is not yet, includes it again otherwise, and
+    /// rewrites the exclusion file.
+    fn toggle_exclusion(&mut self, path_name: &str) {
+        if !self.excluded.remove(path_name) {
+            self.excluded.insert(path_name.to_string());
+        }
+        if self.clustering.sets.len() == self.clustering.points.len() {
+            self.clustering.sets =
+                self.problem().assign(&self.clustering.points, &self.clustering.heights);
+        }
+
+        let saved = exclusions::save_exclusions(&self.exclusions_path, &self.excluded);
+        self.exclusions_status = saved.err().map(|err| {
+            format!("failed to save {}: {}", self.exclusions_path.display(), err)
+        });
+    }
+
+    /// An owned copy of [`Frontend::problem`] for background threads.
+    fn problem_input(&self) -> SweepInput {
+        let problem = self.problem();
+        SweepInput {
+            markers: self.markers.clone(),
+            clustered: problem.clustered,
+            weights: problem.weights,
+            params: self.params.clone(),
+            bounds: problem.bounds,
+            pinned: self.pinned_points.clone(),
+            seeds: self.seed_points.clone(),
+            seeds_fixed: self.seeds_fixed,
+            mask: self.mask.clone(),
+            network: self.network.clone(),
+        }
+    }
+
+    fn start_sweep(&mut self) {
+        let from = self.sweep_from.min(self.sweep_to);
+        let to = self.sweep_from.max(self.sweep_to);
+        let input = self.problem_input();
+        self.sweep_key = (self.params.seed, input.fingerprint(self.sweep_restarts));
+
+        let mut cached = Vec::new();
+        let mut ks = Vec::new();
+        for k in from..=to {
+            match self.sweep_cache.get(&(k, self.sweep_key.0, self.sweep_key.1)) {
+                Some(row) => cached.push(row.clone()),
+                None => ks.push(k),
+            }
+        }
+        self.sweep = Some(Sweep::start(input, ks, self.sweep_restarts, cached));
+        self.sweep_status = None;
+    }
+
+    fn start_gap(&mut self) {
+        let from = self.sweep_from.min(self.sweep_to);
+        let to = self.sweep_from.max(self.sweep_to);
+        self.gap = Some(GapStatistic::start(
+            self.problem_input(),
+            (from..=to).collect(),
+            self.sweep_restarts,
+            self.gap_references,
+        ));
+    }
+
+    /// Plots the gap over k with its spread, marking the suggested k.
+    fn gap_plot(&self, ui: &mut egui::Ui) {
+        let Some(rows) = self.gap.as_ref().map(|gap| &gap.rows) else {
+            return;
+        };
+        let suggested = gap::suggest(rows);
+        let chart = LineChart {
+            points: rows.iter().map(|row| (row.k, row.gap)).collect(),
+            spreads: Some(rows.iter().map(|row| row.spread).collect()),
+            marked: suggested.map(|k| (k, format!("suggested k = {}", k), Color32::LIGHT_GREEN)),
+            from_zero: false,
+        };
+        line_chart(ui, chart, |i| {
+            let row = &rows[i];
+            format!(
+                "k = {}, gap {:.3} ± {:.3}\nlog error {:.3}, reference {:.3}",
+                row.k, row.gap, row.spread, row.log_error, row.reference_log_error
+            )
+        });
+    }
+
+    /// Plots the best error of the sweep over k, marking the knee, or with a
+    /// station cost the error and the cost of the stations together, marking
+    /// the cheapest k. Returns the row clicked on.
+    fn sweep_plot(&self, ui: &mut egui::Ui) -> Option<SweepRow> {
+        let rows = &self.sweep.as_ref()?.rows;
+        let costed = self.station_cost > 0.0;
+        let (marked, label, color) = if costed {
+            (sweep::cheapest(rows, self.station_cost), "cheapest", Color32::LIGHT_GREEN)
+        } else {
+            (sweep::knee(rows), "knee", Color32::LIGHT_RED)
+        };
+        let value = |row: &SweepRow| {
+            if costed {
+                row.combined(self.station_cost)
+            } else {
+                row.best_error
+            }
+        };
+        let chart = LineChart {
+            points: rows.iter().map(|row| (row.k, value(row))).collect(),
+            spreads: None,
+            marked: marked.map(|k| (k, format!("{} at k = {}", label, k), color)),
+            from_zero: true,
+        };
+        let clicked = line_chart(ui, chart, |i| {
+            let row = &rows[i];
+            let cost = if costed {
+                format!(", {:.0} with stations", row.combined(self.station_cost))
+            } else {
+                String::new()
+            };
+            format!("k = {}, best error {:.0}{}\nclick to load", row.k, row.best_error, cost)
+        });
+        clicked.map(|i| rows[i].clone())
+    }
+
+    /// The stations by what they add to the error, largest first. Clicking
+    /// one highlights it on the map.
+    fn station_error_list(&mut self, ui: &mut egui::Ui) {
+        let errors = self.station_errors();
+        let throughputs: Vec<Throughput> = self
+            .clustering
+            .sets
+            .iter()
+            .map(|set| Throughput::of(&self.params.miner, &self.markers, set))
+            .collect();
+        if let Some(cap) = self.params.throughput_cap {
+            let over = throughputs.iter().filter(|t| t.is_over(Some(cap))).count();
+            if over > 0 {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("{} stations over the cap of {:.0} items/min", over, cap),
+                );
+            }
+        }
+        egui::Grid::new("station_errors").striped(true).show(ui, |ui| {
+            ui.label("station");
+            ui.label("nodes");
+            ui.label("error");
+            ui.label("mean");
+            ui.label("worst node");
+            ui.label("items/min");
+            ui.label("m³/min");
+            ui.label("belts").on_hover_text(format!(
+                "Mk5 / Mk6 belts, {} / {} items/min each",
+                rates::BELT_LIMIT,
+                rates::MK6_BELT_LIMIT
+            ));
+            ui.label("pipes")
+                .on_hover_text(format!("pipelines, {} m³/min each", rates::PIPELINE_LIMIT));
+            ui.label("resources");
+            ui.end_row();
+            for station in &errors {
+                let highlighted = self.highlighted_station == Some(station.index);
+                if ui.selectable_label(highlighted, station.index.to_string()).clicked() {
+                    self.highlighted_station = (!highlighted).then_some(station.index);
+                }
+                ui.label(station.nodes.to_string());
+                ui.label(format!("{:.0}", station.error));
+                ui.label(format!("{:.0}", station.mean_distance));
+                match station.worst {
+                    Some((i, distance)) => {
+                        ui.label(format!("{:.0}", distance))
+                            .on_hover_text(&self.markers[i].pathName);
+                    }
+                    None => {
+                        ui.label("-");
+                    }
+                }
+                let throughput = throughputs[station.index];
+                let rate = format!("{:.0}", throughput.items);
+                if throughput.is_over(self.params.throughput_cap) {
+                    ui.colored_label(ui.visuals().warn_fg_color, rate)
+                        .on_hover_text("over the throughput cap");
+                } else {
+                    ui.label(rate);
+                }
+                ui.label(format!("{:.0}", throughput.fluid));
+                ui.label(format!(
+                    "{} / {}",
+                    throughput.belts(rates::BELT_LIMIT),
+                    throughput.belts(rates::MK6_BELT_LIMIT)
+                ));
+                ui.label(throughput.pipelines().to_string());
+                ui.label(self.resource_mix(&self.clustering.sets[station.index]));
+                ui.end_row();
+            }
+        });
+    }
+
+    /// The outliers of the current sets as (station, marker, distance), see
+    /// [`Problem::outliers`].
+    fn outliers(&self) -> Vec<(usize, usize, f32)> {
+        let problem = self.problem();
+        let clustering = &self.clustering;
+        let stations = clustering.points.iter().zip(&clustering.heights).zip(&clustering.sets);
+        stations
+            .enumerate()
+            .flat_map(|(station, ((point, z), set))| {
+                problem
+                    .outliers(*point, *z, set)
+                    .into_iter()
+                    .map(|i| (station, i, problem.distance(*point, *z, i)))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// The resources of a set with their node counts, most first, e.g.
+    /// "Iron Ore 12, Limestone 3".
+    fn resource_mix(&self, set: &[usize]) -> String {
+        let names = self.layer_display_names();
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for i in set {
+            *counts.entry(overlay::resource_name(names[self.markers[*i].layer])).or_default() += 1;
+        }
+        let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        let mix: Vec<String> =
+            counts.iter().map(|(name, count)| format!("{} {}", name, count)).collect();
+        mix.join(", ")
+    }
+
+    /// Bars of the weighted size of every station, in the order of the
+    /// stations.
+    fn size_chart(&self, ui: &mut egui::Ui) {
+        let problem = self.problem();
+        let sizes: Vec<f32> = self.clustering.sets.iter().map(|set| problem.size(set)).collect();
+        let max_size = sizes.iter().copied().fold(f32::MIN_POSITIVE, f32::max);
+
+        let size = vec2(ui.available_width(), 100.0);
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let frame = response.rect.shrink(4.0);
+        painter.rect_stroke(response.rect, 0.0, ui.visuals().widgets.noninteractive.bg_stroke);
+
+        let width = frame.width() / sizes.len() as f32;
+        let bar = ui.visuals().text_color();
+        let bars: Vec<Rect> = sizes
+            .iter()
+            .enumerate()
+            .map(|(i, size)| {
+                let left = frame.left() + i as f32 * width;
+                let top = egui::lerp(frame.bottom()..=frame.top(), size / max_size);
+                Rect::from_min_max(pos2(left + 1.0, top), pos2(left + width - 1.0, frame.bottom()))
+            })
+            .collect();
+        for rect in &bars {
+            painter.rect_filled(*rect, 0.0, bar);
+        }
+
+        let mean = sizes.iter().sum::<f32>() / sizes.len() as f32;
+        let mean_y = egui::lerp(frame.bottom()..=frame.top(), mean / max_size);
+        painter.hline(frame.x_range(), mean_y, Stroke::new(1.0, Color32::LIGHT_RED));
+
+        let Some(pointer) = response.hover_pos() else {
+            return;
+        };
+        let hovered = ((pointer.x - frame.left()) / width).floor();
+        if let Some(size) = (hovered >= 0.0).then(|| sizes.get(hovered as usize)).flatten() {
+            response.on_hover_text(format!(
+                "station {}: size {:.1}, mean {:.1}",
+                hovered as usize + 1,
+                size,
+                mean
+            ));
+        }
+    }
+
+    /// Bars of how many restarts ended in every range of errors, with the
+    /// error of the best so far as a line.
+    fn run_histogram(&self, ui: &mut egui::Ui, summary: &restarts::RunSummary) {
+        const BINS: usize = 20;
+        let counts = restarts::histogram(&self.restart_runs, BINS, summary);
+        let max_count = counts.iter().copied().max().unwrap_or(1).max(1);
+
+        let size = vec2(ui.available_width(), 80.0);
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let frame = response.rect.shrink(4.0);
+        painter.rect_stroke(response.rect, 0.0, ui.visuals().widgets.noninteractive.bg_stroke);
+
+        let width = frame.width() / BINS as f32;
+        let bar = ui.visuals().text_color();
+        for (i, count) in counts.iter().enumerate() {
+            let left = frame.left() + i as f32 * width;
+            let top = egui::lerp(frame.bottom()..=frame.top(), *count as f32 / max_count as f32);
+            let rect =
+                Rect::from_min_max(pos2(left + 1.0, top), pos2(left + width - 1.0, frame.bottom()));
+            painter.rect_filled(rect, 0.0, bar);
+        }
+
+        let spread = summary.max - summary.min;
+        let best = self.clustering.best_so_far;
+        if spread > 0.0 && (summary.min..=summary.max).contains(&best) {
+            let x = egui::lerp(frame.x_range(), (best - summary.min) / spread);
+            painter.vline(x, frame.y_range(), Stroke::new(1.0, Color32::LIGHT_RED));
+        }
+
+        let Some(pointer) = response.hover_pos() else {
+            return;
+        };
+        let hovered = ((pointer.x - frame.left()) / width).floor();
+        if let Some(count) = (hovered >= 0.0).then(|| counts.get(hovered as usize)).flatten() {
+            let from = summary.min + spread * hovered / BINS as f32;
+            let to = from + spread / BINS as f32;
+            let text = format!("{} runs with an error of {:.1} to {:.1}", count, from, to);
+            response.on_hover_text(text);
+        }
+    }
+
+    fn export_runs(&mut self) {
+        let path = Path::new(&self.runs_csv_path);
+        self.restart_status = Some(match restarts::save_runs_csv(path, &self.restart_runs) {
+            Ok(()) => format!("exported {} runs", self.restart_runs.len()),
+            Err(err) => format!("failed to export to {}: {}", path.display(), err),
+        });
+    }
+
+    fn export_stability(&mut self) {
+        let points = &self.clustering.points;
+        let stability = restarts::stability(points, &self.restart_runs);
+        if stability.is_empty() {
+            self.restart_status =
+                Some("no other good runs to compare the stations with".to_string());
+            return;
+        }
+
+        let path = Path::new(&self.stability_csv_path);
+        self.restart_status = Some(match restarts::save_stability_csv(path, points, &stability) {
+            Ok(()) => format!("exported the stability of {} stations", stability.len()),
+            Err(err) => format!("failed to export to {}: {}", path.display(), err),
+        });
+    }
+
+    /// Shows the stations a sweep found for one k.
+    fn load_sweep_row(&mut self, row: SweepRow) {
+        self.cancel_job();
+        self.params.k = row.k;
+        self.clustering.sets = self.problem().assign(&row.points, &row.heights);
+        self.clustering.last_error = row.best_error;
+        self.clustering.best_so_far = row.best_error;
+        self.clustering.best_so_far_points = row.points.clone();
+        self.clustering.best_so_far_heights = row.heights.clone();
+        self.clustering.best_so_far_solver = Some(self.params.median_solver);
+        self.clustering.seed = row.seed;
+        self.adopt_result_key();
+        self.clustering.points = row.points;
+        self.clustering.heights = row.heights;
+        self.rescore_best();
+        self.points_changed();
+    }
+
+    fn start_restarts(&mut self) {
+        self.restart_runs.clear();
+        self.restart_status = None;
+        self.restart_run = Some(Restarts::start(
+            self.problem_input(),
+            self.restarts,
+            self.clustering.best_score(),
+        ));
+    }
+
+    /// Takes in improvements of the running restarts, and once they are done
+    /// or cancelled shows the best points found.
+    fn poll_restarts(&mut self, ctx: &egui::Context) {
+        let Some(restarts) = &mut self.restart_run else {
+            return;
+        };
+
+        restarts.poll();
+        self.restart_runs.extend(restarts.take_runs());
+        let running = restarts.running;
+        if let Some(best) = restarts.take_best() {
+            self.offer_best(best);
+        }
+        if running {
+            ctx.request_repaint();
+            return;
+        }
+
+        self.restart_run = None;
+        self.show_best();
+    }
+
+    /// Takes in the best configuration of the running evolution, and once it
+    /// is done or cancelled shows the best points found.
+    fn poll_evolution(&mut self, ctx: &egui::Context) {
+        let Some(evolution) = &mut self.evolution else {
+            return;
+        };
+
+        let best = evolution.poll();
+        let running = evolution.running;
+        if let Some(best) = best {
+            self.offer_best(best);
+        }
+        if running {
+            ctx.request_repaint();
+            return;
+        }
+
+        self.evolution = None;
+        self.show_best();
+    }
+
+    /// Takes a result found in the background as the best so far if it is
+    /// better.
+    fn offer_best(&mut self, best: RestartBest) {
+        if cluster::is_better(best.score(), self.clustering.best_score()) {
+            self.clustering.best_so_far = best.error;
+            self.clustering.best_so_far_uncovered = best.uncovered;
+            self.clustering.best_so_far_points = best.points;
+            self.clustering.best_so_far_heights = best.heights;
+            self.clustering.best_so_far_solver = best.solver;
+            self.clustering.seed = best.seed;
+        }
+    }
+
+    /// Moves the current points onto the best so far, once a background
+    /// search is done.
+    fn show_best(&mut self) {
+        if !self.clustering.best_so_far_points.is_empty() {
+            self.cancel_job();
+            self.clustering.points = self.clustering.best_so_far_points.clone();
+            self.clustering.heights = self.clustering.best_so_far_heights.clone();
+            self.clustering.sets =
+                self.problem().assign(&self.clustering.points, &self.clustering.heights);
+            self.clustering.last_error = self.clustering.best_so_far;
+            self.points_changed();
+        }
+    }
+
+    fn poll_sweep(&mut self, ctx: &egui::Context) {
+        if let Some(sweep) = &mut self.sweep {
+            if sweep.running {
+                sweep.poll();
+                ctx.request_repaint();
+            }
+            let (seed, fingerprint) = self.sweep_key;
+            for row in sweep.take_fresh() {
+                self.sweep_cache.insert((row.k, seed, fingerprint), row);
+            }
+        }
+    }
+
+    fn poll_gap(&mut self, ctx: &egui::Context) {
+        if let Some(gap) = &mut self.gap {
+            if gap.running {
+                gap.poll();
+                ctx.request_repaint();
+            }
+        }
+    }
+
+    fn export_sweep(&mut self) {
+        let rows = self.sweep.as_ref().map(|sweep| sweep.rows.as_slice()).unwrap_or_default();
+        if rows.is_empty() {
+            self.sweep_status = Some("nothing to export yet, run a sweep first".to_string());
+            return;
+        }
+
+        let path = Path::new(&self.sweep_csv_path);
+        self.sweep_status = Some(match sweep::save_sweep_csv(path, rows) {
+            Ok(()) => format!("exported {} rows", rows.len()),
+            Err(err) => format!("failed to export to {}: {}", path.display(), err),
+        });
+    }
+
+    fn station_export(&self) -> StationExport {
+        let Some(runs) = self.layer_runs() else {
+            return self.problem().station_export(
+                &self.clustering.best_so_far_points,
+                &self.clustering.best_so_far_heights,
+                self.clustering.best_so_far,
+            );
+        };
+
+        // markers only go to the stations of their own layer
+        let mut export = self.problem().station_export_of_sets(
+            &self.clustering.points,
+            &self.clustering.heights,
+            &self.clustering.sets,
+            self.clustering.best_so_far,
+        );
+        let layers = runs.iter().flat_map(|run| std::iter::repeat_n(run.layer, run.k));
+        for (station, layer) in export.stations.iter_mut().zip(layers) {
+            station.layer = Some(self.layers[layer].layerId.clone());
+        }
+        export
+    }
+
+    /// The runs of the last per-layer clustering, while its points are shown.
+    fn layer_runs(&self) -> Option<&[LayerRun]> {
+        self.layer_plan
+            .as_ref()
+            .filter(|(points, _)| self.params.per_layer && *points == self.clustering.points)
+            .map(|(_, runs)| runs.as_slice())
+    }
+
+    /// The clustered nodes of every layer as (weight, nodes).
+    fn layer_nodes(&self) -> Vec<(f32, usize)> {
+        let mut layers = vec![(0.0, 0); self.layers.len()];
+        for marker in self.markers.iter().filter(|m| self.is_clustered(m)) {
+            let layer = &mut layers[marker.layer];
+            layer.0 += self.params.marker_weight(marker, &self.layers[marker.layer].layerId);
+            layer.1 += 1;
+        }
+        layers
+    }
+
+    /// Proposes the k of every layer by sharing `layer_k_total` out over
+    /// them, see [`cluster::allocate_k`].
+    fn allocate_layer_ks(&mut self) {
+        let ks = cluster::allocate_k(self.params.layer_k_total, &self.layer_nodes());
+        self.params.layer_ks.clear();
+        for (layer, k) in self.layers.iter().zip(ks) {
+            self.params.set_layer_k(&layer.layerId, k);
+        }
+    }
+
+    /// The total to share out over the layers and the proposed k of each,
+    /// editable before running.
+    fn allocation_ui(&mut self, ui: &mut egui::Ui) {
+        let mut changed = ui
+            .checkbox(&mut self.params.auto_layer_ks, "auto-allocate the stations")
+            .on_hover_text("in proportion to the weighted nodes of every layer")
+            .changed();
+        if !self.params.auto_layer_ks {
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.label("total k");
+            let total = DragValue::new(&mut self.params.layer_k_total).range(1..=200);
+            changed |= ui.add(total).changed();
+        });
+        if changed {
+            self.allocate_layer_ks();
+        }
+
+        let nodes = self.layer_nodes();
+        let layers = nodes.iter().filter(|(_, count)| *count > 0).count();
+        if layers > self.params.layer_k_total {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!(
+                    "{} layers for {} stations, each still gets one",
+                    layers, self.params.layer_k_total
+                ),
+            );
+        }
+        egui::Grid::new("allocation").striped(true).show(ui, |ui| {
+            ui.label("layer");
+            ui.label("nodes");
+            ui.label("k");
+            ui.end_row();
+            for (layer, (_, count)) in self.layers.iter().zip(&nodes) {
+                if *count == 0 {
+                    continue;
+                }
+                ui.label(self.layer_names.name(layer));
+                ui.label(count.to_string());
+                let mut k = self.params.layer_k(&layer.layerId);
+                if ui.add(DragValue::new(&mut k).range(0..=*count)).changed() {
+                    self.params.set_layer_k(&layer.layerId, k);
+                }
+                ui.end_row();
+            }
+        });
+        let sum: usize = self.params.layer_ks.values().sum();
+        ui.label(format!("{} stations in all", sum));
+    }
+
+    /// Clusters the markers of every included layer with a k on its own.
+    fn run_per_layer(&mut self) {
+        self.cancel_job();
+        let layer_ks: Vec<(usize, usize)> = self
+            .layers
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.included_layers[*i])
+            .map(|(i, layer)| (i, self.params.layer_k(&layer.layerId)))
+            .filter(|(_, k)| *k > 0)
+            .collect();
+        let (clustering, runs) = cluster::run_per_layer(&self.problem(), &layer_ks);
+        self.params.k = clustering.points.len().max(1);
+        self.layer_plan = Some((clustering.points.clone(), runs));
+        self.clustering = clustering;
+        self.adopt_result_key();
+        self.points_changed();
+    }
+
+    /// Places the stations by hierarchical clustering.
+    fn run_hierarchical(&mut self) {
+        self.run_placement(hierarchy::hierarchical);
+    }
+
+    /// Places the stations with a deterministic algorithm, keeping the
+    /// previous best if it was better.
+    fn run_placement(&mut self, place: fn(&Problem) -> Result<Clustering, String>) {
+        self.cancel_job();
+        self.teleports.clear();
+        let problem = self.problem();
+        let best = self.clustering.best_score();
+        let mut clustering = match place(&problem) {
+            Ok(clustering) => clustering,
+            Err(err) => {
+                self.algorithm_status = Some(err);
+                return;
+            }
+        };
+        self.algorithm_status = None;
+        if !cluster::is_better(clustering.best_score(), best) {
+            clustering.best_so_far = self.clustering.best_so_far;
+            clustering.best_so_far_uncovered = self.clustering.best_so_far_uncovered;
+            clustering.best_so_far_points = std::mem::take(&mut self.clustering.best_so_far_points);
+            clustering.best_so_far_heights =
+                std::mem::take(&mut self.clustering.best_so_far_heights);
+            clustering.best_so_far_solver = self.clustering.best_so_far_solver;
+        }
+        self.clustering = clustering;
+        self.points_changed();
+    }
+
+    /// The number of groups of the last DBSCAN run, while its points are
+    /// shown.
+    fn dbscan_result(&self) -> Option<usize> {
+        self.dbscan_points
+            .as_ref()
+            .filter(|points| {
+                self.params.algorithm == Algorithm::Dbscan && **points == self.clustering.points
+            })
+            .map(Vec::len)
+    }
+
+    /// Places a station at the median of every group DBSCAN finds, as many
+    /// as there are.
+    fn run_dbscan(&mut self) {
+        self.cancel_job();
+        let problem = self.problem();
+        let clustering = Clustering::from_sets(&problem, dbscan::dbscan(&problem));
+        self.params.k = clustering.points.len().max(1);
+        self.dbscan_points = Some(clustering.points.clone());
+        self.clustering = clustering;
+        self.adopt_result_key();
+        self.points_changed();
+    }
+
+    fn export_stations(&mut self) {
+        if self.clustering.best_so_far_points.is_empty() {
+            self.export_status = Some("nothing to export yet, run the clustering first".to_string());
+            return;
+        }
+
+        let path = Path::new(&self.export_path);
+        self.export_status = Some(match export::save_stations(path, &self.station_export()) {
+            Ok(()) => format!("exported {} stations", self.clustering.best_so_far_points.len()),
+            Err(err) => format!("failed to export to {}: {}", path.display(), err),
+        });
+    }
+
+    fn import_stations(&mut self) {
+        let path = Path::new(&self.export_path);
+        match export::load_stations(path) {
+            Ok(export) => {
+                self.cancel_job();
+                let points: Vec<Pos2> = export.stations.iter().map(|s| pos2(s.x, s.y)).collect();
+                self.params = export.parameters;
+                self.params.k = points.len();
+                let heights = match export.stations.iter().map(|s| s.z).collect() {
+                    Some(heights) => heights,
+                    None => self.problem().estimate_heights(&points),
+                };
+                self.clustering.sets = self.problem().assign(&points, &heights);
+                self.clustering.best_so_far_points = points.clone();
+                self.clustering.best_so_far_heights = heights.clone();
+                self.clustering.best_so_far_solver = None;
+                self.clustering.seed = None;
+                self.adopt_result_key();
+                self.rescore_best();
+                self.clustering.last_error = self.clustering.best_so_far;
+                self.clustering.points = points;
+                self.clustering.heights = heights;
+                self.points_changed();
+                self.export_status = Some(format!("imported {} stations", self.clustering.points.len()));
+            }
+            Err(err) => {
+                self.export_status =
+                    Some(format!("failed to import from {}: {}", path.display(), err));
+            }
+        }
+    }
+
+    fn export_overlay(&mut self) {
+        if self.clustering.best_so_far_points.is_empty() {
+            self.export_status = Some("nothing to export yet, run the clustering first".to_string());
+            return;
+        }
+
+        let points = &self.clustering.best_so_far_points;
+        let sets = self.problem().assign(points, &self.clustering.best_so_far_heights);
+        let layer =
+            overlay::station_layer(&self.layer_display_names(), &self.markers, points, &sets);
+        let path = Path::new(&self.overlay_path);
+        self.export_status = Some(match overlay::save_overlay(path, &layer) {
+            Ok(()) => format!("exported {} stations as a map layer", layer.markers.len()),
+            Err(err) => format!("failed to export to {}: {}", path.display(), err),
+        });
+    }
+
+    /// Reads a layer from the interactive map as existing stations that stay
+    /// in place.
+    fn import_overlay(&mut self) {
+        let path = Path::new(&self.overlay_path);
+        match overlay::load_overlay_points(path) {
+            Ok(points) => {
+                self.seed_status = Some(if points.len() > self.params.k {
+                    format!("imported {} stations, only the first k are used", points.len())
+                } else {
+                    format!("imported {} stations", points.len())
+                });
+                self.seed_points = points;
+                self.seeds_fixed = true;
+            }
+            Err(err) => {
+                self.seed_status =
+                    Some(format!("failed to import from {}: {}", path.display(), err));
+            }
+        }
+    }
+
+    fn export_assignments(&mut self) {
+        // markers stay unassigned until a run has produced sets for the points
+        let stations = if self.clustering.sets.len() == self.clustering.points.len() {
+            self.problem().stations_of_markers(
+                &self.clustering.points,
+                &self.clustering.heights,
+                &self.clustering.sets,
+            )
+        } else {
+            vec![None; self.markers.len()]
+        };
+
+        let rows: Vec<AssignmentRow> = self
+            .markers
+            .iter()
+            .zip(stations)
+            .map(|(marker, station)| AssignmentRow {
+                path_name: &marker.pathName,
+                layer: self.layer_names.name(&self.layers[marker.layer]),
+                kind: self.layers[marker.layer].kind,
+                x: marker.x,
+                y: marker.y,
+                z: marker.z,
+                purity: marker.purity.to_string(),
+                obstructed: marker.obstructed,
+                station,
+            })
+            .collect();
+
+        let path = Path::new(&self.csv_path);
+        self.export_status = Some(match export::save_assignments_csv(path, &rows) {
+            Ok(()) => format!("exported {} markers", rows.len()),
+            Err(err) => format!("failed to export to {}: {}", path.display(), err),
+        });
+    }
+
+    /// What every current point adds to the error, largest first.
+    fn station_errors(&self) -> Vec<StationError> {
+        let clustering = &self.clustering;
+        self.problem().station_errors(
+            &clustering.points,
+            &clustering.heights,
+            &clustering.sets,
+            &clustering.set_errors,
+        )
+    }
+
+    fn export_station_errors(&mut self) {
+        let errors = self.station_errors();
+        if errors.is_empty() {
+            self.export_status = Some("nothing to export yet, run the clustering first".to_string());
+            return;
+        }
+
+        let path = Path::new(&self.station_csv_path);
+        self.export_status =
+            Some(match export::save_station_errors_csv(path, &self.markers, &errors) {
+                Ok(()) => format!("exported the errors of {} stations", errors.len()),
+                Err(err) => format!("failed to export to {}: {}", path.display(), err),
+            });
+    }
+
+    fn export_report(&mut self) {
+        let clustering = &self.clustering;
+        if clustering.points.is_empty() || clustering.sets.len() != clustering.points.len() {
+            self.export_status = Some("nothing to report yet, run the clustering first".to_string());
+            return;
+        }
+
+        let path = Path::new(&self.report_path);
+        let saved = report::save_report(
+            path,
+            &self.layer_display_names(),
+            &self.layer_kinds(),
+            &self.problem(),
+            &clustering.points,
+            &clustering.heights,
+            &clustering.sets,
+        );
+        self.export_status = Some(match saved {
+            Ok(()) => format!("saved report to {}", path.display()),
+            Err(err) => format!("failed to save report to {}: {}", path.display(), err),
+        });
+    }
+
+    fn export_resource_totals(&mut self) {
+        let clustering = &self.clustering;
+        if clustering.points.is_empty() || clustering.sets.len() != clustering.points.len() {
+            self.export_status = Some("nothing to export yet, run the clustering first".to_string());
+            return;
+        }
+
+        let totals = ResourceTotals::new(
+            &self.markers,
+            &clustering.points,
+            &clustering.sets,
+            &self.params.miner,
+        );
+        let path = Path::new(&self.planner_path);
+        self.export_status = Some(match planner::save_resource_totals(path, &totals) {
+            Ok(()) => format!("exported resources of {} stations", totals.stations.len()),
+            Err(err) => format!("failed to export to {}: {}", path.display(), err),
+        });
+    }
+
+    fn export_svg(&mut self) {
+        let mut layers: Vec<SvgLayer> = self
+            .layers
+            .iter()
+            .zip(&self.layer_colors)
+            .map(|(layer, colors)| SvgLayer {
+                id: &layer.layerId,
+                name: self.layer_names.name(layer),
+                colors: *colors,
+                markers: Vec::new(),
+            })
+            .collect();
+        for marker in self.markers.iter().filter(|m| self.is_present(m)) {
+            layers[marker.layer].markers.push(pos2(marker.x, marker.y));
+        }
+        layers.retain(|layer| !layer.markers.is_empty());
+
+        let assignments = if self.clustering.sets.len() == self.clustering.points.len() {
+            self.clustering.points
+                .iter()
+                .zip(&self.clustering.sets)
+                .flat_map(|(point, set)| {
+                    set.iter()
+                        .map(|i| (pos2(self.markers[*i].x, self.markers[*i].y), *point))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let map = SvgMap {
+            bounds: self.view_bounds(),
+            layers,
+            stations: self.clustering.points.clone(),
+            assignments,
+        };
+        let path = Path::new(&self.svg_path);
+        self.export_status = Some(match svg::save_svg(path, &map) {
+            Ok(()) => format!("saved SVG to {}", path.display()),
+            Err(err) => format!("failed to save SVG to {}: {}", path.display(), err),
+        });
+    }
+
+    fn to_project(&self) -> Project {
+        Project {
+            map_version: Some(self.map_version),
+            parameters: self.params.clone(),
+            points: self.clustering.points.clone(),
+            last_error: self.clustering.last_error,
+            best_so_far: self.clustering.best_so_far,
+            best_so_far_points: self.clustering.best_so_far_points.clone(),
+            result_key: self.result_key,
+            included_layers: Some(
+                self.layers
+                    .iter()
+                    .zip(&self.included_layers)
+                    .filter(|(_, included)| **included)
+                    .map(|(layer, _)| layer.layerId.clone())
+                    .collect(),
+            ),
+            pinned_points: self.pinned_points.clone(),
+        }
+    }
+
+    fn apply_project(&mut self, project: Project) {
+        self.cancel_job();
+        self.params = project.parameters;
+        match &project.included_layers {
+            Some(included) => {
+                for (layer, include) in self.layers.iter().zip(self.included_layers.iter_mut()) {
+                    *include = included.contains(&layer.layerId);
+                }
+            }
+            None => self.included_layers.fill(true),
+        }
+        self.pinned_points = project.pinned_points;
+        let problem = self.problem();
+        let heights = problem.estimate_heights(&project.points);
+        let sets = problem.assign(&project.points, &heights);
+        let best_heights = problem.estimate_heights(&project.best_so_far_points);
+        self.clustering.sets = sets;
+        self.clustering.best_so_far_heights = best_heights;
+        self.clustering.points = project.points;
+        self.clustering.heights = heights;
+        self.clustering.last_error = project.last_error;
+        self.clustering.best_so_far = project.best_so_far;
+        self.clustering.best_so_far_points = project.best_so_far_points;
+        self.clustering.best_so_far_solver = None;
+        self.clustering.seed = None;
+        self.clustering.best_so_far_uncovered = if self.clustering.best_so_far_points.is_empty() {
+            usize::MAX
+        } else {
+            self.problem()
+                .score(&self.clustering.best_so_far_points, &self.clustering.best_so_far_heights)
+                .0
+        };
+        match project.result_key {
+            Some(key) => self.result_key = Some(key),
+            None => self.adopt_result_key(),
+        }
+        self.best_cleared = None;
+        self.points_changed();
+    }
+
+    fn save_project(&mut self) {
+        let project = self.to_project();
+        let path = Path::new(&self.project_path);
+        self.project_status = Some(match project::save_project(path, &project) {
+            Ok(()) => format!("saved project to {}", path.display()),
+            Err(err) => format!("failed to save project to {}: {}", path.display(), err),
+        });
+    }
+
+    fn load_project(&mut self) {
+        let path = PathBuf::from(&self.project_path);
+        let project = match project::load_project(&path) {
+            Ok(project) => project,
+            Err(err) => {
+                self.project_status =
+                    Some(format!("failed to load project from {}: {}", path.display(), err));
+                return;
+            }
+        };
+
+        let map_version = project.map_version;
+        self.apply_project(project);
+
+        self.project_status = Some(match map_version {
+            Some(version) if version != self.map_version => format!(
+                "loaded project, but it was made for map version {} (current: {})",
+                version, self.map_version
+            ),
+            _ => format!("loaded project from {}", path.display()),
+        });
+    }
+
+    /// Restores what [`eframe::App::save`] stored on the last run.
+    fn restore_session(&mut self, storage: &dyn eframe::Storage) {
+        if let Some(session) = eframe::get_value::<Project>(storage, SESSION_KEY) {
+            self.apply_project(session);
+        }
+    }
+
+    /// Forgets the parameters, layer selection and window layout, going back
+    /// to what a first start looks like.
+    fn reset_session(&mut self, ctx: &egui::Context) {
+        self.apply_project(Project::default());
+        ctx.memory_mut(|memory| *memory = Default::default());
+    }
+
+    /// Runs the clustering in the background from its current state, or from
+    /// fresh points if `reinitialize` is set, superseding a running one.
+    fn start_job(&mut self, reinitialize: bool) {
+        self.cancel_job();
+        if reinitialize {
+            self.teleports.clear();
+        }
+        let clustering = self.clustering.clone();
+        self.job = Some(Job::start(self.problem_input(), clustering, reinitialize, false));
+    }
+
+    /// Runs the clustering in the background from the best points so far,
+    /// unless they are for another k or other markers.
+    fn continue_from_best(&mut self) {
+        let k = self.problem().effective_k();
+        let best = self.clustering.best_so_far_points.len();
+        let markers = self.problem().result_key().markers;
+        self.continue_refused = if best == 0 {
+            Some("No best so far to continue from".to_string())
+        } else if best != k {
+            Some(format!("The best so far has {} stations, not {}", best, k))
+        } else if self.result_key.is_some_and(|key| key.markers != markers) {
+            Some("The markers changed since the best so far was found".to_string())
+        } else {
+            None
+        };
+        if self.continue_refused.is_some() {
+            return;
+        }
+
+        self.cancel_job();
+        self.with_clustering(Clustering::continue_from_best);
+        self.points_changed();
+        self.start_job(false);
+    }
+
+    /// Keeps the stations but fits them to the clustered markers again, see
+    /// [`Clustering::refit`].
+    fn refit(&mut self) {
+        self.cancel_job();
+        self.with_clustering(|clustering, problem| clustering.refit(problem, REFIT_STEPS));
+        self.points_changed();
+    }
+
+    /// Runs a single iteration in the background, from fresh points if k
+    /// changed since the last.
+    fn start_step(&mut self) {
+        self.cancel_job();
+        let reinitialize = self.clustering.sets.len() != self.problem().effective_k();
+        if reinitialize {
+            self.teleports.clear();
+        }
+        let clustering = self.clustering.clone();
+        self.job = Some(Job::start(self.problem_input(), clustering, reinitialize, true));
+    }
+
+    fn cancel_job(&mut self) {
+        if let Some(job) = self.job.take() {
+            job.cancel();
+        }
+    }
+
+    /// Shows the latest state of the background run.
+    fn poll_job(&mut self, ctx: &egui::Context) {
+        let Some(job) = &mut self.job else {
+            return;
+        };
+
+        let latest = job.poll();
+        let running = job.running;
+        if let Some(clustering) = latest {
+            self.clustering = clustering;
+            self.points_changed();
+        }
+        if !running {
+            self.job = None;
+        }
+        // keeps "run continuously" going once the run is done
+        ctx.request_repaint();
+    }
+
+    /// Brings what is derived from the current points, their teleport
+    /// commands and set errors, up to date after they changed.
+    fn points_changed(&mut self) {
+        self.teleports = self.teleport_commands();
+        let clustering = &self.clustering;
+        let errors = self.problem().set_errors(
+            &clustering.points,
+            &clustering.heights,
+            &clustering.sets,
+        );
+        self.clustering.set_errors = errors;
+    }
+
+    /// Teleport commands for the current points, at their heights.
+    fn teleport_commands(&self) -> Vec<String> {
+        self.clustering.points
+            .iter()
+            .zip(&self.clustering.heights)
+            .map(|(point, z)| export::teleport_command(point.x, point.y, *z))
+            .collect()
+    }
+}
+
+/// The most recent modification time among the files behind the sources.
+fn sources_mtime(sources: &[MapSource]) -> Option<SystemTime> {
+    map::expand_sources(sources)
+        .ok()?
+        .iter()
+        .filter_map(|source| match source {
+            MapSource::Path(path) => std::fs::metadata(path).and_then(|m| m.modified()).ok(),
+            MapSource::Stdin => None,
+        })
+        .max()
+}
+
+/// Parses station coordinates, either one "x, y" (or "x y") per line or a
+/// JSON list of `[x, y]` pairs or `{"x": .., "y": ..}` objects.
+fn parse_points(text: &str) -> Result<Vec<Pos2>, String> {
+    let text = text.trim();
+    if text.starts_with('[') {
+        let values: Vec<serde_json::Value> =
+            serde_json::from_str(text).map_err(|err| format!("invalid JSON: {}", err))?;
+        return values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let coordinate = |index: usize, key: &str| {
+                    value
+                        .get(index)
+                        .or_else(|| value.get(key))
+                        .and_then(serde_json::Value::as_f64)
+                        .map(|v| v as f32)
+                };
+                match (coordinate(0, "x"), coordinate(1, "y")) {
+                    (Some(x), Some(y)) => Ok(pos2(x, y)),
+                    _ => Err(format!("point {} has no x and y", i + 1)),
+                }
+            })
+            .collect();
+    }
+
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let numbers: Vec<f32> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|err| format!("line {}: {}", i + 1, err))?;
+            match numbers[..] {
+                [x, y, ..] => Ok(pos2(x, y)),
+                _ => Err(format!("line {} needs an x and a y coordinate", i + 1)),
+            }
+        })
+        .collect()
+}
+
+/// Draws a no-station zone given in game coordinates.
+fn paint_region(canvas: &mut impl Canvas, to_screen: &RectTransform, region: Rect) {
+    let rect = Rect::from_two_pos(
+        to_screen.transform_pos(region.min),
+        to_screen.transform_pos(region.max),
+    );
+    canvas.rect(rect, Color32::TRANSPARENT, Stroke::new(1.5, Color32::LIGHT_BLUE));
+}
+
+/// Color of the rail or road network distances are measured along.
+const NETWORK_COLOR: Color32 = Color32::from_rgb(90, 200, 200);
+
+fn paint_zone(canvas: &mut impl Canvas, to_screen: &RectTransform, zone: Rect) {
+    let rect = Rect::from_two_pos(
+        to_screen.transform_pos(zone.min),
+        to_screen.transform_pos(zone.max),
+    );
+    canvas.rect(
+        rect,
+        Color32::from_rgba_unmultiplied(255, 0, 0, 50),
+        Stroke::new(1.0, Color32::RED),
+    );
+}
+
+/// A checkbox enabling a drag value, with `None` meaning disabled.
+fn optional_drag_value<T: egui::emath::Numeric>(
+    ui: &mut egui::Ui,
+    value: &mut Option<T>,
+    label: &str,
+    default: T,
+) {
+    ui.horizontal(|ui| {
+        let mut enabled = value.is_some();
+        ui.checkbox(&mut enabled, label);
+        match (enabled, value.as_mut()) {
+            (true, Some(v)) => {
+                ui.add(DragValue::new(v));
+            }
+            (true, None) => *value = Some(default),
+            (false, _) => *value = None,
+        }
+    });
+}
+
+/// A value over k for [`line_chart`].
+struct LineChart {
+    /// k and the value at it, by increasing k.
+    points: Vec<(usize, f32)>,
+    /// How far the error bar of every point reaches up and down, if any.
+    spreads: Option<Vec<f32>>,
+    /// The k to mark, with its label and colour.
+    marked: Option<(usize, String, Color32)>,
+    /// Whether the value axis starts at zero rather than at the lowest value.
+    from_zero: bool,
+}
+
+/// Paints a line chart of values over k, like the sweep and the gap
+/// statistic give, by hand as egui_plot isn't a dependency. Shows
+/// `hover_text` of the point closest to the pointer and returns the index of
+/// the point clicked on.
+fn line_chart(
+    ui: &mut egui::Ui,
+    chart: LineChart,
+    hover_text: impl Fn(usize) -> String,
+) -> Option<usize> {
+    let LineChart {
+        points,
+        spreads,
+        marked,
+        from_zero,
+    } = chart;
+    if points.len() < 2 {
+        return None;
+    }
+    let spread = |i: usize| spreads.as_ref().map_or(0.0, |spreads| spreads[i]);
+
+    let size = vec2(ui.available_width(), 150.0);
+    let (response, painter) = ui.allocate_painter(size, Sense::click());
+    let frame = response.rect.shrink(8.0);
+    painter.rect_stroke(response.rect, 0.0, ui.visuals().widgets.noninteractive.bg_stroke);
+
+    let first_k = points[0].0 as f32;
+    let last_k = points[points.len() - 1].0 as f32;
+    let (low, high) = points.iter().enumerate().fold(
+        (if from_zero { 0.0 } else { f32::MAX }, f32::MIN),
+        |(low, high), (i, (_, value))| (low.min(value - spread(i)), high.max(value + spread(i))),
+    );
+    let to_screen = |k: usize, value: f32| {
+        pos2(
+            egui::lerp(frame.x_range(), (k as f32 - first_k) / (last_k - first_k)),
+            egui::lerp(frame.bottom()..=frame.top(), (value - low) / (high - low).max(1e-6)),
+        )
+    };
+
+    let line = ui.visuals().text_color();
+    let screen: Vec<Pos2> = points.iter().map(|(k, value)| to_screen(*k, *value)).collect();
+    painter.add(egui::Shape::line(screen.clone(), Stroke::new(1.5, line)));
+    for (i, ((k, value), point)) in points.iter().zip(&screen).enumerate() {
+        if spreads.is_some() {
+            let bar = [to_screen(*k, value - spread(i)), to_screen(*k, value + spread(i))];
+            painter.line_segment(bar, Stroke::new(1.0, line));
+        }
+        match &marked {
+            Some((marked, _, color)) if marked == k => {
+                painter.circle_filled(*point, 5.0, *color);
+            }
+            _ => {
+                painter.circle_filled(*point, 3.0, line);
+            }
+        }
+    }
+    if let Some((_, label, color)) = &marked {
+        painter.text(
+            frame.right_top(),
+            egui::Align2::RIGHT_TOP,
+            label,
+            egui::FontId::proportional(12.0),
+            *color,
+        );
+    }
+
+    let pointer = response.hover_pos()?;
+    let (i, point) = screen
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.distance(pointer).total_cmp(&b.1.distance(pointer)))?;
+    painter.circle_stroke(*point, 6.0, Stroke::new(1.0, line));
+    let clicked = response.clicked();
+    response.on_hover_text(hover_text(i));
+    clicked.then_some(i)
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(ParseOutcome::Run(args)) => args,
+        Ok(ParseOutcome::Plan(args)) => return plan::run(&args),
+        Ok(ParseOutcome::Help) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut map_status = None;
+    let downloaded = if args.download {
+        match download::cached_or_fetch(&args.map_url) {
+            Ok(map) => Some(map),
+            Err(err) => {
+                eprintln!("warning: {}, falling back to local map data", err);
+                map_status = Some(err.to_string());
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut load_error = None;
+    let (data, map_sources) = match downloaded {
+        Some(map) => (MapData::from_map(args.map_url.clone(), &map), Vec::new()),
+        None => match map::load_maps(&args.maps) {
+            Ok(data) => (data, args.maps),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                load_error = Some(err.to_string());
+                (MapData::default(), args.maps)
+            }
+        },
+    };
+    for summary in &data.sources {
+        eprintln!(
+            "loaded {}: {} layers, {} markers in {:.0?}{}",
+            summary.source,
+            summary.layers,
+            summary.markers,
+            summary.load_time,
+            if summary.from_snapshot { " (from snapshot)" } else { "" }
+        );
+        for skipped in &summary.skipped {
+            eprintln!("warning: skipped {}", skipped);
+        }
+    }
+    if data.duplicates > 0 {
+        eprintln!("dropped {} duplicate markers", data.duplicates);
+    }
+
+    let mut frontend = Frontend::new(
+        data,
+        map_sources,
+        args.map_url,
+        args.custom_markers,
+        args.config,
+        args.exclusions,
+        args.layer_names,
+    );
+    frontend.map_status = map_status;
+    frontend.load_error = load_error;
+    if args.verbose {
+        eprintln!("layers = {:#?}", frontend.layers);
+    }
+
+    let native_options = eframe::NativeOptions {
+        persist_window: true,
+        ..Default::default()
+    };
+    eframe::run_native(
+        "Satisfactory station planner",
         native_options,
-        Box::new(|cc| Ok(Box::new(Frontend::new(layers, markers)))),
+        Box::new(|cc| {
+            if let Some(storage) = cc.storage {
+                frontend.restore_session(storage);
+            }
+            Ok(Box::new(frontend))
+        }),
     )
         .unwrap();
 
-    Ok(())
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,1027 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt, fs,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use egui::{Pos2, Rect};
+use flate2::bufread::GzDecoder;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+use serde_json::{error::Category, Value};
+
+use crate::snapshot;
+
+// Fields the upstream data has not always carried default instead of failing
+// the parse. So far all schema versions share this layout, so `Map.version`
+// is only reported, not used to pick a parser.
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+pub struct Map {
+    pub options: Lenient<Resources>,
+    #[serde(default)]
+    pub version: i64,
+    #[serde(default)]
+    pub lastBuild: String,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Deserialize)]
+pub struct Resources {
+    #[serde(default)]
+    pub tabId: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub options: Lenient<ResourceCategory>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResourceCategory {
+    #[serde(default)]
+    pub name: String,
+    pub r#type: Option<String>,
+    #[serde(default)]
+    pub options: Lenient<ResourceLayer>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResourceLayer {
+    pub layerId: String,
+    #[serde(default)]
+    pub name: String,
+    pub purity: Option<Purity>,
+    #[serde(default)]
+    pub outsideColor: String,
+    #[serde(default)]
+    pub insideColor: String,
+    #[serde(default)]
+    pub icon: String,
+    #[serde(default)]
+    pub markers: Vec<ResourceMarker>,
+    /// For a layer of collectibles rather than resources, the name of its
+    /// category, like "Power Slugs". Filled in when flattening.
+    #[serde(skip)]
+    pub collectible: Option<String>,
+    /// What kind of node the markers are, from the type of the category.
+    /// Filled in when flattening.
+    #[serde(skip)]
+    pub kind: NodeKind,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResourceMarker {
+    pub pathName: String,
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub z: f32,
+    pub r#type: Option<String>,
+    #[serde(default)]
+    pub purity: Purity,
+    pub obstructed: Option<bool>,
+    #[serde(default)]
+    pub lastCheck: LastCheck,
+    /// `pathName` of the pressurizer a resource well satellite belongs to.
+    pub core: Option<String>,
+    /// Index of the layer this marker belongs to, filled in when flattening.
+    #[serde(skip)]
+    pub layer: usize,
+    /// The kind of its layer, filled in when flattening.
+    #[serde(skip)]
+    pub kind: NodeKind,
+    /// For a pressurizer marker, the indices of its satellites.
+    #[serde(skip)]
+    pub satellites: Vec<usize>,
+}
+
+/// A list whose entries are deserialized one by one, setting aside those that
+/// fail instead of failing the whole list.
+#[derive(Debug, Clone)]
+pub struct Lenient<T> {
+    pub items: Vec<T>,
+    pub skipped: Vec<Skipped>,
+}
+
+/// An entry of a [`Lenient`] list that could not be deserialized.
+#[derive(Debug, Clone)]
+pub struct Skipped {
+    /// The entry's id or name, if it has a recognizable one.
+    pub id: String,
+    pub reason: String,
+}
+
+impl<T> Default for Lenient<T> {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Lenient<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Lenient<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut lenient = Lenient::default();
+        for (i, value) in Vec::<Value>::deserialize(deserializer)?.into_iter().enumerate() {
+            let id = ["layerId", "tabId", "name"]
+                .iter()
+                .find_map(|key| value.get(key).and_then(Value::as_str))
+                .map_or_else(|| format!("#{}", i), str::to_string);
+            match serde_json::from_value(value) {
+                Ok(item) => lenient.items.push(item),
+                Err(err) => lenient.skipped.push(Skipped {
+                    id,
+                    reason: err.to_string(),
+                }),
+            }
+        }
+
+        Ok(lenient)
+    }
+}
+
+impl Map {
+    /// Everything that was set aside while parsing, as "path: reason".
+    pub fn skipped_entries(&self) -> Vec<String> {
+        let mut skipped = Vec::new();
+        let mut report = |path: String, list: &[Skipped]| {
+            for s in list {
+                skipped.push(format!("{}{}: {}", path, s.id, s.reason));
+            }
+        };
+
+        report(String::new(), &self.options.skipped);
+        for tab in &self.options {
+            report(format!("{}/", tab.name), &tab.options.skipped);
+            for category in &tab.options {
+                report(format!("{}/{}/", tab.name, category.name), &category.options.skipped);
+            }
+        }
+
+        skipped
+    }
+}
+
+impl ResourceMarker {
+    pub fn is_pressurizer(&self) -> bool {
+        !self.satellites.is_empty()
+    }
+
+    pub fn is_satellite(&self) -> bool {
+        self.core.is_some()
+    }
+
+    /// Whether the marker is on the side of a resource well in use: the
+    /// pressurizer with wells collapsed, the satellites otherwise. Markers of
+    /// no well are always in use.
+    pub fn is_used_part_of_well(&self, collapse_wells: bool) -> bool {
+        if collapse_wells {
+            !self.is_satellite()
+        } else {
+            !self.is_pressurizer()
+        }
+    }
+    /// Whether this marker was verified more recently than `other`.
+    pub fn checked_after(&self, other: &ResourceMarker) -> bool {
+        use LastCheck::*;
+        match (&self.lastCheck, &other.lastCheck) {
+            (Date(a), Date(b)) => a > b,
+            (Build(a), Build(b)) => a > b,
+            (Date(_), Build(_)) => true,
+            (Date(_) | Build(_), Unknown(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// When the site last verified a marker. Recent exports store the game build
+/// the check was made on, older ones a date or unix timestamp.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LastCheck {
+    Date(DateTime<Utc>),
+    Build(u32),
+    Unknown(String),
+}
+
+impl Default for LastCheck {
+    fn default() -> Self {
+        LastCheck::Unknown(String::new())
+    }
+}
+
+impl LastCheck {
+    /// Numbers below this are game build numbers rather than unix timestamps.
+    const MIN_TIMESTAMP: i64 = 100_000_000;
+
+    pub fn parse(s: &str) -> Self {
+        let s = s.trim();
+        if let Ok(n) = s.parse::<i64>() {
+            return Self::from_number(n).unwrap_or_else(|| LastCheck::Unknown(s.to_string()));
+        }
+
+        if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+            return LastCheck::Date(date.with_timezone(&Utc));
+        }
+        for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"] {
+            if let Ok(date) = NaiveDateTime::parse_from_str(s, format) {
+                return LastCheck::Date(date.and_utc());
+            }
+        }
+        for format in ["%Y-%m-%d", "%d/%m/%Y"] {
+            if let Ok(date) = NaiveDate::parse_from_str(s, format) {
+                return LastCheck::Date(date.and_time(Default::default()).and_utc());
+            }
+        }
+
+        LastCheck::Unknown(s.to_string())
+    }
+
+    fn from_number(n: i64) -> Option<Self> {
+        if n < 0 {
+            None
+        } else if n < Self::MIN_TIMESTAMP {
+            u32::try_from(n).ok().map(LastCheck::Build)
+        } else if n < 100_000_000_000 {
+            DateTime::from_timestamp(n, 0).map(LastCheck::Date)
+        } else {
+            DateTime::from_timestamp_millis(n).map(LastCheck::Date)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LastCheck {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(i64),
+            Text(String),
+            Other(serde::de::IgnoredAny),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Number(n) => {
+                LastCheck::from_number(n).unwrap_or_else(|| LastCheck::Unknown(n.to_string()))
+            }
+            Raw::Text(s) => LastCheck::parse(&s),
+            Raw::Other(_) => LastCheck::Unknown(String::new()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Purity {
+    Impure,
+    Normal,
+    Pure,
+    Unknown(String),
+}
+
+impl Default for Purity {
+    fn default() -> Self {
+        Purity::Unknown(String::new())
+    }
+}
+
+impl fmt::Display for Purity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Purity::Impure => write!(f, "impure"),
+            Purity::Normal => write!(f, "normal"),
+            Purity::Pure => write!(f, "pure"),
+            Purity::Unknown(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl From<&str> for Purity {
+    fn from(s: &str) -> Self {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "impure" => Purity::Impure,
+            "normal" => Purity::Normal,
+            "pure" => Purity::Pure,
+            _ => Purity::Unknown(s.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Purity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(Purity::from(s.as_str()))
+    }
+}
+
+/// How much a single map file contributed to the merged data.
+#[derive(Debug, Clone)]
+pub struct SourceSummary {
+    pub source: String,
+    pub version: i64,
+    /// `Map.lastBuild` as it was given.
+    pub last_build: String,
+    pub layers: usize,
+    pub markers: usize,
+    /// Entries that could not be parsed, see [`Map::skipped_entries`].
+    pub skipped: Vec<String>,
+    /// Whether the data came from a snapshot rather than the JSON itself.
+    pub from_snapshot: bool,
+    pub load_time: Duration,
+}
+
+impl SourceSummary {
+    /// When the map data was built. `lastBuild` has been a date, a timestamp
+    /// and a game build number, so if it isn't a date the version is tried,
+    /// which recent exports set to the export's unix timestamp.
+    pub fn build_date(&self) -> Option<DateTime<Utc>> {
+        match LastCheck::parse(&self.last_build) {
+            LastCheck::Date(date) => Some(date),
+            LastCheck::Build(_) | LastCheck::Unknown(_) => {
+                match LastCheck::from_number(self.version) {
+                    Some(LastCheck::Date(date)) => Some(date),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Layers and markers flattened from one or more maps. The layers are kept
+/// without their markers, which all live in `markers` instead.
+#[derive(Debug, Default)]
+pub struct MapData {
+    pub layers: Vec<ResourceLayer>,
+    pub markers: Vec<ResourceMarker>,
+    pub sources: Vec<SourceSummary>,
+    /// Markers dropped because another entry had the same `pathName`.
+    pub duplicates: usize,
+    layer_ids: HashMap<String, usize>,
+    marker_ids: HashMap<String, usize>,
+}
+
+impl MapData {
+    pub fn from_map(source: String, map: &Map) -> Self {
+        let mut data = Self::default();
+        data.extract_layers_and_markers(source, map);
+        data
+    }
+
+    /// The newest version among the merged maps.
+    pub fn version(&self) -> i64 {
+        self.sources.iter().map(|s| s.version).max().unwrap_or_default()
+    }
+
+    /// Flattens the option tree of `map` into its layers and the markers of
+    /// all layers. Layers sharing a `layerId` are merged and a node listed
+    /// several times only appears once, taking the most recently checked
+    /// entry.
+    pub fn extract_layers_and_markers(&mut self, source: String, map: &Map) {
+        let mut summary = SourceSummary {
+            source,
+            version: map.version,
+            last_build: map.lastBuild.clone(),
+            layers: 0,
+            markers: 0,
+            skipped: map.skipped_entries(),
+            from_snapshot: false,
+            load_time: Duration::ZERO,
+        };
+
+        for o0 in &map.options {
+            for o1 in &o0.options {
+                let collectible = (!is_resource_tab(&o0.tabId)).then(|| o1.name.clone());
+                let kind = NodeKind::classify(&o0.tabId, o1.r#type.as_deref());
+                for o2 in &o1.options {
+                    summary.layers += 1;
+                    let layer = self.add_layer(ResourceLayer {
+                        markers: Vec::new(),
+                        collectible: collectible.clone(),
+                        kind,
+                        ..o2.clone()
+                    });
+                    for marker in &o2.markers {
+                        summary.markers += 1;
+                        self.add_marker(ResourceMarker {
+                            layer,
+                            kind,
+                            ..marker.clone()
+                        });
+                    }
+                }
+            }
+        }
+
+        self.sources.push(summary);
+    }
+
+    /// Adds the data of other maps, merging it like
+    /// [`MapData::extract_layers_and_markers`] does.
+    pub fn merge(&mut self, other: MapData) {
+        let layers: Vec<usize> = other.layers.into_iter().map(|l| self.add_layer(l)).collect();
+        for marker in other.markers {
+            self.add_marker(ResourceMarker {
+                layer: layers[marker.layer],
+                ..marker
+            });
+        }
+        self.sources.extend(other.sources);
+        self.duplicates += other.duplicates;
+    }
+
+    /// Index of the layer with the `layerId` of `layer`, adding it if needed.
+    fn add_layer(&mut self, layer: ResourceLayer) -> usize {
+        *self.layer_ids.entry(layer.layerId.clone()).or_insert_with(|| {
+            self.layers.push(layer);
+            self.layers.len() - 1
+        })
+    }
+
+    fn add_marker(&mut self, marker: ResourceMarker) {
+        match self.marker_ids.get(&marker.pathName) {
+            Some(&i) => {
+                self.duplicates += 1;
+                if marker.checked_after(&self.markers[i]) {
+                    self.markers[i] = marker;
+                }
+            }
+            None => {
+                self.marker_ids.insert(marker.pathName.clone(), self.markers.len());
+                self.markers.push(marker);
+            }
+        }
+    }
+}
+
+/// The earliest build date among the given sources, see
+/// [`SourceSummary::build_date`].
+pub fn oldest_build_date(sources: &[SourceSummary]) -> Option<DateTime<Utc>> {
+    sources.iter().filter_map(SourceSummary::build_date).min()
+}
+
+/// Whether a tab of the map holds resources to plan around, as opposed to
+/// collectibles like power slugs or crash sites.
+fn is_resource_tab(tab_id: &str) -> bool {
+    matches!(tab_id, "resource_nodes" | "resource_wells")
+}
+
+/// How the resource of a layer is extracted, which decides whether it is
+/// usually shipped by train or piped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    Solid,
+    /// A node of a fluid, like crude oil, tapped by an extractor.
+    Liquid,
+    /// The satellites of a resource well and their pressurizers.
+    Well,
+    /// Anything without a known resource type: geysers, collectibles and
+    /// layers that do not come from the map.
+    #[default]
+    Other,
+}
+
+impl NodeKind {
+    pub const ALL: [NodeKind; 4] = [
+        NodeKind::Solid,
+        NodeKind::Liquid,
+        NodeKind::Well,
+        NodeKind::Other,
+    ];
+
+    /// Classifies a layer by the tab it is in and its category's type, like
+    /// `Desc_OreIron_C`.
+    pub fn classify(tab_id: &str, category_type: Option<&str>) -> Self {
+        const LIQUID_TYPES: [&str; 3] = ["Desc_LiquidOil_C", "Desc_Water_C", "Desc_NitrogenGas_C"];
+
+        let Some(t) = category_type else {
+            return NodeKind::Other;
+        };
+        match tab_id {
+            "resource_wells" if t != "Desc_Geyser_C" => NodeKind::Well,
+            "resource_nodes" if LIQUID_TYPES.contains(&t) || t.starts_with("Desc_Liquid") => {
+                NodeKind::Liquid
+            }
+            "resource_nodes" if t.starts_with("Desc_") => NodeKind::Solid,
+            _ => NodeKind::Other,
+        }
+    }
+
+    /// Whether the resource is a fluid and so usually piped rather than
+    /// shipped by train.
+    pub fn is_fluid(self) -> bool {
+        matches!(self, NodeKind::Liquid | NodeKind::Well)
+    }
+
+    pub fn heading(self) -> &'static str {
+        match self {
+            NodeKind::Solid => "Solid nodes",
+            NodeKind::Liquid => "Liquid nodes",
+            NodeKind::Well => "Resource wells",
+            NodeKind::Other => "Other",
+        }
+    }
+}
+
+impl fmt::Display for NodeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeKind::Solid => write!(f, "solid"),
+            NodeKind::Liquid => write!(f, "liquid"),
+            NodeKind::Well => write!(f, "well"),
+            NodeKind::Other => write!(f, "other"),
+        }
+    }
+}
+
+impl From<&str> for NodeKind {
+    fn from(s: &str) -> Self {
+        match s {
+            "solid" => NodeKind::Solid,
+            "liquid" => NodeKind::Liquid,
+            "well" => NodeKind::Well,
+            _ => NodeKind::Other,
+        }
+    }
+}
+
+/// Why a marker's position can not be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkerIssue {
+    NonFinite,
+    AtOrigin,
+    OutOfBounds,
+}
+
+impl fmt::Display for MarkerIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarkerIssue::NonFinite => write!(f, "coordinates are not finite"),
+            MarkerIssue::AtOrigin => write!(f, "sits exactly at the origin"),
+            MarkerIssue::OutOfBounds => write!(f, "lies outside the map"),
+        }
+    }
+}
+
+impl ResourceMarker {
+    /// Checks the position against the world bounds. Markers at (0, 0, 0) are
+    /// placeholders rather than real nodes.
+    pub fn issue(&self, world: Rect) -> Option<MarkerIssue> {
+        if !(self.x.is_finite() && self.y.is_finite() && self.z.is_finite()) {
+            Some(MarkerIssue::NonFinite)
+        } else if self.x == 0.0 && self.y == 0.0 && self.z == 0.0 {
+            Some(MarkerIssue::AtOrigin)
+        } else if !world.contains(Pos2::new(self.x, self.y)) {
+            Some(MarkerIssue::OutOfBounds)
+        } else {
+            None
+        }
+    }
+}
+
+/// Builds a marker for the pressurizer of every resource well, standing in
+/// for all its satellites. The map data has no pressurizer positions, so the
+/// marker sits at the satellites' centroid and takes their most common purity.
+pub fn pressurizer_markers(markers: &[ResourceMarker]) -> Vec<ResourceMarker> {
+    let mut wells: Vec<(&str, Vec<usize>)> = Vec::new();
+    for (i, marker) in markers.iter().enumerate() {
+        if let Some(core) = &marker.core {
+            match wells.iter_mut().find(|(c, _)| c == core) {
+                Some((_, satellites)) => satellites.push(i),
+                None => wells.push((core, vec![i])),
+            }
+        }
+    }
+
+    wells
+        .into_iter()
+        .map(|(core, satellites)| {
+            let n = satellites.len() as f32;
+            let centroid = |f: fn(&ResourceMarker) -> f32| {
+                satellites.iter().map(|i| f(&markers[*i])).sum::<f32>() / n
+            };
+
+            let mut purities: Vec<(&Purity, usize)> = Vec::new();
+            for i in &satellites {
+                match purities.iter_mut().find(|(p, _)| **p == markers[*i].purity) {
+                    Some((_, count)) => *count += 1,
+                    None => purities.push((&markers[*i].purity, 1)),
+                }
+            }
+            let purity = purities
+                .iter()
+                .max_by_key(|(_, count)| *count)
+                .map(|(p, _)| (*p).clone())
+                .unwrap_or(Purity::Unknown(String::new()));
+            let representative = satellites
+                .iter()
+                .map(|i| &markers[*i])
+                .find(|m| m.purity == purity)
+                .unwrap_or(&markers[satellites[0]]);
+
+            ResourceMarker {
+                pathName: core.to_string(),
+                x: centroid(|m| m.x),
+                y: centroid(|m| m.y),
+                z: centroid(|m| m.z),
+                purity,
+                core: None,
+                satellites,
+                ..representative.clone()
+            }
+        })
+        .collect()
+}
+
+/// Where the map JSON is read from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapSource {
+    Path(PathBuf),
+    Stdin,
+}
+
+impl fmt::Display for MapSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapSource::Path(path) => write!(f, "{}", path.display()),
+            MapSource::Stdin => write!(f, "<stdin>"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io { source: MapSource, err: io::Error },
+    Parse { source: MapSource, err: serde_json::Error },
+    /// The gzip stream ended early or is corrupt.
+    Decompress { source: MapSource, err: serde_json::Error },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { source, err } => {
+                write!(f, "failed to read map data from {}: {}", source, err)
+            }
+            LoadError::Parse { source, err } => {
+                let hint = match err.classify() {
+                    Category::Eof => " (the file ends early, it may be truncated)",
+                    Category::Data => " (the data does not match the expected format)",
+                    Category::Syntax | Category::Io => "",
+                };
+                write!(f, "failed to parse map data from {}: {}{}", source, err, hint)
+            }
+            LoadError::Decompress { source, err } => {
+                write!(f, "failed to decompress map data from {}: {}", source, err)
+            }
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io { err, .. } => Some(err),
+            LoadError::Parse { err, .. } => Some(err),
+            LoadError::Decompress { err, .. } => Some(err),
+        }
+    }
+}
+
+/// Replaces directories by the JSON files (plain or gzipped) inside them, in
+/// name order.
+pub fn expand_sources(sources: &[MapSource]) -> Result<Vec<MapSource>, LoadError> {
+    let mut expanded = Vec::new();
+    for source in sources {
+        match source {
+            MapSource::Path(path) if path.is_dir() => {
+                let io_err = |err| LoadError::Io {
+                    source: source.clone(),
+                    err,
+                };
+                let mut files = fs::read_dir(path)
+                    .map_err(io_err)?
+                    .map(|entry| entry.map(|e| e.path()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(io_err)?;
+                files.retain(|p| p.is_file() && is_map_file(p));
+                files.sort();
+                expanded.extend(files.into_iter().map(MapSource::Path));
+            }
+            _ => expanded.push(source.clone()),
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Loads and merges all given sources, see [`expand_sources`].
+pub fn load_maps(sources: &[MapSource]) -> Result<MapData, LoadError> {
+    let mut data = MapData::default();
+    for source in expand_sources(sources)? {
+        data.merge(load_source(&source)?);
+    }
+
+    Ok(data)
+}
+
+/// Loads the data of a single source, from its snapshot if there is an up to
+/// date one, and refreshes the snapshot otherwise.
+fn load_source(source: &MapSource) -> Result<MapData, LoadError> {
+    let started = Instant::now();
+    let mut data = match source {
+        MapSource::Path(path) => {
+            let bytes = fs::read(path).map_err(|err| LoadError::Io {
+                source: source.clone(),
+                err,
+            })?;
+            let hash = snapshot::hash(&bytes);
+            match snapshot::load(path, hash) {
+                Some(data) => data,
+                None => {
+                    let gzip_extension = path.extension().is_some_and(|e| e == "gz");
+                    let map = parse_map(source, bytes.as_slice(), gzip_extension)?;
+                    let data = MapData::from_map(source.to_string(), &map);
+                    // without a snapshot the next launch just parses again
+                    let _ = snapshot::save(path, hash, &data);
+                    data
+                }
+            }
+        }
+        MapSource::Stdin => MapData::from_map(source.to_string(), &load_map(source)?),
+    };
+
+    for summary in &mut data.sources {
+        summary.load_time = started.elapsed();
+    }
+    Ok(data)
+}
+
+fn is_map_file(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+    name.ends_with(".json") || name.ends_with(".json.gz")
+}
+
+pub fn load_map(source: &MapSource) -> Result<Map, LoadError> {
+    let io_err = |err| LoadError::Io {
+        source: source.clone(),
+        err,
+    };
+
+    match source {
+        MapSource::Path(path) => {
+            let file = fs::File::open(path).map_err(io_err)?;
+            let gzip_extension = path.extension().is_some_and(|e| e == "gz");
+            parse_map(source, BufReader::new(file), gzip_extension)
+        }
+        MapSource::Stdin => parse_map(source, io::stdin().lock(), false),
+    }
+}
+
+/// Parses map JSON from `reader`, decompressing it on the fly if it is
+/// gzipped, which is detected from its magic bytes.
+fn parse_map<R: BufRead>(
+    source: &MapSource,
+    mut reader: R,
+    gzip_extension: bool,
+) -> Result<Map, LoadError> {
+    const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+    let header = reader.fill_buf().map_err(|err| LoadError::Io {
+        source: source.clone(),
+        err,
+    })?;
+
+    if gzip_extension || header.starts_with(&GZIP_MAGIC) {
+        serde_json::from_reader(BufReader::new(GzDecoder::new(reader))).map_err(|err| {
+            if err.is_io() {
+                LoadError::Decompress {
+                    source: source.clone(),
+                    err,
+                }
+            } else {
+                LoadError::Parse {
+                    source: source.clone(),
+                    err,
+                }
+            }
+        })
+    } else {
+        serde_json::from_reader(reader).map_err(|err| LoadError::Parse {
+            source: source.clone(),
+            err,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    const MAP: &str = r#"{
+        "options": [{
+            "tabId": "resource_nodes",
+            "name": "Resources",
+            "options": [{
+                "name": "Iron",
+                "type": "Desc_OreIron_C",
+                "options": [{
+                    "layerId": "ironPure",
+                    "name": "Pure",
+                    "markers": [{"pathName": "Node_1", "x": 1, "y": 2, "purity": "pure"}]
+                }]
+            }]
+        }]
+    }"#;
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn parse(bytes: &[u8], gzip_extension: bool) -> Result<MapData, LoadError> {
+        let map = parse_map(&MapSource::Stdin, bytes, gzip_extension)?;
+        Ok(MapData::from_map("test".to_string(), &map))
+    }
+
+    #[test]
+    fn parses_plain_and_gzipped_maps() {
+        let gzipped = gzip(MAP.as_bytes());
+        for (bytes, gzip_extension) in [
+            (MAP.as_bytes(), false),
+            // detected from the magic bytes, without the extension
+            (gzipped.as_slice(), false),
+            (gzipped.as_slice(), true),
+        ] {
+            let data = parse(bytes, gzip_extension).unwrap();
+            assert_eq!(data.layers.len(), 1);
+            assert_eq!(data.markers.len(), 1);
+            assert_eq!(data.markers[0].pathName, "Node_1");
+            assert_eq!(data.markers[0].kind, NodeKind::Solid);
+        }
+    }
+
+    #[test]
+    fn reports_broken_archives() {
+        let gzipped = gzip(MAP.as_bytes());
+        let truncated = &gzipped[..gzipped.len() / 2];
+        assert!(matches!(parse(truncated, false), Err(LoadError::Decompress { .. })));
+        let mut corrupt = gzipped.clone();
+        corrupt[12..20].fill(0xff);
+        assert!(matches!(parse(&corrupt, false), Err(LoadError::Decompress { .. })));
+
+        // JSON that ends early inside an intact archive is a parse error
+        let cut = gzip(&MAP.as_bytes()[..MAP.len() / 2]);
+        let err = parse(&cut, false).unwrap_err();
+        assert!(matches!(err, LoadError::Parse { .. }));
+        assert!(err.to_string().contains("truncated"), "{}", err);
+        assert!(matches!(parse(b"{\"options\": 3}", false), Err(LoadError::Parse { .. })));
+    }
+
+    #[test]
+    fn purities_ignore_case() {
+        let markers: Vec<ResourceMarker> = serde_json::from_str(
+            r#"[
+                {"pathName": "a", "x": 1, "y": 1, "type": "iron", "purity": "PURE"},
+                {"pathName": "b", "x": 2, "y": 2, "type": "iron", "purity": "impure"},
+                {"pathName": "c", "x": 3, "y": 3, "type": "iron", "purity": " Normal "},
+                {"pathName": "d", "x": 4, "y": 4, "type": "iron", "purity": ""},
+                {"pathName": "e", "x": 5, "y": 5, "type": "iron", "purity": "Rich"},
+                {"pathName": "f", "x": 6, "y": 6, "type": "iron"}
+            ]"#,
+        )
+        .unwrap();
+        let purities: Vec<Purity> = markers.into_iter().map(|m| m.purity).collect();
+        assert_eq!(
+            purities,
+            [
+                Purity::Pure,
+                Purity::Impure,
+                Purity::Normal,
+                Purity::Unknown(String::new()),
+                Purity::Unknown("Rich".to_string()),
+                Purity::Unknown(String::new()),
+            ]
+        );
+        assert_eq!(Purity::Unknown("Rich".to_string()).to_string(), "Rich");
+    }
+
+    fn map_with_marker(marker: &str) -> Map {
+        let json = MAP.replace(
+            r#"{"pathName": "Node_1", "x": 1, "y": 2, "purity": "pure"}"#,
+            marker,
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn duplicates_keep_the_latest_check() {
+        let older = map_with_marker(
+            r#"{"pathName": "Node_1", "x": 1, "y": 2, "purity": "pure",
+                "lastCheck": "2023-01-01"}"#,
+        );
+        let newer = map_with_marker(
+            r#"{"pathName": "Node_1", "x": 5, "y": 6, "purity": "impure",
+                "lastCheck": "2024-06-01"}"#,
+        );
+        for maps in [[&older, &newer], [&newer, &older]] {
+            let mut data = MapData::default();
+            for map in maps {
+                data.extract_layers_and_markers("test".to_string(), map);
+            }
+            assert_eq!(data.markers.len(), 1);
+            assert_eq!(data.duplicates, 1);
+            assert_eq!((data.markers[0].x, data.markers[0].purity.clone()), (5.0, Purity::Impure));
+        }
+
+        // a dated check beats one without any
+        let unchecked = map_with_marker(r#"{"pathName": "Node_1", "x": 9, "y": 9}"#);
+        let mut data = MapData::from_map("older".to_string(), &older);
+        data.merge(MapData::from_map("unchecked".to_string(), &unchecked));
+        assert_eq!(data.markers[0].x, 1.0);
+    }
+
+    #[test]
+    fn flags_markers_with_broken_positions() {
+        let world = crate::cluster::default_map_bounds();
+        let marker = |x: f32, y: f32, z: f32| {
+            let mut marker: ResourceMarker = serde_json::from_str(
+                r#"{"pathName": "Node_1", "x": 0, "y": 0, "type": "iron", "purity": "pure"}"#,
+            )
+            .unwrap();
+            (marker.x, marker.y, marker.z) = (x, y, z);
+            marker
+        };
+        assert_eq!(marker(1000.0, -2000.0, 0.0).issue(world), None);
+        assert_eq!(marker(f32::NAN, 0.0, 0.0).issue(world), Some(MarkerIssue::NonFinite));
+        assert_eq!(marker(0.0, 0.0, f32::INFINITY).issue(world), Some(MarkerIssue::NonFinite));
+        assert_eq!(marker(0.0, 0.0, 0.0).issue(world), Some(MarkerIssue::AtOrigin));
+        // only all three at zero is a placeholder
+        assert_eq!(marker(0.0, 0.0, 10.0).issue(world), None);
+        assert_eq!(marker(1e9, 0.0, 0.0).issue(world), Some(MarkerIssue::OutOfBounds));
+
+        // a missing type or an unknown purity is no reason to distrust the
+        // position
+        let untyped: ResourceMarker =
+            serde_json::from_str(r#"{"pathName": "a", "x": 5, "y": 5, "purity": "??"}"#).unwrap();
+        assert_eq!((untyped.r#type.as_deref(), untyped.issue(world)), (None, None));
+    }
+
+    #[test]
+    fn groups_a_real_well_under_its_pressurizer() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/map_data.json");
+        let mut markers = load_maps(&[MapSource::Path(path)]).unwrap().markers;
+        let core = "Persistent_Level:PersistentLevel.BP_FrackingCore2";
+        let mut satellites: Vec<usize> = (0..markers.len())
+            .filter(|i| markers[*i].core.as_deref() == Some(core))
+            .collect();
+        assert_eq!(satellites.len(), 8);
+
+        let pressurizers = pressurizer_markers(&markers);
+        let pressurizer = pressurizers.iter().find(|p| p.pathName == core).unwrap();
+        let mut grouped = pressurizer.satellites.clone();
+        grouped.sort();
+        satellites.sort();
+        assert_eq!(grouped, satellites);
+        let mean_x = satellites.iter().map(|i| markers[*i].x).sum::<f32>() / 8.0;
+        assert!((pressurizer.x - mean_x).abs() < 0.1);
+        // four of the eight satellites are pure
+        assert_eq!(pressurizer.purity, Purity::Pure);
+        assert_eq!(pressurizer.kind, NodeKind::Well);
+        assert_eq!(pressurizer.core, None);
+
+        // only one side of the well takes part at a time
+        let well_start = markers.len();
+        markers.extend(pressurizers);
+        let well: Vec<&ResourceMarker> = markers
+            .iter()
+            .filter(|m| m.core.as_deref() == Some(core) || m.pathName == core)
+            .collect();
+        assert_eq!(well.len(), 9);
+        let used = |collapse| well.iter().filter(|m| m.is_used_part_of_well(collapse)).count();
+        assert_eq!(used(true), 1);
+        assert_eq!(used(false), 8);
+        assert!(markers[..well_start]
+            .iter()
+            .filter(|m| !m.is_satellite())
+            .all(|m| m.is_used_part_of_well(true) && m.is_used_part_of_well(false)));
+    }
+}
@@ -0,0 +1,266 @@
+use std::{fs, path::Path};
+
+use egui::{vec2, Color32, ColorImage, Pos2, Rect};
+use serde::Serialize;
+use tiny_skia::Pixmap;
+
+/// Where stations can be built, as a grid of cells over an area of the map.
+/// Every cell knows its closest buildable cell, so a point off land is moved
+/// there without a search.
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildMask {
+    /// Area the cells cover, in game units.
+    bounds: Rect,
+    width: usize,
+    height: usize,
+    /// Per cell, row by row, whether stations can be built in it.
+    buildable: Vec<bool>,
+    /// Per cell, the index of the closest buildable cell by the distance of
+    /// their centers. None only if no cell is buildable.
+    nearest: Vec<Option<usize>>,
+}
+
+/// Reads a mask image spanning `bounds`: light pixels are buildable, dark or
+/// transparent ones aren't.
+pub fn load_mask(path: &Path, bounds: Rect) -> Result<BuildMask, String> {
+    let bytes = fs::read(path).map_err(|err| err.to_string())?;
+    let pixmap = Pixmap::decode_png(&bytes).map_err(|err| err.to_string())?;
+    // premultiplied, so transparent pixels are black
+    let buildable = pixmap
+        .data()
+        .chunks_exact(4)
+        .map(|rgba| rgba[..3].iter().map(|c| *c as u32).sum::<u32>() >= 3 * 128)
+        .collect();
+    Ok(BuildMask::new(bounds, pixmap.width() as usize, pixmap.height() as usize, buildable))
+}
+
+impl BuildMask {
+    /// A mask of `width` × `height` cells over `bounds`, given row by row.
+    pub fn new(bounds: Rect, width: usize, height: usize, buildable: Vec<bool>) -> Self {
+        assert_eq!(buildable.len(), width * height, "a cell per pixel");
+        let nearest = nearest_buildable(&buildable, width, height, bounds);
+        Self {
+            bounds,
+            width,
+            height,
+            buildable,
+            nearest,
+        }
+    }
+
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    /// Column and row of the cell a point falls in, points off the mask
+    /// going to the closest cell on its edge.
+    pub fn cell(&self, point: Pos2) -> (usize, usize) {
+        let column = (point.x - self.bounds.min.x) / self.bounds.width() * self.width as f32;
+        let row = (point.y - self.bounds.min.y) / self.bounds.height() * self.height as f32;
+        let clamp = |at: f32, cells: usize| (at.max(0.0) as usize).min(cells.saturating_sub(1));
+        (clamp(column, self.width), clamp(row, self.height))
+    }
+
+    /// Area of a cell in game units.
+    pub fn cell_rect(&self, (column, row): (usize, usize)) -> Rect {
+        let size = self.bounds.size() / vec2(self.width as f32, self.height as f32);
+        let min = self.bounds.min + vec2(column as f32, row as f32) * size;
+        Rect::from_min_size(min, size)
+    }
+
+    /// Whether a station can go at the point.
+    pub fn is_buildable(&self, point: Pos2) -> bool {
+        let (column, row) = self.cell(point);
+        self.buildable.get(row * self.width + column).copied().unwrap_or(true)
+    }
+
+    /// The point if it is buildable, else the spot closest to it in the
+    /// closest buildable cell, kept a quarter cell from its edges so it
+    /// doesn't fall into the neighboring cell. Unchanged without buildable
+    /// cells.
+    pub fn project(&self, point: Pos2) -> Pos2 {
+        if self.is_buildable(point) {
+            return point;
+        }
+        let (column, row) = self.cell(point);
+        let Some(Some(nearest)) = self.nearest.get(row * self.width + column) else {
+            return point;
+        };
+        let rect = self.cell_rect((nearest % self.width, nearest / self.width));
+        rect.shrink2(rect.size() / 4.0).clamp(point)
+    }
+
+    /// Cells stations can't go in as translucent red, the others clear, to
+    /// draw over the map.
+    pub fn overlay_image(&self) -> ColorImage {
+        let blocked = Color32::from_rgba_unmultiplied(220, 40, 40, 110);
+        let pixels = self
+            .buildable
+            .iter()
+            .map(|buildable| if *buildable { Color32::TRANSPARENT } else { blocked })
+            .collect();
+        ColorImage {
+            size: [self.width, self.height],
+            pixels,
+        }
+    }
+}
+
+/// The closest buildable cell to every cell, by an exact Euclidean distance
+/// transform in two passes: down every column to the closest buildable row,
+/// then along every row over the lower envelope of the parabolas those
+/// distances give (Felzenszwalb and Huttenlocher). Cells are measured in
+/// game units, as they need not be square.
+fn nearest_buildable(
+    buildable: &[bool],
+    width: usize,
+    height: usize,
+    bounds: Rect,
+) -> Vec<Option<usize>> {
+    let cell_width = (bounds.width() / width.max(1) as f32) as f64;
+    let cell_height = (bounds.height() / height.max(1) as f32) as f64;
+
+    // per cell, the closest buildable row in its column
+    let mut rows: Vec<Option<usize>> = vec![None; width * height];
+    for column in 0..width {
+        let mut last = None;
+        for row in 0..height {
+            if buildable[row * width + column] {
+                last = Some(row);
+            }
+            rows[row * width + column] = last;
+        }
+        let mut next = None;
+        for row in (0..height).rev() {
+            if buildable[row * width + column] {
+                next = Some(row);
+            }
+            let cell = &mut rows[row * width + column];
+            if let Some(below) = next {
+                if cell.is_none_or(|above| below - row < row - above) {
+                    *cell = Some(below);
+                }
+            }
+        }
+    }
+
+    let mut nearest = vec![None; width * height];
+    // columns on the lower envelope and from where on each is lowest
+    let mut hull: Vec<usize> = Vec::with_capacity(width);
+    let mut starts: Vec<f64> = Vec::with_capacity(width);
+    for row in 0..height {
+        // squared distance to the closest buildable cell of a column, with
+        // the column's own offset folded in
+        let lift = |column: usize| {
+            rows[row * width + column].map(|found| {
+                let dy = (found as f64 - row as f64) * cell_height;
+                let x = column as f64 * cell_width;
+                dy * dy + x * x
+            })
+        };
+        hull.clear();
+        starts.clear();
+        for column in 0..width {
+            let Some(lifted) = lift(column) else {
+                continue;
+            };
+            let x = column as f64 * cell_width;
+            loop {
+                let Some(&last) = hull.last() else {
+                    hull.push(column);
+                    starts.push(f64::NEG_INFINITY);
+                    break;
+                };
+                let last_x = last as f64 * cell_width;
+                let lifted_last = lift(last).expect("hull columns have a buildable cell");
+                // where the parabolas of the two columns cross
+                let cross = (lifted - lifted_last) / (2.0 * (x - last_x));
+                if cross <= *starts.last().expect("as long as the hull") {
+                    hull.pop();
+                    starts.pop();
+                } else {
+                    hull.push(column);
+                    starts.push(cross);
+                    break;
+                }
+            }
+        }
+        if hull.is_empty() {
+            continue;
+        }
+
+        let mut at = 0;
+        for column in 0..width {
+            let x = column as f64 * cell_width;
+            while at + 1 < hull.len() && starts[at + 1] < x {
+                at += 1;
+            }
+            let found = hull[at];
+            nearest[row * width + column] = rows[row * width + found].map(|r| r * width + found);
+        }
+    }
+    nearest
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::pos2;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    /// Distance between the centers of two cells in game units.
+    fn center_distance(mask: &BuildMask, a: usize, b: usize) -> f32 {
+        let center = |i: usize| mask.cell_rect((i % mask.width, i / mask.width)).center();
+        center(a).distance(center(b))
+    }
+
+    #[test]
+    fn nearest_buildable_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(96);
+        for (width, height, share) in [(1, 1, 0.5), (7, 1, 0.3), (13, 9, 0.1), (20, 20, 0.02)] {
+            // cells that aren't square, as distances are in game units
+            let bounds = Rect::from_min_size(pos2(-50.0, 10.0), vec2(40.0, 20.0));
+            let buildable: Vec<bool> = (0..width * height).map(|_| rng.gen_bool(share)).collect();
+            let mask = BuildMask::new(bounds, width, height, buildable.clone());
+            for cell in 0..width * height {
+                let brute = (0..width * height)
+                    .filter(|other| buildable[*other])
+                    .map(|other| center_distance(&mask, cell, other))
+                    .fold(None, |best: Option<f32>, d| Some(best.map_or(d, |b| b.min(d))));
+                let found = mask.nearest[cell].map(|other| center_distance(&mask, cell, other));
+                match (found, brute) {
+                    (Some(found), Some(brute)) => assert!((found - brute).abs() < 1e-3),
+                    (found, brute) => assert_eq!(found, brute),
+                }
+                assert!(mask.nearest[cell].is_none_or(|other| buildable[other]));
+            }
+        }
+    }
+
+    #[test]
+    fn projects_onto_buildable_cells() {
+        // a 4 × 3 mask over 400 × 300 with only the right column buildable
+        //   . . . #
+        //   . . . #
+        //   . . . #
+        let bounds = Rect::from_min_size(pos2(0.0, 0.0), vec2(400.0, 300.0));
+        let buildable = (0..12).map(|i| i % 4 == 3).collect();
+        let mask = BuildMask::new(bounds, 4, 3, buildable);
+
+        assert!(mask.is_buildable(pos2(350.0, 150.0)));
+        assert!(!mask.is_buildable(pos2(50.0, 150.0)));
+        assert_eq!(mask.project(pos2(350.0, 20.0)), pos2(350.0, 20.0));
+
+        // into the closest buildable cell, a quarter cell from its edges
+        assert_eq!(mask.project(pos2(10.0, 150.0)), pos2(325.0, 150.0));
+        assert_eq!(mask.project(pos2(250.0, 290.0)), pos2(325.0, 275.0));
+        // off the mask, the cell on its edge counts
+        let outside = mask.project(pos2(-1000.0, -1000.0));
+        assert!(mask.is_buildable(outside));
+        assert_eq!(outside, pos2(325.0, 25.0));
+
+        let nothing = BuildMask::new(bounds, 4, 3, vec![false; 12]);
+        assert_eq!(nothing.project(pos2(10.0, 20.0)), pos2(10.0, 20.0));
+    }
+}
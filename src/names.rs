@@ -0,0 +1,43 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use crate::{config::ConfigError, map::ResourceLayer};
+
+pub const DEFAULT_LAYER_NAMES_PATH: &str = "./layer_names.toml";
+
+/// Display names replacing the map data's, by `layerId`, e.g.
+/// `cateriumPure = "Caterium (pure)"`.
+#[derive(Debug, Clone, Default)]
+pub struct LayerNames(BTreeMap<String, String>);
+
+impl LayerNames {
+    /// The name to show for a layer, falling back to the map data's.
+    pub fn name<'a>(&'a self, layer: &'a ResourceLayer) -> &'a str {
+        self.0.get(&layer.layerId).unwrap_or(&layer.name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// Loads the display names, treating a missing file as an empty one.
+pub fn load_layer_names(path: &Path) -> Result<LayerNames, ConfigError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(LayerNames::default()),
+        Err(err) => return Err(ConfigError::Io(err)),
+    };
+
+    let names: BTreeMap<String, String> = toml::from_str(&content).map_err(ConfigError::Parse)?;
+    if let Some(layer_id) = names
+        .iter()
+        .find(|(_, name)| name.trim().is_empty())
+        .map(|(id, _)| id)
+    {
+        return Err(ConfigError::Invalid(format!(
+            "empty name for layer '{}'",
+            layer_id
+        )));
+    }
+    Ok(LayerNames(names))
+}
@@ -0,0 +1,242 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fs,
+    path::Path,
+};
+
+use egui::{pos2, Pos2};
+use serde::Serialize;
+
+/// Most vertices a network may have, as the distances between every pair of
+/// them are kept.
+pub const MAX_NETWORK_VERTICES: usize = 2000;
+
+/// A rail or road network of polylines joined where they share a vertex,
+/// with the shortest distances along it between every pair of vertices.
+/// Serializes as the lines alone, as everything else follows from them.
+#[derive(Debug, Clone, Serialize)]
+pub struct Network {
+    /// The polylines it was built from.
+    lines: Vec<Vec<Pos2>>,
+    #[serde(skip)]
+    vertices: Vec<Pos2>,
+    /// Every piece of the polylines by the vertices it joins.
+    #[serde(skip)]
+    segments: Vec<(usize, usize)>,
+    /// Distance along the network from every vertex to every other, row by
+    /// row, infinite between unconnected ones.
+    #[serde(skip)]
+    distances: Vec<f32>,
+    /// Per pair of vertices as above, the vertex before the second one on
+    /// the shortest path from the first, `u32::MAX` if there is none.
+    #[serde(skip)]
+    previous: Vec<u32>,
+}
+
+/// Where a point gets onto the network: the closest spot on a segment.
+#[derive(Debug, Clone, Copy)]
+struct Access {
+    segment: usize,
+    spot: Pos2,
+    /// Distance of the spot from the first vertex of the segment.
+    along: f32,
+    /// Straight-line distance from the point to the spot.
+    gap: f32,
+}
+
+/// The shortest way between two points: onto the network at `from`, to the
+/// vertices `via` unless both spots are on the same segment, and off again at
+/// `to`.
+struct Way {
+    length: f32,
+    from: Access,
+    to: Access,
+    via: Option<(usize, usize)>,
+}
+
+/// Reads polylines from a JSON file: a list of lines, each a list of `[x, y]`
+/// points in game units.
+pub fn load_lines(path: &Path) -> Result<Vec<Vec<Pos2>>, String> {
+    let content = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let lines: Vec<Vec<[f32; 2]>> =
+        serde_json::from_str(&content).map_err(|err| err.to_string())?;
+    Ok(lines
+        .into_iter()
+        .map(|line| line.into_iter().map(|[x, y]| pos2(x, y)).collect())
+        .collect())
+}
+
+impl Network {
+    /// Joins the lines at the vertices they share and runs Dijkstra from every
+    /// vertex. Refuses more than [`MAX_NETWORK_VERTICES`] vertices.
+    pub fn new(lines: Vec<Vec<Pos2>>) -> Result<Self, String> {
+        let mut vertices = Vec::new();
+        let mut indices = HashMap::new();
+        let mut segments = Vec::new();
+        for line in &lines {
+            let mut last = None;
+            for point in line {
+                let next = vertices.len();
+                let vertex = *indices.entry((point.x.to_bits(), point.y.to_bits())).or_insert(next);
+                if vertex == next {
+                    vertices.push(*point);
+                }
+                if let Some(last) = last.filter(|last| *last != vertex) {
+                    segments.push((last, vertex));
+                }
+                last = Some(vertex);
+            }
+        }
+        if vertices.len() > MAX_NETWORK_VERTICES {
+            return Err(format!(
+                "a network has at most {} vertices, not {}",
+                MAX_NETWORK_VERTICES,
+                vertices.len()
+            ));
+        }
+
+        let n = vertices.len();
+        let mut neighbors = vec![Vec::new(); n];
+        for &(a, b) in &segments {
+            let length = vertices[a].distance(vertices[b]);
+            neighbors[a].push((b, length));
+            neighbors[b].push((a, length));
+        }
+        let mut distances = vec![f32::INFINITY; n * n];
+        let mut previous = vec![u32::MAX; n * n];
+        for source in 0..n {
+            let row = source * n..(source + 1) * n;
+            dijkstra(
+                &neighbors,
+                source,
+                &mut distances[row.clone()],
+                &mut previous[row],
+            );
+        }
+
+        Ok(Self {
+            lines,
+            vertices,
+            segments,
+            distances,
+            previous,
+        })
+    }
+
+    pub fn lines(&self) -> &[Vec<Pos2>] {
+        &self.lines
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    /// Distance from `a` onto the network, along it and off to `b`. None
+    /// without segments or if the network doesn't connect the two.
+    pub fn distance(&self, a: Pos2, b: Pos2) -> Option<f32> {
+        self.way(a, b).map(|way| way.length)
+    }
+
+    /// The points of the way [`Network::distance`] measures, from `a` to `b`.
+    pub fn route(&self, a: Pos2, b: Pos2) -> Option<Vec<Pos2>> {
+        let way = self.way(a, b)?;
+        let mut route = vec![a, way.from.spot];
+        if let Some((first, last)) = way.via {
+            let n = self.vertices.len();
+            let mut path = vec![last];
+            let mut at = last;
+            while at != first {
+                at = self.previous[first * n + at] as usize;
+                path.push(at);
+            }
+            route.extend(path.iter().rev().map(|vertex| self.vertices[*vertex]));
+        }
+        route.extend([way.to.spot, b]);
+        Some(route)
+    }
+
+    fn way(&self, a: Pos2, b: Pos2) -> Option<Way> {
+        let from = self.access(a)?;
+        let to = self.access(b)?;
+        let mut best = (from.segment == to.segment).then(|| Way {
+            length: (from.along - to.along).abs(),
+            from,
+            to,
+            via: None,
+        });
+        let n = self.vertices.len();
+        for (first, onto) in self.ends(&from) {
+            for (last, off) in self.ends(&to) {
+                let length = onto + self.distances[first * n + last] + off;
+                if length.is_finite() && best.as_ref().is_none_or(|best| length < best.length) {
+                    best = Some(Way {
+                        length,
+                        from,
+                        to,
+                        via: Some((first, last)),
+                    });
+                }
+            }
+        }
+        best.map(|way| Way {
+            length: from.gap + way.length + to.gap,
+            ..way
+        })
+    }
+
+    /// The vertices of the segment of an access and how far along the
+    /// segment each is from its spot.
+    fn ends(&self, access: &Access) -> [(usize, f32); 2] {
+        let (a, b) = self.segments[access.segment];
+        let length = self.vertices[a].distance(self.vertices[b]);
+        [(a, access.along), (b, length - access.along)]
+    }
+
+    /// The closest spot to the point on any segment.
+    fn access(&self, point: Pos2) -> Option<Access> {
+        self.segments
+            .iter()
+            .enumerate()
+            .map(|(segment, (a, b))| {
+                let (a, b) = (self.vertices[*a], self.vertices[*b]);
+                let length = a.distance(b);
+                let along = ((point - a).dot(b - a) / length).clamp(0.0, length);
+                let spot = a + (b - a) * (along / length);
+                Access {
+                    segment,
+                    spot,
+                    along,
+                    gap: point.distance(spot),
+                }
+            })
+            .min_by(|x, y| x.gap.total_cmp(&y.gap))
+    }
+}
+
+/// Shortest distances from `source` to every vertex and the vertex before
+/// each on its path.
+fn dijkstra(
+    neighbors: &[Vec<(usize, f32)>],
+    source: usize,
+    distances: &mut [f32],
+    previous: &mut [u32],
+) {
+    // the bits of non-negative floats order like the floats
+    let mut queue = BinaryHeap::new();
+    distances[source] = 0.0;
+    queue.push(Reverse((0.0f32.to_bits(), source)));
+    while let Some(Reverse((bits, vertex))) = queue.pop() {
+        if f32::from_bits(bits) > distances[vertex] {
+            continue;
+        }
+        for &(next, length) in &neighbors[vertex] {
+            let distance = distances[vertex] + length;
+            if distance < distances[next] {
+                distances[next] = distance;
+                previous[next] = vertex as u32;
+                queue.push(Reverse((distance.to_bits(), next)));
+            }
+        }
+    }
+}
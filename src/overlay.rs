@@ -0,0 +1,125 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use egui::{pos2, Pos2};
+use serde::{Deserialize, Serialize};
+
+use crate::map::ResourceMarker;
+
+pub const STATION_LAYER_ID: &str = "plannedStations";
+
+/// A marker layer in the schema the interactive map imports and exports, so a
+/// station plan can be shown there next to the real nodes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OverlayLayer {
+    pub layer_id: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub outside_color: String,
+    #[serde(default)]
+    pub inside_color: String,
+    #[serde(default)]
+    pub icon: String,
+    #[serde(default)]
+    pub markers: Vec<OverlayMarker>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OverlayMarker {
+    #[serde(default)]
+    pub path_name: String,
+    #[serde(default)]
+    pub name: String,
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub z: f32,
+}
+
+/// What the site may hand out: a single layer or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OverlayFile {
+    Layer(OverlayLayer),
+    Layers(Vec<OverlayLayer>),
+}
+
+/// The resource a layer holds, without the purity its name ends in.
+pub fn resource_name(layer_name: &str) -> &str {
+    layer_name.split(" (").next().unwrap_or(layer_name)
+}
+
+/// A station named after the resource most of its nodes are, e.g.
+/// "Iron Ore hub (14 nodes)".
+fn station_name(layer_names: &[&str], markers: &[ResourceMarker], set: &[usize]) -> String {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for i in set {
+        *counts
+            .entry(resource_name(layer_names[markers[*i].layer]))
+            .or_default() += 1;
+    }
+    // max_by_key keeps the last maximum, so ties go to the name sorting last
+    match counts.into_iter().max_by_key(|(_, count)| *count) {
+        Some((resource, _)) => {
+            let nodes = if set.len() == 1 { "node" } else { "nodes" };
+            format!("{} hub ({} {})", resource, set.len(), nodes)
+        }
+        None => "Empty station".to_string(),
+    }
+}
+
+/// The stations as one layer, each at the average height of its nodes.
+pub fn station_layer(
+    layer_names: &[&str],
+    markers: &[ResourceMarker],
+    points: &[Pos2],
+    sets: &[Vec<usize>],
+) -> OverlayLayer {
+    let markers = points
+        .iter()
+        .zip(sets)
+        .enumerate()
+        .map(|(index, (point, set))| {
+            let z = if set.is_empty() {
+                0.0
+            } else {
+                set.iter().map(|i| markers[*i].z).sum::<f32>() / set.len() as f32
+            };
+            OverlayMarker {
+                path_name: format!("{}:{}", STATION_LAYER_ID, index),
+                name: station_name(layer_names, markers, set),
+                x: point.x,
+                y: point.y,
+                z,
+            }
+        })
+        .collect();
+
+    OverlayLayer {
+        layer_id: STATION_LAYER_ID.to_string(),
+        name: "Planned stations".to_string(),
+        outside_color: "#ffffff".to_string(),
+        inside_color: "#d43f3f".to_string(),
+        icon: String::new(),
+        markers,
+    }
+}
+
+pub fn save_overlay(path: &Path, layer: &OverlayLayer) -> io::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(layer)?)
+}
+
+/// The marker positions of every layer in the file, in order.
+pub fn load_overlay_points(path: &Path) -> io::Result<Vec<Pos2>> {
+    let layers = match serde_json::from_str(&fs::read_to_string(path)?)? {
+        OverlayFile::Layer(layer) => vec![layer],
+        OverlayFile::Layers(layers) => layers,
+    };
+    Ok(layers
+        .iter()
+        .flat_map(|layer| &layer.markers)
+        .map(|marker| pos2(marker.x, marker.y))
+        .collect())
+}
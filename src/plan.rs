@@ -0,0 +1,120 @@
+use std::{
+    io::Write,
+    process::ExitCode,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::{
+    args::PlanArgs,
+    cluster::{self, Parameters, Problem},
+    export,
+    map::{self, LoadError, ResourceMarker},
+    restarts,
+};
+
+/// Exit code for map data that was read but could not be parsed.
+const EXIT_PARSE_ERROR: u8 = 2;
+/// Exit code for map data without any markers to cluster.
+const EXIT_NO_MARKERS: u8 = 3;
+
+/// Runs the clustering on the map data without a window and writes the best
+/// stations found as JSON.
+pub fn run(args: &PlanArgs) -> ExitCode {
+    let data = match map::load_maps(&args.maps) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            return match err {
+                LoadError::Io { .. } => ExitCode::FAILURE,
+                LoadError::Parse { .. } | LoadError::Decompress { .. } => {
+                    ExitCode::from(EXIT_PARSE_ERROR)
+                }
+            };
+        }
+    };
+    for summary in &data.sources {
+        for skipped in &summary.skipped {
+            eprintln!("warning: skipped {}", skipped);
+        }
+    }
+    if args.verbose {
+        eprintln!("layers = {:#?}", data.layers);
+    }
+
+    let mut markers = data.markers;
+    markers.extend(map::pressurizer_markers(&markers));
+    let params = Parameters {
+        k: args.k,
+        seed: args.seed,
+        ..Parameters::default()
+    };
+    // the GUI's defaults: every layer but collectibles, wells as their
+    // satellites, no markers with broken positions
+    let world = cluster::default_map_bounds();
+    let valid: Vec<ResourceMarker> = markers
+        .iter()
+        .filter(|m| m.issue(world).is_none())
+        .cloned()
+        .collect();
+    let problem = Problem {
+        markers: &markers,
+        clustered: markers
+            .iter()
+            .map(|m| {
+                data.layers[m.layer].collectible.is_none()
+                    && !m.is_pressurizer()
+                    && m.issue(world).is_none()
+            })
+            .collect(),
+        weights: markers
+            .iter()
+            .map(|m| params.marker_weight(m, &data.layers[m.layer].layerId))
+            .collect(),
+        params: &params,
+        bounds: cluster::marker_bounds(&valid).unwrap_or_else(cluster::default_map_bounds),
+        pinned: &[],
+        seeds: &[],
+        seeds_fixed: false,
+        mask: None,
+        network: None,
+    };
+    let clustered = problem.clustered.iter().filter(|clustered| **clustered).count();
+    if clustered == 0 {
+        eprintln!("error: no markers to cluster");
+        return ExitCode::from(EXIT_NO_MARKERS);
+    }
+    eprintln!("clustering {} markers into {} stations", clustered, args.k);
+
+    let restarts = args.restarts as usize;
+    let base_seed = params.seed.unwrap_or_else(rand::random);
+    let done = AtomicUsize::new(0);
+    let best = restarts::best_of_restarts(
+        &problem,
+        restarts,
+        base_seed,
+        &AtomicBool::new(false),
+        |result| {
+            let run = done.fetch_add(1, Ordering::Relaxed) + 1;
+            eprintln!("run {}/{}: error {}", run, restarts, result.error);
+        },
+    );
+    eprintln!("seed {}", base_seed);
+
+    let stations = match best {
+        Some(best) => problem.station_export(&best.points, &best.heights, best.error),
+        None => problem.station_export(&[], &[], f32::MAX),
+    };
+    let written = match &args.out {
+        Some(path) => export::save_stations(path, &stations),
+        None => serde_json::to_string_pretty(&stations)
+            .map_err(Into::into)
+            .and_then(|json| writeln!(std::io::stdout(), "{}", json)),
+    };
+    match written {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: failed to write stations: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use egui::Pos2;
+use serde::Serialize;
+
+use crate::{map::ResourceMarker, rates::Miner};
+
+/// Items, or cubic meters of fluids, per minute available at every station,
+/// by resource class name (like `Desc_OreIron_C`), the way production
+/// planners such as Satisfactory Tools name their inputs.
+#[derive(Debug, Serialize)]
+pub struct ResourceTotals {
+    pub miner_mark: u32,
+    /// 1.0 is 100%.
+    pub clock_speed: f32,
+    pub belt_capped: bool,
+    pub stations: Vec<StationResources>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StationResources {
+    pub name: String,
+    pub x: f32,
+    pub y: f32,
+    pub resources: BTreeMap<String, f32>,
+}
+
+impl ResourceTotals {
+    /// Sums the rates of the nodes assigned to each station, skipping markers
+    /// without a resource type or purity.
+    pub fn new(
+        markers: &[ResourceMarker],
+        points: &[Pos2],
+        sets: &[Vec<usize>],
+        miner: &Miner,
+    ) -> Self {
+        let stations = points
+            .iter()
+            .zip(sets)
+            .enumerate()
+            .map(|(i, (point, set))| {
+                let mut resources = BTreeMap::new();
+                for marker in set.iter().map(|m| &markers[*m]) {
+                    let Some(class) = &marker.r#type else {
+                        continue;
+                    };
+                    let rate = miner.node_rate(marker);
+                    if rate > 0.0 {
+                        *resources.entry(class.clone()).or_default() += rate;
+                    }
+                }
+                StationResources {
+                    name: format!("Station {}", i),
+                    x: point.x,
+                    y: point.y,
+                    resources,
+                }
+            })
+            .collect();
+
+        Self {
+            miner_mark: miner.mark,
+            clock_speed: miner.clock_speed,
+            belt_capped: miner.belt_capped,
+            stations,
+        }
+    }
+}
+
+pub fn save_resource_totals(path: &Path, totals: &ResourceTotals) -> io::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(totals)?)
+}
@@ -0,0 +1,51 @@
+use std::{fs, io, path::Path};
+
+use egui::Pos2;
+use serde::{Deserialize, Serialize};
+
+use crate::cluster::{Parameters, ResultKey};
+
+/// Everything needed to pick up planning where it was left off. Fields
+/// missing from older project files fall back to their defaults.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Project {
+    /// `Map.version` of the map data the project was made with.
+    pub map_version: Option<i64>,
+    pub parameters: Parameters,
+    pub points: Vec<Pos2>,
+    pub last_error: f32,
+    pub best_so_far: f32,
+    pub best_so_far_points: Vec<Pos2>,
+    /// What the best points were found for, so they are dropped once loaded
+    /// for other markers. Taken from the loaded project if absent.
+    pub result_key: Option<ResultKey>,
+    /// `layerId`s included in the clustering, all of them if absent.
+    pub included_layers: Option<Vec<String>>,
+    /// Stations pinned in place.
+    pub pinned_points: Vec<Pos2>,
+}
+
+impl Default for Project {
+    fn default() -> Self {
+        Self {
+            map_version: None,
+            parameters: Parameters::default(),
+            points: Vec::new(),
+            last_error: f32::MAX,
+            best_so_far: f32::MAX,
+            best_so_far_points: Vec::new(),
+            result_key: None,
+            included_layers: None,
+            pinned_points: Vec::new(),
+        }
+    }
+}
+
+pub fn save_project(path: &Path, project: &Project) -> io::Result<()> {
+    fs::write(path, serde_json::to_string_pretty(project)?)
+}
+
+pub fn load_project(path: &Path) -> io::Result<Project> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
@@ -0,0 +1,222 @@
+use egui::{pos2, Pos2};
+
+use crate::cluster::Problem;
+
+/// Markers the silhouette is averaged over at most, as it compares each with
+/// every other marker. Larger assignments are sampled evenly.
+pub const SILHOUETTE_SAMPLE: usize = 500;
+
+/// How well the markers are split into clusters, besides the total error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quality {
+    /// Mean silhouette coefficient, from -1 for markers closer to another
+    /// cluster than their own to 1 for well separated clusters.
+    pub silhouette: Option<f32>,
+    /// Distance from a station to its farthest assigned marker, over all
+    /// stations.
+    pub max_radius: f32,
+    /// Davies–Bouldin index, lower for compact clusters far apart. Needs two
+    /// clusters with markers.
+    pub davies_bouldin: Option<f32>,
+}
+
+/// The quality of the given assignment of markers to the points.
+pub fn quality(
+    problem: &Problem,
+    points: &[Pos2],
+    heights: &[f32],
+    sets: &[Vec<usize>],
+) -> Quality {
+    let radii: Vec<Option<f32>> = points
+        .iter()
+        .zip(heights)
+        .zip(sets)
+        .map(|((point, z), set)| {
+            set.iter()
+                .map(|i| problem.distance(*point, *z, *i))
+                .reduce(f32::max)
+        })
+        .collect();
+
+    Quality {
+        silhouette: silhouette(problem, sets),
+        max_radius: radii.iter().flatten().copied().fold(0.0, f32::max),
+        davies_bouldin: davies_bouldin(problem, points, heights, sets),
+    }
+}
+
+fn marker_distance(problem: &Problem, a: usize, b: usize) -> f32 {
+    let marker = &problem.markers[a];
+    problem.distance(pos2(marker.x, marker.y), marker.z, b)
+}
+
+fn mean_distance(problem: &Problem, i: usize, set: &[usize]) -> f32 {
+    set.iter().map(|j| marker_distance(problem, i, *j)).sum::<f32>() / set.len() as f32
+}
+
+/// Markers in singleton clusters count as 0. None without two clusters with
+/// markers.
+fn silhouette(problem: &Problem, sets: &[Vec<usize>]) -> Option<f32> {
+    let filled: Vec<&Vec<usize>> = sets.iter().filter(|set| !set.is_empty()).collect();
+    if filled.len() < 2 {
+        return None;
+    }
+
+    let members: Vec<(usize, usize)> = filled
+        .iter()
+        .enumerate()
+        .flat_map(|(seti, set)| set.iter().map(move |i| (seti, *i)))
+        .collect();
+    let stride = members.len().div_ceil(SILHOUETTE_SAMPLE);
+    let sampled: Vec<f32> = members
+        .iter()
+        .step_by(stride)
+        .map(|(seti, i)| {
+            let own = filled[*seti];
+            if own.len() == 1 {
+                return 0.0;
+            }
+            // the marker itself adds 0 to the sum but not to the count
+            let a = mean_distance(problem, *i, own) * own.len() as f32 / (own.len() - 1) as f32;
+            let b = filled
+                .iter()
+                .enumerate()
+                .filter(|(setj, _)| setj != seti)
+                .map(|(_, set)| mean_distance(problem, *i, set))
+                .fold(f32::MAX, f32::min);
+            if a.max(b) > 0.0 {
+                (b - a) / a.max(b)
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    Some(sampled.iter().sum::<f32>() / sampled.len() as f32)
+}
+
+/// None without two clusters with markers at different points.
+fn davies_bouldin(
+    problem: &Problem,
+    points: &[Pos2],
+    heights: &[f32],
+    sets: &[Vec<usize>],
+) -> Option<f32> {
+    // (point, height, mean distance of the markers to it)
+    let clusters: Vec<(Pos2, f32, f32)> = points
+        .iter()
+        .zip(heights)
+        .zip(sets)
+        .filter(|(_, set)| !set.is_empty())
+        .map(|((point, z), set)| {
+            let scatter = set.iter().map(|i| problem.distance(*point, *z, *i)).sum::<f32>()
+                / set.len() as f32;
+            (*point, *z, scatter)
+        })
+        .collect();
+    if clusters.len() < 2 {
+        return None;
+    }
+
+    let z_scale = problem.z_scale();
+    let worst: Vec<f32> = clusters
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (a, a_z, a_scatter))| {
+            clusters
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .filter_map(|(_, (b, b_z, b_scatter))| {
+                    let d = *b - *a;
+                    let separation = problem
+                        .params
+                        .metric
+                        .length([d.x, d.y, (b_z - a_z) * z_scale])
+                        + problem.params.climb_cost * (b_z - a_z).abs();
+                    (separation > 0.0).then(|| (a_scatter + b_scatter) / separation)
+                })
+                .reduce(f32::max)
+        })
+        .collect();
+    if worst.is_empty() {
+        None
+    } else {
+        Some(worst.iter().sum::<f32>() / worst.len() as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::{
+        tests::{markers, problem},
+        Parameters,
+    };
+
+    const PAIRS: [(f32, f32); 4] = [(0.0, 0.0), (2.0, 0.0), (100.0, 0.0), (102.0, 0.0)];
+
+    fn close(a: Option<f32>, b: f32) -> bool {
+        a.is_some_and(|a| (a - b).abs() < 1e-5)
+    }
+
+    #[test]
+    fn separated_pairs() {
+        let markers = markers(&PAIRS);
+        let params = Parameters::default();
+        let problem = problem(&markers, &params);
+        let points = [pos2(1.0, 0.0), pos2(101.0, 0.0)];
+        let quality = quality(&problem, &points, &[0.0, 0.0], &[vec![0, 1], vec![2, 3]]);
+        // the outer markers are 2 from their partner and 101 from the other
+        // pair on average, the inner ones 2 and 99
+        let silhouette = (99.0 / 101.0 + 97.0 / 99.0) / 2.0;
+        assert!(close(quality.silhouette, silhouette), "{:?}", quality);
+        // scatters of 1 each, 100 apart
+        assert!(close(quality.davies_bouldin, 0.02), "{:?}", quality);
+        assert_eq!(quality.max_radius, 1.0);
+
+        // an empty set in between changes nothing
+        let points = [points[0], pos2(50.0, 0.0), points[1]];
+        let sets = [vec![0, 1], vec![], vec![2, 3]];
+        let with_empty = super::quality(&problem, &points, &[0.0; 3], &sets);
+        assert_eq!(with_empty, quality);
+    }
+
+    #[test]
+    fn singletons_count_as_zero() {
+        let markers = markers(&[(0.0, 0.0), (10.0, 0.0), (12.0, 0.0)]);
+        let params = Parameters::default();
+        let problem = problem(&markers, &params);
+        let silhouette = silhouette(&problem, &[vec![0], vec![1, 2]]);
+        // 0 for the singleton, then a of 2 against b of 10 and 12
+        assert!(close(silhouette, (0.0 + 0.8 + 10.0 / 12.0) / 3.0), "{:?}", silhouette);
+    }
+
+    #[test]
+    fn needs_two_filled_clusters() {
+        let markers = markers(&PAIRS);
+        let params = Parameters::default();
+        let problem = problem(&markers, &params);
+        let points = [pos2(51.0, 0.0), pos2(0.0, 0.0)];
+        let quality = quality(&problem, &points, &[0.0, 0.0], &[vec![0, 1, 2, 3], vec![]]);
+        assert_eq!(quality.silhouette, None);
+        assert_eq!(quality.davies_bouldin, None);
+        assert_eq!(quality.max_radius, 51.0);
+    }
+
+    #[test]
+    fn coincident_stations_are_skipped() {
+        let markers = markers(&PAIRS);
+        let params = Parameters::default();
+        let problem = problem(&markers, &params);
+        let sets = [vec![0, 1], vec![2, 3]];
+        let same = [pos2(50.0, 0.0), pos2(50.0, 0.0)];
+        assert_eq!(davies_bouldin(&problem, &same, &[0.0, 0.0], &sets), None);
+
+        // a third station still gets compared with the other two
+        let sets = [vec![0], vec![1], vec![2, 3]];
+        let points = [pos2(1.0, 0.0), pos2(1.0, 0.0), pos2(101.0, 0.0)];
+        let index = davies_bouldin(&problem, &points, &[0.0; 3], &sets);
+        // every cluster has a scatter of 1 and the only separation is 100
+        assert!(close(index, 0.02), "{:?}", index);
+    }
+}
@@ -0,0 +1,335 @@
+use serde::{Deserialize, Serialize};
+
+use crate::map::{NodeKind, Purity, ResourceMarker};
+
+/// Most items per minute a single belt carries, which caps what one miner can
+/// ship. That of a Mk5 belt.
+pub const BELT_LIMIT: f32 = 780.0;
+/// Items per minute a Mk6 belt carries.
+pub const MK6_BELT_LIMIT: f32 = 1200.0;
+/// Cubic meters per minute a Mk2 pipeline carries.
+pub const PIPELINE_LIMIT: f32 = 600.0;
+
+/// Items per minute a Mk1 miner at 100% extracts from a node of this purity.
+fn base_rate(purity: &Purity) -> Option<f32> {
+    match purity {
+        Purity::Impure => Some(30.0),
+        Purity::Normal => Some(60.0),
+        Purity::Pure => Some(120.0),
+        Purity::Unknown(_) => None,
+    }
+}
+
+/// Items per minute a miner of the given mark (1 to 3) at 100% extracts from
+/// a node, if its purity is known. Every mark doubles the rate of the last.
+pub fn miner_rate(purity: &Purity, mark: u32) -> Option<f32> {
+    base_rate(purity).map(|rate| rate * 2f32.powi(mark.saturating_sub(1) as i32))
+}
+
+/// [`miner_rate`] with the miner clocked to `clock_speed`, where 1.0 is 100%.
+/// Output scales linearly with the clock speed.
+pub fn clocked_miner_rate(purity: &Purity, mark: u32, clock_speed: f32) -> Option<f32> {
+    miner_rate(purity, mark).map(|rate| rate * clock_speed)
+}
+
+/// Cubic meters per minute an oil extractor at 100% pumps from a node of
+/// this purity, twice what a Mk1 miner would mine.
+pub fn oil_extractor_rate(purity: &Purity) -> Option<f32> {
+    base_rate(purity).map(|rate| rate * 2.0)
+}
+
+/// Cubic meters per minute a water extractor at 100% pumps, wherever it
+/// stands.
+pub const WATER_EXTRACTOR_RATE: f32 = 120.0;
+
+/// Cubic meters per minute a resource well extractor on a satellite of this
+/// purity gives with its pressurizer at 100%.
+pub fn well_rate(purity: &Purity) -> Option<f32> {
+    base_rate(purity)
+}
+
+/// Belts or pipelines carrying `capacity` each it takes to carry `rate`.
+pub fn lines_needed(rate: f32, capacity: f32) -> u32 {
+    (rate / capacity).ceil().max(0.0) as u32
+}
+
+/// The miners assumed on every node, for weights and reported rates alike.
+/// Extractors and pressurizers run at the same clock speed, with their mark
+/// and the belt cap only applying to miners.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Miner {
+    /// 1 to 3.
+    pub mark: u32,
+    /// 1.0 is 100%.
+    pub clock_speed: f32,
+    /// Whether rates are capped at [`BELT_LIMIT`].
+    pub belt_capped: bool,
+}
+
+impl Default for Miner {
+    fn default() -> Self {
+        Self {
+            mark: 3,
+            clock_speed: 1.0,
+            belt_capped: false,
+        }
+    }
+}
+
+impl Miner {
+    /// Items per minute this miner ships from a node, if its purity is known.
+    pub fn rate(&self, purity: &Purity) -> Option<f32> {
+        let rate = clocked_miner_rate(purity, self.mark, self.clock_speed)?;
+        Some(if self.belt_capped { rate.min(BELT_LIMIT) } else { rate })
+    }
+
+    /// Items or cubic meters per minute a marker gives, by its kind: mined,
+    /// pumped by an extractor or by the extractors of a resource well. A
+    /// pressurizer gives what its satellites do if they were all of its
+    /// purity. 0 for markers without a resource type or of unknown purity.
+    pub fn node_rate(&self, marker: &ResourceMarker) -> f32 {
+        if marker.r#type.is_none() {
+            return 0.0;
+        }
+        let rate = match marker.kind {
+            NodeKind::Solid | NodeKind::Other => {
+                return self.rate(&marker.purity).unwrap_or_default();
+            }
+            NodeKind::Liquid if marker.r#type.as_deref() == Some("water") => {
+                Some(WATER_EXTRACTOR_RATE)
+            }
+            NodeKind::Liquid => oil_extractor_rate(&marker.purity),
+            NodeKind::Well => well_rate(&marker.purity)
+                .map(|rate| rate * marker.satellites.len().max(1) as f32),
+        };
+        rate.map_or(0.0, |rate| rate * self.clock_speed)
+    }
+}
+
+/// What the nodes of a station produce, split by how it leaves.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Throughput {
+    /// Items per minute of solid and other nodes, shipped on belts.
+    pub items: f32,
+    /// Cubic meters per minute of liquid nodes and wells, piped.
+    pub fluid: f32,
+}
+
+impl Throughput {
+    /// Of the markers in `set` with the miner.
+    pub fn of(miner: &Miner, markers: &[ResourceMarker], set: &[usize]) -> Self {
+        let mut throughput = Throughput::default();
+        for marker in set.iter().map(|i| &markers[*i]) {
+            throughput.add(miner, marker);
+        }
+        throughput
+    }
+
+    /// Adds the rate of a marker to the items or the fluids, by its kind.
+    pub fn add(&mut self, miner: &Miner, marker: &ResourceMarker) {
+        let rate = miner.node_rate(marker);
+        if marker.kind.is_fluid() {
+            self.fluid += rate;
+        } else {
+            self.items += rate;
+        }
+    }
+
+    /// Whether the items exceed the cap, if there is one. Fluids are piped
+    /// and don't count.
+    pub fn is_over(&self, cap: Option<f32>) -> bool {
+        cap.is_some_and(|cap| self.items > cap)
+    }
+
+    /// Belts carrying `limit` items per minute it takes to ship the items.
+    pub fn belts(&self, limit: f32) -> u32 {
+        lines_needed(self.items, limit)
+    }
+
+    /// Pipelines it takes to ship the fluids.
+    pub fn pipelines(&self) -> u32 {
+        lines_needed(self.fluid, PIPELINE_LIMIT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::tests::marker;
+
+    #[test]
+    fn over_the_cap_only_past_it() {
+        let throughput = Throughput {
+            items: 900.0,
+            fluid: 300.0,
+        };
+        assert!(!throughput.is_over(None));
+        assert!(!throughput.is_over(Some(900.0)));
+        assert!(throughput.is_over(Some(899.9)));
+        assert!(!Throughput::default().is_over(Some(0.0)));
+        // only items are belted, so fluids never count against the cap
+        let piped = Throughput {
+            items: 0.0,
+            fluid: 1000.0,
+        };
+        assert!(!piped.is_over(Some(780.0)));
+    }
+
+    #[test]
+    fn lines_at_their_limits() {
+        assert_eq!(lines_needed(0.0, BELT_LIMIT), 0);
+        assert_eq!(lines_needed(BELT_LIMIT, BELT_LIMIT), 1);
+        assert_eq!(lines_needed(BELT_LIMIT + 1.0, BELT_LIMIT), 2);
+        let throughput = Throughput {
+            items: 1560.0,
+            fluid: 600.5,
+        };
+        assert_eq!(throughput.belts(BELT_LIMIT), 2);
+        assert_eq!(throughput.belts(MK6_BELT_LIMIT), 2);
+        assert_eq!(throughput.pipelines(), 2);
+    }
+
+    fn node(resource: &str, kind: NodeKind, purity: Purity) -> ResourceMarker {
+        ResourceMarker {
+            r#type: Some(resource.to_string()),
+            kind,
+            purity,
+            ..marker(0.0, 0.0)
+        }
+    }
+
+    #[test]
+    fn splits_items_and_fluids() {
+        let markers = [
+            node("iron", NodeKind::Solid, Purity::Pure),
+            node("iron", NodeKind::Solid, Purity::Normal),
+            node("oil", NodeKind::Liquid, Purity::Impure),
+        ];
+        let miner = Miner::default();
+        let throughput = Throughput::of(&miner, &markers, &[0, 1, 2]);
+        // a Mk3 miner at 100% quadruples the Mk1 rates, extractors have no
+        // mark
+        assert_eq!(
+            throughput,
+            Throughput {
+                items: 720.0,
+                fluid: 60.0
+            }
+        );
+        assert!(!throughput.is_over(Some(780.0)));
+        assert!(throughput.is_over(Some(700.0)));
+    }
+
+    #[test]
+    fn extractors_pump_by_their_own_rates() {
+        let miner = Miner {
+            belt_capped: true,
+            ..Miner::default()
+        };
+        let oil: Vec<f32> = [Purity::Impure, Purity::Normal, Purity::Pure]
+            .into_iter()
+            .map(|purity| miner.node_rate(&node("oil", NodeKind::Liquid, purity)))
+            .collect();
+        assert_eq!(oil, [60.0, 120.0, 240.0]);
+        // water extractors pump the same anywhere
+        let water = node("water", NodeKind::Liquid, Purity::Impure);
+        assert_eq!(miner.node_rate(&water), WATER_EXTRACTOR_RATE);
+        let unknown = node("oil", NodeKind::Liquid, Purity::Unknown(String::new()));
+        assert_eq!(miner.node_rate(&unknown), 0.0);
+
+        // the clock speed applies, the belt cap doesn't
+        let overclocked = Miner {
+            clock_speed: 2.5,
+            ..miner
+        };
+        let pure_oil = node("oil", NodeKind::Liquid, Purity::Pure);
+        assert_eq!(overclocked.node_rate(&pure_oil), 600.0);
+        let throughput = Throughput::of(&overclocked, &[pure_oil.clone(), pure_oil], &[0, 1]);
+        assert_eq!(throughput.items, 0.0);
+        assert_eq!(throughput.pipelines(), 2);
+    }
+
+    #[test]
+    fn wells_pump_per_satellite() {
+        let miner = Miner::default();
+        let satellites: Vec<ResourceMarker> = [Purity::Impure, Purity::Normal, Purity::Pure]
+            .into_iter()
+            .map(|purity| ResourceMarker {
+                core: Some("core".to_string()),
+                ..node("nitrogenGas", NodeKind::Well, purity)
+            })
+            .collect();
+        let throughput = Throughput::of(&miner, &satellites, &[0, 1, 2]);
+        assert_eq!(
+            throughput,
+            Throughput {
+                items: 0.0,
+                fluid: 210.0
+            }
+        );
+
+        // the pressurizer stands in for its three satellites, all normal
+        let pressurizer = ResourceMarker {
+            satellites: vec![0, 1, 2],
+            ..node("water", NodeKind::Well, Purity::Normal)
+        };
+        assert_eq!(miner.node_rate(&pressurizer), 180.0);
+        let underclocked = Miner {
+            clock_speed: 0.5,
+            ..miner
+        };
+        assert_eq!(underclocked.node_rate(&pressurizer), 90.0);
+    }
+
+    #[test]
+    fn miner_rates_double_per_mark() {
+        let purities = [Purity::Impure, Purity::Normal, Purity::Pure];
+        let table: Vec<Vec<Option<f32>>> = (1..=3)
+            .map(|mark| purities.iter().map(|purity| miner_rate(purity, mark)).collect())
+            .collect();
+        assert_eq!(
+            table,
+            [
+                [Some(30.0), Some(60.0), Some(120.0)],
+                [Some(60.0), Some(120.0), Some(240.0)],
+                [Some(120.0), Some(240.0), Some(480.0)],
+            ]
+        );
+        assert_eq!(miner_rate(&Purity::Unknown("rich".to_string()), 3), None);
+    }
+
+    #[test]
+    fn clocked_and_capped_miner_rates() {
+        let purities = [Purity::Impure, Purity::Normal, Purity::Pure];
+        let overclocked: Vec<Option<f32>> =
+            purities.iter().map(|purity| clocked_miner_rate(purity, 3, 2.5)).collect();
+        assert_eq!(overclocked, [Some(300.0), Some(600.0), Some(1200.0)]);
+        assert_eq!(clocked_miner_rate(&Purity::Normal, 1, 0.5), Some(30.0));
+
+        let mut miner = Miner {
+            mark: 3,
+            clock_speed: 2.5,
+            belt_capped: false,
+        };
+        assert_eq!(miner.rate(&Purity::Pure), Some(1200.0));
+        assert_eq!(miner.rate(&Purity::Normal), Some(600.0));
+        miner.belt_capped = true;
+        assert_eq!(miner.rate(&Purity::Pure), Some(BELT_LIMIT));
+        assert_eq!(miner.rate(&Purity::Normal), Some(600.0));
+        assert_eq!(miner.rate(&Purity::Unknown(String::new())), None);
+    }
+
+    #[test]
+    fn weights_follow_the_rates() {
+        let params = crate::cluster::Parameters {
+            weight_by_rate: true,
+            ..Default::default()
+        };
+        let pure = node("iron", NodeKind::Solid, Purity::Pure);
+        let unknown = node("iron", NodeKind::Solid, Purity::Unknown(String::new()));
+        // Mk3 at 100%, with unknown purities counting as normal
+        assert_eq!(params.marker_weight(&pure, "ironPure"), 480.0);
+        assert_eq!(params.marker_weight(&unknown, "iron"), 240.0);
+    }
+}
@@ -0,0 +1,133 @@
+use std::path::Path;
+
+use egui::{Color32, Pos2, Rect, Stroke};
+use tiny_skia::{FillRule, Paint, PathBuilder, Pixmap, Transform};
+
+/// Something the map can be drawn onto, either the window or an image.
+pub trait Canvas {
+    fn circle(&mut self, center: Pos2, radius: f32, fill: Color32, stroke: Stroke);
+    fn rect(&mut self, rect: Rect, fill: Color32, stroke: Stroke);
+    fn line(&mut self, from: Pos2, to: Pos2, stroke: Stroke);
+    fn label(&mut self, pos: Pos2, text: &str, color: Color32);
+
+    fn circle_stroke(&mut self, center: Pos2, radius: f32, stroke: Stroke) {
+        self.circle(center, radius, Color32::TRANSPARENT, stroke);
+    }
+}
+
+impl Canvas for egui::Painter {
+    fn circle(&mut self, center: Pos2, radius: f32, fill: Color32, stroke: Stroke) {
+        egui::Painter::circle(self, center, radius, fill, stroke);
+    }
+
+    fn rect(&mut self, rect: Rect, fill: Color32, stroke: Stroke) {
+        egui::Painter::rect(self, rect, 2.0, fill, stroke);
+    }
+
+    fn line(&mut self, from: Pos2, to: Pos2, stroke: Stroke) {
+        self.line_segment([from, to], stroke);
+    }
+
+    fn label(&mut self, pos: Pos2, text: &str, color: Color32) {
+        self.text(
+            pos,
+            egui::Align2::LEFT_CENTER,
+            text,
+            egui::FontId::proportional(12.0),
+            color,
+        );
+    }
+}
+
+/// An offscreen image. Sizes are given as for the window and scaled by
+/// `scale`, so the picture looks alike at any resolution.
+pub struct Raster {
+    pixmap: Pixmap,
+    scale: f32,
+}
+
+impl Raster {
+    pub fn new(width: u32, height: u32, scale: f32, background: Color32) -> Option<Self> {
+        let mut pixmap = Pixmap::new(width, height)?;
+        pixmap.fill(skia_color(background));
+        Some(Self { pixmap, scale })
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), String> {
+        self.pixmap.save_png(path).map_err(|err| err.to_string())
+    }
+
+    fn fill(&mut self, path: &tiny_skia::Path, color: Color32) {
+        if color.a() == 0 {
+            return;
+        }
+        self.pixmap.fill_path(
+            path,
+            &paint(color),
+            FillRule::Winding,
+            Transform::identity(),
+            None,
+        );
+    }
+
+    fn stroke(&mut self, path: &tiny_skia::Path, stroke: Stroke) {
+        if stroke.is_empty() {
+            return;
+        }
+        let style = tiny_skia::Stroke {
+            width: stroke.width * self.scale,
+            ..Default::default()
+        };
+        self.pixmap.stroke_path(
+            path,
+            &paint(stroke.color),
+            &style,
+            Transform::identity(),
+            None,
+        );
+    }
+}
+
+impl Canvas for Raster {
+    fn circle(&mut self, center: Pos2, radius: f32, fill: Color32, stroke: Stroke) {
+        if let Some(path) = PathBuilder::from_circle(center.x, center.y, radius * self.scale) {
+            self.fill(&path, fill);
+            self.stroke(&path, stroke);
+        }
+    }
+
+    fn rect(&mut self, rect: Rect, fill: Color32, stroke: Stroke) {
+        let rect = Rect::from_center_size(rect.center(), rect.size() * self.scale);
+        if let Some(rect) =
+            tiny_skia::Rect::from_xywh(rect.min.x, rect.min.y, rect.width(), rect.height())
+        {
+            let path = PathBuilder::from_rect(rect);
+            self.fill(&path, fill);
+            self.stroke(&path, stroke);
+        }
+    }
+
+    fn line(&mut self, from: Pos2, to: Pos2, stroke: Stroke) {
+        let mut builder = PathBuilder::new();
+        builder.move_to(from.x, from.y);
+        builder.line_to(to.x, to.y);
+        if let Some(path) = builder.finish() {
+            self.stroke(&path, stroke);
+        }
+    }
+
+    /// There is no font to draw text with, so labels are left out.
+    fn label(&mut self, _pos: Pos2, _text: &str, _color: Color32) {}
+}
+
+fn skia_color(color: Color32) -> tiny_skia::Color {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    tiny_skia::Color::from_rgba8(r, g, b, a)
+}
+
+fn paint(color: Color32) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color(skia_color(color));
+    paint.anti_alias = true;
+    paint
+}
@@ -0,0 +1,223 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use egui::Pos2;
+
+use crate::{
+    cluster::Problem,
+    map::NodeKind,
+    rates::{self, Throughput},
+};
+
+/// Writes a Markdown report with the stations by what they add to the error,
+/// then a section per station: its position, the nodes assigned to it by
+/// layer, kind and purity, their distances and heights and what the miners of
+/// the parameters would produce on them, with the belts and pipelines that
+/// takes. Stations over the throughput cap are flagged. Stations without
+/// nodes are listed too.
+pub fn write_report<W: Write>(
+    mut w: W,
+    layer_names: &[&str],
+    layer_kinds: &[NodeKind],
+    problem: &Problem,
+    points: &[Pos2],
+    heights: &[f32],
+    sets: &[Vec<usize>],
+) -> io::Result<()> {
+    let markers = problem.markers;
+    writeln!(w, "# Station report")?;
+    writeln!(w)?;
+    write!(w, "{} stations, {} distances", points.len(), problem.params.metric)?;
+    if problem.params.climb_cost > 0.0 {
+        write!(w, " plus {} per unit of height", problem.params.climb_cost)?;
+    }
+    writeln!(w)?;
+
+    let errors = problem.set_errors(points, heights, sets);
+    let miner = &problem.params.miner;
+    let throughputs: Vec<Throughput> =
+        sets.iter().map(|set| Throughput::of(miner, markers, set)).collect();
+    let cap = problem.params.throughput_cap;
+    writeln!(w)?;
+    writeln!(w, "## Errors by station")?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "| station | nodes | error | mean distance | worst node | distance | items/min | m³/min |"
+    )?;
+    writeln!(w, "| --- | --- | --- | --- | --- | --- | --- | --- |")?;
+    for station in problem.station_errors(points, heights, sets, &errors) {
+        let (worst, distance) = match station.worst {
+            Some((m, distance)) => (markers[m].pathName.as_str(), format!("{:.0}", distance)),
+            None => ("-", "-".to_string()),
+        };
+        let throughput = throughputs.get(station.index).copied().unwrap_or_default();
+        let flag = if throughput.is_over(cap) { " (over cap)" } else { "" };
+        writeln!(
+            w,
+            "| {} | {} | {:.0} | {:.0} | {} | {} | {:.0}{} | {:.0} |",
+            station.index,
+            station.nodes,
+            station.error,
+            station.mean_distance,
+            worst,
+            distance,
+            throughput.items,
+            flag,
+            throughput.fluid
+        )?;
+    }
+
+    let empty = Vec::new();
+    for (i, point) in points.iter().enumerate() {
+        let set = sets.get(i).unwrap_or(&empty);
+        writeln!(w)?;
+        writeln!(w, "## Station {} at ({:.0}, {:.0})", i, point.x, point.y)?;
+        writeln!(w)?;
+        if set.is_empty() {
+            writeln!(w, "No nodes assigned.")?;
+            continue;
+        }
+
+        let z = heights.get(i).copied().unwrap_or_default();
+        let distances: Vec<f32> = set.iter().map(|m| problem.distance(*point, z, *m)).collect();
+        let mean = distances.iter().sum::<f32>() / distances.len() as f32;
+        let max = distances.iter().copied().fold(0.0, f32::max);
+        let lowest = set.iter().map(|m| markers[*m].z).fold(f32::MAX, f32::min);
+        let highest = set.iter().map(|m| markers[*m].z).fold(f32::MIN, f32::max);
+        writeln!(w, "- nodes: {}", set.len())?;
+        writeln!(w, "- error: {:.0}", errors.get(i).copied().unwrap_or_default())?;
+        writeln!(w, "- mean distance: {:.0}", mean)?;
+        writeln!(w, "- max distance: {:.0}", max)?;
+        writeln!(w, "- node heights: {:.0} to {:.0}", lowest, highest)?;
+
+        // (layer, kind, purity) -> (nodes, items/min, m³/min)
+        let mut breakdown: BTreeMap<(&str, String, String), (usize, Throughput)> =
+            BTreeMap::new();
+        for m in set {
+            let marker = &markers[*m];
+            let key = (
+                layer_names[marker.layer],
+                layer_kinds[marker.layer].to_string(),
+                marker.purity.to_string(),
+            );
+            let entry = breakdown.entry(key).or_default();
+            entry.0 += 1;
+            entry.1.add(miner, marker);
+        }
+
+        writeln!(w)?;
+        writeln!(
+            w,
+            "Rates of Mk{} miners and extractors at {:.0}%:",
+            miner.mark,
+            miner.clock_speed * 100.0
+        )?;
+        writeln!(w)?;
+        writeln!(w, "| layer | kind | purity | nodes | items/min | m³/min |")?;
+        writeln!(w, "| --- | --- | --- | --- | --- | --- |")?;
+        for ((layer, kind, purity), (count, rate)) in &breakdown {
+            let purity = if purity.is_empty() { "unknown" } else { purity };
+            writeln!(
+                w,
+                "| {} | {} | {} | {} | {:.0} | {:.0} |",
+                layer, kind, purity, count, rate.items, rate.fluid
+            )?;
+        }
+        let throughput = throughputs[i];
+        writeln!(w)?;
+        writeln!(
+            w,
+            "Estimated output: {:.0} items/min and {:.0} m³/min",
+            throughput.items, throughput.fluid
+        )?;
+        writeln!(w)?;
+        writeln!(
+            w,
+            "Shipping it takes {} Mk5 or {} Mk6 belts for {:.0} items/min and {} pipelines \
+             for {:.0} m³/min.",
+            throughput.belts(rates::BELT_LIMIT),
+            throughput.belts(rates::MK6_BELT_LIMIT),
+            throughput.items,
+            throughput.pipelines(),
+            throughput.fluid
+        )?;
+        if let Some(cap) = cap.filter(|_| throughput.is_over(cap)) {
+            writeln!(w)?;
+            writeln!(w, "Over the throughput cap of {:.0} items/min.", cap)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn save_report(
+    path: &Path,
+    layer_names: &[&str],
+    layer_kinds: &[NodeKind],
+    problem: &Problem,
+    points: &[Pos2],
+    heights: &[f32],
+    sets: &[Vec<usize>],
+) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    write_report(&mut file, layer_names, layer_kinds, problem, points, heights, sets)?;
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cluster::{
+            tests::{marker, problem},
+            Parameters,
+        },
+        map::{Purity, ResourceMarker},
+    };
+
+    #[test]
+    fn keeps_items_and_fluids_apart() {
+        let markers = [
+            ResourceMarker {
+                r#type: Some("iron".to_string()),
+                purity: Purity::Pure,
+                ..marker(0.0, 0.0)
+            },
+            ResourceMarker {
+                r#type: Some("oil".to_string()),
+                purity: Purity::Pure,
+                kind: NodeKind::Liquid,
+                layer: 1,
+                ..marker(100.0, 0.0)
+            },
+        ];
+        let params = Parameters::default();
+        let problem = problem(&markers, &params);
+        let mut report = Vec::new();
+        write_report(
+            &mut report,
+            &["iron", "oil"],
+            &[NodeKind::Solid, NodeKind::Liquid],
+            &problem,
+            &[egui::pos2(50.0, 0.0)],
+            &[0.0],
+            &[vec![0, 1]],
+        )
+        .unwrap();
+        let report = String::from_utf8(report).unwrap();
+
+        // a pure node gives 480 items with a Mk3 miner, 240 m³ with an
+        // extractor, never 720 of anything
+        assert!(report.contains("| 0 | 2 | 100 | 50 | "), "{}", report);
+        assert!(report.contains(" | 480 | 240 |\n"), "{}", report);
+        assert!(report.contains("| iron | solid | pure | 1 | 480 | 0 |"), "{}", report);
+        assert!(report.contains("| oil | liquid | pure | 1 | 0 | 240 |"), "{}", report);
+        assert!(report.contains("Estimated output: 480 items/min and 240 m³/min"));
+        assert!(!report.contains("720"), "{}", report);
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    fs,
+    io::{self, Write},
+    num::NonZeroUsize,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+use egui::Pos2;
+
+use crate::{
+    cluster::{is_better, Clustering, MedianSolver, Problem},
+    sweep::SweepInput,
+};
+
+/// Fraction of the restarts, the best by score, that the stability of the
+/// stations is measured against.
+pub const GOOD_RUN_FRACTION: f32 = 0.25;
+
+/// The result of one restart, or a new best result of a [`Restarts`] run.
+#[derive(Debug, Clone)]
+pub struct RestartBest {
+    pub error: f32,
+    /// Markers out of range of the points.
+    pub uncovered: usize,
+    pub points: Vec<Pos2>,
+    pub heights: Vec<f32>,
+    pub solver: Option<MedianSolver>,
+    /// Seed of the restart, giving the same result as a single run.
+    pub seed: Option<u64>,
+}
+
+impl RestartBest {
+    pub fn score(&self) -> (usize, f32) {
+        (self.uncovered, self.error)
+    }
+}
+
+/// Runs restarts `0..restarts` on all cores and returns the best result, the
+/// first restart's among equally good ones. Every restart draws from its own
+/// seed derived from `base_seed` and its index, so the result doesn't depend
+/// on the number of threads. `on_run` is called with the result of every
+/// finished restart; once `cancel` is set the best so far is returned.
+pub fn best_of_restarts(
+    problem: &Problem,
+    restarts: usize,
+    base_seed: u64,
+    cancel: &AtomicBool,
+    on_run: impl Fn(&RestartBest) + Sync,
+) -> Option<RestartBest> {
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    best_of_restarts_on(threads, problem, restarts, base_seed, cancel, on_run)
+}
+
+/// [`best_of_restarts`] on the given number of threads. Scoped threads take
+/// the next restart from a shared counter, which balances restarts as well
+/// as a rayon pool would without adding it as a dependency.
+fn best_of_restarts_on(
+    threads: usize,
+    problem: &Problem,
+    restarts: usize,
+    base_seed: u64,
+    cancel: &AtomicBool,
+    on_run: impl Fn(&RestartBest) + Sync,
+) -> Option<RestartBest> {
+    let next = AtomicUsize::new(0);
+    let threads = threads.clamp(1, restarts.max(1));
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut best: Option<(usize, RestartBest)> = None;
+                    loop {
+                        let restart = next.fetch_add(1, Ordering::Relaxed);
+                        if restart >= restarts || cancel.load(Ordering::Relaxed) {
+                            return best;
+                        }
+                        let mut clustering = Clustering::default();
+                        let seed = base_seed.wrapping_add(restart as u64);
+                        clustering.reinitialize_seeded(problem, seed);
+                        clustering.run_k_median(problem);
+                        let result = RestartBest {
+                            error: clustering.best_so_far,
+                            uncovered: clustering.best_so_far_uncovered,
+                            points: clustering.best_so_far_points,
+                            heights: clustering.best_so_far_heights,
+                            solver: clustering.best_so_far_solver,
+                            seed: clustering.seed,
+                        };
+                        on_run(&result);
+
+                        // restarts come in ascending order, so ties keep the first
+                        if best.as_ref().is_none_or(|(_, b)| is_better(result.score(), b.score())) {
+                            best = Some((restart, result));
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .filter_map(|worker| worker.join().expect("restart thread panicked"))
+            .min_by(|(a, a_best), (b, b_best)| {
+                (a_best.uncovered.cmp(&b_best.uncovered))
+                    .then(a_best.error.total_cmp(&b_best.error))
+                    .then(a.cmp(b))
+            })
+            .map(|(_, best)| best)
+    })
+}
+
+/// How the errors of a set of restarts spread.
+#[derive(Debug, Clone, Copy)]
+pub struct RunSummary {
+    pub runs: usize,
+    pub min: f32,
+    pub median: f32,
+    pub mean: f32,
+    pub max: f32,
+}
+
+/// Summary of the errors of the runs, None without runs.
+pub fn summarize(runs: &[RestartBest]) -> Option<RunSummary> {
+    let mut errors: Vec<f32> = runs.iter().map(|run| run.error).collect();
+    errors.sort_by(f32::total_cmp);
+    let (min, max) = (*errors.first()?, *errors.last()?);
+    let middle = errors.len() / 2;
+    let median = if errors.len() % 2 == 1 {
+        errors[middle]
+    } else {
+        (errors[middle - 1] + errors[middle]) / 2.0
+    };
+    Some(RunSummary {
+        runs: errors.len(),
+        min,
+        median,
+        mean: errors.iter().sum::<f32>() / errors.len() as f32,
+        max,
+    })
+}
+
+/// Counts of the errors of the runs in `bins` equally wide bins from the
+/// smallest error to the largest.
+pub fn histogram(runs: &[RestartBest], bins: usize, summary: &RunSummary) -> Vec<usize> {
+    let mut counts = vec![0; bins];
+    let width = (summary.max - summary.min) / bins as f32;
+    for run in runs {
+        let bin = if width > 0.0 { ((run.error - summary.min) / width) as usize } else { 0 };
+        counts[bin.min(bins - 1)] += 1;
+    }
+    counts
+}
+
+/// Per point, the mean distance to the closest point of every other good
+/// run, the best [`GOOD_RUN_FRACTION`] of the runs by score: small where the
+/// restarts agree on a station, large where it moves from run to run. Runs
+/// with exactly the given points don't count. Empty without other runs.
+pub fn stability(points: &[Pos2], runs: &[RestartBest]) -> Vec<f32> {
+    let mut ranked: Vec<&RestartBest> = runs.iter().collect();
+    ranked.sort_by(|a, b| a.uncovered.cmp(&b.uncovered).then(a.error.total_cmp(&b.error)));
+    let good = ((runs.len() as f32 * GOOD_RUN_FRACTION).ceil() as usize).max(1);
+    let others: Vec<&RestartBest> = ranked
+        .into_iter()
+        .take(good)
+        .filter(|run| run.points != points && !run.points.is_empty())
+        .collect();
+    if others.is_empty() {
+        return Vec::new();
+    }
+
+    points
+        .iter()
+        .map(|point| {
+            let total: f32 = others
+                .iter()
+                .map(|run| {
+                    run.points.iter().map(|other| point.distance(*other)).fold(f32::MAX, f32::min)
+                })
+                .sum();
+            total / others.len() as f32
+        })
+        .collect()
+}
+
+pub fn write_runs_csv<W: Write>(mut w: W, runs: &[RestartBest]) -> io::Result<()> {
+    writeln!(w, "run,seed,uncovered,error")?;
+    for (i, run) in runs.iter().enumerate() {
+        let seed = run.seed.map(|seed| seed.to_string()).unwrap_or_default();
+        writeln!(w, "{},{},{},{}", i + 1, seed, run.uncovered, run.error)?;
+    }
+    Ok(())
+}
+
+pub fn save_runs_csv(path: &Path, runs: &[RestartBest]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    write_runs_csv(&mut file, runs)?;
+    file.flush()
+}
+
+pub fn write_stability_csv<W: Write>(
+    mut w: W,
+    points: &[Pos2],
+    stability: &[f32],
+) -> io::Result<()> {
+    writeln!(w, "station,x,y,stability")?;
+    for (i, (point, distance)) in points.iter().zip(stability).enumerate() {
+        writeln!(w, "{},{},{},{}", i + 1, point.x, point.y, distance)?;
+    }
+    Ok(())
+}
+
+pub fn save_stability_csv(path: &Path, points: &[Pos2], stability: &[f32]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    write_stability_csv(&mut file, points, stability)?;
+    file.flush()
+}
+
+enum RestartMessage {
+    Run(RestartBest),
+    Best(RestartBest),
+}
+
+/// Repeated runs from fresh initial points in the background, passing on the
+/// result of every run and reporting the best if it improves on the best so
+/// far.
+pub struct Restarts {
+    rx: Receiver<RestartMessage>,
+    cancel: Arc<AtomicBool>,
+    done: Arc<AtomicUsize>,
+    /// Result received since the last [`Restarts::take_best`].
+    best: Option<RestartBest>,
+    /// Runs received since the last [`Restarts::take_runs`].
+    runs: Vec<RestartBest>,
+    pub runs_total: usize,
+    pub running: bool,
+}
+
+impl Restarts {
+    /// Runs the clustering `restarts` times, only reporting a result better
+    /// than `best_so_far`, given as uncovered markers and error.
+    pub fn start(input: SweepInput, restarts: usize, best_so_far: (usize, f32)) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let done = Arc::new(AtomicUsize::new(0));
+        let base_seed = input.params.seed.unwrap_or_else(rand::random);
+
+        let cancelled = cancel.clone();
+        let runs = done.clone();
+        thread::spawn(move || {
+            let problem = input.problem(&input.params);
+            let on_run = |result: &RestartBest| {
+                runs.fetch_add(1, Ordering::Relaxed);
+                // the receiver is gone if the app was closed meanwhile
+                if tx.send(RestartMessage::Run(result.clone())).is_err() {
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+            };
+            let best = best_of_restarts(&problem, restarts, base_seed, &cancelled, on_run);
+            if let Some(best) = best.filter(|best| is_better(best.score(), best_so_far)) {
+                let _ = tx.send(RestartMessage::Best(best));
+            }
+        });
+
+        Self {
+            rx,
+            cancel,
+            done,
+            best: None,
+            runs: Vec::new(),
+            runs_total: restarts,
+            running: true,
+        }
+    }
+
+    /// Takes in what the thread has sent so far.
+    pub fn poll(&mut self) {
+        loop {
+            match self.rx.try_recv() {
+                Ok(RestartMessage::Run(run)) => self.runs.push(run),
+                Ok(RestartMessage::Best(best)) => self.best = Some(best),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.running = false;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// The runs received since the last call.
+    pub fn take_runs(&mut self) -> Vec<RestartBest> {
+        std::mem::take(&mut self.runs)
+    }
+
+    /// The best result received since the last call, if it improved.
+    pub fn take_best(&mut self) -> Option<RestartBest> {
+        self.best.take()
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn runs_done(&self) -> usize {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.runs_total == 0 {
+            1.0
+        } else {
+            self.runs_done() as f32 / self.runs_total as f32
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::cluster::{
+        tests::{markers, problem},
+        Initialization, MedianSolver, Parameters,
+    };
+
+    fn scattered(count: usize) -> Vec<(f32, f32)> {
+        let mut rng = StdRng::seed_from_u64(7);
+        (0..count)
+            .map(|_| (rng.gen_range(0.0..50000.0), rng.gen_range(0.0..50000.0)))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_bit_identical_runs() {
+        let markers = markers(&scattered(80));
+        for (initialization, median_solver) in [
+            (Initialization::Uniform, MedianSolver::SimulatedAnnealing),
+            (Initialization::KMeansPlusPlus, MedianSolver::Annealing),
+            (Initialization::RandomMarkers, MedianSolver::Weiszfeld),
+        ] {
+            let params = Parameters {
+                k: 6,
+                initialization,
+                median_solver,
+                ..Parameters::default()
+            };
+            let problem = problem(&markers, &params);
+            let run = || {
+                let mut clustering = Clustering::default();
+                clustering.reinitialize_seeded(&problem, 42);
+                clustering.run_k_median(&problem);
+                clustering
+            };
+            let (a, b) = (run(), run());
+            assert_eq!(a.points, b.points);
+            assert_eq!(a.heights, b.heights);
+            assert_eq!(a.best_so_far.to_bits(), b.best_so_far.to_bits());
+            assert_eq!(a.best_so_far_points, b.best_so_far_points);
+        }
+    }
+
+    #[test]
+    fn restarts_give_the_same_best_on_any_number_of_threads() {
+        let markers = markers(&scattered(80));
+        let params = Parameters {
+            k: 6,
+            median_solver: MedianSolver::SimulatedAnnealing,
+            ..Parameters::default()
+        };
+        let problem = problem(&markers, &params);
+        let cancel = AtomicBool::new(false);
+        let best = |threads: usize| {
+            best_of_restarts_on(threads, &problem, 12, 1234, &cancel, |_: &RestartBest| {})
+                .expect("restarts ran")
+        };
+
+        let single = best(1);
+        for threads in [1, 2, 3, 8] {
+            let other = best(threads);
+            assert_eq!(other.points, single.points, "{} threads", threads);
+            assert_eq!(other.heights, single.heights, "{} threads", threads);
+            assert_eq!(other.error.to_bits(), single.error.to_bits(), "{} threads", threads);
+            assert_eq!(other.seed, single.seed, "{} threads", threads);
+        }
+
+        // the seed of the best restart gives the same result on its own
+        let mut clustering = Clustering::default();
+        clustering.reinitialize_seeded(&problem, single.seed.expect("seeded"));
+        clustering.run_k_median(&problem);
+        assert_eq!(clustering.best_so_far_points, single.points);
+        assert_eq!(clustering.best_so_far.to_bits(), single.error.to_bits());
+    }
+}
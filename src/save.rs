@@ -0,0 +1,264 @@
+use std::{
+    error::Error,
+    fmt, fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use flate2::read::ZlibDecoder;
+
+use crate::map::{LastCheck, NodeKind, Purity, ResourceLayer, ResourceMarker};
+
+pub const MINER_LAYER_ID: &str = "builtMiners";
+
+/// Marks the start of every compressed chunk of the save body.
+const PACKAGE_FILE_TAG: u32 = 0x9E2A_83C1;
+
+/// Every buildable class path starts with this.
+const BUILDABLE_PREFIX: &[u8] = b"/Game/FactoryGame/Buildable/";
+
+const MINER_CLASSES: [&str; 3] = ["Build_MinerMk1_C", "Build_MinerMk2_C", "Build_MinerMk3_C"];
+
+const TRAIN_STATION_CLASS: &str = "Build_TrainStation_C";
+
+/// How far from a resource node a miner may be and still be taken to sit on it.
+const NODE_MATCH_DISTANCE: f32 = 2000.0;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Format(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "{}", err),
+            SaveError::Format(msg) => write!(f, "not a readable save file: {}", msg),
+        }
+    }
+}
+
+impl Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+/// A placed actor as stored in the save, with its world position in the same
+/// coordinates the map data uses.
+#[derive(Debug, Clone)]
+pub struct BuiltActor {
+    pub class: String,
+    pub path_name: String,
+    pub position: [f32; 3],
+}
+
+impl BuiltActor {
+    /// Turns a miner into a marker, taking purity and resource type from the
+    /// node it was built on if one of `map_markers` is close enough.
+    pub fn to_resource_marker(&self, map_markers: &[ResourceMarker]) -> ResourceMarker {
+        let [x, y, z] = self.position;
+        let node = map_markers
+            .iter()
+            .filter(|m| !m.is_pressurizer())
+            .map(|m| (m, (m.x - x).powi(2) + (m.y - y).powi(2)))
+            .filter(|(_, dist)| *dist <= NODE_MATCH_DISTANCE.powi(2))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(m, _)| m);
+
+        ResourceMarker {
+            pathName: self.path_name.clone(),
+            x,
+            y,
+            z,
+            r#type: node.and_then(|m| m.r#type.clone()),
+            purity: node.map_or_else(|| Purity::Unknown(String::new()), |m| m.purity.clone()),
+            obstructed: None,
+            lastCheck: LastCheck::Unknown(String::new()),
+            core: None,
+            layer: 0,
+            kind: node.map_or(NodeKind::Solid, |m| m.kind),
+            satellites: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SaveObjects {
+    pub miners: Vec<BuiltActor>,
+    pub stations: Vec<BuiltActor>,
+}
+
+pub fn miner_layer() -> ResourceLayer {
+    ResourceLayer {
+        layerId: MINER_LAYER_ID.to_string(),
+        name: "Built miners".to_string(),
+        purity: None,
+        outsideColor: "#000000".to_string(),
+        insideColor: "#ffa500".to_string(),
+        icon: String::new(),
+        markers: Vec::new(),
+        collectible: None,
+        kind: NodeKind::Solid,
+    }
+}
+
+/// Reads the built miners and train stations from a Satisfactory save.
+///
+/// This does not understand the full save format. It decompresses the body and
+/// looks for actor headers of the classes we care about, which is enough to
+/// get at their positions without knowing every property type.
+pub fn read_save(path: &Path) -> Result<SaveObjects, SaveError> {
+    let bytes = fs::read(path)?;
+    let body = decompress_body(&bytes)?;
+
+    let mut objects = SaveObjects::default();
+    for actor in find_actors(&body) {
+        if MINER_CLASSES
+            .iter()
+            .any(|class| actor.class.ends_with(class))
+        {
+            objects.miners.push(actor);
+        } else if actor.class.ends_with(TRAIN_STATION_CLASS) {
+            objects.stations.push(actor);
+        }
+    }
+
+    Ok(objects)
+}
+
+/// Concatenates the zlib-compressed chunks following the save header.
+fn decompress_body(bytes: &[u8]) -> Result<Vec<u8>, SaveError> {
+    let tag = PACKAGE_FILE_TAG.to_le_bytes();
+    let mut offset = bytes
+        .windows(tag.len())
+        .position(|window| window == tag)
+        .ok_or_else(|| SaveError::Format("no compressed chunks found".to_string()))?;
+
+    let mut body = Vec::new();
+    while offset < bytes.len() {
+        let header = &bytes[offset..];
+        if !header.starts_with(&tag) {
+            return Err(SaveError::Format(format!("bad chunk tag at {}", offset)));
+        }
+
+        let (header_len, compressed_len) = chunk_sizes(header)
+            .ok_or_else(|| SaveError::Format(format!("bad chunk header at {}", offset)))?;
+        let start = offset + header_len;
+        let end = start
+            .checked_add(compressed_len)
+            .filter(|end| *end <= bytes.len())
+            .ok_or_else(|| SaveError::Format(format!("truncated chunk at {}", offset)))?;
+
+        ZlibDecoder::new(&bytes[start..end])
+            .read_to_end(&mut body)
+            .map_err(|err| SaveError::Format(format!("corrupt chunk at {}: {}", offset, err)))?;
+        offset = end;
+    }
+
+    Ok(body)
+}
+
+/// Returns the header length and compressed size of the chunk `header` starts
+/// with. Newer saves put a compression algorithm byte before the sizes, which
+/// we detect by the summary sizes having to match those of the single block.
+fn chunk_sizes(header: &[u8]) -> Option<(usize, usize)> {
+    [16, 17].into_iter().find_map(|sizes_at| {
+        let sizes: Vec<i64> = (0..4)
+            .map(|i| read_i64(header, sizes_at + i * 8))
+            .collect::<Option<_>>()?;
+        let valid = sizes[0] == sizes[2] && sizes[1] == sizes[3] && sizes[0] > 0 && sizes[1] > 0;
+        valid.then(|| (sizes_at + 32, sizes[0] as usize))
+    })
+}
+
+fn read_i64(bytes: &[u8], at: usize) -> Option<i64> {
+    let bytes = bytes.get(at..at + 8)?;
+    Some(i64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+/// Scans the decompressed body for actor headers with a buildable class.
+fn find_actors(body: &[u8]) -> Vec<BuiltActor> {
+    let mut actors = Vec::new();
+    let mut search_from = 0;
+    while let Some(found) = body[search_from..]
+        .windows(BUILDABLE_PREFIX.len())
+        .position(|window| window == BUILDABLE_PREFIX)
+    {
+        let at = search_from + found;
+        search_from = at + 1;
+
+        // the class path is an FString preceded by the object type, 1 for actors
+        let Some(header_at) = at.checked_sub(8) else {
+            continue;
+        };
+        let mut reader = Reader::new(body, header_at);
+        if reader.i32() != Some(1) {
+            continue;
+        }
+        if let Some(actor) = reader.actor_header() {
+            search_from = reader.offset;
+            actors.push(actor);
+        }
+    }
+    actors
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], offset: usize) -> Self {
+        Self { bytes, offset }
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.offset..self.offset.checked_add(len)?)?;
+        self.offset += len;
+        Some(bytes)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    /// Reads a null-terminated ASCII FString. Actor paths are never UTF-16.
+    fn string(&mut self) -> Option<String> {
+        let len = usize::try_from(self.i32()?).ok().filter(|len| *len > 0)?;
+        let (text, nul) = self.take(len)?.split_at(len - 1);
+        if nul != [0] || !text.is_ascii() {
+            return None;
+        }
+        String::from_utf8(text.to_vec()).ok()
+    }
+
+    /// Reads the rest of an actor header, past its type.
+    fn actor_header(&mut self) -> Option<BuiltActor> {
+        let class = self.string()?;
+        let _level = self.string()?;
+        let path_name = self.string()?;
+        let _need_transform = self.i32()?;
+        for _ in 0..4 {
+            self.f32()?;
+        }
+        let position = [self.f32()?, self.f32()?, self.f32()?];
+
+        // the world is a few thousand meters across, anything beyond that is
+        // not a header after all
+        let plausible = position.iter().all(|p| p.is_finite() && p.abs() < 1.0e7);
+        plausible.then_some(BuiltActor {
+            class,
+            path_name,
+            position,
+        })
+    }
+}
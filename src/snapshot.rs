@@ -0,0 +1,206 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::map::{
+    LastCheck, MapData, NodeKind, Purity, ResourceLayer, ResourceMarker, SourceSummary,
+};
+
+/// Bumped whenever the snapshot layout changes, so old snapshots are reparsed.
+const SNAPSHOT_FORMAT: u32 = 4;
+
+/// The layers and markers extracted from one map file, stored next to it so
+/// later launches can skip parsing the JSON.
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    format: u32,
+    /// Hash of the map file the snapshot was made from.
+    hash: u64,
+    version: i64,
+    last_build: String,
+    layers: Vec<SnapshotLayer>,
+    markers: Vec<SnapshotMarker>,
+    layer_count: usize,
+    marker_count: usize,
+    skipped: Vec<String>,
+    duplicates: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotLayer {
+    layer_id: String,
+    name: String,
+    purity: Option<String>,
+    outside_color: String,
+    inside_color: String,
+    icon: String,
+    collectible: Option<String>,
+    kind: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotMarker {
+    path_name: String,
+    x: f32,
+    y: f32,
+    z: f32,
+    kind: Option<String>,
+    purity: String,
+    obstructed: Option<bool>,
+    last_check: SnapshotCheck,
+    core: Option<String>,
+    layer: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum SnapshotCheck {
+    /// Milliseconds since the unix epoch.
+    Date(i64),
+    Build(u32),
+    Unknown(String),
+}
+
+/// FNV-1a, which unlike the std hashers is the same across Rust versions.
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+pub fn snapshot_path(map_path: &Path) -> PathBuf {
+    let mut name = map_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".snapshot");
+    map_path.with_file_name(name)
+}
+
+/// The data of the map file with the given hash, if there is a usable
+/// snapshot of it. Anything wrong with the snapshot just means there is none.
+pub fn load(map_path: &Path, hash: u64) -> Option<MapData> {
+    let bytes = fs::read(snapshot_path(map_path)).ok()?;
+    let snapshot: Snapshot = bincode::deserialize(&bytes).ok()?;
+    if snapshot.format != SNAPSHOT_FORMAT || snapshot.hash != hash {
+        return None;
+    }
+
+    let mut markers = snapshot
+        .markers
+        .into_iter()
+        .map(|m| {
+            Some(ResourceMarker {
+                pathName: m.path_name,
+                x: m.x,
+                y: m.y,
+                z: m.z,
+                r#type: m.kind,
+                purity: Purity::from(m.purity.as_str()),
+                obstructed: m.obstructed,
+                lastCheck: match m.last_check {
+                    SnapshotCheck::Date(millis) => {
+                        LastCheck::Date(DateTime::from_timestamp_millis(millis)?)
+                    }
+                    SnapshotCheck::Build(build) => LastCheck::Build(build),
+                    SnapshotCheck::Unknown(s) => LastCheck::Unknown(s),
+                },
+                core: m.core,
+                layer: m.layer,
+                kind: NodeKind::default(),
+                satellites: Vec::new(),
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if markers.iter().any(|m| m.layer >= snapshot.layers.len()) {
+        return None;
+    }
+
+    let layers: Vec<ResourceLayer> = snapshot
+        .layers
+        .into_iter()
+        .map(|l| ResourceLayer {
+            layerId: l.layer_id,
+            name: l.name,
+            purity: l.purity.as_deref().map(Purity::from),
+            outsideColor: l.outside_color,
+            insideColor: l.inside_color,
+            icon: l.icon,
+            markers: Vec::new(),
+            collectible: l.collectible,
+            kind: NodeKind::from(l.kind.as_str()),
+        })
+        .collect();
+
+    for marker in &mut markers {
+        marker.kind = layers[marker.layer].kind;
+    }
+
+    let mut data = MapData::default();
+    data.layers = layers;
+    data.markers = markers;
+    data.sources = vec![SourceSummary {
+        source: map_path.display().to_string(),
+        version: snapshot.version,
+        last_build: snapshot.last_build,
+        layers: snapshot.layer_count,
+        markers: snapshot.marker_count,
+        skipped: snapshot.skipped,
+        from_snapshot: true,
+        load_time: Default::default(),
+    }];
+    data.duplicates = snapshot.duplicates;
+    Some(data)
+}
+
+/// Writes a snapshot of `data`, which must come from that map file alone.
+pub fn save(map_path: &Path, hash: u64, data: &MapData) -> Result<(), String> {
+    let summary = data.sources.first().ok_or("no map data to snapshot")?;
+    let snapshot = Snapshot {
+        format: SNAPSHOT_FORMAT,
+        hash,
+        version: summary.version,
+        last_build: summary.last_build.clone(),
+        layers: data
+            .layers
+            .iter()
+            .map(|l| SnapshotLayer {
+                layer_id: l.layerId.clone(),
+                name: l.name.clone(),
+                purity: l.purity.as_ref().map(|p| p.to_string()),
+                outside_color: l.outsideColor.clone(),
+                inside_color: l.insideColor.clone(),
+                icon: l.icon.clone(),
+                collectible: l.collectible.clone(),
+                kind: l.kind.to_string(),
+            })
+            .collect(),
+        markers: data
+            .markers
+            .iter()
+            .map(|m| SnapshotMarker {
+                path_name: m.pathName.clone(),
+                x: m.x,
+                y: m.y,
+                z: m.z,
+                kind: m.r#type.clone(),
+                purity: m.purity.to_string(),
+                obstructed: m.obstructed,
+                last_check: match &m.lastCheck {
+                    LastCheck::Date(date) => SnapshotCheck::Date(date.timestamp_millis()),
+                    LastCheck::Build(build) => SnapshotCheck::Build(*build),
+                    LastCheck::Unknown(s) => SnapshotCheck::Unknown(s.clone()),
+                },
+                core: m.core.clone(),
+                layer: m.layer,
+            })
+            .collect(),
+        layer_count: summary.layers,
+        marker_count: summary.markers,
+        skipped: summary.skipped.clone(),
+        duplicates: data.duplicates,
+    };
+
+    let bytes = bincode::serialize(&snapshot).map_err(|err| err.to_string())?;
+    fs::write(snapshot_path(map_path), bytes).map_err(|err| err.to_string())
+}
@@ -0,0 +1,168 @@
+use egui::Pos2;
+
+/// Points split alternately along x and y at their median, for finding the
+/// closest point or those around a spot without scanning all of them.
+#[derive(Default)]
+pub struct KdTree {
+    /// Index and position of every point, ordered so that every subtree is a
+    /// range with the point splitting it in the middle.
+    nodes: Vec<(usize, Pos2)>,
+}
+
+impl KdTree {
+    /// Builds the tree over the points with finite positions, given with the
+    /// index queries return for them.
+    pub fn new(points: impl IntoIterator<Item = (usize, Pos2)>) -> Self {
+        let mut nodes: Vec<(usize, Pos2)> = points
+            .into_iter()
+            .filter(|(_, point)| point.x.is_finite() && point.y.is_finite())
+            .collect();
+        build(&mut nodes, 0);
+        Self { nodes }
+    }
+
+    /// The point with the smallest `distance`, by its index, and that
+    /// distance. Ties go to the lowest index, as with a scan in order.
+    /// `distance` must never be shorter than the straight line on the plane
+    /// from `query` to the point, which holds for every metric, in 3D and
+    /// with a climb cost. None without points.
+    pub fn nearest(&self, query: Pos2, distance: impl Fn(usize) -> f32) -> Option<(usize, f32)> {
+        let mut best = None;
+        nearest_in(&self.nodes, 0, query, &distance, &mut best);
+        best
+    }
+
+    /// The points at most `radius` from `query` in a straight line on the
+    /// plane, in no particular order.
+    pub fn within(&self, query: Pos2, radius: f32) -> Vec<usize> {
+        let mut found = Vec::new();
+        within_in(&self.nodes, 0, query, radius, &mut found);
+        found
+    }
+}
+
+fn axis(point: Pos2, depth: usize) -> f32 {
+    if depth.is_multiple_of(2) {
+        point.x
+    } else {
+        point.y
+    }
+}
+
+fn build(nodes: &mut [(usize, Pos2)], depth: usize) {
+    if nodes.len() <= 1 {
+        return;
+    }
+    let mid = nodes.len() / 2;
+    nodes.select_nth_unstable_by(mid, |a, b| axis(a.1, depth).total_cmp(&axis(b.1, depth)));
+    let (before, after) = nodes.split_at_mut(mid);
+    build(before, depth + 1);
+    build(&mut after[1..], depth + 1);
+}
+
+fn nearest_in(
+    nodes: &[(usize, Pos2)],
+    depth: usize,
+    query: Pos2,
+    distance: &impl Fn(usize) -> f32,
+    best: &mut Option<(usize, f32)>,
+) {
+    if nodes.is_empty() {
+        return;
+    }
+    let mid = nodes.len() / 2;
+    let (i, point) = nodes[mid];
+    let d = distance(i);
+    let better = best.is_none_or(|(best_i, best_d)| d < best_d || (d == best_d && i < best_i));
+    if !d.is_nan() && better {
+        *best = Some((i, d));
+    }
+
+    // the points on the other side are at least the gap away
+    let gap = axis(query, depth) - axis(point, depth);
+    let (near, far) = if gap < 0.0 {
+        (&nodes[..mid], &nodes[mid + 1..])
+    } else {
+        (&nodes[mid + 1..], &nodes[..mid])
+    };
+    nearest_in(near, depth + 1, query, distance, best);
+    if best.is_none_or(|(_, best_d)| gap.abs() <= best_d) {
+        nearest_in(far, depth + 1, query, distance, best);
+    }
+}
+
+fn within_in(
+    nodes: &[(usize, Pos2)],
+    depth: usize,
+    query: Pos2,
+    radius: f32,
+    found: &mut Vec<usize>,
+) {
+    if nodes.is_empty() {
+        return;
+    }
+    let mid = nodes.len() / 2;
+    let (i, point) = nodes[mid];
+    if point.distance(query) <= radius {
+        found.push(i);
+    }
+    let gap = axis(query, depth) - axis(point, depth);
+    if gap <= radius {
+        within_in(&nodes[..mid], depth + 1, query, radius, found);
+    }
+    if gap >= -radius {
+        within_in(&nodes[mid + 1..], depth + 1, query, radius, found);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::pos2;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    fn random_points(rng: &mut StdRng, count: usize) -> Vec<Pos2> {
+        (0..count)
+            .map(|_| {
+                // a coarse grid now and then, for ties and shared axes
+                if rng.gen_bool(0.2) {
+                    pos2(rng.gen_range(0..5) as f32 * 100.0, rng.gen_range(0..5) as f32 * 100.0)
+                } else {
+                    pos2(rng.gen_range(-1000.0..1000.0), rng.gen_range(-1000.0..1000.0))
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_brute_force_on_random_points() {
+        let mut rng = StdRng::seed_from_u64(91);
+        for count in [0, 1, 2, 3, 10, 57, 300] {
+            let points = random_points(&mut rng, count);
+            let tree = KdTree::new(points.iter().copied().enumerate());
+            for _ in 0..200 {
+                let query = pos2(rng.gen_range(-1200.0..1200.0), rng.gen_range(-1200.0..1200.0));
+                // a metric longer than the straight line, as the clustering's are
+                let distance = |i: usize| {
+                    let d = points[i] - query;
+                    d.x.abs() + d.y.abs()
+                };
+                let brute = (0..points.len())
+                    .map(|i| (i, distance(i)))
+                    .fold(None, |best: Option<(usize, f32)>, (i, d)| match best {
+                        Some((_, best_d)) if best_d <= d => best,
+                        _ => Some((i, d)),
+                    });
+                assert_eq!(tree.nearest(query, distance), brute, "{} points", count);
+
+                let radius = rng.gen_range(0.0..500.0);
+                let mut found = tree.within(query, radius);
+                found.sort_unstable();
+                let expected: Vec<usize> =
+                    (0..points.len()).filter(|i| points[*i].distance(query) <= radius).collect();
+                assert_eq!(found, expected, "{} points", count);
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use egui::{Color32, Pos2, Rect};
+
+use crate::color::LayerColors;
+
+/// The markers of one resource layer, drawn as a group of their own.
+#[derive(Debug)]
+pub struct SvgLayer<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub colors: LayerColors,
+    pub markers: Vec<Pos2>,
+}
+
+/// Everything in an SVG export, in game coordinates.
+#[derive(Debug)]
+pub struct SvgMap<'a> {
+    pub bounds: Rect,
+    pub layers: Vec<SvgLayer<'a>>,
+    pub stations: Vec<Pos2>,
+    /// From each assigned marker to its station.
+    pub assignments: Vec<(Pos2, Pos2)>,
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `fill`/`stroke` attribute value plus the matching opacity attribute.
+fn paint(attribute: &str, color: Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    format!(
+        "{0}=\"#{1:02x}{2:02x}{3:02x}\" {0}-opacity=\"{4:.3}\"",
+        attribute,
+        r,
+        g,
+        b,
+        a as f32 / 255.0
+    )
+}
+
+/// Writes the map with the viewBox set to its bounds, so that one SVG unit is
+/// one game unit and it lines up with map images of the same extent.
+pub fn write_svg<W: Write>(mut w: W, map: &SvgMap) -> io::Result<()> {
+    let bounds = map.bounds;
+    // sizes relative to the map so the picture looks the same at any extent
+    let unit = bounds.width().max(bounds.height()) / 1000.0;
+
+    writeln!(
+        w,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{} {} {} {}\">",
+        bounds.min.x,
+        bounds.min.y,
+        bounds.width(),
+        bounds.height()
+    )?;
+
+    writeln!(
+        w,
+        "<g id=\"assignments\" stroke=\"#a0a0a0\" stroke-width=\"{}\">",
+        unit * 0.5
+    )?;
+    for (from, to) in &map.assignments {
+        writeln!(
+            w,
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"/>",
+            from.x, from.y, to.x, to.y
+        )?;
+    }
+    writeln!(w, "</g>")?;
+
+    for layer in &map.layers {
+        writeln!(
+            w,
+            "<g id=\"{}\" {} {} stroke-width=\"{}\">",
+            escape(layer.id),
+            paint("fill", layer.colors.inside),
+            paint("stroke", layer.colors.outside),
+            unit
+        )?;
+        writeln!(w, "<title>{}</title>", escape(layer.name))?;
+        for marker in &layer.markers {
+            writeln!(
+                w,
+                "<circle cx=\"{}\" cy=\"{}\" r=\"{}\"/>",
+                marker.x,
+                marker.y,
+                unit * 3.5
+            )?;
+        }
+        writeln!(w, "</g>")?;
+    }
+
+    writeln!(w, "<g id=\"stations\" {}>", paint("fill", Color32::GREEN))?;
+    for station in &map.stations {
+        writeln!(
+            w,
+            "<circle cx=\"{}\" cy=\"{}\" r=\"{}\"/>",
+            station.x,
+            station.y,
+            unit * 8.0
+        )?;
+    }
+    writeln!(w, "</g>")?;
+
+    writeln!(w, "</svg>")
+}
+
+pub fn save_svg(path: &Path, map: &SvgMap) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    write_svg(&mut file, map)?;
+    file.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::pos2;
+
+    use super::*;
+
+    struct Element {
+        name: String,
+        /// Id of the group it is in.
+        group: String,
+        attributes: Vec<(String, String)>,
+    }
+
+    impl Element {
+        fn number(&self, key: &str) -> f32 {
+            let value = &self.attributes.iter().find(|(k, _)| k == key).expect(key).1;
+            value.parse().expect("a number")
+        }
+    }
+
+    /// The elements of a document in order.
+    fn elements(svg: &str) -> Vec<Element> {
+        let mut elements = Vec::new();
+        let mut group = String::new();
+        for tag in svg.split('<').skip(1) {
+            let tag = tag.split('>').next().expect("every tag is closed");
+            if tag.starts_with('/') {
+                continue;
+            }
+            let name = tag.split_whitespace().next().unwrap_or_default().to_string();
+            let attributes: Vec<(String, String)> = tag
+                .split('"')
+                .collect::<Vec<_>>()
+                .chunks_exact(2)
+                .map(|pair| {
+                    let key = pair[0].split_whitespace().last().unwrap_or_default();
+                    (key.trim_end_matches('=').to_string(), pair[1].to_string())
+                })
+                .collect();
+            if name == "g" {
+                group = attributes.iter().find(|(k, _)| k == "id").unwrap().1.clone();
+            }
+            elements.push(Element {
+                name,
+                group: group.clone(),
+                attributes,
+            });
+        }
+        elements
+    }
+
+    #[test]
+    fn parses_back() {
+        let iron = LayerColors::parse("#a414a4", "#ffffff");
+        let copper = LayerColors::parse("#c07040", "#000000");
+        let map = SvgMap {
+            bounds: Rect::from_min_max(pos2(-100.0, -50.0), pos2(300.0, 150.0)),
+            layers: vec![
+                SvgLayer {
+                    id: "iron",
+                    name: "Iron <ore>",
+                    colors: iron,
+                    markers: vec![pos2(0.0, 0.0), pos2(10.5, -20.0)],
+                },
+                SvgLayer {
+                    id: "copper",
+                    name: "Copper",
+                    colors: copper,
+                    markers: vec![pos2(200.0, 100.0)],
+                },
+            ],
+            stations: vec![pos2(5.0, -10.0), pos2(200.0, 100.0)],
+            assignments: vec![
+                (pos2(0.0, 0.0), pos2(5.0, -10.0)),
+                (pos2(10.5, -20.0), pos2(5.0, -10.0)),
+                (pos2(200.0, 100.0), pos2(200.0, 100.0)),
+            ],
+        };
+        let mut svg = Vec::new();
+        write_svg(&mut svg, &map).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.contains("<title>Iron &lt;ore&gt;</title>"));
+
+        let elements = elements(&svg);
+        let root = &elements[0];
+        assert_eq!(root.name, "svg");
+        let view_box = &root.attributes.iter().find(|(k, _)| k == "viewBox").unwrap().1;
+        assert_eq!(view_box, "-100 -50 400 200");
+
+        let circles = |group: &str| -> Vec<Pos2> {
+            elements
+                .iter()
+                .filter(|e| e.name == "circle" && e.group == group)
+                .map(|e| pos2(e.number("cx"), e.number("cy")))
+                .collect()
+        };
+        assert_eq!(circles("iron"), map.layers[0].markers);
+        assert_eq!(circles("copper"), map.layers[1].markers);
+        assert_eq!(circles("stations"), map.stations);
+
+        let lines: Vec<(Pos2, Pos2)> = elements
+            .iter()
+            .filter(|e| e.name == "line" && e.group == "assignments")
+            .map(|e| (pos2(e.number("x1"), e.number("y1")), pos2(e.number("x2"), e.number("y2"))))
+            .collect();
+        assert_eq!(lines, map.assignments);
+        let groups = elements.iter().filter(|e| e.name == "g").count();
+        assert_eq!(groups, 4);
+    }
+}
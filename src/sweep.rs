@@ -0,0 +1,293 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+use egui::{Pos2, Rect};
+
+use crate::{
+    cluster::{Parameters, Problem},
+    map::ResourceMarker,
+    mask::BuildMask,
+    network::Network,
+    restarts::{best_of_restarts, RestartBest},
+    snapshot,
+};
+
+/// Sweep rows already computed, by k, seed and [`SweepInput::fingerprint`].
+pub type SweepCache = HashMap<(usize, Option<u64>, u64), SweepRow>;
+
+/// Owned copy of what a [`Problem`] borrows, so it can be sent to the sweep
+/// thread.
+#[derive(Debug, Clone)]
+pub struct SweepInput {
+    pub markers: Vec<ResourceMarker>,
+    pub clustered: Vec<bool>,
+    pub weights: Vec<f32>,
+    pub params: Parameters,
+    pub bounds: Rect,
+    pub pinned: Vec<Pos2>,
+    pub seeds: Vec<Pos2>,
+    pub seeds_fixed: bool,
+    pub mask: Option<BuildMask>,
+    pub network: Option<Network>,
+}
+
+impl SweepInput {
+    /// The problem this is a copy of, with other parameters.
+    pub fn problem<'a>(&'a self, params: &'a Parameters) -> Problem<'a> {
+        Problem {
+            markers: &self.markers,
+            clustered: self.clustered.clone(),
+            weights: self.weights.clone(),
+            params,
+            bounds: self.bounds,
+            pinned: &self.pinned,
+            seeds: &self.seeds,
+            seeds_fixed: self.seeds_fixed,
+            mask: self.mask.as_ref(),
+            network: self.network.as_ref(),
+        }
+    }
+
+    /// Hash of everything but k that the results of `restarts` runs depend on.
+    pub fn fingerprint(&self, restarts: usize) -> u64 {
+        let clustered: Vec<_> = self
+            .markers
+            .iter()
+            .zip(&self.clustered)
+            .zip(&self.weights)
+            .filter(|((_, clustered), _)| **clustered)
+            .map(|((m, _), weight)| (&m.pathName, m.x, m.y, m.z, *weight))
+            .collect();
+        let params = Parameters {
+            k: 0,
+            ..self.params.clone()
+        };
+        let key = (
+            clustered,
+            params,
+            self.bounds,
+            &self.pinned,
+            &self.seeds,
+            self.seeds_fixed,
+            &self.mask,
+            &self.network,
+            restarts,
+        );
+        snapshot::hash(&bincode::serialize(&key).expect("sweep input can be serialized"))
+    }
+}
+
+/// Best result found for one k.
+#[derive(Debug, Clone)]
+pub struct SweepRow {
+    pub k: usize,
+    pub best_error: f32,
+    /// Distance from a station to its farthest assigned marker, over all
+    /// stations.
+    pub max_radius: f32,
+    pub points: Vec<Pos2>,
+    pub heights: Vec<f32>,
+    pub seed: Option<u64>,
+}
+
+enum SweepMessage {
+    Run,
+    Row(SweepRow),
+}
+
+/// A sweep over a range of k running in the background.
+pub struct Sweep {
+    rx: Receiver<SweepMessage>,
+    cancel: Arc<AtomicBool>,
+    /// Rows by k.
+    pub rows: Vec<SweepRow>,
+    /// Rows computed since the last [`Sweep::take_fresh`].
+    fresh: Vec<SweepRow>,
+    pub runs_done: usize,
+    pub runs_total: usize,
+    pub running: bool,
+}
+
+impl Sweep {
+    /// Runs the clustering `restarts` times for every k in `ks`, starting out
+    /// with the `cached` rows of other ks.
+    pub fn start(
+        input: SweepInput,
+        ks: Vec<usize>,
+        restarts: usize,
+        cached: Vec<SweepRow>,
+    ) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let runs_total = ks.len() * restarts;
+
+        let cancelled = cancel.clone();
+        let base_seed = input.params.seed.unwrap_or_else(rand::random);
+        thread::spawn(move || {
+            for k in ks {
+                let params = Parameters {
+                    k,
+                    ..input.params.clone()
+                };
+                let problem = input.problem(&params);
+
+                let on_run = |_: &RestartBest| {
+                    // the receiver is gone if the app was closed mid-sweep
+                    if tx.send(SweepMessage::Run).is_err() {
+                        cancelled.store(true, Ordering::Relaxed);
+                    }
+                };
+                let best = best_of_restarts(&problem, restarts, base_seed, &cancelled, on_run);
+                if cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                let Some(best) = best else {
+                    continue;
+                };
+
+                let row = SweepRow {
+                    k,
+                    best_error: best.error,
+                    max_radius: max_radius(&problem, &best.points, &best.heights),
+                    points: best.points,
+                    heights: best.heights,
+                    seed: best.seed,
+                };
+                if tx.send(SweepMessage::Row(row)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self {
+            rx,
+            cancel,
+            rows: cached,
+            fresh: Vec::new(),
+            runs_done: 0,
+            runs_total,
+            running: true,
+        }
+    }
+
+    /// Takes in what the thread has sent so far.
+    pub fn poll(&mut self) {
+        loop {
+            match self.rx.try_recv() {
+                Ok(SweepMessage::Run) => self.runs_done += 1,
+                Ok(SweepMessage::Row(row)) => {
+                    self.fresh.push(row.clone());
+                    self.rows.push(row);
+                    self.rows.sort_by_key(|row| row.k);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.running = false;
+                    break;
+                }
+            }
+        }
+    }
+
+    pub fn take_fresh(&mut self) -> Vec<SweepRow> {
+        std::mem::take(&mut self.fresh)
+    }
+
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.runs_total == 0 {
+            1.0
+        } else {
+            self.runs_done as f32 / self.runs_total as f32
+        }
+    }
+}
+
+impl SweepRow {
+    /// The best error plus `station_cost` for every station, what facility
+    /// location minimizes.
+    pub fn combined(&self, station_cost: f32) -> f32 {
+        self.best_error + station_cost * self.points.len() as f32
+    }
+}
+
+/// The k whose stations cost the least with `station_cost` per station on
+/// top of their error, the smallest on ties.
+pub fn cheapest(rows: &[SweepRow], station_cost: f32) -> Option<usize> {
+    rows.iter()
+        .min_by(|a, b| {
+            a.combined(station_cost).total_cmp(&b.combined(station_cost)).then(a.k.cmp(&b.k))
+        })
+        .map(|row| row.k)
+}
+
+/// The k of the knee in the curve of best errors over k, where it bends the
+/// most once both axes are scaled to the unit range.
+pub fn knee(rows: &[SweepRow]) -> Option<usize> {
+    let first = rows.first()?;
+    let last = rows.last()?;
+    let k_range = (last.k - first.k) as f32;
+    let (min, max) = rows.iter().fold((f32::MAX, f32::MIN), |(min, max), row| {
+        (min.min(row.best_error), max.max(row.best_error))
+    });
+    if k_range == 0.0 || max <= min {
+        return None;
+    }
+
+    let scaled: Vec<(f32, f32)> = rows
+        .iter()
+        .map(|row| ((row.k - first.k) as f32 / k_range, (row.best_error - min) / (max - min)))
+        .collect();
+    scaled
+        .windows(3)
+        .zip(&rows[1..])
+        .map(|(window, row)| {
+            let [(x0, y0), (x1, y1), (x2, y2)] = [window[0], window[1], window[2]];
+            // curvature of the circle through the three points
+            let area = (x1 - x0) * (y2 - y0) - (x2 - x0) * (y1 - y0);
+            let sides = (x1 - x0).hypot(y1 - y0)
+                * (x2 - x1).hypot(y2 - y1)
+                * (x2 - x0).hypot(y2 - y0);
+            (row.k, 2.0 * area.abs() / sides)
+        })
+        .filter(|(_, curvature)| curvature.is_finite())
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(k, _)| k)
+}
+
+fn max_radius(problem: &Problem, points: &[Pos2], heights: &[f32]) -> f32 {
+    let sets = problem.assign(points, heights);
+    points
+        .iter()
+        .zip(heights)
+        .zip(&sets)
+        .flat_map(|((point, z), set)| set.iter().map(|i| problem.distance(*point, *z, *i)))
+        .fold(0.0, f32::max)
+}
+
+pub fn write_sweep_csv<W: Write>(mut w: W, rows: &[SweepRow]) -> io::Result<()> {
+    writeln!(w, "k,best_error,max_radius")?;
+    for row in rows {
+        writeln!(w, "{},{},{}", row.k, row.best_error, row.max_radius)?;
+    }
+    Ok(())
+}
+
+pub fn save_sweep_csv(path: &Path, rows: &[SweepRow]) -> io::Result<()> {
+    let mut file = io::BufWriter::new(fs::File::create(path)?);
+    write_sweep_csv(&mut file, rows)?;
+    file.flush()
+}